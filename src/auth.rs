@@ -1,10 +1,11 @@
 use crate::{
     config::Config,
+    error::OmenError,
     types::RequestContext,
 };
 use axum::{
     extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    http::HeaderMap,
     middleware::Next,
     response::Response,
 };
@@ -59,36 +60,13 @@ impl AuthService {
     }
 
     pub fn extract_auth_info(&self, headers: &HeaderMap) -> Option<ApiKeyInfo> {
-        // Try different authentication methods
-        if let Some(api_key) = self.extract_bearer_token(headers) {
-            return self.api_keys.get(&api_key).cloned();
-        }
-
-        if let Some(api_key) = self.extract_api_key_header(headers) {
-            return self.api_keys.get(&api_key).cloned();
-        }
-
-        None
-    }
-
-    fn extract_bearer_token(&self, headers: &HeaderMap) -> Option<String> {
-        headers
-            .get("authorization")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|auth| {
-                if auth.starts_with("Bearer ") {
-                    Some(auth[7..].to_string())
-                } else {
-                    None
-                }
-            })
+        let api_key = presented_key(|name| headers.get(name).and_then(|v| v.to_str().ok()))?;
+        self.authenticate_key(api_key)
     }
 
-    fn extract_api_key_header(&self, headers: &HeaderMap) -> Option<String> {
-        headers
-            .get("x-api-key")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string())
+    /// Key info for an API key presented over HTTP or gRPC
+    pub fn authenticate_key(&self, api_key: &str) -> Option<ApiKeyInfo> {
+        self.api_keys.get(api_key).cloned()
     }
 
     pub fn validate_permissions(&self, auth_info: &ApiKeyInfo, action: &str) -> bool {
@@ -116,12 +94,19 @@ impl AuthService {
     }
 }
 
+/// The API key a caller presented: `authorization: Bearer <key>`, else `x-api-key`
+fn presented_key<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Option<&'a str> {
+    header("authorization")
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+        .or_else(|| header("x-api-key"))
+}
+
 // Middleware function for authentication
 pub async fn auth_middleware(
     State(auth_service): State<Arc<AuthService>>,
     mut request: Request,
     next: Next,
-) -> std::result::Result<Response, StatusCode> {
+) -> std::result::Result<Response, OmenError> {
     // Skip auth if not required
     if !auth_service.config.auth.require_api_key {
         return Ok(next.run(request).await);
@@ -139,7 +124,33 @@ pub async fn auth_middleware(
         Ok(next.run(request).await)
     } else {
         warn!("Unauthorized request - missing or invalid API key");
-        Err(StatusCode::UNAUTHORIZED)
+        Err(OmenError::Unauthorized)
+    }
+}
+
+/// gRPC counterpart of `auth_middleware`: validates the API key in
+/// `authorization: Bearer ...` (or `x-api-key`) metadata and attaches its
+/// `ApiKeyInfo` to the request extensions
+#[allow(clippy::result_large_err)] // tonic's Interceptor returns Status
+pub fn grpc_interceptor(auth_service: Arc<AuthService>) -> impl tonic::service::Interceptor + Clone {
+    move |mut request: tonic::Request<()>| {
+        if !auth_service.config.auth.require_api_key {
+            return Ok(request);
+        }
+
+        let metadata = request.metadata();
+        let api_key = presented_key(|name| metadata.get(name).and_then(|v| v.to_str().ok()));
+        match api_key.and_then(|key| auth_service.authenticate_key(key)) {
+            Some(auth_info) => {
+                debug!("Authenticated gRPC request from user: {}", auth_info.user_id);
+                request.extensions_mut().insert(auth_info);
+                Ok(request)
+            }
+            None => {
+                warn!("Unauthorized gRPC request - missing or invalid API key");
+                Err(tonic::Status::from(OmenError::Unauthorized))
+            }
+        }
     }
 }
 
@@ -182,36 +193,11 @@ pub fn create_authenticated_context(
         request_id: Uuid::new_v4(),
         user_id: auth_info.map(|a| a.user_id.clone()),
         api_key: None, // Don't store the actual key
-        intent: classify_request_intent(request),
+        intent: None, // classified by the router
         tags,
     }
 }
 
-fn classify_request_intent(request: &crate::types::ChatCompletionRequest) -> Option<String> {
-    // Simple intent classification based on message content
-    if let Some(last_message) = request.messages.last() {
-        let content = last_message.content.to_lowercase();
-
-        if content.contains("code") || content.contains("function") || content.contains("implement") {
-            return Some("code".to_string());
-        }
-        if content.contains("test") || content.contains("unit test") {
-            return Some("tests".to_string());
-        }
-        if content.contains("regex") || content.contains("pattern") {
-            return Some("regex".to_string());
-        }
-        if content.contains("analyze") || content.contains("review") {
-            return Some("analysis".to_string());
-        }
-        if content.contains("explain") || content.contains("summarize") {
-            return Some("explanation".to_string());
-        }
-    }
-
-    Some("general".to_string())
-}
-
 // Rate limiting structures - part of public API
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -0,0 +1,540 @@
+//! Per-request budget enforcement
+//!
+//! Before a request is sent, each candidate's worst-case cost (prompt tokens
+//! plus `max_tokens` at the model's catalog pricing) is checked against the
+//! request's `budget_usd`. Candidates that can't fit are excluded, or have
+//! `max_tokens` clamped to what the budget affords when clamping is enabled;
+//! a clamp is reported in the response's `omen.budget` metadata.
+//! Streams are metered as they arrive and cut off with `finish_reason:
+//! "length"` once the running cost crosses the cap. What a stream actually
+//! generated is reported when it ends or is dropped, so billing covers only
+//! tokens produced before a client disconnect.
+
+use crate::{
+    config::BudgetConfig,
+    error::Result,
+    merge,
+    providers::Provider,
+    types::{ChatCompletionRequest, ChatMessage, ContentPart, MessageContent, Model, ModelPricing},
+};
+use futures::{stream::Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+/// Budget applied to requests with OMEN options that don't set `budget_usd`
+pub const DEFAULT_REQUEST_BUDGET_USD: f64 = 0.10;
+
+/// How long a provider's model list is trusted for pricing
+const PRICING_TTL: Duration = Duration::from_secs(300);
+
+/// Tokens added per message for role and separators
+const TOKENS_PER_MESSAGE: u32 = 4;
+/// Tokens priming the assistant reply
+const REPLY_PRIMING_TOKENS: u32 = 3;
+
+/// Request budget: explicit `budget_usd`, the default when OMEN options are
+/// present, otherwise unlimited
+pub fn request_budget(request: &ChatCompletionRequest) -> Option<f64> {
+    request
+        .omen
+        .as_ref()
+        .map(|o| o.budget_usd.unwrap_or(DEFAULT_REQUEST_BUDGET_USD))
+}
+
+/// BPE token count: o200k for the GPT-4o/o-series family, cl100k otherwise.
+/// Other vendors' tokenizers differ, but cl100k is a close approximation.
+pub fn count_tokens(model: &str, text: &str) -> u32 {
+    if text.is_empty() {
+        return 0;
+    }
+
+    let bpe = if uses_o200k(model) {
+        tiktoken_rs::o200k_base_singleton()
+    } else {
+        tiktoken_rs::cl100k_base_singleton()
+    };
+    let count = bpe.lock().encode_with_special_tokens(text).len();
+    count as u32
+}
+
+fn uses_o200k(model: &str) -> bool {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"]
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+}
+
+/// Prompt tokens for a chat request, including message overhead, images and tools
+pub fn count_request_tokens(request: &ChatCompletionRequest) -> u32 {
+    let mut total = REPLY_PRIMING_TOKENS;
+
+    for message in &request.messages {
+        total += count_message_tokens(&request.model, message);
+    }
+
+    if let Some(ref tools) = request.tools {
+        total += count_tokens(&request.model, &serde_json::to_string(tools).unwrap_or_default());
+    }
+
+    total
+}
+
+/// Tokens one message adds to a prompt, including its overhead, images and tool calls
+pub fn count_message_tokens(model: &str, message: &ChatMessage) -> u32 {
+    let mut total = TOKENS_PER_MESSAGE + count_tokens(model, &message.content.text());
+
+    if let MessageContent::Parts(parts) = &message.content {
+        for part in parts {
+            if let ContentPart::ImageUrl { image_url } = part {
+                total += match image_url.detail.as_deref() {
+                    Some("low") => 85,
+                    Some("high") => 765,
+                    _ => 425,
+                };
+            }
+        }
+    }
+    if let Some(ref tool_calls) = message.tool_calls {
+        total += count_tokens(model, &serde_json::to_string(tool_calls).unwrap_or_default());
+    }
+
+    total
+}
+
+/// Pricing assumed for models a provider doesn't list
+pub fn fallback_pricing() -> ModelPricing {
+    ModelPricing {
+        input_per_1k: 0.02,
+        output_per_1k: 0.02,
+    }
+}
+
+/// A provider's model list and when it was fetched
+type CachedModels = (Instant, HashMap<String, Model>);
+
+/// Model pricing and context windows from each provider's catalog, refreshed
+/// every few minutes so estimates don't cost a `list_models` round trip per request
+#[derive(Debug, Default)]
+pub struct PricingCache {
+    entries: RwLock<HashMap<String, CachedModels>>,
+}
+
+impl PricingCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn pricing(&self, provider: &dyn Provider, model: &str) -> ModelPricing {
+        self.model(provider, model)
+            .await
+            .map(|m| m.pricing)
+            .unwrap_or_else(fallback_pricing)
+    }
+
+    /// Context window of a model, if the provider lists it with one
+    pub async fn context_length(&self, provider: &dyn Provider, model: &str) -> Option<u32> {
+        self.model(provider, model)
+            .await
+            .map(|m| m.context_length)
+            .filter(|&length| length > 0)
+    }
+
+    /// Whether the provider's (cached) catalog lists the model
+    pub async fn serves(&self, provider: &dyn Provider, model: &str) -> bool {
+        self.model(provider, model).await.is_some()
+    }
+
+    async fn model(&self, provider: &dyn Provider, model: &str) -> Option<Model> {
+        if let Some((fetched_at, models)) = self.entries.read().await.get(provider.id())
+            && fetched_at.elapsed() < PRICING_TTL
+        {
+            return models.get(model).cloned();
+        }
+
+        match provider.list_models().await {
+            Ok(models) => {
+                let models: HashMap<_, _> = models.into_iter().map(|m| (m.id.clone(), m)).collect();
+                let found = models.get(model).cloned();
+                self.entries
+                    .write()
+                    .await
+                    .insert(provider.id().to_string(), (Instant::now(), models));
+                found
+            }
+            Err(e) => {
+                debug!("Model list unavailable for {}, using fallbacks: {}", provider.id(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Worst-case cost of sending a request to one provider
+#[derive(Debug, Clone, Serialize)]
+pub struct CostEstimate {
+    pub provider_id: String,
+    pub model: String,
+    pub input_tokens: u32,
+    pub max_output_tokens: u32,
+    pub worst_case_usd: f64,
+    #[serde(skip)]
+    pub pricing: ModelPricing,
+}
+
+impl CostEstimate {
+    /// Largest `max_tokens` whose worst case fits the budget
+    pub fn max_output_tokens_within(&self, budget_usd: f64) -> u32 {
+        let input_cost = merge::cost_usd(&self.pricing, self.input_tokens, 0);
+        if self.pricing.output_per_1k <= 0.0 {
+            return u32::MAX;
+        }
+        let remaining = (budget_usd - input_cost).max(0.0);
+        (remaining / self.pricing.output_per_1k * 1000.0).floor().min(u32::MAX as f64) as u32
+    }
+}
+
+/// Estimate a request's worst-case cost on a provider, using the provider's
+/// catalog pricing for the model it will be sent
+pub async fn estimate(
+    pricing_cache: &PricingCache,
+    provider: &dyn Provider,
+    request: &ChatCompletionRequest,
+    default_output_tokens: u32,
+) -> CostEstimate {
+    let pricing = pricing_cache.pricing(provider, &request.model).await;
+    let input_tokens = count_request_tokens(request);
+    let max_output_tokens = request.max_tokens.unwrap_or(default_output_tokens);
+
+    CostEstimate {
+        provider_id: provider.id().to_string(),
+        model: request.model.clone(),
+        input_tokens,
+        max_output_tokens,
+        worst_case_usd: merge::cost_usd(&pricing, input_tokens, max_output_tokens),
+        pricing,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetVerdict {
+    Fits,
+    /// Fits once `max_tokens` is clamped to this value
+    Clamp(u32),
+    Exceeds,
+}
+
+/// `max_tokens` lowered so the serving candidate fits the request budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetClamp {
+    pub provider: String,
+    pub model: String,
+    /// What the request asked for; unset when the default output was assumed
+    pub requested_max_tokens: Option<u32>,
+    pub max_tokens: u32,
+    pub budget_usd: f64,
+}
+
+/// Whether a request fits the budget as-is, after clamping, or not at all
+pub fn check(estimate: &CostEstimate, budget_usd: f64, config: &BudgetConfig) -> BudgetVerdict {
+    if estimate.worst_case_usd <= budget_usd {
+        return BudgetVerdict::Fits;
+    }
+
+    if config.clamp_max_tokens {
+        let affordable = estimate.max_output_tokens_within(budget_usd);
+        if affordable >= config.min_output_tokens {
+            return BudgetVerdict::Clamp(affordable);
+        }
+    }
+
+    BudgetVerdict::Exceeds
+}
+
+/// Running cost of a streamed response
+#[derive(Debug, Clone)]
+pub struct StreamBudget {
+    model: String,
+    pricing: ModelPricing,
+    input_tokens: u32,
+    output_tokens: u32,
+    cap_usd: f64,
+    last_id: Option<String>,
+    last_created: Option<i64>,
+}
+
+impl StreamBudget {
+    pub fn new(model: &str, pricing: ModelPricing, input_tokens: u32, cap_usd: f64) -> Self {
+        Self {
+            model: model.to_string(),
+            pricing,
+            input_tokens,
+            output_tokens: 0,
+            cap_usd,
+            last_id: None,
+            last_created: None,
+        }
+    }
+
+    pub fn cost_usd(&self) -> f64 {
+        merge::cost_usd(&self.pricing, self.input_tokens, self.output_tokens)
+    }
+
+    pub fn input_tokens(&self) -> u32 {
+        self.input_tokens
+    }
+
+    pub fn output_tokens(&self) -> u32 {
+        self.output_tokens
+    }
+
+    /// Account the generated tokens in an SSE chunk; true once the cap is crossed
+    pub fn record_chunk(&mut self, chunk: &str) -> bool {
+        for data in chunk.lines().filter_map(|line| line.strip_prefix("data: ")) {
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(data.trim()) else {
+                continue;
+            };
+            if let Some(id) = event["id"].as_str() {
+                self.last_id = Some(id.to_string());
+            }
+            if let Some(created) = event["created"].as_i64() {
+                self.last_created = Some(created);
+            }
+
+            for choice in event["choices"].as_array().into_iter().flatten() {
+                let delta = &choice["delta"];
+                if let Some(content) = delta["content"].as_str() {
+                    self.output_tokens += count_tokens(&self.model, content);
+                }
+                for call in delta["tool_calls"].as_array().into_iter().flatten() {
+                    if let Some(arguments) = call["function"]["arguments"].as_str() {
+                        self.output_tokens += count_tokens(&self.model, arguments);
+                    }
+                }
+            }
+        }
+
+        self.cost_usd() > self.cap_usd
+    }
+
+    /// Final SSE events closing a stream that hit the cap
+    pub fn finish_events(&self) -> String {
+        let chunk = serde_json::json!({
+            "id": self.last_id.clone().unwrap_or_else(|| format!("chatcmpl-{}", uuid::Uuid::new_v4())),
+            "object": "chat.completion.chunk",
+            "created": self.last_created.unwrap_or_else(|| chrono::Utc::now().timestamp()),
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": {}, "finish_reason": "length" }],
+        });
+        format!("data: {}\n\ndata: [DONE]\n\n", chunk)
+    }
+}
+
+/// Meter a stream and end it (dropping the upstream) once the budget is spent
+pub fn cap_stream(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    budget: StreamBudget,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let capped = futures::stream::unfold(Some((stream, budget)), |state| async move {
+        let (mut stream, mut budget) = state?;
+        let item = stream.next().await?;
+
+        if let Ok(ref chunk) = item
+            && budget.record_chunk(chunk)
+        {
+            warn!(
+                "💰 Stream cost ${:.4} crossed budget ${:.4} after {} tokens, cutting off",
+                budget.cost_usd(), budget.cap_usd, budget.output_tokens
+            );
+            let closed = format!("{}{}", chunk, budget.finish_events());
+            return Some((Ok(closed), None));
+        }
+
+        Some((item, Some((stream, budget))))
+    });
+
+    Box::new(Box::pin(capped))
+}
+
+/// Tokens and cost a provider stream actually generated
+#[derive(Debug, Clone)]
+pub struct StreamUsage {
+    pub provider_id: String,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cost_usd: f64,
+    /// Dropped before the provider finished
+    pub cancelled: bool,
+}
+
+/// Called once per provider stream with its final usage
+pub type UsageCallback = Arc<dyn Fn(StreamUsage) + Send + Sync>;
+
+/// Meters a provider stream and reports its usage when dropped, whether it
+/// finished or was cancelled
+pub struct UsageGuard {
+    pub meter: StreamBudget,
+    provider_id: String,
+    on_usage: Option<UsageCallback>,
+    finished: bool,
+}
+
+impl UsageGuard {
+    pub fn new(meter: StreamBudget, provider_id: &str, on_usage: Option<UsageCallback>) -> Self {
+        Self {
+            meter,
+            provider_id: provider_id.to_string(),
+            on_usage,
+            finished: false,
+        }
+    }
+
+    /// The provider ended the stream (completed, failed or cut off by the budget)
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for UsageGuard {
+    fn drop(&mut self) {
+        if let Some(on_usage) = self.on_usage.take() {
+            on_usage(StreamUsage {
+                provider_id: std::mem::take(&mut self.provider_id),
+                model: self.meter.model.clone(),
+                input_tokens: self.meter.input_tokens,
+                output_tokens: self.meter.output_tokens,
+                cost_usd: self.meter.cost_usd(),
+                cancelled: !self.finished,
+            });
+        }
+    }
+}
+
+/// Report a stream's usage through `guard` once it ends or is dropped
+pub fn meter_stream(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    guard: UsageGuard,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let metered = futures::stream::unfold(Some((stream, guard)), |state| async move {
+        let (mut stream, mut guard) = state?;
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                guard.meter.record_chunk(&chunk);
+                Some((Ok(chunk), Some((stream, guard))))
+            }
+            Some(Err(e)) => {
+                guard.finish();
+                Some((Err(e), None))
+            }
+            None => {
+                guard.finish();
+                None
+            }
+        }
+    });
+
+    Box::new(Box::pin(metered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pricing() -> ModelPricing {
+        ModelPricing {
+            input_per_1k: 0.01,
+            output_per_1k: 0.03,
+        }
+    }
+
+    fn estimate(max_output_tokens: u32) -> CostEstimate {
+        CostEstimate {
+            provider_id: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            input_tokens: 1000,
+            max_output_tokens,
+            worst_case_usd: merge::cost_usd(&pricing(), 1000, max_output_tokens),
+            pricing: pricing(),
+        }
+    }
+
+    #[test]
+    fn test_counts_bpe_tokens() {
+        assert_eq!(count_tokens("gpt-4o", "hello world"), 2);
+        assert_eq!(count_tokens("claude-3-5-sonnet", ""), 0);
+    }
+
+    #[test]
+    fn test_budget_verdicts() {
+        let config = BudgetConfig {
+            clamp_max_tokens: true,
+            ..BudgetConfig::default()
+        };
+
+        // $0.01 input + $0.03 per 1k output
+        assert_eq!(check(&estimate(1000), 0.05, &config), BudgetVerdict::Fits);
+        assert_eq!(check(&estimate(4000), 0.04, &config), BudgetVerdict::Clamp(1000));
+        assert_eq!(check(&estimate(4000), 0.0101, &config), BudgetVerdict::Exceeds);
+
+        // Clamping is opt-in
+        let no_clamp = BudgetConfig::default();
+        assert_eq!(check(&estimate(4000), 0.04, &no_clamp), BudgetVerdict::Exceeds);
+    }
+
+    #[tokio::test]
+    async fn test_stream_cut_off_at_cap() {
+        let chunk = |text: &str| {
+            format!(
+                "data: {}\n\n",
+                serde_json::json!({"id": "c1", "created": 1, "model": "gpt-4o",
+                    "choices": [{"index": 0, "delta": {"content": text}, "finish_reason": null}]})
+            )
+        };
+        let upstream: Vec<Result<String>> = (0..10).map(|_| Ok(chunk("hello world"))).collect();
+
+        // Each chunk costs 2 tokens * $0.03/1k; the cap is crossed on the third
+        let budget = StreamBudget::new("gpt-4o", ModelPricing { input_per_1k: 0.0, output_per_1k: 0.03 }, 0, 0.00015);
+        let capped: Vec<_> = cap_stream(Box::new(futures::stream::iter(upstream)), budget).collect().await;
+
+        assert_eq!(capped.len(), 3);
+        let last = capped.last().unwrap().as_ref().unwrap();
+        assert!(last.contains("\"finish_reason\":\"length\""));
+        assert!(last.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_usage_reported_for_dropped_stream() {
+        let chunk = format!(
+            "data: {}\n\n",
+            serde_json::json!({"choices": [{"index": 0, "delta": {"content": "hello world"}}]})
+        );
+        let usage = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = usage.clone();
+        let on_usage: UsageCallback = Arc::new(move |u| reported.lock().unwrap().push(u));
+        let meter = || StreamBudget::new("gpt-4o", ModelPricing { input_per_1k: 0.0, output_per_1k: 0.03 }, 5, f64::INFINITY);
+
+        let upstream = futures::stream::iter(vec![Ok(chunk.clone()), Ok(chunk.clone()), Ok(chunk.clone())]);
+        let mut stream = meter_stream(Box::new(upstream), UsageGuard::new(meter(), "openai", Some(on_usage.clone())));
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        let upstream = futures::stream::iter(vec![Ok(chunk.clone()), Ok(chunk)]);
+        let finished: Vec<_> = meter_stream(Box::new(upstream), UsageGuard::new(meter(), "openai", Some(on_usage)))
+            .collect()
+            .await;
+        assert_eq!(finished.len(), 2);
+
+        let usage = usage.lock().unwrap();
+        assert_eq!(usage.len(), 2);
+        assert!(usage[0].cancelled);
+        assert_eq!((usage[0].input_tokens, usage[0].output_tokens), (5, 2));
+        assert!(!usage[1].cancelled);
+        assert_eq!(usage[1].output_tokens, 4);
+    }
+}
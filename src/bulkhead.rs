@@ -0,0 +1,287 @@
+//! Per-provider concurrency bulkheads
+//!
+//! `[bulkheads]` caps the requests in flight to each provider instance and
+//! model, so a burst can't overload a local GPU or trip a cloud rate limit.
+//! Requests over the cap wait in a bounded queue ordered by priority: the
+//! request's `priority` tag (set from `GhostService::priority()` for Ghost
+//! traffic) scaled by the user's `BillingTier.priority_weight`, first come
+//! first served within a priority. A full queue or an expired wait fails the
+//! attempt so routing can fail over. Recent queue wait is added to a
+//! provider's latency when it is scored.
+
+use crate::{
+    config::BulkheadConfig,
+    error::{OmenError, Result},
+    wait_queue::{SlotPool, WaitQueue, Waiting},
+};
+use futures::{Stream, StreamExt};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// Priority of requests without a `priority` tag (that of external Ghost services)
+pub const DEFAULT_PRIORITY: f64 = 100.0;
+
+/// Weight of the newest wait in the running average
+const WAIT_SMOOTHING: f64 = 0.2;
+
+/// Bulkheads for every limited provider model
+#[derive(Debug)]
+pub struct BulkheadRegistry {
+    config: BulkheadConfig,
+    bulkheads: Mutex<HashMap<(String, String), Arc<Bulkhead>>>,
+}
+
+impl BulkheadRegistry {
+    pub fn new(config: BulkheadConfig) -> Self {
+        Self { config, bulkheads: Mutex::new(HashMap::new()) }
+    }
+
+    /// In-flight and queue limits for a provider model, most specific first
+    fn limits(&self, provider_id: &str, model: &str) -> Option<(usize, usize)> {
+        let configured = self
+            .config
+            .limits
+            .iter()
+            .filter(|limit| limit.provider == provider_id)
+            .find(|limit| limit.model.as_deref() == Some(model))
+            .or_else(|| {
+                self.config.limits.iter().find(|limit| limit.provider == provider_id && limit.model.is_none())
+            });
+
+        match configured {
+            Some(limit) => Some((limit.max_in_flight, limit.max_queue.unwrap_or(self.config.max_queue))),
+            None => self.config.default_max_in_flight.map(|max| (max, self.config.max_queue)),
+        }
+    }
+
+    fn bulkhead(&self, provider_id: &str, model: &str) -> Option<Arc<Bulkhead>> {
+        let (max_in_flight, max_queue) = self.limits(provider_id, model)?;
+        let mut bulkheads = self.bulkheads.lock().unwrap();
+        let bulkhead = bulkheads
+            .entry((provider_id.to_string(), model.to_string()))
+            .or_insert_with(|| Arc::new(Bulkhead::new(max_in_flight.max(1), max_queue)));
+        Some(bulkhead.clone())
+    }
+
+    /// Take a slot, waiting in line behind higher-priority requests.
+    /// None when the provider model is unlimited.
+    pub async fn acquire(&self, provider_id: &str, model: &str, priority: f64) -> Result<Option<BulkheadPermit>> {
+        let Some(bulkhead) = self.bulkhead(provider_id, model) else {
+            return Ok(None);
+        };
+        let timeout = Duration::from_millis(self.config.queue_timeout_ms);
+        bulkhead.acquire(priority, timeout).await.map(Some).map_err(|reason| {
+            debug!("🚧 Bulkhead for {}/{} rejected request: {}", provider_id, model, reason);
+            OmenError::ProviderUnavailable(format!("{}/{} {}", provider_id, model, reason))
+        })
+    }
+
+    /// Recent average wait for a slot, for routing
+    pub fn queue_wait_ms(&self, provider_id: &str, model: &str) -> f64 {
+        let bulkheads = self.bulkheads.lock().unwrap();
+        bulkheads
+            .get(&(provider_id.to_string(), model.to_string()))
+            .map_or(0.0, |bulkhead| bulkhead.state.lock().unwrap().wait_ms)
+    }
+}
+
+/// A caller's place in line: the registry and the priority it queues with
+#[derive(Debug, Clone)]
+pub struct Admission {
+    registry: Arc<BulkheadRegistry>,
+    priority: f64,
+}
+
+impl Admission {
+    pub fn new(registry: Arc<BulkheadRegistry>, priority: f64) -> Self {
+        Self { registry, priority }
+    }
+
+    pub async fn acquire(&self, provider_id: &str, model: &str) -> Result<Option<BulkheadPermit>> {
+        self.registry.acquire(provider_id, model, self.priority).await
+    }
+}
+
+#[derive(Debug)]
+struct Bulkhead {
+    max_in_flight: usize,
+    max_queue: usize,
+    state: Mutex<BulkheadState>,
+}
+
+#[derive(Debug, Default)]
+struct BulkheadState {
+    /// Waiters ranked by priority
+    queue: WaitQueue<()>,
+    /// Smoothed wait for a slot
+    wait_ms: f64,
+}
+
+impl BulkheadState {
+    fn record_wait(&mut self, waited: Duration) {
+        let waited_ms = waited.as_secs_f64() * 1000.0;
+        self.wait_ms += WAIT_SMOOTHING * (waited_ms - self.wait_ms);
+    }
+}
+
+impl Bulkhead {
+    fn new(max_in_flight: usize, max_queue: usize) -> Self {
+        Self { max_in_flight, max_queue, state: Mutex::new(BulkheadState::default()) }
+    }
+
+    async fn acquire(self: Arc<Self>, priority: f64, timeout: Duration) -> std::result::Result<BulkheadPermit, String> {
+        let started = Instant::now();
+        let (seq, grant) = {
+            let mut state = self.state.lock().unwrap();
+            if state.queue.in_flight < self.max_in_flight {
+                state.queue.in_flight += 1;
+                state.record_wait(Duration::ZERO);
+                return Ok(BulkheadPermit { bulkhead: self.clone() });
+            }
+            if state.queue.len() >= self.max_queue {
+                return Err(format!("queue is full ({} waiting)", state.queue.len()));
+            }
+            state.queue.enqueue(priority, ())
+        };
+
+        if Waiting::new(self.clone(), seq, grant).granted(timeout).await {
+            self.state.lock().unwrap().record_wait(started.elapsed());
+            Ok(BulkheadPermit { bulkhead: self.clone() })
+        } else {
+            self.state.lock().unwrap().record_wait(timeout);
+            Err(format!("waited {}ms without a free slot", timeout.as_millis()))
+        }
+    }
+
+    /// Hand the slot to the highest-priority waiter, or free it
+    fn release(&self) {
+        self.state.lock().unwrap().queue.release(|_| {});
+    }
+}
+
+impl SlotPool for Bulkhead {
+    fn cancel(&self, seq: u64) -> bool {
+        self.state.lock().unwrap().queue.remove(seq).is_some()
+    }
+
+    fn release_unused(&self) {
+        self.release();
+    }
+}
+
+
+/// A slot in a bulkhead, released when dropped
+#[derive(Debug)]
+pub struct BulkheadPermit {
+    bulkhead: Arc<Bulkhead>,
+}
+
+impl Drop for BulkheadPermit {
+    fn drop(&mut self) {
+        self.bulkhead.release();
+    }
+}
+
+/// Keep a slot (a bulkhead or admission permit) taken until the stream ends or is dropped
+pub fn hold_stream<P: Send + 'static>(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    permit: Option<P>,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let Some(permit) = permit else {
+        return stream;
+    };
+    let held = futures::stream::unfold((stream, permit), |(mut stream, permit)| async move {
+        let item = stream.next().await?;
+        Some((item, (stream, permit)))
+    });
+    Box::new(Box::pin(held))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BulkheadLimitConfig;
+
+    fn registry(max_in_flight: usize, max_queue: usize) -> Arc<BulkheadRegistry> {
+        Arc::new(BulkheadRegistry::new(BulkheadConfig {
+            queue_timeout_ms: 1000,
+            limits: vec![BulkheadLimitConfig {
+                provider: "ollama".to_string(),
+                model: None,
+                max_in_flight,
+                max_queue: Some(max_queue),
+            }],
+            ..Default::default()
+        }))
+    }
+
+    fn registry_with_model_limit() -> BulkheadRegistry {
+        BulkheadRegistry::new(BulkheadConfig {
+            limits: vec![BulkheadLimitConfig {
+                provider: "ollama".to_string(),
+                model: Some("llama3".to_string()),
+                max_in_flight: 1,
+                max_queue: None,
+            }],
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_without_config() {
+        let registry = BulkheadRegistry::new(BulkheadConfig::default());
+        assert!(registry.acquire("openai", "gpt-4o", DEFAULT_PRIORITY).await.unwrap().is_none());
+
+        let limited = registry_with_model_limit();
+        assert!(limited.acquire("ollama", "llama3", DEFAULT_PRIORITY).await.unwrap().is_some());
+        assert!(limited.acquire("ollama", "qwen", DEFAULT_PRIORITY).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_queue_full_rejects() {
+        let registry = registry(1, 1);
+        let held = registry.acquire("ollama", "llama3", DEFAULT_PRIORITY).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let registry = registry.clone();
+            async move { registry.acquire("ollama", "llama3", DEFAULT_PRIORITY).await.map(|p| p.is_some()) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let rejected = registry.acquire("ollama", "llama3", DEFAULT_PRIORITY).await;
+        assert!(matches!(rejected, Err(OmenError::ProviderUnavailable(_))));
+
+        drop(held);
+        assert!(waiting.await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_higher_priority_served_first() {
+        let registry = registry(1, 8);
+        let held = registry.acquire("ollama", "llama3", DEFAULT_PRIORITY).await.unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut waiters = Vec::new();
+        for (name, priority) in [("external", 100.0), ("development", 50.0), ("ghostllm", 255.0)] {
+            let (registry, order) = (registry.clone(), order.clone());
+            waiters.push(tokio::spawn(async move {
+                let permit = registry.acquire("ollama", "llama3", priority).await.unwrap();
+                order.lock().unwrap().push(name);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                drop(permit);
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["ghostllm", "external", "development"]);
+        assert!(registry.queue_wait_ms("ollama", "llama3") > 0.0);
+    }
+}
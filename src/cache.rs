@@ -393,12 +393,10 @@ impl RedisCache {
     pub async fn warm_cache(&self) -> Result<()> {
         info!("🔥 Warming up Redis cache...");
 
-        // Pre-load common provider health checks
-        let common_providers = vec!["ollama", "openai", "anthropic", "google"];
-
-        for provider_id in common_providers {
-            self.cache_provider_health(provider_id, true, 100, None).await?;
-        }
+        // Verify the pool can hand out a working connection. Provider health
+        // is written by the background health monitor once real checks run.
+        let conn = self.get_connection().await?;
+        self.return_connection(conn).await;
 
         info!("✅ Cache warming completed");
         Ok(())
@@ -0,0 +1,563 @@
+//! Per-provider and per-model circuit breakers
+//!
+//! A breaker trips to `open` after too many consecutive failures or when the
+//! error rate over a rolling window crosses a threshold. While open, the
+//! provider is excluded from routing. After a cooldown it moves to
+//! `half_open` and admits a limited number of probe requests; enough
+//! successful probes close the circuit again, any failure re-opens it.
+//!
+//! Transport failures count against the provider's breaker and failed
+//! responses against the model's, so one broken model doesn't take down the
+//! rest of its provider.
+
+use crate::{
+    config::CircuitBreakerConfig,
+    error::{OmenError, Result, UpstreamError},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half_open"),
+        }
+    }
+}
+
+/// The breaker a failure counts against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureScope {
+    Provider,
+    Model,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerStatus {
+    pub key: String,
+    pub provider_id: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub error_rate: f64,
+    pub requests_in_window: usize,
+    #[serde(default)]
+    pub opened_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_failure_at: Option<DateTime<Utc>>,
+    pub trip_count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    outcomes: VecDeque<bool>, // true = success
+    opened_at: Option<Instant>,
+    opened_at_utc: Option<DateTime<Utc>>,
+    last_failure_at: Option<DateTime<Utc>>,
+    half_open_in_flight: u32,
+    half_open_successes: u32,
+    trip_count: u32,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            outcomes: VecDeque::new(),
+            opened_at: None,
+            opened_at_utc: None,
+            last_failure_at: None,
+            half_open_in_flight: 0,
+            half_open_successes: 0,
+            trip_count: 0,
+        }
+    }
+
+    /// Effective state, treating an open circuit whose cooldown has elapsed as half-open
+    pub fn state(&self, config: &CircuitBreakerConfig) -> CircuitState {
+        match self.state {
+            CircuitState::Open if self.cooldown_elapsed(config) => CircuitState::HalfOpen,
+            state => state,
+        }
+    }
+
+    /// Whether a new request may be routed through this breaker
+    pub fn can_route(&self, config: &CircuitBreakerConfig) -> bool {
+        match self.state(config) {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => {
+                // A freshly expired open circuit has no probes in flight yet
+                self.state == CircuitState::Open
+                    || self.half_open_in_flight < config.half_open_max_probes
+            }
+        }
+    }
+
+    /// Mark a request as dispatched; in half-open state this claims a probe slot
+    pub fn on_request_start(&mut self, config: &CircuitBreakerConfig) {
+        if self.state == CircuitState::Open && self.cooldown_elapsed(config) {
+            self.state = CircuitState::HalfOpen;
+            self.half_open_in_flight = 0;
+            self.half_open_successes = 0;
+        }
+
+        if self.state == CircuitState::HalfOpen {
+            self.half_open_in_flight += 1;
+        }
+    }
+
+    pub fn record_success(&mut self, config: &CircuitBreakerConfig) {
+        self.push_outcome(true, config);
+        self.consecutive_failures = 0;
+
+        if self.state == CircuitState::HalfOpen {
+            self.half_open_in_flight = self.half_open_in_flight.saturating_sub(1);
+            self.half_open_successes += 1;
+
+            if self.half_open_successes >= config.success_threshold {
+                self.close();
+            }
+        }
+    }
+
+    pub fn record_failure(&mut self, config: &CircuitBreakerConfig) {
+        self.push_outcome(false, config);
+        self.consecutive_failures += 1;
+        self.last_failure_at = Some(Utc::now());
+
+        match self.state {
+            CircuitState::HalfOpen => self.trip(),
+            CircuitState::Closed => {
+                let rate_exceeded = self.outcomes.len() >= config.min_requests as usize
+                    && self.error_rate() >= config.error_rate_threshold;
+
+                if self.consecutive_failures >= config.failure_threshold || rate_exceeded {
+                    self.trip();
+                }
+            }
+            CircuitState::Open => {}
+        }
+    }
+
+    /// Release a probe slot for a request that ended without a verdict (e.g. cancelled)
+    pub fn release(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            self.half_open_in_flight = self.half_open_in_flight.saturating_sub(1);
+        }
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        let failures = self.outcomes.iter().filter(|ok| !**ok).count();
+        failures as f64 / self.outcomes.len() as f64
+    }
+
+    fn cooldown_elapsed(&self, config: &CircuitBreakerConfig) -> bool {
+        self.opened_at
+            .map(|opened| opened.elapsed() >= Duration::from_secs(config.open_duration_seconds))
+            .unwrap_or(true)
+    }
+
+    fn push_outcome(&mut self, success: bool, config: &CircuitBreakerConfig) {
+        self.outcomes.push_back(success);
+        while self.outcomes.len() > config.window_size.max(1) as usize {
+            self.outcomes.pop_front();
+        }
+    }
+
+    fn trip(&mut self) {
+        self.state = CircuitState::Open;
+        self.opened_at = Some(Instant::now());
+        self.opened_at_utc = Some(Utc::now());
+        self.half_open_in_flight = 0;
+        self.half_open_successes = 0;
+        self.trip_count += 1;
+    }
+
+    fn close(&mut self) {
+        self.state = CircuitState::Closed;
+        self.consecutive_failures = 0;
+        self.outcomes.clear();
+        self.opened_at = None;
+        self.opened_at_utc = None;
+        self.half_open_in_flight = 0;
+        self.half_open_successes = 0;
+    }
+}
+
+/// Registry of breakers keyed by provider id and by `provider/model`
+#[derive(Debug)]
+pub struct CircuitBreakerRegistry {
+    config: CircuitBreakerConfig,
+    breakers: RwLock<HashMap<String, CircuitBreaker>>,
+}
+
+/// CircuitBreakerRegistry implementation - all public methods are part of the routing API
+#[allow(dead_code)]
+impl CircuitBreakerRegistry {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    /// Which breaker an error counts against, if any. Transport failures
+    /// say the provider itself is down; failed responses only say the model
+    /// is. Auth, client and serialization errors say neither.
+    pub fn failure_scope(error: &OmenError) -> Option<FailureScope> {
+        match error {
+            OmenError::ProviderUnavailable(_) | OmenError::HttpClient(_) => Some(FailureScope::Provider),
+            OmenError::Provider(_) | OmenError::Upstream(UpstreamError { status: 408 | 500.., .. }) => {
+                Some(FailureScope::Model)
+            }
+            _ => None,
+        }
+    }
+
+    /// Errors that say something about provider health (as opposed to a bad client request)
+    pub fn is_breaker_failure(error: &OmenError) -> bool {
+        Self::failure_scope(error).is_some()
+    }
+
+    /// Whether both the provider-level and the model-level circuit admit a request
+    pub async fn can_route(&self, provider_id: &str, model: Option<&str>) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+
+        let breakers = self.breakers.read().await;
+        Self::keys(provider_id, model)
+            .iter()
+            .all(|key| breakers.get(key).map(|b| b.can_route(&self.config)).unwrap_or(true))
+    }
+
+    /// Admit a request about to be dispatched, claiming a half-open probe
+    /// slot in the same write lock so concurrent requests can't all probe
+    pub async fn try_acquire(&self, provider_id: &str, model: Option<&str>) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let mut breakers = self.breakers.write().await;
+        let keys = Self::keys(provider_id, model);
+        if !keys.iter().all(|key| breakers.get(key).is_none_or(|b| b.can_route(&self.config))) {
+            return Err(OmenError::ProviderUnavailable(format!("Circuit open for {}", keys.join(", "))));
+        }
+        for key in keys {
+            breakers.entry(key).or_default().on_request_start(&self.config);
+        }
+        Ok(())
+    }
+
+    pub async fn record_success(&self, provider_id: &str, model: Option<&str>) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let mut breakers = self.breakers.write().await;
+        for key in Self::keys(provider_id, model) {
+            let breaker = breakers.entry(key.clone()).or_default();
+            let was_half_open = breaker.state == CircuitState::HalfOpen;
+            breaker.record_success(&self.config);
+
+            if was_half_open && breaker.state == CircuitState::Closed {
+                info!("🟢 Circuit for {} closed after successful probes", key);
+            }
+        }
+    }
+
+    /// Count a failure against the model's breaker, or the provider's when
+    /// the failure is provider-wide or no model is known
+    pub async fn record_failure(&self, provider_id: &str, model: Option<&str>, scope: FailureScope) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let keys = Self::keys(provider_id, model);
+        let failed = match scope {
+            FailureScope::Model => keys.last(),
+            FailureScope::Provider => keys.first(),
+        };
+
+        let mut breakers = self.breakers.write().await;
+        for key in keys.iter() {
+            let breaker = breakers.entry(key.clone()).or_default();
+            if Some(key) != failed {
+                // The other breaker's probe slot, if it held one, is freed
+                breaker.release();
+                continue;
+            }
+
+            let was_open = breaker.state == CircuitState::Open;
+            breaker.record_failure(&self.config);
+
+            if !was_open && breaker.state == CircuitState::Open {
+                warn!(
+                    "🔴 Circuit for {} opened ({} consecutive failures, {:.0}% error rate)",
+                    key,
+                    breaker.consecutive_failures,
+                    breaker.error_rate() * 100.0
+                );
+            }
+        }
+    }
+
+    /// Record the outcome of a provider call in one step (`None` means success)
+    pub async fn record_result(
+        &self,
+        provider_id: &str,
+        model: Option<&str>,
+        error: Option<&OmenError>,
+    ) {
+        match error.map(Self::failure_scope) {
+            None => self.record_success(provider_id, model).await,
+            Some(Some(scope)) => self.record_failure(provider_id, model, scope).await,
+            Some(None) => self.release(provider_id, model).await,
+        }
+    }
+
+    pub async fn release(&self, provider_id: &str, model: Option<&str>) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let mut breakers = self.breakers.write().await;
+        for key in Self::keys(provider_id, model) {
+            if let Some(breaker) = breakers.get_mut(&key) {
+                breaker.release();
+            }
+        }
+    }
+
+    /// Provider-level circuit state
+    pub async fn provider_state(&self, provider_id: &str) -> CircuitState {
+        let breakers = self.breakers.read().await;
+        breakers
+            .get(provider_id)
+            .map(|b| b.state(&self.config))
+            .unwrap_or_default()
+    }
+
+    pub async fn provider_status(&self, provider_id: &str) -> CircuitBreakerStatus {
+        let breakers = self.breakers.read().await;
+        match breakers.get(provider_id) {
+            Some(breaker) => self.status_for(provider_id, breaker),
+            None => self.status_for(provider_id, &CircuitBreaker::new()),
+        }
+    }
+
+    /// All tracked breakers, provider-level and model-level
+    pub async fn snapshot(&self) -> Vec<CircuitBreakerStatus> {
+        let breakers = self.breakers.read().await;
+        let mut statuses: Vec<_> = breakers
+            .iter()
+            .map(|(key, breaker)| self.status_for(key, breaker))
+            .collect();
+        statuses.sort_by(|a, b| a.key.cmp(&b.key));
+        statuses
+    }
+
+    fn status_for(&self, key: &str, breaker: &CircuitBreaker) -> CircuitBreakerStatus {
+        let (provider_id, model) = match key.split_once('/') {
+            Some((provider, model)) => (provider.to_string(), Some(model.to_string())),
+            None => (key.to_string(), None),
+        };
+
+        CircuitBreakerStatus {
+            key: key.to_string(),
+            provider_id,
+            model,
+            state: breaker.state(&self.config),
+            consecutive_failures: breaker.consecutive_failures,
+            error_rate: breaker.error_rate(),
+            requests_in_window: breaker.outcomes.len(),
+            opened_at: breaker.opened_at_utc,
+            last_failure_at: breaker.last_failure_at,
+            trip_count: breaker.trip_count,
+        }
+    }
+
+    fn keys(provider_id: &str, model: Option<&str>) -> Vec<String> {
+        let mut keys = vec![provider_id.to_string()];
+        if let Some(model) = model.filter(|m| !m.is_empty() && *m != "auto") {
+            keys.push(format!("{}/{}", provider_id, model));
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 3,
+            error_rate_threshold: 0.5,
+            min_requests: 4,
+            window_size: 10,
+            open_duration_seconds: 0,
+            half_open_max_probes: 1,
+            success_threshold: 2,
+        }
+    }
+
+    #[test]
+    fn test_trips_on_consecutive_failures() {
+        let config = CircuitBreakerConfig {
+            open_duration_seconds: 60,
+            ..test_config()
+        };
+        let mut breaker = CircuitBreaker::new();
+
+        breaker.record_failure(&config);
+        breaker.record_failure(&config);
+        assert_eq!(breaker.state(&config), CircuitState::Closed);
+
+        breaker.record_failure(&config);
+        assert_eq!(breaker.state(&config), CircuitState::Open);
+        assert!(!breaker.can_route(&config));
+    }
+
+    #[test]
+    fn test_trips_on_error_rate() {
+        let config = CircuitBreakerConfig {
+            open_duration_seconds: 60,
+            ..test_config()
+        };
+        let mut breaker = CircuitBreaker::new();
+
+        // Alternate so consecutive failures never reach the threshold
+        breaker.record_success(&config);
+        breaker.record_failure(&config);
+        breaker.record_success(&config);
+        assert_eq!(breaker.state(&config), CircuitState::Closed);
+
+        breaker.record_failure(&config);
+        assert_eq!(breaker.state(&config), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_half_open_probe_cycle() {
+        let config = test_config();
+        let mut breaker = CircuitBreaker::new();
+
+        for _ in 0..3 {
+            breaker.record_failure(&config);
+        }
+
+        // Zero cooldown: the open circuit is immediately eligible for probing
+        assert_eq!(breaker.state(&config), CircuitState::HalfOpen);
+        assert!(breaker.can_route(&config));
+
+        breaker.on_request_start(&config);
+        assert!(!breaker.can_route(&config)); // single probe slot taken
+
+        breaker.record_success(&config);
+        assert_eq!(breaker.state(&config), CircuitState::HalfOpen);
+
+        breaker.on_request_start(&config);
+        breaker.record_success(&config);
+        assert_eq!(breaker.state(&config), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_half_open_failure_reopens() {
+        let config = test_config();
+        let mut breaker = CircuitBreaker::new();
+
+        for _ in 0..3 {
+            breaker.record_failure(&config);
+        }
+
+        breaker.on_request_start(&config);
+        breaker.record_failure(&config);
+        assert_eq!(breaker.trip_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_registry_tracks_provider_and_model() {
+        let registry = CircuitBreakerRegistry::new(CircuitBreakerConfig {
+            open_duration_seconds: 60,
+            ..test_config()
+        });
+
+        let server_error = OmenError::upstream("bedrock", 500, "internal error");
+        for _ in 0..3 {
+            registry.record_result("bedrock", Some("anthropic.claude-v2"), Some(&server_error)).await;
+        }
+
+        // One failing model leaves the provider's other models routable
+        assert!(!registry.can_route("bedrock", Some("anthropic.claude-v2")).await);
+        assert!(registry.can_route("bedrock", Some("amazon.titan-text-express-v1")).await);
+        assert!(registry.can_route("bedrock", None).await);
+
+        // Auth failures say nothing about provider health
+        let unauthorized = OmenError::upstream("anthropic", 401, "invalid x-api-key");
+        for _ in 0..3 {
+            registry.record_result("anthropic", Some("claude-3-5-haiku-20241022"), Some(&unauthorized)).await;
+        }
+        assert!(registry.can_route("anthropic", Some("claude-3-5-haiku-20241022")).await);
+
+        // Transport failures take the whole provider out
+        for _ in 0..3 {
+            registry.record_failure("ollama", Some("llama3"), FailureScope::Provider).await;
+        }
+        assert!(!registry.can_route("ollama", Some("mistral")).await);
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_claims_one_half_open_probe() {
+        let registry = std::sync::Arc::new(CircuitBreakerRegistry::new(test_config()));
+        for _ in 0..3 {
+            registry.record_failure("openai", None, FailureScope::Provider).await;
+        }
+
+        // Zero cooldown: half-open with a single probe slot
+        let attempts = (0..8).map(|_| {
+            let registry = registry.clone();
+            tokio::spawn(async move { registry.try_acquire("openai", Some("gpt-4o")).await.is_ok() })
+        });
+        let mut admitted = 0;
+        for attempt in attempts {
+            admitted += attempt.await.unwrap() as usize;
+        }
+        assert_eq!(admitted, 1);
+    }
+}
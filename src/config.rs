@@ -14,6 +14,44 @@ pub struct Config {
     pub logging: LoggingConfig,
     #[serde(default)]
     pub cache: CacheConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub health_monitor: HealthMonitorConfig,
+    /// Virtual model aliases (e.g. "fast", "smart") keyed by alias name
+    #[serde(default)]
+    pub virtual_models: HashMap<String, VirtualModelConfig>,
+    #[serde(default)]
+    pub model_catalog: ModelCatalogConfig,
+    #[serde(default)]
+    pub merge: MergeConfig,
+    #[serde(default)]
+    pub stickiness: StickinessConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub context_window: ContextWindowConfig,
+    #[serde(default)]
+    pub learned_metrics: LearnedMetricsConfig,
+    #[serde(default)]
+    pub intent: IntentConfig,
+    #[serde(default)]
+    pub shadow: ShadowConfig,
+    /// A/B experiments splitting matching traffic between weighted arms
+    #[serde(default)]
+    pub experiments: Vec<ExperimentConfig>,
+    #[serde(default)]
+    pub hedging: HedgingConfig,
+    #[serde(default)]
+    pub bulkheads: BulkheadConfig,
+    #[serde(default)]
+    pub fair_share: FairShareConfig,
+    #[serde(default)]
+    pub upstream_quota: UpstreamQuotaConfig,
+    #[serde(default)]
+    pub realtime: RealtimeConfig,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +84,113 @@ pub struct RoutingConfig {
     pub soft_limits: HashMap<String, f64>,
     #[serde(default = "default_auto_swap")]
     pub auto_swap: bool,
+    /// Default scoring weights for intents without built-in weights
+    #[serde(default)]
+    pub weights: RoutingWeightsConfig,
+    /// Routing profiles keyed by API key name
+    #[serde(default)]
+    pub api_keys: HashMap<String, RoutingProfileConfig>,
+    /// Ordered rules applied to requests before provider scoring
+    #[serde(default)]
+    pub rules: Vec<RoutingRuleConfig>,
+}
+
+/// Scoring weights; unset dimensions keep the value they are layered over
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingWeightsConfig {
+    #[serde(default)]
+    pub cost: Option<f32>,
+    #[serde(default)]
+    pub latency: Option<f32>,
+    #[serde(default)]
+    pub quality: Option<f32>,
+    #[serde(default)]
+    pub reliability: Option<f32>,
+}
+
+impl RoutingWeightsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.cost.is_none() && self.latency.is_none() && self.quality.is_none() && self.reliability.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingProfileConfig {
+    #[serde(default)]
+    pub weights: RoutingWeightsConfig,
+    /// Score multipliers by provider id (e.g. 1.5 favours, 0.5 penalises)
+    #[serde(default)]
+    pub provider_multipliers: HashMap<String, f32>,
+}
+
+/// A routing rule: when every condition in `match` holds, its actions apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingRuleConfig {
+    pub name: String,
+    /// Conditions that must all hold; an empty match applies to every request
+    #[serde(default, rename = "match")]
+    pub matches: RuleMatchConfig,
+    /// Send the request to this provider and/or model
+    #[serde(default)]
+    pub route: Option<RuleRouteConfig>,
+    /// Routing strategy: single, race, speculate_k, parallel_merge
+    #[serde(default)]
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub k: Option<u32>,
+    /// Reject the request with this message
+    #[serde(default)]
+    pub deny: Option<String>,
+    /// Cap the request budget in USD
+    #[serde(default)]
+    pub budget_usd: Option<f64>,
+    /// Tags added to the request context
+    #[serde(default)]
+    pub add_tags: HashMap<String, String>,
+    /// Stop evaluating later rules once this one matches
+    #[serde(default)]
+    pub stop: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleRouteConfig {
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Rule conditions; unset conditions always hold
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleMatchConfig {
+    /// API key names
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// Tags that must all be present with these values
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub intents: Vec<String>,
+    /// Requested models; `*` matches any run of characters
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Bounds on the estimated prompt tokens
+    #[serde(default)]
+    pub min_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub has_images: Option<bool>,
+    #[serde(default)]
+    pub has_tools: Option<bool>,
+    /// UTC time window "HH:MM-HH:MM"; may wrap past midnight
+    #[serde(default)]
+    pub hours: Option<String>,
+    /// UTC weekdays: mon, tue, wed, thu, fri, sat, sun
+    #[serde(default)]
+    pub days: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -222,6 +367,863 @@ impl Default for CacheConfig {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualModelConfig {
+    #[serde(default)]
+    pub description: Option<String>,
+    /// How deployments are ordered for each request
+    #[serde(default)]
+    pub selection: DeploymentSelection,
+    pub deployments: Vec<DeploymentConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentSelection {
+    /// Try deployments in the order they are listed
+    #[default]
+    Ordered,
+    /// Pick the first deployment by weight, then fail over through the rest
+    Weighted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentConfig {
+    /// Provider instance id (e.g. "openai", "ollama")
+    pub provider: String,
+    /// Model id as understood by that provider
+    pub model: String,
+    #[serde(default = "default_deployment_weight")]
+    pub weight: u32,
+    /// Parameters forced onto requests sent to this deployment
+    #[serde(default)]
+    pub overrides: DeploymentOverrides,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DeploymentOverrides {
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCatalogConfig {
+    /// Include OMEN's built-in identities for models hosted on several providers
+    #[serde(default = "default_builtin_catalog")]
+    pub builtin: bool,
+    /// Extra or overriding identities keyed by canonical model id
+    #[serde(default)]
+    pub models: HashMap<String, ModelIdentityConfig>,
+}
+
+impl Default for ModelCatalogConfig {
+    fn default() -> Self {
+        Self {
+            builtin: default_builtin_catalog(),
+            models: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelIdentityConfig {
+    /// Additional names that resolve to this identity (e.g. "claude-sonnet")
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Provider-specific ids, in failover order
+    pub providers: Vec<ProviderModelIdConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderModelIdConfig {
+    pub provider: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeConfig {
+    /// Selector used when a parallel_merge request doesn't name one
+    #[serde(default = "default_merge_selector")]
+    pub default_selector: String,
+    /// Provider that judges candidates for the llm_judge selector
+    #[serde(default)]
+    pub judge_provider: Option<String>,
+    /// Model the judge provider should use
+    #[serde(default)]
+    pub judge_model: Option<String>,
+    /// Answers longer than this are not compared by majority vote
+    #[serde(default = "default_majority_max_chars")]
+    pub majority_max_chars: usize,
+    /// Output tokens assumed for budgeting when a request sets no max_tokens
+    #[serde(default = "default_merge_output_tokens")]
+    pub default_output_tokens: u32,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            default_selector: default_merge_selector(),
+            judge_provider: None,
+            judge_model: None,
+            majority_max_chars: default_majority_max_chars(),
+            default_output_tokens: default_merge_output_tokens(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// Lower max_tokens to fit the request budget instead of excluding the candidate
+    #[serde(default = "default_clamp_max_tokens")]
+    pub clamp_max_tokens: bool,
+    /// Smallest max_tokens worth clamping to; below this the candidate is excluded
+    #[serde(default = "default_min_output_tokens")]
+    pub min_output_tokens: u32,
+    /// Output tokens assumed for requests without max_tokens
+    #[serde(default = "default_budget_output_tokens")]
+    pub default_output_tokens: u32,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            clamp_max_tokens: default_clamp_max_tokens(),
+            min_output_tokens: default_min_output_tokens(),
+            default_output_tokens: default_budget_output_tokens(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextWindowConfig {
+    /// Exclude candidates whose context window can't hold the prompt plus requested output
+    #[serde(default = "default_context_window_enabled")]
+    pub enabled: bool,
+    /// Overflow policy when a request doesn't set one: reject, drop_oldest, keep_last or summarize
+    #[serde(default = "default_context_overflow")]
+    pub default_overflow: String,
+    /// Most recent turns kept by the keep_last and summarize policies
+    #[serde(default = "default_keep_last_turns")]
+    pub keep_last_turns: usize,
+    /// Output tokens reserved for requests without max_tokens
+    #[serde(default = "default_context_output_tokens")]
+    pub default_output_tokens: u32,
+    /// Provider that summarizes dropped turns for the summarize policy
+    #[serde(default)]
+    pub summarizer_provider: Option<String>,
+    /// Model the summarizer provider should use (something cheap)
+    #[serde(default)]
+    pub summarizer_model: Option<String>,
+    /// Length cap for the summary of dropped turns
+    #[serde(default = "default_summary_max_tokens")]
+    pub summary_max_tokens: u32,
+}
+
+impl Default for ContextWindowConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_context_window_enabled(),
+            default_overflow: default_context_overflow(),
+            keep_last_turns: default_keep_last_turns(),
+            default_output_tokens: default_context_output_tokens(),
+            summarizer_provider: None,
+            summarizer_model: None,
+            summary_max_tokens: default_summary_max_tokens(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LearnedMetricsConfig {
+    /// Learn latency, throughput, error and cost statistics from live traffic
+    #[serde(default = "default_learned_metrics_enabled")]
+    pub enabled: bool,
+    /// Keep learned statistics in the storage database across restarts
+    #[serde(default = "default_learned_metrics_persist")]
+    pub persist: bool,
+    /// Age at which an observation counts half as much as a new one
+    #[serde(default = "default_metrics_half_life")]
+    pub half_life_seconds: u64,
+    #[serde(default = "default_metrics_flush_interval")]
+    pub flush_interval_seconds: u64,
+    /// Decayed sample weight needed before learned values replace the defaults
+    #[serde(default = "default_metrics_min_samples")]
+    pub min_samples: f64,
+}
+
+impl Default for LearnedMetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_learned_metrics_enabled(),
+            persist: default_learned_metrics_persist(),
+            half_life_seconds: default_metrics_half_life(),
+            flush_interval_seconds: default_metrics_flush_interval(),
+            min_samples: default_metrics_min_samples(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntentConfig {
+    /// Classifier used for requests: keyword or embedding
+    #[serde(default = "default_intent_classifier")]
+    pub classifier: String,
+    /// Provider that embeds prompts for the embedding classifier
+    #[serde(default = "default_embedding_provider")]
+    pub embedding_provider: String,
+    #[serde(default = "default_embedding_model")]
+    pub embedding_model: String,
+    /// Give up on the embedding classifier after this long and use keywords
+    #[serde(default = "default_embedding_timeout")]
+    pub embedding_timeout_ms: u64,
+    /// Below this confidence the embedding classifier defers to keywords
+    #[serde(default = "default_min_intent_confidence")]
+    pub min_confidence: f32,
+    /// Intent labels, added to or replacing the built-in ones
+    #[serde(default)]
+    pub labels: HashMap<String, IntentLabelConfig>,
+}
+
+impl Default for IntentConfig {
+    fn default() -> Self {
+        Self {
+            classifier: default_intent_classifier(),
+            embedding_provider: default_embedding_provider(),
+            embedding_model: default_embedding_model(),
+            embedding_timeout_ms: default_embedding_timeout(),
+            min_confidence: default_min_intent_confidence(),
+            labels: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntentLabelConfig {
+    /// Example prompts the embedding classifier learns this label from
+    #[serde(default)]
+    pub examples: Vec<String>,
+    /// Substrings that select this label for the keyword classifier
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Latency target used when scoring providers for this intent
+    #[serde(default)]
+    pub latency_target_ms: Option<u64>,
+    /// Scoring weights for this intent, layered over the built-in or default weights
+    #[serde(default)]
+    pub weights: RoutingWeightsConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShadowConfig {
+    /// Mirroring policies; a request is mirrored by the first one that matches
+    #[serde(default)]
+    pub policies: Vec<ShadowPolicyConfig>,
+    /// Shadow calls allowed in flight at once; further mirrors are skipped
+    #[serde(default = "default_shadow_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Give up on a shadow call after this long
+    #[serde(default = "default_shadow_timeout")]
+    pub timeout_seconds: u64,
+    /// Store response text alongside latency and cost
+    #[serde(default = "default_shadow_store_outputs")]
+    pub store_outputs: bool,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            policies: Vec::new(),
+            max_in_flight: default_shadow_max_in_flight(),
+            timeout_seconds: default_shadow_timeout(),
+            store_outputs: default_shadow_store_outputs(),
+        }
+    }
+}
+
+/// Mirror a sample of the requests matching `match` to shadow targets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShadowPolicyConfig {
+    pub name: String,
+    /// Share of matching requests mirrored, 0-100
+    #[serde(default)]
+    pub sample_percent: f64,
+    #[serde(default, rename = "match")]
+    pub matches: RuleMatchConfig,
+    pub targets: Vec<ShadowTargetConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShadowTargetConfig {
+    pub provider: String,
+    /// Model to call; defaults to the requested model
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Hedging: when a non-streaming primary is slow, also ask the next-best provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HedgingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Fixed wait before hedging; unset waits for the primary's learned p95 latency
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    /// Wait used while no p95 has been learned for the primary
+    #[serde(default = "default_hedge_delay")]
+    pub default_delay_ms: u64,
+    /// Floor for the learned wait, so fast providers aren't hedged on noise
+    #[serde(default = "default_hedge_min_delay")]
+    pub min_delay_ms: u64,
+    /// Extra requests allowed, as a percentage of eligible requests
+    #[serde(default = "default_hedge_max_percent")]
+    pub max_hedge_percent: f64,
+    /// Hedges that can be spent back to back before the percentage applies
+    #[serde(default = "default_hedge_burst")]
+    pub burst: f64,
+}
+
+impl Default for HedgingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            delay_ms: None,
+            default_delay_ms: default_hedge_delay(),
+            min_delay_ms: default_hedge_min_delay(),
+            max_hedge_percent: default_hedge_max_percent(),
+            burst: default_hedge_burst(),
+        }
+    }
+}
+
+/// Weighted fair queuing of requests across tenants (Ghost services or users)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FairShareConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Requests served at once across all tenants
+    #[serde(default = "default_fair_share_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Requests waiting for admission; beyond this new requests are shed with 503
+    #[serde(default = "default_fair_share_max_queue")]
+    pub max_queue: usize,
+    /// Requests one tenant may have waiting; beyond this it is shed with 429
+    #[serde(default = "default_fair_share_max_queue_per_tenant")]
+    pub max_queue_per_tenant: usize,
+    /// Longest a request waits for admission before it is shed
+    #[serde(default = "default_fair_share_queue_timeout")]
+    pub queue_timeout_ms: u64,
+    /// Relative share per tenant: a Ghost service name or a user id
+    #[serde(default)]
+    pub shares: HashMap<String, f64>,
+    /// Share of tenants not listed in `shares`
+    #[serde(default = "default_fair_share_weight")]
+    pub default_share: f64,
+}
+
+impl Default for FairShareConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_concurrent: default_fair_share_max_concurrent(),
+            max_queue: default_fair_share_max_queue(),
+            max_queue_per_tenant: default_fair_share_max_queue_per_tenant(),
+            queue_timeout_ms: default_fair_share_queue_timeout(),
+            shares: HashMap::new(),
+            default_share: default_fair_share_weight(),
+        }
+    }
+}
+
+/// Tracking of provider rate limits from their response headers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamQuotaConfig {
+    /// Remaining quota fraction below which a provider is ranked down
+    #[serde(default = "default_quota_low_headroom")]
+    pub low_headroom: f64,
+    /// Cooldown after a 429 that didn't say how long to wait
+    #[serde(default = "default_quota_retry_after")]
+    pub default_retry_after_seconds: u64,
+    /// Longest cooldown honoured from a provider's Retry-After
+    #[serde(default = "default_quota_max_retry_after")]
+    pub max_retry_after_seconds: u64,
+}
+
+impl Default for UpstreamQuotaConfig {
+    fn default() -> Self {
+        Self {
+            low_headroom: default_quota_low_headroom(),
+            default_retry_after_seconds: default_quota_retry_after(),
+            max_retry_after_seconds: default_quota_max_retry_after(),
+        }
+    }
+}
+
+/// WebSocket chat sessions (`/v1/realtime`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealtimeConfig {
+    /// Seconds between heartbeat messages on an open socket
+    #[serde(default = "default_realtime_heartbeat")]
+    pub heartbeat_interval_seconds: u64,
+    /// Completions one socket may have streaming at once
+    #[serde(default = "default_realtime_max_concurrent")]
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for RealtimeConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_seconds: default_realtime_heartbeat(),
+            max_concurrent_requests: default_realtime_max_concurrent(),
+        }
+    }
+}
+
+/// The gRPC API (`omen.v1.OmenService`), served beside the HTTP API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrpcConfig {
+    #[serde(default = "default_grpc_enabled")]
+    pub enabled: bool,
+    /// Port on `server.bind` the gRPC API listens on
+    #[serde(default = "default_grpc_port")]
+    pub port: u16,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_grpc_enabled(),
+            port: default_grpc_port(),
+        }
+    }
+}
+
+/// Concurrency limits per provider and model, with a priority-ordered wait queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkheadConfig {
+    /// In-flight limit for provider models without a specific one; unset is unlimited
+    #[serde(default)]
+    pub default_max_in_flight: Option<usize>,
+    /// Requests allowed to wait for a slot; beyond this they fail over or are rejected
+    #[serde(default = "default_bulkhead_max_queue")]
+    pub max_queue: usize,
+    /// Longest a request waits for a slot
+    #[serde(default = "default_bulkhead_queue_timeout")]
+    pub queue_timeout_ms: u64,
+    #[serde(default)]
+    pub limits: Vec<BulkheadLimitConfig>,
+}
+
+impl Default for BulkheadConfig {
+    fn default() -> Self {
+        Self {
+            default_max_in_flight: None,
+            max_queue: default_bulkhead_max_queue(),
+            queue_timeout_ms: default_bulkhead_queue_timeout(),
+            limits: Vec::new(),
+        }
+    }
+}
+
+/// In-flight limit for a provider instance, or one of its models
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkheadLimitConfig {
+    pub provider: String,
+    /// Model the limit applies to; unset applies it to each model of the provider
+    #[serde(default)]
+    pub model: Option<String>,
+    pub max_in_flight: usize,
+    #[serde(default)]
+    pub max_queue: Option<usize>,
+}
+
+/// An experiment: requests matching `match` are split between its arms by weight
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentConfig {
+    pub name: String,
+    #[serde(default = "default_experiment_enabled")]
+    pub enabled: bool,
+    #[serde(default, rename = "match")]
+    pub matches: RuleMatchConfig,
+    /// What keeps a caller on one arm: user or session
+    #[serde(default = "default_experiment_bucket_by")]
+    pub bucket_by: String,
+    pub arms: Vec<ExperimentArmConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExperimentArmConfig {
+    pub name: String,
+    /// Relative share of the experiment's traffic
+    pub weight: u32,
+    /// Provider the arm is routed to; unset leaves provider selection to the router
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StickinessConfig {
+    #[serde(default = "default_stickiness_enabled")]
+    pub enabled: bool,
+    /// Mode used when a request doesn't set one: none, turn or session
+    #[serde(default = "default_stickiness_mode")]
+    pub default_mode: String,
+    /// Idle time after which a session binding is dropped
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl_seconds: u64,
+    /// Idle time after which a turn (tool loop) binding is dropped
+    #[serde(default = "default_turn_ttl")]
+    pub turn_ttl_seconds: u64,
+}
+
+impl Default for StickinessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_stickiness_enabled(),
+            default_mode: default_stickiness_mode(),
+            session_ttl_seconds: default_session_ttl(),
+            turn_ttl_seconds: default_turn_ttl(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_circuit_enabled")]
+    pub enabled: bool,
+    /// Consecutive failures that trip the circuit
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Error rate (0.0-1.0) over the rolling window that trips the circuit
+    #[serde(default = "default_error_rate_threshold")]
+    pub error_rate_threshold: f64,
+    /// Minimum requests in the window before the error rate is considered
+    #[serde(default = "default_min_requests")]
+    pub min_requests: u32,
+    /// Number of recent outcomes kept per breaker
+    #[serde(default = "default_window_size")]
+    pub window_size: u32,
+    /// How long an open circuit rejects traffic before probing
+    #[serde(default = "default_open_duration")]
+    pub open_duration_seconds: u64,
+    /// Concurrent probe requests allowed while half-open
+    #[serde(default = "default_half_open_probes")]
+    pub half_open_max_probes: u32,
+    /// Successful probes required to close the circuit
+    #[serde(default = "default_success_threshold")]
+    pub success_threshold: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_circuit_enabled(),
+            failure_threshold: default_failure_threshold(),
+            error_rate_threshold: default_error_rate_threshold(),
+            min_requests: default_min_requests(),
+            window_size: default_window_size(),
+            open_duration_seconds: default_open_duration(),
+            half_open_max_probes: default_half_open_probes(),
+            success_threshold: default_success_threshold(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthMonitorConfig {
+    /// Run provider checks in the background; when disabled every health
+    /// request probes providers directly
+    #[serde(default = "default_health_monitor_enabled")]
+    pub enabled: bool,
+    /// Seconds between check rounds
+    #[serde(default = "default_health_interval")]
+    pub interval_seconds: u64,
+    /// Random extra delay (0..=jitter) added to each interval
+    #[serde(default = "default_health_jitter")]
+    pub jitter_seconds: u64,
+    /// Per-provider timeout for a single check
+    #[serde(default = "default_health_check_timeout")]
+    pub timeout_seconds: u64,
+    /// Number of past checks kept per provider
+    #[serde(default = "default_health_history_size")]
+    pub history_size: usize,
+}
+
+impl Default for HealthMonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_health_monitor_enabled(),
+            interval_seconds: default_health_interval(),
+            jitter_seconds: default_health_jitter(),
+            timeout_seconds: default_health_check_timeout(),
+            history_size: default_health_history_size(),
+        }
+    }
+}
+
+fn default_context_window_enabled() -> bool {
+    true
+}
+
+fn default_context_overflow() -> String {
+    "reject".to_string()
+}
+
+fn default_keep_last_turns() -> usize {
+    6
+}
+
+fn default_context_output_tokens() -> u32 {
+    1024
+}
+
+fn default_summary_max_tokens() -> u32 {
+    512
+}
+
+fn default_learned_metrics_enabled() -> bool {
+    true
+}
+
+fn default_learned_metrics_persist() -> bool {
+    true
+}
+
+fn default_metrics_half_life() -> u64 {
+    3600
+}
+
+fn default_metrics_flush_interval() -> u64 {
+    30
+}
+
+fn default_metrics_min_samples() -> f64 {
+    5.0
+}
+
+fn default_intent_classifier() -> String {
+    "keyword".to_string()
+}
+
+fn default_embedding_provider() -> String {
+    "ollama".to_string()
+}
+
+fn default_embedding_model() -> String {
+    "nomic-embed-text".to_string()
+}
+
+fn default_embedding_timeout() -> u64 {
+    300
+}
+
+fn default_min_intent_confidence() -> f32 {
+    0.4
+}
+
+fn default_experiment_enabled() -> bool {
+    true
+}
+
+fn default_experiment_bucket_by() -> String {
+    "user".to_string()
+}
+
+fn default_fair_share_max_concurrent() -> usize {
+    64
+}
+
+fn default_fair_share_max_queue() -> usize {
+    256
+}
+
+fn default_fair_share_max_queue_per_tenant() -> usize {
+    64
+}
+
+fn default_fair_share_queue_timeout() -> u64 {
+    10000
+}
+
+fn default_fair_share_weight() -> f64 {
+    1.0
+}
+
+fn default_quota_low_headroom() -> f64 {
+    0.1
+}
+
+fn default_quota_retry_after() -> u64 {
+    10
+}
+
+fn default_quota_max_retry_after() -> u64 {
+    300
+}
+
+fn default_grpc_enabled() -> bool {
+    false
+}
+
+fn default_grpc_port() -> u16 {
+    50051
+}
+
+fn default_realtime_heartbeat() -> u64 {
+    15
+}
+
+fn default_realtime_max_concurrent() -> usize {
+    8
+}
+
+fn default_bulkhead_max_queue() -> usize {
+    64
+}
+
+fn default_bulkhead_queue_timeout() -> u64 {
+    30000
+}
+
+fn default_hedge_delay() -> u64 {
+    2000
+}
+
+fn default_hedge_min_delay() -> u64 {
+    250
+}
+
+fn default_hedge_max_percent() -> f64 {
+    10.0
+}
+
+fn default_hedge_burst() -> f64 {
+    5.0
+}
+
+fn default_shadow_max_in_flight() -> usize {
+    16
+}
+
+fn default_shadow_timeout() -> u64 {
+    120
+}
+
+fn default_shadow_store_outputs() -> bool {
+    true
+}
+
+fn default_clamp_max_tokens() -> bool {
+    false
+}
+
+fn default_min_output_tokens() -> u32 {
+    64
+}
+
+fn default_budget_output_tokens() -> u32 {
+    1024
+}
+
+fn default_stickiness_enabled() -> bool {
+    true
+}
+
+fn default_stickiness_mode() -> String {
+    "session".to_string()
+}
+
+fn default_session_ttl() -> u64 {
+    1800
+}
+
+fn default_turn_ttl() -> u64 {
+    300
+}
+
+fn default_merge_selector() -> String {
+    "longest_valid".to_string()
+}
+
+fn default_majority_max_chars() -> usize {
+    200
+}
+
+fn default_merge_output_tokens() -> u32 {
+    1024
+}
+
+fn default_builtin_catalog() -> bool {
+    true
+}
+
+fn default_deployment_weight() -> u32 {
+    1
+}
+
+fn default_circuit_enabled() -> bool {
+    true
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_error_rate_threshold() -> f64 {
+    0.5
+}
+
+fn default_min_requests() -> u32 {
+    10
+}
+
+fn default_window_size() -> u32 {
+    50
+}
+
+fn default_open_duration() -> u64 {
+    30
+}
+
+fn default_half_open_probes() -> u32 {
+    1
+}
+
+fn default_success_threshold() -> u32 {
+    2
+}
+
+fn default_health_monitor_enabled() -> bool {
+    true
+}
+
+fn default_health_interval() -> u64 {
+    30
+}
+
+fn default_health_jitter() -> u64 {
+    5
+}
+
+fn default_health_check_timeout() -> u64 {
+    10
+}
+
+fn default_health_history_size() -> usize {
+    20
+}
+
 fn default_redis_url() -> String {
     "redis://localhost:6379".to_string()
 }
@@ -371,6 +1373,9 @@ impl Default for Config {
                 budget_monthly_usd: default_budget(),
                 soft_limits: HashMap::new(),
                 auto_swap: default_auto_swap(),
+                weights: RoutingWeightsConfig::default(),
+                api_keys: HashMap::new(),
+                rules: Vec::new(),
             },
             providers: ProvidersConfig {
                 openai: ProviderConfig::default(),
@@ -385,6 +1390,24 @@ impl Default for Config {
             auth: AuthConfig::default(),
             logging: LoggingConfig::default(),
             cache: CacheConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            health_monitor: HealthMonitorConfig::default(),
+            virtual_models: HashMap::new(),
+            model_catalog: ModelCatalogConfig::default(),
+            merge: MergeConfig::default(),
+            stickiness: StickinessConfig::default(),
+            budget: BudgetConfig::default(),
+            context_window: ContextWindowConfig::default(),
+            learned_metrics: LearnedMetricsConfig::default(),
+            intent: IntentConfig::default(),
+            shadow: ShadowConfig::default(),
+            experiments: Vec::new(),
+            hedging: HedgingConfig::default(),
+            bulkheads: BulkheadConfig::default(),
+            fair_share: FairShareConfig::default(),
+            upstream_quota: UpstreamQuotaConfig::default(),
+            realtime: RealtimeConfig::default(),
+            grpc: GrpcConfig::default(),
         }
     }
 }
\ No newline at end of file
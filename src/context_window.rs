@@ -0,0 +1,274 @@
+//! Context-window aware routing
+//!
+//! Candidates whose context window can't hold the prompt plus the requested
+//! output are excluded before a request is sent. When no candidate fits, an
+//! opt-in overflow policy shortens the conversation to fit the largest window:
+//!
+//! - `reject`: fail with a context length error (the default)
+//! - `drop_oldest`: drop the oldest turns until the prompt fits
+//! - `keep_last`: keep the system prompt plus the last N turns
+//! - `summarize`: replace the turns before the last N with a summary written
+//!   by a cheap model
+//!
+//! The policy applied and the tokens dropped are reported to the client in
+//! the response's `omen.context` metadata, or a leading chunk for streams.
+
+use crate::{
+    budget,
+    error::Result,
+    types::{ChatCompletionRequest, ChatMessage, MessageContent, OmenResponseMetadata},
+};
+use futures::stream::Stream;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    Reject,
+    DropOldest,
+    KeepLast,
+    Summarize,
+}
+
+impl OverflowPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reject" => Some(OverflowPolicy::Reject),
+            "drop_oldest" => Some(OverflowPolicy::DropOldest),
+            "keep_last" => Some(OverflowPolicy::KeepLast),
+            "summarize" => Some(OverflowPolicy::Summarize),
+            _ => None,
+        }
+    }
+}
+
+/// How a conversation was shortened to fit a context window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextTruncation {
+    pub policy: OverflowPolicy,
+    /// Window the conversation was fitted to
+    pub context_length: u32,
+    pub original_tokens: u32,
+    pub fitted_tokens: u32,
+    pub dropped_tokens: u32,
+    /// Original messages no longer sent (summarized turns included)
+    pub dropped_messages: usize,
+}
+
+impl ContextTruncation {
+    pub fn new(
+        policy: OverflowPolicy,
+        context_length: u32,
+        original: &ChatCompletionRequest,
+        fitted: &ChatCompletionRequest,
+        summary_messages: usize,
+    ) -> Self {
+        let original_tokens = budget::count_request_tokens(original);
+        let fitted_tokens = budget::count_request_tokens(fitted);
+        let kept = fitted.messages.len().saturating_sub(summary_messages);
+
+        Self {
+            policy,
+            context_length,
+            original_tokens,
+            fitted_tokens,
+            dropped_tokens: original_tokens.saturating_sub(fitted_tokens),
+            dropped_messages: original.messages.len().saturating_sub(kept),
+        }
+    }
+}
+
+/// Whether a prompt plus its requested output fits a context window
+pub fn fits(prompt_tokens: u32, output_tokens: u32, context_length: u32) -> bool {
+    prompt_tokens.saturating_add(output_tokens) <= context_length
+}
+
+/// Leading system messages and the turns that follow them
+fn split_system(messages: &[ChatMessage]) -> (Vec<ChatMessage>, Vec<ChatMessage>) {
+    let split = messages.iter().position(|m| m.role != "system").unwrap_or(messages.len());
+    (messages[..split].to_vec(), messages[split..].to_vec())
+}
+
+/// Drop tool results whose assistant tool call was dropped
+fn drop_orphaned_tool_results(turns: &mut Vec<ChatMessage>) {
+    let orphaned = turns.iter().take_while(|m| m.role == "tool").count();
+    turns.drain(..orphaned);
+}
+
+/// Drop the oldest turns until the prompt is at most `max_prompt_tokens`.
+/// The system prompt and the final message are always kept; `None` when even
+/// those don't fit.
+pub fn drop_oldest(request: &ChatCompletionRequest, max_prompt_tokens: u32) -> Option<Vec<ChatMessage>> {
+    let (system, mut turns) = split_system(&request.messages);
+    let mut tokens = budget::count_request_tokens(request);
+
+    while tokens > max_prompt_tokens {
+        if turns.len() <= 1 {
+            return None;
+        }
+        let before = turns.len();
+        turns.remove(0);
+        drop_orphaned_tool_results(&mut turns);
+        if turns.is_empty() {
+            return None;
+        }
+
+        let dropped = &request.messages[request.messages.len() - before..request.messages.len() - turns.len()];
+        let dropped_tokens: u32 = dropped.iter().map(|m| budget::count_message_tokens(&request.model, m)).sum();
+        tokens = tokens.saturating_sub(dropped_tokens);
+    }
+
+    Some(system.into_iter().chain(turns).collect())
+}
+
+/// The system prompt plus the last `n` turns
+pub fn keep_last(messages: &[ChatMessage], n: usize) -> Vec<ChatMessage> {
+    let (system, turns) = split_system(messages);
+    let mut kept = turns[turns.len().saturating_sub(n.max(1))..].to_vec();
+    drop_orphaned_tool_results(&mut kept);
+    if kept.is_empty() {
+        kept = turns.last().cloned().into_iter().collect();
+    }
+    system.into_iter().chain(kept).collect()
+}
+
+/// Turns that the summarize policy replaces: everything before the last `n`
+pub fn turns_to_summarize(messages: &[ChatMessage], n: usize) -> Vec<ChatMessage> {
+    let (_, turns) = split_system(messages);
+    turns[..turns.len().saturating_sub(n.max(1))].to_vec()
+}
+
+/// Request asking a summarizer model to condense earlier turns
+pub fn summary_request(model: &str, turns: &[ChatMessage], max_tokens: u32) -> ChatCompletionRequest {
+    let transcript = turns
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content.text()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    ChatCompletionRequest {
+        model: model.to_string(),
+        messages: vec![
+            text_message(
+                "system",
+                "Summarize this conversation so it can continue without the original messages. \
+                 Keep facts, decisions, open questions and any code or identifiers that were referenced.",
+            ),
+            text_message("user", &transcript),
+        ],
+        temperature: Some(0.0),
+        max_tokens: Some(max_tokens),
+        stream: false,
+        top_p: None,
+        frequency_penalty: None,
+        presence_penalty: None,
+        stop: None,
+        tools: None,
+        tool_choice: None,
+        tags: None,
+        omen: None,
+    }
+}
+
+/// The system prompt, a summary of the earlier turns, then the last `n` turns
+pub fn with_summary(messages: &[ChatMessage], summary: &str, n: usize) -> Vec<ChatMessage> {
+    let mut fitted = keep_last(messages, n);
+    let system_len = fitted.iter().take_while(|m| m.role == "system").count();
+    fitted.insert(
+        system_len,
+        text_message("system", &format!("Summary of the earlier conversation:\n{}", summary)),
+    );
+    fitted
+}
+
+fn text_message(role: &str, text: &str) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: MessageContent::Text(text.to_string()),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+/// Prefix a stream with a chunk reporting how the conversation was shortened
+pub fn report_stream(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    model: &str,
+    truncation: ContextTruncation,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let metadata = OmenResponseMetadata {
+        context: Some(truncation),
+        ..Default::default()
+    };
+    let chunk = serde_json::json!({
+        "id": format!("chatcmpl-{}", uuid::Uuid::new_v4()),
+        "object": "chat.completion.chunk",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [],
+        "omen": metadata,
+    });
+
+    let report = futures::stream::once(async move { Ok(format!("data: {}\n\n", chunk)) });
+    Box::new(Box::pin(futures::StreamExt::chain(report, stream)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(messages: Vec<ChatMessage>) -> ChatCompletionRequest {
+        let mut request = summary_request("gpt-4o", &[], 256);
+        request.messages = messages;
+        request
+    }
+
+    fn conversation(turns: usize) -> Vec<ChatMessage> {
+        let mut messages = vec![text_message("system", "You are terse.")];
+        for i in 0..turns {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            messages.push(text_message(role, &format!("turn {} {}", i, "lorem ipsum ".repeat(50))));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_system_and_latest() {
+        let original = request(conversation(6));
+        let limit = budget::count_request_tokens(&original) / 2;
+
+        let fitted = drop_oldest(&original, limit).unwrap();
+        assert_eq!(fitted[0].role, "system");
+        assert_eq!(fitted.last().unwrap().content.text(), original.messages.last().unwrap().content.text());
+        assert!(budget::count_request_tokens(&request(fitted)) <= limit);
+
+        assert!(drop_oldest(&original, 10).is_none());
+    }
+
+    #[test]
+    fn test_keep_last_drops_orphaned_tool_results() {
+        let mut messages = conversation(2);
+        messages.push(text_message("tool", "result"));
+        messages.push(text_message("user", "thanks"));
+
+        let kept = keep_last(&messages, 2);
+        let roles: Vec<_> = kept.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user"]);
+    }
+
+    #[test]
+    fn test_summary_replaces_earlier_turns() {
+        let messages = conversation(6);
+        assert_eq!(turns_to_summarize(&messages, 2).len(), 4);
+
+        let original = request(messages.clone());
+        let fitted = request(with_summary(&messages, "earlier", 2));
+        let roles: Vec<_> = fitted.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "system", "user", "assistant"]);
+
+        let truncation = ContextTruncation::new(OverflowPolicy::Summarize, 8192, &original, &fitted, 1);
+        assert_eq!(truncation.dropped_messages, 4);
+        assert!(truncation.dropped_tokens > 0);
+    }
+}
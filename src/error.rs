@@ -1,3 +1,4 @@
+use serde::Serialize;
 use thiserror::Error;
 
 /// OMEN error types - all variants are part of the public API
@@ -13,6 +14,10 @@ pub enum OmenError {
     #[error("Provider error: {0}")]
     Provider(String),
 
+    /// An error response from a provider, with what it said went wrong
+    #[error("{} returned {}: {}", .0.provider, .0.status, .0.message)]
+    Upstream(UpstreamError),
+
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
@@ -25,9 +30,34 @@ pub enum OmenError {
     #[error("Rate limit exceeded")]
     RateLimitExceeded,
 
+    /// Load shed at admission; `tenant_limited` when the caller is over its
+    /// share rather than the whole system being saturated
+    #[error("Overloaded: {message}")]
+    Overloaded {
+        message: String,
+        retry_after_seconds: u64,
+        tenant_limited: bool,
+    },
+
+    /// A provider answered 429; `retry_after_seconds` is how long it asked to be left alone
+    #[error("Upstream rate limit from {provider}")]
+    UpstreamRateLimited {
+        provider: String,
+        retry_after_seconds: u64,
+    },
+
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    #[error("Context length exceeded: {0}")]
+    ContextLengthExceeded(String),
+
     #[error("Unauthorized")]
     Unauthorized,
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Cache error: {0}")]
     CacheError(String),
 
@@ -52,30 +82,301 @@ pub enum OmenError {
 
 pub type Result<T> = std::result::Result<T, OmenError>;
 
+/// Most of a provider's error message kept when it isn't JSON
+const MAX_UPSTREAM_MESSAGE: usize = 500;
+
+/// An error response from a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamError {
+    pub provider: String,
+    pub status: u16,
+    pub message: String,
+    /// The provider's error code or type, when it sent one
+    pub code: Option<String>,
+    pub param: Option<String>,
+}
+
+impl UpstreamError {
+    /// Read an error body in the OpenAI (`{"error": {"message", "type",
+    /// "code", "param"}}`), Anthropic, Gemini or Ollama (`{"error": "..."}`) shape
+    pub fn parse(provider: &str, status: u16, body: &str) -> Self {
+        let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let error = json.get("error").unwrap_or(&json);
+        let field = |name: &str| error.get(name).and_then(|v| v.as_str()).map(str::to_string);
+
+        let message = field("message")
+            .or_else(|| error.as_str().map(str::to_string))
+            .unwrap_or_else(|| match body.trim() {
+                "" => format!("HTTP {}", status),
+                text => text.chars().take(MAX_UPSTREAM_MESSAGE).collect(),
+            });
+        Self {
+            provider: provider.to_string(),
+            status,
+            message,
+            code: field("code").or_else(|| field("type")).or_else(|| field("status")),
+            param: field("param"),
+        }
+    }
+
+    fn mentions(&self, needles: &[&str]) -> bool {
+        let code = self.code.as_deref().unwrap_or_default().to_lowercase();
+        let message = self.message.to_lowercase();
+        needles.iter().any(|needle| code.contains(needle) || message.contains(needle))
+    }
+
+    fn is_context_length(&self) -> bool {
+        self.mentions(&["context_length_exceeded", "context length", "context window", "prompt is too long", "too many tokens"])
+    }
+
+    fn is_content_filter(&self) -> bool {
+        self.mentions(&["content_filter", "content_policy", "content management policy", "safety"])
+    }
+}
+
+/// OpenAI-compatible error object, sent as `{"error": ...}` over HTTP and in
+/// stream error events
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: u16,
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: &'static str,
+    pub code: String,
+    pub param: Option<String>,
+    /// Provider whose error this is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// HTTP status the provider answered with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+    /// Whether the same request may succeed if sent again later
+    pub retryable: bool,
+    #[serde(skip)]
+    pub retry_after_seconds: Option<u64>,
+}
+
+impl ApiError {
+    fn new(status: u16, error_type: &'static str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            error_type,
+            code: code.to_string(),
+            param: None,
+            provider: None,
+            upstream_status: None,
+            retryable: false,
+            retry_after_seconds: None,
+        }
+    }
+
+    fn param(mut self, param: &str) -> Self {
+        self.param = Some(param.to_string());
+        self
+    }
+
+    fn retryable(mut self) -> Self {
+        self.retryable = true;
+        self
+    }
+
+    fn upstream(mut self, provider: &str, status: Option<u16>) -> Self {
+        self.provider = Some(provider.to_string());
+        self.upstream_status = status;
+        self
+    }
+
+    /// `{"error": ...}`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "error": self })
+    }
+
+    /// A server-sent event reporting the error to a streaming client
+    pub fn to_sse_event(&self) -> String {
+        format!("data: {}\n\n", self.to_json())
+    }
+}
+
+impl OmenError {
+    /// Classify a provider's error response
+    pub fn upstream(provider: &str, status: u16, body: &str) -> Self {
+        OmenError::Upstream(UpstreamError::parse(provider, status, body))
+    }
+
+    /// The error as clients see it, following OpenAI error semantics
+    pub fn to_api_error(&self) -> ApiError {
+        const INVALID: &str = "invalid_request_error";
+        const SERVER: &str = "server_error";
+        const RATE_LIMIT: &str = "rate_limit_error";
+
+        match self {
+            OmenError::InvalidRequest(msg) => ApiError::new(400, INVALID, "invalid_request", msg),
+            OmenError::ModelNotFound(model) => {
+                ApiError::new(404, INVALID, "model_not_found", format!("Model not found: {}", model)).param("model")
+            }
+            OmenError::ContextLengthExceeded(msg) => {
+                ApiError::new(400, INVALID, "context_length_exceeded", msg).param("messages")
+            }
+            OmenError::BudgetExceeded(msg) => ApiError::new(402, INVALID, "budget_exceeded", msg),
+            OmenError::Unauthorized => {
+                ApiError::new(401, "authentication_error", "invalid_api_key", "Invalid or missing API key")
+            }
+            OmenError::Forbidden(msg) => ApiError::new(403, "permission_error", "permission_denied", msg),
+            OmenError::RateLimitExceeded => {
+                ApiError::new(429, RATE_LIMIT, "rate_limit_exceeded", "Rate limit exceeded").retryable()
+            }
+            OmenError::Overloaded { message, retry_after_seconds, tenant_limited } => {
+                let error = match tenant_limited {
+                    true => ApiError::new(429, RATE_LIMIT, "rate_limit_exceeded", message),
+                    false => ApiError::new(503, SERVER, "server_overloaded", message),
+                };
+                ApiError { retry_after_seconds: Some(*retry_after_seconds), ..error.retryable() }
+            }
+            OmenError::UpstreamRateLimited { provider, retry_after_seconds } => {
+                let message = format!("Rate limited by upstream provider {}", provider);
+                let error = ApiError::new(429, RATE_LIMIT, "rate_limit_exceeded", message).retryable();
+                ApiError { retry_after_seconds: Some(*retry_after_seconds), ..error.upstream(provider, Some(429)) }
+            }
+            OmenError::ProviderUnavailable(msg) => ApiError::new(503, SERVER, "provider_unavailable", msg).retryable(),
+            OmenError::Provider(msg) => ApiError::new(502, SERVER, "upstream_error", msg).retryable(),
+            OmenError::Upstream(upstream) => Self::upstream_api_error(upstream),
+            OmenError::HttpClient(e) if e.is_timeout() => {
+                ApiError::new(504, SERVER, "timeout", "Upstream provider timed out").retryable()
+            }
+            OmenError::HttpClient(_) => {
+                ApiError::new(502, SERVER, "upstream_connection_error", "Could not reach upstream provider").retryable()
+            }
+            _ => ApiError::new(500, SERVER, "internal_error", "Internal server error"),
+        }
+    }
+
+    fn upstream_api_error(upstream: &UpstreamError) -> ApiError {
+        let message = upstream.message.clone();
+        let status = upstream.status;
+        let error = match status {
+            // Rate limit and overload bodies can also say "too many tokens"
+            400 | 413 if upstream.is_context_length() => {
+                ApiError::new(400, "invalid_request_error", "context_length_exceeded", message).param("messages")
+            }
+            400..=499 if upstream.is_content_filter() => {
+                ApiError::new(400, "invalid_request_error", "content_filter", message)
+            }
+            // OMEN's credentials for the provider, not the caller's, were refused
+            401 | 403 => ApiError::new(502, "server_error", "upstream_authentication_error", message).retryable(),
+            404 => ApiError::new(404, "invalid_request_error", "model_not_found", message).param("model"),
+            408 | 504 => ApiError::new(504, "server_error", "timeout", message).retryable(),
+            429 => ApiError::new(429, "rate_limit_error", "rate_limit_exceeded", message).retryable(),
+            400..=499 => {
+                let code = upstream.code.as_deref().unwrap_or("invalid_request");
+                ApiError::new(400, "invalid_request_error", code, message)
+            }
+            _ => ApiError::new(502, "server_error", "upstream_error", message).retryable(),
+        };
+        let error = match (&error.param, &upstream.param) {
+            (None, Some(param)) => error.param(param),
+            _ => error,
+        };
+        error.upstream(&upstream.provider, Some(status))
+    }
+}
+
 // Convert to HTTP response
 impl axum::response::IntoResponse for OmenError {
     fn into_response(self) -> axum::response::Response {
-        use axum::http::StatusCode;
+        use axum::http::{header, StatusCode};
         use axum::Json;
-        use serde_json::json;
-
-        let (status, error_message) = match self {
-            OmenError::InvalidRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            OmenError::ModelNotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            OmenError::ProviderUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
-            OmenError::RateLimitExceeded => (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded".to_string()),
-            OmenError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
-            _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
+
+        let error = self.to_api_error();
+        let status = StatusCode::from_u16(error.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = Json(error.to_json());
+
+        match error.retry_after_seconds {
+            Some(seconds) => (status, [(header::RETRY_AFTER, seconds.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+// Convert to a gRPC status with the same classification as HTTP; the error
+// object travels as JSON in the `x-omen-error` metadata
+impl From<OmenError> for tonic::Status {
+    fn from(error: OmenError) -> Self {
+        use tonic::Code;
+
+        let error = error.to_api_error();
+        let code = match error.status {
+            400 => Code::InvalidArgument,
+            401 => Code::Unauthenticated,
+            402 => Code::FailedPrecondition,
+            403 => Code::PermissionDenied,
+            404 => Code::NotFound,
+            429 => Code::ResourceExhausted,
+            502 | 503 => Code::Unavailable,
+            504 => Code::DeadlineExceeded,
+            _ => Code::Internal,
         };
+        let mut status = tonic::Status::new(code, error.message.clone());
+        if let Ok(value) = error.to_json()["error"].to_string().parse() {
+            status.metadata_mut().insert("x-omen-error", value);
+        }
+        if let Some(seconds) = error.retry_after_seconds
+            && let Ok(value) = seconds.to_string().parse()
+        {
+            status.metadata_mut().insert("retry-after", value);
+        }
+        status
+    }
+}
 
-        let body = Json(json!({
-            "error": {
-                "message": error_message,
-                "type": "api_error",
-                "code": status.as_u16()
-            }
-        }));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_upstream_errors() {
+        let body = r#"{"error": {"message": "This model's maximum context length is 8192 tokens", "type": "invalid_request_error", "param": "messages", "code": "context_length_exceeded"}}"#;
+        let error = OmenError::upstream("openai", 400, body).to_api_error();
+        assert_eq!((error.status, error.code.as_str()), (400, "context_length_exceeded"));
+        assert_eq!(error.provider.as_deref(), Some("openai"));
+        assert_eq!(error.upstream_status, Some(400));
+        assert!(!error.retryable);
 
-        (status, body).into_response()
+        let body = r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#;
+        let error = OmenError::upstream("anthropic", 529, body).to_api_error();
+        assert_eq!((error.status, error.code.as_str(), error.message.as_str()), (502, "upstream_error", "Overloaded"));
+        assert!(error.retryable);
+
+        let error = OmenError::upstream("ollama", 404, r#"{"error": "model 'llama9' not found"}"#).to_api_error();
+        assert_eq!((error.status, error.code.as_str()), (404, "model_not_found"));
+
+        let error = OmenError::upstream("azure", 400, r#"{"error": {"code": "content_filter", "message": "filtered"}}"#);
+        assert_eq!(error.to_api_error().code, "content_filter");
+
+        let error = OmenError::upstream("openai", 401, "bad key").to_api_error();
+        assert_eq!((error.status, error.code.as_str()), (502, "upstream_authentication_error"));
+
+        let body = r#"{"error": {"message": "Rate limit reached: too many tokens per minute"}}"#;
+        let error = OmenError::upstream("openai", 429, body).to_api_error();
+        assert_eq!((error.status, error.code.as_str()), (429, "rate_limit_exceeded"));
+        let error = OmenError::upstream("openai", 413, "Request too large: too many tokens").to_api_error();
+        assert_eq!((error.status, error.code.as_str()), (400, "context_length_exceeded"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_error_body_and_status_codes() {
+        let error = OmenError::Unauthorized.to_api_error();
+        assert_eq!(error.to_json()["error"]["code"], "invalid_api_key");
+        assert_eq!(error.to_json()["error"]["type"], "authentication_error");
+
+        let limited = OmenError::UpstreamRateLimited { provider: "openai".to_string(), retry_after_seconds: 7 };
+        assert!(limited.to_api_error().to_sse_event().starts_with("data: {\"error\":"));
+        let status = tonic::Status::from(limited);
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "7");
+
+        let internal = OmenError::Database("connection refused".to_string()).to_api_error();
+        assert_eq!((internal.status, internal.message.as_str()), (500, "Internal server error"));
+    }
+}
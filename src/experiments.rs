@@ -0,0 +1,470 @@
+//! A/B traffic experiments
+//!
+//! `[[experiments]]` split the requests matching `match` between weighted
+//! arms, each routed to its own provider and/or model. Callers are bucketed
+//! deterministically by user or session id so each one stays on an arm.
+//! Latency, cost, error rate and user feedback are tracked per arm and
+//! reported on `/admin/experiments`. A streamed request's cost is what its
+//! provider streams reported, summed when the client's stream ends.
+
+use crate::{
+    config::{ExperimentArmConfig, ExperimentConfig},
+    error::{OmenError, Result},
+    learned_metrics::{DecayedHistogram, ErrorClass},
+    rules::RequestMatcher,
+    types::{ChatCompletionRequest, OmenConfig, RequestContext},
+};
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// The arm a request was placed in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Assignment {
+    pub experiment: String,
+    pub arm: String,
+}
+
+/// How a request served by an arm went
+#[derive(Debug, Clone, Default)]
+pub struct ArmOutcome {
+    pub latency_ms: u64,
+    /// Unknown when no provider stream reported its usage
+    pub cost_usd: Option<f64>,
+    /// A provider-side failure: timeout, rate limit, unavailable or upstream error
+    pub error: bool,
+}
+
+impl ArmOutcome {
+    pub fn served(latency_ms: u64, cost_usd: Option<f64>) -> Self {
+        Self { latency_ms, cost_usd, error: false }
+    }
+
+    /// None when the request failed for reasons of its own (invalid, denied,
+    /// over budget) rather than the arm's
+    pub fn failed(error: &OmenError, latency_ms: u64) -> Option<Self> {
+        ErrorClass::of(error)?;
+        Some(Self { latency_ms, cost_usd: None, error: true })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ArmStats {
+    requests: u64,
+    errors: u64,
+    latency: DecayedHistogram,
+    latency_total_ms: u64,
+    latency_samples: u64,
+    cost_total_usd: f64,
+    cost_samples: u64,
+    feedback_total: f64,
+    feedback_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArmReport {
+    pub name: String,
+    pub weight: u32,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub avg_latency_ms: Option<f64>,
+    pub p50_latency_ms: Option<f64>,
+    pub p95_latency_ms: Option<f64>,
+    pub avg_cost_usd: Option<f64>,
+    pub total_cost_usd: f64,
+    pub feedback_count: u64,
+    /// Mean feedback score, 0-1
+    pub avg_feedback: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentReport {
+    pub name: String,
+    pub enabled: bool,
+    pub bucket_by: String,
+    pub arms: Vec<ArmReport>,
+}
+
+#[derive(Debug)]
+pub struct ExperimentTracker {
+    experiments: Vec<(ExperimentConfig, RequestMatcher)>,
+    stats: RwLock<HashMap<(String, String), ArmStats>>,
+    /// Usage reported so far by the provider streams of each arm stream
+    stream_costs: Mutex<HashMap<Uuid, f64>>,
+}
+
+impl ExperimentTracker {
+    /// Compile the experiments, rejecting ones without arms or weight and
+    /// unknown bucketing keys
+    pub fn new(experiments: &[ExperimentConfig]) -> Result<Self> {
+        let mut names = Vec::new();
+        let experiments = experiments
+            .iter()
+            .map(|experiment| {
+                let owner = format!("Experiment '{}'", experiment.name);
+                if names.contains(&experiment.name) {
+                    return Err(OmenError::Config(format!("{}: defined twice", owner)));
+                }
+                names.push(experiment.name.clone());
+                if experiment.arms.iter().map(|arm| arm.weight as u64).sum::<u64>() == 0 {
+                    return Err(OmenError::Config(format!("{}: needs arms with a positive weight", owner)));
+                }
+                if !matches!(experiment.bucket_by.as_str(), "user" | "session") {
+                    return Err(OmenError::Config(format!(
+                        "{}: bucket_by must be user or session, not '{}'",
+                        owner, experiment.bucket_by
+                    )));
+                }
+                Ok((experiment.clone(), RequestMatcher::new(&owner, &experiment.matches)?))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            experiments,
+            stats: RwLock::new(HashMap::new()),
+            stream_costs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Place the request in an arm of the first enabled experiment it matches,
+    /// rewriting it to the arm's provider and model
+    pub fn assign(&self, request: &mut ChatCompletionRequest, context: &mut RequestContext) -> Option<Assignment> {
+        let now = Utc::now();
+        let mut tokens = None;
+        let (experiment, _) = self.experiments.iter().find(|(experiment, matcher)| {
+            experiment.enabled && matcher.mismatch(request, context, now, &mut tokens).is_none()
+        })?;
+
+        let session_id = context.tags.get("session_id").map(String::as_str);
+        let key = bucket_key(experiment, context.user_id.as_deref(), session_id)
+            .unwrap_or_else(|| context.request_id.to_string());
+        let arm = pick_arm(experiment, &key)?;
+        if let Some(ref model) = arm.model {
+            request.model = model.clone();
+        }
+        if let Some(ref provider) = arm.provider {
+            request.omen.get_or_insert_with(OmenConfig::empty).providers = Some(vec![provider.clone()]);
+        }
+        context.tags.insert("experiment".to_string(), experiment.name.clone());
+        context.tags.insert("experiment_arm".to_string(), arm.name.clone());
+
+        Some(Assignment { experiment: experiment.name.clone(), arm: arm.name.clone() })
+    }
+
+    /// The arm a user or session is bucketed into
+    pub fn arm_for(&self, experiment: &str, user_id: Option<&str>, session_id: Option<&str>) -> Option<String> {
+        let (experiment, _) = self.experiments.iter().find(|(e, _)| e.name == experiment)?;
+        let key = bucket_key(experiment, user_id, session_id)?;
+        pick_arm(experiment, &key).map(|arm| arm.name.clone())
+    }
+
+    pub async fn record(&self, assignment: &Assignment, outcome: ArmOutcome) {
+        let mut stats = self.stats.write().await;
+        let arm = stats.entry((assignment.experiment.clone(), assignment.arm.clone())).or_default();
+        arm.requests += 1;
+        if outcome.error {
+            arm.errors += 1;
+            return;
+        }
+        arm.latency.record(outcome.latency_ms as f64);
+        arm.latency_total_ms += outcome.latency_ms;
+        arm.latency_samples += 1;
+        if let Some(cost_usd) = outcome.cost_usd {
+            arm.cost_total_usd += cost_usd;
+            arm.cost_samples += 1;
+        }
+    }
+
+    /// Start summing the usage of a streamed request's provider streams
+    pub fn track_stream_cost(&self, request_id: Uuid) {
+        self.stream_costs.lock().unwrap().insert(request_id, 0.0);
+    }
+
+    /// Add one provider stream's cost to a tracked request
+    pub fn add_stream_cost(&self, request_id: Uuid, cost_usd: f64) {
+        if let Some(total) = self.stream_costs.lock().unwrap().get_mut(&request_id) {
+            *total += cost_usd;
+        }
+    }
+
+    /// Stop tracking a request, returning what its streams cost
+    pub fn take_stream_cost(&self, request_id: Uuid) -> Option<f64> {
+        self.stream_costs.lock().unwrap().remove(&request_id)
+    }
+
+    /// Record a user's 0-1 rating of an arm's answers
+    pub async fn record_feedback(&self, experiment: &str, arm: &str, score: f64) -> Result<()> {
+        if !(0.0..=1.0).contains(&score) {
+            return Err(OmenError::InvalidRequest(format!("Feedback score {} outside 0-1", score)));
+        }
+        let known = self
+            .experiments
+            .iter()
+            .any(|(e, _)| e.name == experiment && e.arms.iter().any(|a| a.name == arm));
+        if !known {
+            return Err(OmenError::InvalidRequest(format!("Unknown experiment arm {}/{}", experiment, arm)));
+        }
+
+        let mut stats = self.stats.write().await;
+        let stats = stats.entry((experiment.to_string(), arm.to_string())).or_default();
+        stats.feedback_total += score;
+        stats.feedback_count += 1;
+        Ok(())
+    }
+
+    pub async fn report(&self) -> Vec<ExperimentReport> {
+        let stats = self.stats.read().await;
+        self.experiments
+            .iter()
+            .map(|(experiment, _)| ExperimentReport {
+                name: experiment.name.clone(),
+                enabled: experiment.enabled,
+                bucket_by: experiment.bucket_by.clone(),
+                arms: experiment
+                    .arms
+                    .iter()
+                    .map(|arm| {
+                        let arm_stats = stats.get(&(experiment.name.clone(), arm.name.clone())).cloned().unwrap_or_default();
+                        arm_report(arm, &arm_stats)
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+fn arm_report(arm: &ExperimentArmConfig, stats: &ArmStats) -> ArmReport {
+    let mean = |total: f64, count: u64| (count > 0).then(|| total / count as f64);
+    ArmReport {
+        name: arm.name.clone(),
+        weight: arm.weight,
+        provider: arm.provider.clone(),
+        model: arm.model.clone(),
+        requests: stats.requests,
+        errors: stats.errors,
+        error_rate: mean(stats.errors as f64, stats.requests).unwrap_or(0.0),
+        avg_latency_ms: mean(stats.latency_total_ms as f64, stats.latency_samples),
+        p50_latency_ms: stats.latency.percentile(0.5),
+        p95_latency_ms: stats.latency.percentile(0.95),
+        avg_cost_usd: mean(stats.cost_total_usd, stats.cost_samples),
+        total_cost_usd: stats.cost_total_usd,
+        feedback_count: stats.feedback_count,
+        avg_feedback: mean(stats.feedback_total, stats.feedback_count),
+    }
+}
+
+/// The id that keeps a caller on one arm, falling back to the other id.
+/// Requests with neither are bucketed individually.
+fn bucket_key(experiment: &ExperimentConfig, user_id: Option<&str>, session_id: Option<&str>) -> Option<String> {
+    let key = match experiment.bucket_by.as_str() {
+        "session" => session_id.or(user_id),
+        _ => user_id.or(session_id),
+    };
+    key.map(str::to_string)
+}
+
+/// Weighted arm for a bucket key, stable for the experiment's name and arms
+fn pick_arm<'a>(experiment: &'a ExperimentConfig, bucket_key: &str) -> Option<&'a ExperimentArmConfig> {
+    let total: u64 = experiment.arms.iter().map(|arm| arm.weight as u64).sum();
+    if total == 0 {
+        return None;
+    }
+
+    // A fixed hash, so callers keep their arm across restarts and upgrades
+    let digest = Sha256::new()
+        .chain_update(experiment.name.as_bytes())
+        .chain_update([0])
+        .chain_update(bucket_key.as_bytes())
+        .finalize();
+    let mut point = u64::from_be_bytes(digest[..8].try_into().unwrap()) % total;
+
+    experiment.arms.iter().find(|arm| {
+        if point < arm.weight as u64 {
+            return true;
+        }
+        point -= arm.weight as u64;
+        false
+    })
+}
+
+/// Stops tracking a stream's cost if the client drops it before the end
+struct StreamCostGuard {
+    tracker: Arc<ExperimentTracker>,
+    request_id: Uuid,
+}
+
+impl Drop for StreamCostGuard {
+    fn drop(&mut self) {
+        self.tracker.take_stream_cost(self.request_id);
+    }
+}
+
+/// Record a streamed arm request once its stream ends, with the cost its
+/// provider streams reported (see `track_stream_cost`)
+pub fn observe_stream(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    tracker: Arc<ExperimentTracker>,
+    assignment: Assignment,
+    request_id: Uuid,
+    started: Instant,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let guard = StreamCostGuard { tracker: tracker.clone(), request_id };
+    let observed = futures::stream::unfold(Some((stream, guard)), move |state| {
+        let (tracker, assignment) = (tracker.clone(), assignment.clone());
+        async move {
+            let (mut stream, guard) = state?;
+            match stream.next().await {
+                Some(Ok(chunk)) => Some((Ok(chunk), Some((stream, guard)))),
+                Some(Err(e)) => {
+                    if let Some(outcome) = ArmOutcome::failed(&e, started.elapsed().as_millis() as u64) {
+                        tracker.record(&assignment, outcome).await;
+                    }
+                    Some((Err(e), None))
+                }
+                None => {
+                    let cost_usd = tracker.take_stream_cost(request_id);
+                    let outcome = ArmOutcome::served(started.elapsed().as_millis() as u64, cost_usd);
+                    tracker.record(&assignment, outcome).await;
+                    None
+                }
+            }
+        }
+    });
+
+    Box::new(Box::pin(observed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RuleMatchConfig;
+    use uuid::Uuid;
+
+    fn experiment() -> ExperimentConfig {
+        let arm = |name: &str, weight: u32, provider: &str, model: &str| ExperimentArmConfig {
+            name: name.to_string(),
+            weight,
+            provider: Some(provider.to_string()),
+            model: Some(model.to_string()),
+        };
+        ExperimentConfig {
+            name: "zeke-gemini".to_string(),
+            enabled: true,
+            matches: RuleMatchConfig { api_keys: vec!["zeke".to_string()], ..Default::default() },
+            bucket_by: "user".to_string(),
+            arms: vec![
+                arm("gemini", 30, "google", "gemini-2.0-flash"),
+                arm("claude", 70, "anthropic", "claude-3-5-sonnet"),
+            ],
+        }
+    }
+
+    fn request() -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "auto",
+            "messages": [{ "role": "user", "content": "hello" }],
+        }))
+        .unwrap()
+    }
+
+    fn context(user_id: &str, api_key: &str) -> RequestContext {
+        RequestContext {
+            request_id: Uuid::new_v4(),
+            user_id: Some(user_id.to_string()),
+            api_key: None,
+            intent: None,
+            tags: HashMap::from([("api_key_name".to_string(), api_key.to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_assignment_is_stable_and_weighted() {
+        let tracker = ExperimentTracker::new(&[experiment()]).unwrap();
+
+        let first = tracker.assign(&mut request(), &mut context("u-1", "zeke")).unwrap();
+        for _ in 0..10 {
+            assert_eq!(tracker.assign(&mut request(), &mut context("u-1", "zeke")).unwrap(), first);
+        }
+        assert!(tracker.assign(&mut request(), &mut context("u-1", "other")).is_none());
+
+        let gemini = (0..10_000)
+            .filter(|i| tracker.arm_for("zeke-gemini", Some(&format!("user-{}", i)), None).as_deref() == Some("gemini"))
+            .count();
+        assert!((2_700..3_300).contains(&gemini), "{} of 10000 on the 30% arm", gemini);
+    }
+
+    #[test]
+    fn test_assignment_rewrites_request() {
+        let tracker = ExperimentTracker::new(&[experiment()]).unwrap();
+        let (mut request, mut context) = (request(), context("u-2", "zeke"));
+        let assignment = tracker.assign(&mut request, &mut context).unwrap();
+
+        let (provider, model) = match assignment.arm.as_str() {
+            "gemini" => ("google", "gemini-2.0-flash"),
+            _ => ("anthropic", "claude-3-5-sonnet"),
+        };
+        assert_eq!(request.model, model);
+        assert_eq!(request.omen.unwrap().providers, Some(vec![provider.to_string()]));
+        assert_eq!(context.tags["experiment_arm"], assignment.arm);
+
+        let mut invalid = experiment();
+        invalid.arms.iter_mut().for_each(|arm| arm.weight = 0);
+        assert!(ExperimentTracker::new(&[invalid]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_cost_sums_provider_streams() {
+        let tracker = Arc::new(ExperimentTracker::new(&[experiment()]).unwrap());
+        let assignment = Assignment { experiment: "zeke-gemini".to_string(), arm: "claude".to_string() };
+        let request_id = Uuid::new_v4();
+
+        tracker.track_stream_cost(request_id);
+        let upstream = futures::stream::iter(vec![Ok("data: [DONE]\n\n".to_string())]);
+        let mut stream = observe_stream(Box::new(upstream), tracker.clone(), assignment, request_id, Instant::now());
+        // A race winner and the loser cancelled behind it
+        tracker.add_stream_cost(request_id, 0.003);
+        tracker.add_stream_cost(request_id, 0.001);
+        while stream.next().await.is_some() {}
+
+        let claude = &tracker.report().await[0].arms[1];
+        assert_eq!(claude.requests, 1);
+        assert!((claude.total_cost_usd - 0.004).abs() < 1e-9);
+        assert!(tracker.take_stream_cost(request_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_arm_report() {
+        let tracker = ExperimentTracker::new(&[experiment()]).unwrap();
+        let assignment = Assignment { experiment: "zeke-gemini".to_string(), arm: "gemini".to_string() };
+
+        for latency_ms in [400, 600] {
+            tracker.record(&assignment, ArmOutcome::served(latency_ms, Some(0.01))).await;
+        }
+        let failure = ArmOutcome::failed(&OmenError::Provider("overloaded".to_string()), 30_000).unwrap();
+        tracker.record(&assignment, failure).await;
+        assert!(ArmOutcome::failed(&OmenError::InvalidRequest("bad".to_string()), 1).is_none());
+        tracker.record_feedback("zeke-gemini", "gemini", 1.0).await.unwrap();
+        tracker.record_feedback("zeke-gemini", "gemini", 0.0).await.unwrap();
+        assert!(tracker.record_feedback("zeke-gemini", "gpt", 1.0).await.is_err());
+
+        let report = tracker.report().await;
+        let gemini = &report[0].arms[0];
+        assert_eq!(gemini.requests, 3);
+        assert!((gemini.error_rate - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(gemini.avg_latency_ms, Some(500.0));
+        assert!((gemini.total_cost_usd - 0.02).abs() < 1e-9);
+        assert_eq!(gemini.avg_feedback, Some(0.5));
+        assert_eq!(report[0].arms[1].requests, 0);
+    }
+}
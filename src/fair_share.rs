@@ -0,0 +1,290 @@
+//! Fair-share admission control
+//!
+//! With `[fair_share]` enabled, at most `max_concurrent` requests are served
+//! at once. Requests over that limit queue by start-time fair queuing: each
+//! tenant (the Ghost service for Ghost traffic, else the user) advances its
+//! virtual time by `1 / share` per request, and the waiting request with the
+//! earliest virtual start is admitted next. A batch tenant with a full
+//! backlog therefore gets its share of capacity but cannot starve others.
+//!
+//! When the system is saturated requests are shed instead of queuing without
+//! bound: 429 when a tenant already has `max_queue_per_tenant` waiting, 503
+//! when the whole queue is full or a wait times out, both with Retry-After.
+
+use crate::{
+    config::FairShareConfig,
+    error::{OmenError, Result},
+    types::RequestContext,
+    wait_queue::{SlotPool, WaitQueue, Waiting},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+/// Weight of the newest request duration in the running average
+const HOLD_SMOOTHING: f64 = 0.2;
+
+/// Retry-After bounds, seconds
+const MIN_RETRY_AFTER: u64 = 1;
+const MAX_RETRY_AFTER: u64 = 60;
+
+/// Tenant a request is scheduled as: its Ghost service, else its user
+pub fn tenant(context: &RequestContext) -> String {
+    context
+        .tags
+        .get("ghost_service")
+        .or(context.user_id.as_ref())
+        .cloned()
+        .unwrap_or_else(|| "anonymous".to_string())
+}
+
+/// Admission queue shared by every request
+#[derive(Debug)]
+pub struct FairShareScheduler {
+    config: FairShareConfig,
+    state: Mutex<SchedulerState>,
+}
+
+#[derive(Debug, Default)]
+struct SchedulerState {
+    /// Waiters tagged with their tenant and virtual start, earliest start first
+    queue: WaitQueue<(String, f64)>,
+    tenants: HashMap<String, TenantState>,
+    /// Virtual start of the most recently admitted request
+    virtual_time: f64,
+    /// Smoothed time a request holds its slot
+    hold_ms: f64,
+}
+
+#[derive(Debug, Default)]
+struct TenantState {
+    /// Virtual time at which the tenant's latest request finishes
+    finish: f64,
+    queued: usize,
+}
+
+impl SchedulerState {
+    /// Virtual start for a tenant's next request, advancing its finish by 1 / share
+    fn tag(&mut self, tenant: &str, share: f64) -> f64 {
+        let virtual_time = self.virtual_time;
+        let state = self.tenants.entry(tenant.to_string()).or_default();
+        let start = state.finish.max(virtual_time);
+        state.finish = start + 1.0 / share;
+        start
+    }
+
+    /// Admit the earliest waiting request, or free the slot
+    fn dispatch(&mut self) {
+        let SchedulerState { queue, tenants, virtual_time, .. } = self;
+        queue.release(|(tenant, start)| {
+            if let Some(tenant) = tenants.get_mut(tenant) {
+                tenant.queued = tenant.queued.saturating_sub(1);
+            }
+            *virtual_time = virtual_time.max(*start);
+        });
+    }
+
+    /// Forget idle tenants that hold no credit or debt against the clock
+    fn prune(&mut self) {
+        let virtual_time = self.virtual_time;
+        self.tenants.retain(|_, tenant| tenant.queued > 0 || tenant.finish > virtual_time);
+    }
+}
+
+impl FairShareScheduler {
+    pub fn new(config: FairShareConfig) -> Self {
+        Self { config, state: Mutex::new(SchedulerState::default()) }
+    }
+
+    fn share(&self, tenant: &str) -> f64 {
+        let share = self.config.shares.get(tenant).copied().unwrap_or(self.config.default_share);
+        share.max(1e-6)
+    }
+
+    /// Seconds a shed client should wait: the time to drain the current queue
+    fn retry_after(&self, state: &SchedulerState) -> u64 {
+        let drain_ms = (state.queue.len() + 1) as f64 * state.hold_ms / self.config.max_concurrent.max(1) as f64;
+        ((drain_ms / 1000.0).ceil() as u64).clamp(MIN_RETRY_AFTER, MAX_RETRY_AFTER)
+    }
+
+    fn shed(&self, state: &SchedulerState, message: String, tenant_limited: bool) -> OmenError {
+        OmenError::Overloaded { message, retry_after_seconds: self.retry_after(state), tenant_limited }
+    }
+
+    /// Wait for a turn to be served. None when admission control is disabled.
+    pub async fn admit(self: &Arc<Self>, context: &RequestContext) -> Result<Option<FairSharePermit>> {
+        if !self.config.enabled {
+            return Ok(None);
+        }
+
+        let tenant = tenant(context);
+        let share = self.share(&tenant);
+        let (seq, grant) = {
+            let mut state = self.state.lock().unwrap();
+            if state.queue.in_flight < self.config.max_concurrent && state.queue.is_empty() {
+                state.queue.in_flight += 1;
+                let start = state.tag(&tenant, share);
+                state.virtual_time = state.virtual_time.max(start);
+                return Ok(Some(FairSharePermit { scheduler: self.clone(), admitted: Instant::now() }));
+            }
+
+            let tenant_queued = state.tenants.get(&tenant).map_or(0, |t| t.queued);
+            if tenant_queued >= self.config.max_queue_per_tenant {
+                warn!("🚦 Shedding request from {}: {} already waiting", tenant, tenant_queued);
+                let message = format!("Too many requests waiting for {}; retry later", tenant);
+                return Err(self.shed(&state, message, true));
+            }
+            if state.queue.len() >= self.config.max_queue {
+                warn!("🚦 Shedding request from {}: admission queue full", tenant);
+                return Err(self.shed(&state, "Server is at capacity; retry later".to_string(), false));
+            }
+
+            let start = state.tag(&tenant, share);
+            state.tenants.entry(tenant.clone()).or_default().queued += 1;
+            state.queue.enqueue(-start, (tenant.clone(), start))
+        };
+
+        let timeout = Duration::from_millis(self.config.queue_timeout_ms);
+        if Waiting::new(self.clone(), seq, grant).granted(timeout).await {
+            debug!("🚦 Admitted queued request from {}", tenant);
+            Ok(Some(FairSharePermit { scheduler: self.clone(), admitted: Instant::now() }))
+        } else {
+            let state = self.state.lock().unwrap();
+            let message = format!("Request waited {}ms for admission; retry later", timeout.as_millis());
+            Err(self.shed(&state, message, false))
+        }
+    }
+
+    fn release(&self, held: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let held_ms = held.as_secs_f64() * 1000.0;
+        state.hold_ms += HOLD_SMOOTHING * (held_ms - state.hold_ms);
+        state.dispatch();
+        state.prune();
+    }
+}
+
+impl SlotPool for FairShareScheduler {
+    fn cancel(&self, seq: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some((tenant, _)) = state.queue.remove(seq) else {
+            return false;
+        };
+        if let Some(tenant) = state.tenants.get_mut(&tenant) {
+            tenant.queued = tenant.queued.saturating_sub(1);
+        }
+        true
+    }
+
+    fn release_unused(&self) {
+        self.release(Duration::ZERO);
+    }
+}
+
+/// An admitted request's slot, released when dropped
+#[derive(Debug)]
+pub struct FairSharePermit {
+    scheduler: Arc<FairShareScheduler>,
+    admitted: Instant,
+}
+
+impl Drop for FairSharePermit {
+    fn drop(&mut self) {
+        self.scheduler.release(self.admitted.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(max_queue_per_tenant: usize) -> Arc<FairShareScheduler> {
+        Arc::new(FairShareScheduler::new(FairShareConfig {
+            enabled: true,
+            max_concurrent: 1,
+            max_queue: 16,
+            max_queue_per_tenant,
+            queue_timeout_ms: 1000,
+            shares: HashMap::from([("zeke".to_string(), 3.0), ("ghostflow".to_string(), 1.0)]),
+            default_share: 1.0,
+        }))
+    }
+
+    fn context(service: &str) -> RequestContext {
+        RequestContext {
+            request_id: uuid::Uuid::new_v4(),
+            user_id: Some("user-1".to_string()),
+            api_key: None,
+            intent: None,
+            tags: HashMap::from([("ghost_service".to_string(), service.to_string())]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shares_interleave_tenants() {
+        let scheduler = scheduler(16);
+        let held = scheduler.admit(&context("ghostflow")).await.unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // A batch backlog queues first, then interactive requests arrive
+        let mut waiters = Vec::new();
+        for service in ["ghostflow", "ghostflow", "ghostflow", "ghostflow", "zeke", "zeke", "zeke"] {
+            let (scheduler, order) = (scheduler.clone(), order.clone());
+            waiters.push(tokio::spawn(async move {
+                let permit = scheduler.admit(&context(service)).await.unwrap();
+                order.lock().unwrap().push(service);
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                drop(permit);
+            }));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        let order = order.lock().unwrap();
+        let last_zeke = order.iter().rposition(|s| *s == "zeke").unwrap();
+        assert!(last_zeke < 5, "zeke was starved: {:?}", order);
+    }
+
+    #[tokio::test]
+    async fn test_sheds_tenant_over_queue_limit() {
+        let scheduler = scheduler(1);
+        let _held = scheduler.admit(&context("ghostflow")).await.unwrap();
+
+        let queued = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.admit(&context("ghostflow")).await.is_ok() }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        match scheduler.admit(&context("ghostflow")).await {
+            Err(OmenError::Overloaded { tenant_limited, retry_after_seconds, .. }) => {
+                assert!(tenant_limited);
+                assert!(retry_after_seconds >= MIN_RETRY_AFTER);
+            }
+            other => panic!("expected load shedding, got {:?}", other.map(|p| p.is_some())),
+        }
+
+        // Another tenant can still queue
+        let other = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.admit(&context("zeke")).await.is_ok() }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(_held);
+        assert!(queued.await.unwrap());
+        assert!(other.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_admits_everything() {
+        let scheduler = Arc::new(FairShareScheduler::new(FairShareConfig::default()));
+        assert!(scheduler.admit(&context("zeke")).await.unwrap().is_none());
+        assert_eq!(tenant(&context("zeke")), "zeke");
+    }
+}
@@ -1,11 +1,16 @@
 use crate::{
+    auth::{self, AuthService},
+    budget,
+    circuit_breaker::CircuitState,
     error::{OmenError, Result},
     router::OmenRouter,
+    stickiness, types,
 };
-use std::sync::Arc;
-use tonic::{Request, Response, Status};
-use tracing::{debug, error, info};
-use uuid::Uuid;
+use std::{collections::HashMap, future::Future, sync::Arc};
+use tonic::{
+    codegen::InterceptedService, service::Interceptor, Request, Response, Status,
+};
+use tracing::{debug, error, info, warn};
 
 // Include the generated protobuf code
 pub mod proto {
@@ -25,6 +30,19 @@ use proto::{
     ChatChoiceDelta as ProtoChatChoiceDelta, ChatMessageDelta as ProtoChatMessageDelta,
     Usage as ProtoUsage, ModelPricing as ProtoModelPricing, ModelCapabilities as ProtoModelCapabilities,
     ToolCall as ProtoToolCall, Function as ProtoFunction,
+    ContentPart as ProtoContentPart, ImageUrl as ProtoImageUrl,
+    chat_completion_request::ToolChoice as ProtoToolChoice, content_part::Part as ProtoPart,
+    OmenOptions as ProtoOmenOptions, RoutingMetadata as ProtoRoutingMetadata,
+    EmbeddingsRequest as ProtoEmbeddingsRequest, EmbeddingsResponse as ProtoEmbeddingsResponse,
+    Embedding as ProtoEmbedding, EmbeddingUsage as ProtoEmbeddingUsage,
+    TokenCountRequest, TokenCountResponse,
+    GetUsageRequest, UsageStats as ProtoUsageStats, UserLimits as ProtoUserLimits,
+    ListBillingTiersRequest, ListBillingTiersResponse, BillingTier as ProtoBillingTier,
+    BillingSummaryRequest, BillingSummaryResponse, UserBillingSummary as ProtoUserBillingSummary,
+    ProviderScoresRequest, ProviderScoresResponse,
+    ProviderScore as ProtoProviderScore, LearnedMetrics as ProtoLearnedMetrics,
+    CacheStatsRequest, CacheStatsResponse, ClearCacheRequest, ClearCacheResponse,
+    GhostSessionStatsRequest, GhostSessionStats as ProtoGhostSessionStats,
 };
 
 pub struct OmenGrpcService {
@@ -36,8 +54,13 @@ impl OmenGrpcService {
         Self { router }
     }
 
-    pub fn into_service(self) -> OmenServiceServer<Self> {
-        OmenServiceServer::new(self)
+    /// The tonic service, with every RPC authenticated like the HTTP API's
+    /// protected routes
+    pub fn into_service(
+        self,
+        auth_service: Arc<AuthService>,
+    ) -> InterceptedService<OmenServiceServer<Self>, impl Interceptor + Clone> {
+        OmenServiceServer::with_interceptor(self, auth::grpc_interceptor(auth_service))
     }
 }
 
@@ -47,22 +70,22 @@ impl OmenService for OmenGrpcService {
         &self,
         request: Request<ProtoChatCompletionRequest>,
     ) -> std::result::Result<Response<ProtoChatCompletionResponse>, Status> {
-        let req = request.into_inner();
-        debug!("gRPC chat completion request for model: {}", req.model);
+        debug!("gRPC chat completion request for model: {}", request.get_ref().model);
 
         // Convert protobuf request to internal types
-        let chat_request = convert_grpc_to_chat_request(req)?;
-        let context = create_request_context();
+        let (chat_request, context) = chat_call(request)?;
+
+        let explain = chat_request.omen.as_ref().and_then(|o| o.explain).unwrap_or(false);
 
         // Process the request
         match self.router.chat_completion(chat_request, context).await {
             Ok(response) => {
-                let grpc_response = convert_chat_response_to_grpc(response)?;
+                let grpc_response = convert_chat_response_to_grpc(response, explain)?;
                 Ok(Response::new(grpc_response))
             }
             Err(e) => {
                 error!("gRPC chat completion failed: {}", e);
-                Err(Status::internal(format!("Chat completion failed: {}", e)))
+                Err(Status::from(e))
             }
         }
     }
@@ -75,48 +98,51 @@ impl OmenService for OmenGrpcService {
         &self,
         request: Request<ProtoChatCompletionRequest>,
     ) -> std::result::Result<Response<Self::StreamChatCompletionStream>, Status> {
-        let req = request.into_inner();
-        debug!("gRPC streaming chat completion request for model: {}", req.model);
+        debug!("gRPC streaming chat completion request for model: {}", request.get_ref().model);
 
         // Convert protobuf request to internal types
-        let mut chat_request = convert_grpc_to_chat_request(req)?;
+        let (mut chat_request, context) = chat_call(request)?;
         chat_request.stream = true;
-        let context = create_request_context();
+
+        let (mut stream, routing) = self.router.stream_chat_completion_routed(chat_request, context).await
+            .map_err(Status::from)?;
 
         // Create a channel for streaming responses
         let (tx, rx) = tokio::sync::mpsc::channel(100);
 
-        // Process the streaming request
-        let router = self.router.clone();
         tokio::spawn(async move {
-            match router.stream_chat_completion(chat_request, context).await {
-                Ok(mut stream) => {
-                    use futures::StreamExt;
-                    while let Some(chunk_result) = stream.next().await {
-                        match chunk_result {
-                            Ok(chunk_text) => {
-                                // Parse SSE chunk and convert to gRPC
-                                if let Ok(grpc_chunk) = parse_sse_to_grpc_chunk(&chunk_text) {
-                                    if tx.send(Ok(grpc_chunk)).await.is_err() {
-                                        break; // Client disconnected
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                let _ = tx.send(Err(Status::internal(format!("Stream error: {}", e)))).await;
-                                break;
-                            }
+            use futures::StreamExt;
+            let mut decoder = ChunkDecoder::default();
+            loop {
+                // Dropping the stream cancels the upstream provider requests
+                let (chunks, done) = tokio::select! {
+                    item = stream.next() => match item {
+                        Some(Ok(text)) => (decoder.push(&text), false),
+                        Some(Err(e)) => {
+                            let _ = tx.send(Err(Status::from(e))).await;
+                            break;
                         }
+                        None => (decoder.finish(), true),
+                    },
+                    _ = tx.closed() => {
+                        debug!("gRPC client disconnected, cancelling stream");
+                        break;
+                    }
+                };
+                for chunk in chunks {
+                    if tx.send(Ok(convert_chunk_to_grpc(chunk))).await.is_err() {
+                        return; // Client disconnected
                     }
                 }
-                Err(e) => {
-                    let _ = tx.send(Err(Status::internal(format!("Failed to start stream: {}", e)))).await;
+                if done {
+                    break;
                 }
             }
         });
 
-        let stream = tokio_stream::wrappers::ReceiverStream::new(rx);
-        Ok(Response::new(stream))
+        let mut response = Response::new(tokio_stream::wrappers::ReceiverStream::new(rx));
+        insert_routing_metadata(response.metadata_mut(), &routing);
+        Ok(response)
     }
 
     async fn list_models(
@@ -141,7 +167,7 @@ impl OmenService for OmenGrpcService {
             }
             Err(e) => {
                 error!("gRPC list models failed: {}", e);
-                Err(Status::internal(format!("Failed to list models: {}", e)))
+                Err(Status::from(e))
             }
         }
     }
@@ -152,23 +178,12 @@ impl OmenService for OmenGrpcService {
     ) -> std::result::Result<Response<HealthCheckResponse>, Status> {
         debug!("gRPC health check request");
 
-        let providers = self.router.list_providers().await;
-        let mut provider_health = std::collections::HashMap::new();
-        let mut healthy_count = 0;
-
-        for provider in &providers {
-            match provider.health_check().await {
-                Ok(healthy) => {
-                    provider_health.insert(provider.id().to_string(), healthy);
-                    if healthy {
-                        healthy_count += 1;
-                    }
-                }
-                Err(_) => {
-                    provider_health.insert(provider.id().to_string(), false);
-                }
-            }
-        }
+        let providers = self.router.get_provider_health().await
+            .map_err(Status::from)?;
+        let healthy_count = providers.iter().filter(|p| p.healthy).count();
+        let provider_health = providers.iter()
+            .map(|p| (p.id.clone(), p.healthy))
+            .collect::<std::collections::HashMap<_, _>>();
 
         let response = HealthCheckResponse {
             status: if healthy_count > 0 { "healthy" } else { "unhealthy" }.to_string(),
@@ -187,20 +202,21 @@ impl OmenService for OmenGrpcService {
         debug!("gRPC provider status request");
 
         let providers = self.router.list_providers().await;
+        let health = self.router.get_provider_health().await
+            .map_err(Status::from)?;
         let mut provider_infos = Vec::new();
 
         for provider in providers {
-            let healthy = provider.health_check().await.unwrap_or(false);
-            let models = provider.list_models().await.unwrap_or_default();
+            let status = health.iter().find(|h| h.id == provider.id());
 
             provider_infos.push(ProviderInfo {
                 id: provider.id().to_string(),
                 name: provider.name().to_string(),
                 r#type: format!("{:?}", provider.provider_type()),
                 enabled: true, // If it's in the list, it's enabled
-                healthy,
-                models_count: models.len() as i32,
-                last_error: None, // TODO: Track last error
+                healthy: status.is_some_and(|h| h.healthy),
+                models_count: status.map_or(0, |h| h.models_count) as i32,
+                last_error: status.and_then(|h| h.last_error.clone()),
             });
         }
 
@@ -210,38 +226,244 @@ impl OmenService for OmenGrpcService {
 
         Ok(Response::new(response))
     }
+
+    async fn embeddings(
+        &self,
+        request: Request<ProtoEmbeddingsRequest>,
+    ) -> std::result::Result<Response<ProtoEmbeddingsResponse>, Status> {
+        debug!("gRPC embeddings request for model: {}", request.get_ref().model);
+
+        let context = caller_context(&request, &request.get_ref().model);
+        let req = request.into_inner();
+        let input = match <[String; 1]>::try_from(req.input) {
+            Ok([text]) => types::EmbeddingInput::Single(text),
+            Err(texts) if texts.is_empty() => return Err(Status::invalid_argument("input is empty")),
+            Err(texts) => types::EmbeddingInput::Multiple(texts),
+        };
+        let embeddings_request = types::EmbeddingsRequest {
+            input,
+            model: req.model,
+            encoding_format: req.encoding_format,
+            dimensions: req.dimensions,
+        };
+
+        let response = self.router.embeddings(embeddings_request, context).await
+            .map_err(Status::from)?;
+
+        Ok(Response::new(ProtoEmbeddingsResponse {
+            object: response.object,
+            data: response.data
+                .into_iter()
+                .map(|data| ProtoEmbedding {
+                    object: data.object,
+                    embedding: data.embedding,
+                    index: data.index as u32,
+                })
+                .collect(),
+            model: response.model,
+            usage: Some(ProtoEmbeddingUsage {
+                prompt_tokens: response.usage.prompt_tokens,
+                total_tokens: response.usage.total_tokens,
+            }),
+        }))
+    }
+
+    async fn token_count(
+        &self,
+        request: Request<TokenCountRequest>,
+    ) -> std::result::Result<Response<TokenCountResponse>, Status> {
+        let req = request.into_inner();
+        let chat_request = convert_grpc_to_chat_request(ProtoChatCompletionRequest {
+            model: req.model,
+            messages: req.messages,
+            tools: req.tools,
+            ..Default::default()
+        })?;
+
+        Ok(Response::new(TokenCountResponse {
+            prompt_tokens: budget::count_request_tokens(&chat_request),
+        }))
+    }
+
+    async fn get_usage(
+        &self,
+        request: Request<GetUsageRequest>,
+    ) -> std::result::Result<Response<ProtoUsageStats>, Status> {
+        let auth_info = request.extensions().get::<auth::ApiKeyInfo>()
+            .ok_or_else(|| Status::from(OmenError::Unauthorized))?;
+        let stats = self.router.get_user_usage_stats(&auth_info.user_id).await
+            .map_err(Status::from)?;
+
+        Ok(Response::new(ProtoUsageStats {
+            user_id: stats.user_id,
+            tier: stats.tier,
+            daily_requests: stats.daily_requests,
+            daily_tokens: stats.daily_tokens,
+            daily_cost_usd: stats.daily_cost_usd,
+            monthly_tokens: stats.monthly_tokens,
+            monthly_cost_usd: stats.monthly_cost_usd,
+            total_cost_usd: stats.total_cost_usd,
+            daily_limits: Some(ProtoUserLimits {
+                requests: stats.daily_limits.requests,
+                tokens: stats.daily_limits.tokens,
+                budget_usd: stats.daily_limits.budget_usd,
+            }),
+            can_make_request: stats.can_make_request,
+        }))
+    }
+
+    async fn list_billing_tiers(
+        &self,
+        _request: Request<ListBillingTiersRequest>,
+    ) -> std::result::Result<Response<ListBillingTiersResponse>, Status> {
+        let tiers = self.router.get_available_billing_tiers()
+            .into_iter()
+            .map(|tier| ProtoBillingTier {
+                name: tier.name.clone(),
+                requests_per_day: tier.requests_per_day,
+                tokens_per_day: tier.tokens_per_day,
+                budget_per_day_usd: tier.budget_per_day_usd,
+                cost_multiplier: tier.cost_multiplier,
+                priority_weight: tier.priority_weight,
+            })
+            .collect();
+
+        Ok(Response::new(ListBillingTiersResponse { tiers }))
+    }
+
+    async fn get_billing_summary(
+        &self,
+        _request: Request<BillingSummaryRequest>,
+    ) -> std::result::Result<Response<BillingSummaryResponse>, Status> {
+        let users = self.router.get_billing_summary().await
+            .into_iter()
+            .map(|user| ProtoUserBillingSummary {
+                user_id: user.user_id,
+                tier: user.tier,
+                daily_cost_usd: user.daily_cost_usd,
+                monthly_spend_usd: user.monthly_spend_usd,
+                total_spend_usd: user.total_spend_usd,
+                last_activity: user.last_activity.to_rfc3339(),
+            })
+            .collect();
+
+        Ok(Response::new(BillingSummaryResponse { users }))
+    }
+
+    async fn get_provider_scores(
+        &self,
+        _request: Request<ProviderScoresRequest>,
+    ) -> std::result::Result<Response<ProviderScoresResponse>, Status> {
+        let scores = self.router.get_provider_scores().await
+            .map_err(Status::from)?
+            .into_iter()
+            .map(convert_provider_score_to_grpc)
+            .collect();
+
+        Ok(Response::new(ProviderScoresResponse { scores }))
+    }
+
+    async fn get_cache_stats(
+        &self,
+        _request: Request<CacheStatsRequest>,
+    ) -> std::result::Result<Response<CacheStatsResponse>, Status> {
+        let Some(cache) = &self.router.cache else {
+            return Ok(Response::new(CacheStatsResponse::default()));
+        };
+        let stats = cache.get_cache_stats().await.map_err(Status::from)?;
+
+        Ok(Response::new(CacheStatsResponse {
+            cache_enabled: true,
+            memory_used_bytes: stats.memory_used_bytes,
+            memory_peak_bytes: stats.memory_peak_bytes,
+            hit_rate: stats.hit_rate,
+            total_hits: stats.total_hits,
+            total_misses: stats.total_misses,
+        }))
+    }
+
+    async fn clear_cache(
+        &self,
+        request: Request<ClearCacheRequest>,
+    ) -> std::result::Result<Response<ClearCacheResponse>, Status> {
+        let pattern = request.into_inner().pattern;
+        let Some(cache) = &self.router.cache else {
+            return Ok(Response::new(ClearCacheResponse::default()));
+        };
+        let deleted = cache.clear_cache(pattern.as_deref()).await.map_err(Status::from)?;
+        info!("gRPC cache clear removed {} entries", deleted);
+
+        Ok(Response::new(ClearCacheResponse {
+            cache_enabled: true,
+            deleted_keys: deleted as u64,
+            pattern: pattern.unwrap_or_else(|| "*".to_string()),
+        }))
+    }
+
+    async fn get_ghost_session_stats(
+        &self,
+        request: Request<GhostSessionStatsRequest>,
+    ) -> std::result::Result<Response<ProtoGhostSessionStats>, Status> {
+        let session_id = request.into_inner().session_id;
+        let session_uuid = session_id.parse::<uuid::Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid session ID"))?;
+
+        let stats = self.router.create_ghost_orchestrator()
+            .get_ghost_session_stats(session_uuid).await
+            .ok_or_else(|| Status::not_found(format!("Session {} not found", session_id)))?;
+
+        Ok(Response::new(ProtoGhostSessionStats {
+            session_id: stats.session_id.to_string(),
+            service: stats.service.as_str().to_string(),
+            user_id: stats.user_id,
+            created_at: stats.created_at.to_rfc3339(),
+            last_activity: stats.last_activity.to_rfc3339(),
+            request_count: stats.request_count,
+            total_cost: stats.total_cost,
+            is_active: stats.is_active,
+        }))
+    }
 }
 
 // Helper functions for conversion
 
-fn convert_grpc_to_chat_request(req: ProtoChatCompletionRequest) -> std::result::Result<crate::types::ChatCompletionRequest, Status> {
+#[allow(clippy::result_large_err)] // tonic's Status
+fn convert_grpc_to_chat_request(req: ProtoChatCompletionRequest) -> std::result::Result<types::ChatCompletionRequest, Status> {
     let messages = req.messages
         .into_iter()
-        .map(|msg| {
-            let tool_calls = if msg.tool_calls.is_empty() {
-                None
-            } else {
-                Some(msg.tool_calls.into_iter().map(|tc| crate::types::ToolCall {
-                    id: tc.id,
-                    tool_type: tc.r#type,
-                    function: crate::types::ToolCallFunction {
-                        name: tc.function.map(|f| f.name).unwrap_or_default(),
-                        arguments: "{}".to_string(), // TODO: Convert from protobuf Value
-                    },
-                }).collect())
-            };
-
-            crate::types::ChatMessage {
-                role: msg.role,
-                content: crate::types::MessageContent::Text(msg.content),
-                name: msg.name,
-                tool_calls,
-                tool_call_id: msg.tool_call_id,
-            }
+        .map(convert_grpc_to_message)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let tools = req.tools
+        .into_iter()
+        .map(|tool| {
+            let function = tool.function?;
+            Some(types::Tool {
+                tool_type: if tool.r#type.is_empty() { "function".to_string() } else { tool.r#type },
+                function: types::ToolFunction {
+                    name: function.name,
+                    description: function.description,
+                    parameters: proto_struct_to_json(function.parameters),
+                },
+            })
         })
-        .collect();
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Status::invalid_argument("tool is missing its function"))?;
+
+    let tool_choice = match req.tool_choice {
+        None => None,
+        Some(ProtoToolChoice::ToolChoiceAuto(_)) => Some(types::ToolChoice::Auto),
+        Some(ProtoToolChoice::ToolChoiceNone(_)) => Some(types::ToolChoice::None),
+        Some(ProtoToolChoice::ToolChoiceFunction(choice)) => {
+            let function = choice.function
+                .ok_or_else(|| Status::invalid_argument("tool_choice_function is missing its function"))?;
+            Some(types::ToolChoice::Function {
+                function: types::ToolFunctionChoice { name: function.name },
+            })
+        }
+    };
 
-    Ok(crate::types::ChatCompletionRequest {
+    Ok(types::ChatCompletionRequest {
         model: req.model,
         messages,
         temperature: req.temperature.map(|v| v as f32),
@@ -251,36 +473,83 @@ fn convert_grpc_to_chat_request(req: ProtoChatCompletionRequest) -> std::result:
         presence_penalty: req.presence_penalty.map(|v| v as f32),
         stop: if req.stop.is_empty() { None } else { Some(req.stop) },
         stream: req.stream,
-        tools: None, // TODO: Convert tools
-        tool_choice: None, // TODO: Convert tool choice
-        tags: None,
-        omen: None,
+        tools: if tools.is_empty() { None } else { Some(tools) },
+        tool_choice,
+        tags: if req.metadata.is_empty() { None } else { Some(req.metadata) },
+        omen: req.omen.map(convert_grpc_to_omen_options),
+    })
+}
+
+fn convert_grpc_to_omen_options(options: ProtoOmenOptions) -> types::OmenConfig {
+    types::OmenConfig {
+        strategy: options.strategy,
+        k: options.k,
+        providers: if options.providers.is_empty() { None } else { Some(options.providers) },
+        budget_usd: options.budget_usd,
+        max_latency_ms: options.max_latency_ms,
+        stickiness: options.stickiness,
+        priority_weights: if options.priority_weights.is_empty() { None } else { Some(options.priority_weights) },
+        min_useful_tokens: options.min_useful_tokens,
+        selector: options.selector,
+        json_schema: if options.json_schema.is_empty() { None } else { Some(proto_struct_to_json(options.json_schema)) },
+        context_overflow: options.context_overflow,
+        keep_last_turns: options.keep_last_turns,
+        explain: options.explain,
+    }
+}
+
+#[allow(clippy::result_large_err)] // tonic's Status
+fn convert_grpc_to_message(msg: ProtoChatMessage) -> std::result::Result<types::ChatMessage, Status> {
+    let content = if msg.content_parts.is_empty() {
+        types::MessageContent::Text(msg.content)
+    } else {
+        let parts = msg.content_parts
+            .into_iter()
+            .map(|part| match part.part? {
+                ProtoPart::Text(text) => Some(types::ContentPart::Text { text }),
+                ProtoPart::ImageUrl(image) => Some(types::ContentPart::ImageUrl {
+                    image_url: types::ImageUrl { url: image.url, detail: image.detail },
+                }),
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Status::invalid_argument("content part is empty"))?;
+        types::MessageContent::Parts(parts)
+    };
+
+    let tool_calls = if msg.tool_calls.is_empty() {
+        None
+    } else {
+        Some(msg.tool_calls.into_iter().map(|tc| {
+            let function = tc.function.unwrap_or_default();
+            // Arguments travel as JSON text; clients may send a structured
+            // object in parameters instead
+            let arguments = function.arguments.unwrap_or_else(|| {
+                proto_struct_to_json(function.parameters).to_string()
+            });
+            types::ToolCall {
+                id: tc.id,
+                tool_type: if tc.r#type.is_empty() { "function".to_string() } else { tc.r#type },
+                function: types::ToolCallFunction { name: function.name, arguments },
+            }
+        }).collect())
+    };
+
+    Ok(types::ChatMessage {
+        role: msg.role,
+        content,
+        name: msg.name,
+        tool_calls,
+        tool_call_id: msg.tool_call_id,
     })
 }
 
-fn convert_chat_response_to_grpc(resp: crate::types::ChatCompletionResponse) -> std::result::Result<ProtoChatCompletionResponse, Status> {
+#[allow(clippy::result_large_err)] // tonic's Status
+fn convert_chat_response_to_grpc(resp: types::ChatCompletionResponse, explain: bool) -> std::result::Result<ProtoChatCompletionResponse, Status> {
     let choices = resp.choices
         .into_iter()
         .map(|choice| ProtoChatChoice {
             index: choice.index as i32,
-            message: Some(ProtoChatMessage {
-                role: choice.message.role,
-                content: choice.message.content.text(),
-                name: choice.message.name,
-                tool_calls: choice.message.tool_calls.unwrap_or_default()
-                    .into_iter()
-                    .map(|tc| ProtoToolCall {
-                        id: tc.id,
-                        r#type: tc.tool_type,
-                        function: Some(ProtoFunction {
-                            name: tc.function.name,
-                            description: None,
-                            parameters: std::collections::HashMap::new(), // TODO: Parse arguments
-                        }),
-                    })
-                    .collect(),
-                tool_call_id: choice.message.tool_call_id,
-            }),
+            message: Some(convert_message_to_grpc(choice.message)),
             finish_reason: choice.finish_reason,
         })
         .collect();
@@ -297,9 +566,212 @@ fn convert_chat_response_to_grpc(resp: crate::types::ChatCompletionResponse) ->
             total_tokens: resp.usage.total_tokens,
         }),
         system_fingerprint: resp.system_fingerprint,
+        routing: resp.omen
+            .and_then(|omen| omen.routing)
+            .map(|routing| convert_routing_to_grpc(routing, explain)),
     })
 }
 
+/// Routing metadata; the reasoning behind the decision only when `explain`
+/// was asked for, as in the REST response body
+fn convert_routing_to_grpc(routing: types::RoutingMetadata, explain: bool) -> ProtoRoutingMetadata {
+    let mut metadata = ProtoRoutingMetadata {
+        provider: routing.provider,
+        model: routing.model,
+        strategy: routing.strategy,
+        cost_usd: routing.cost_usd,
+        cache: routing.cache,
+        ..Default::default()
+    };
+    if explain {
+        metadata.intent = routing.intent;
+        metadata.rules = routing.rules;
+        metadata.confidence = routing.confidence;
+        metadata.reasoning = routing.reasoning;
+        if let Some(weights) = routing.weights {
+            metadata.weights = HashMap::from([
+                ("cost".to_string(), weights.cost_weight as f64),
+                ("latency".to_string(), weights.latency_weight as f64),
+                ("quality".to_string(), weights.quality_weight as f64),
+                ("reliability".to_string(), weights.reliability_weight as f64),
+            ]);
+        }
+        metadata.provider_multipliers =
+            routing.provider_multipliers.into_iter().map(|(provider, multiplier)| (provider, multiplier as f64)).collect();
+    }
+    metadata
+}
+
+/// `x-omen-*` response metadata, matching the REST API's headers
+fn insert_routing_metadata(metadata: &mut tonic::metadata::MetadataMap, routing: &types::RoutingMetadata) {
+    let values = [
+        ("x-omen-provider", routing.provider.clone()),
+        ("x-omen-model", routing.model.clone()),
+        ("x-omen-strategy", Some(routing.strategy.clone())),
+        ("x-omen-cost-usd", routing.cost_usd.map(|cost| format!("{:.6}", cost))),
+        ("x-omen-max-tokens", routing.max_tokens_clamped.map(|tokens| tokens.to_string())),
+        ("x-omen-cache", Some(routing.cache.clone())),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value.and_then(|v| v.parse().ok()) {
+            metadata.insert(name, value);
+        }
+    }
+}
+
+fn convert_provider_score_to_grpc(score: types::ProviderScore) -> ProtoProviderScore {
+    ProtoProviderScore {
+        provider_id: score.provider_id,
+        provider_name: score.provider_name,
+        health_score: score.health_score,
+        latency_ms: score.latency_ms,
+        cost_score: score.cost_score,
+        reliability_score: score.reliability_score,
+        overall_score: score.overall_score,
+        recommended: score.recommended,
+        circuit_state: match score.circuit_state {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }.to_string(),
+        learned: score.learned.map(|learned| {
+            let overall = learned.overall;
+            ProtoLearnedMetrics {
+                samples: overall.samples,
+                total_requests: overall.total_requests,
+                p50_ttft_ms: overall.p50_ttft_ms,
+                p95_ttft_ms: overall.p95_ttft_ms,
+                p50_latency_ms: overall.p50_latency_ms,
+                p95_latency_ms: overall.p95_latency_ms,
+                tokens_per_second: overall.tokens_per_second,
+                error_rate: overall.error_rate,
+                cancel_rate: overall.cancel_rate,
+                cost_per_request_usd: overall.cost_per_request_usd,
+                cost_per_1k_tokens: overall.cost_per_1k_tokens,
+            }
+        }),
+    }
+}
+
+fn convert_message_to_grpc(msg: types::ChatMessage) -> ProtoChatMessage {
+    let content_parts = match &msg.content {
+        types::MessageContent::Text(_) => Vec::new(),
+        types::MessageContent::Parts(parts) => parts.iter()
+            .map(|part| ProtoContentPart {
+                part: Some(match part {
+                    types::ContentPart::Text { text } => ProtoPart::Text(text.clone()),
+                    types::ContentPart::ImageUrl { image_url } => ProtoPart::ImageUrl(ProtoImageUrl {
+                        url: image_url.url.clone(),
+                        detail: image_url.detail.clone(),
+                    }),
+                }),
+            })
+            .collect(),
+    };
+
+    ProtoChatMessage {
+        role: msg.role,
+        content: msg.content.text(),
+        name: msg.name,
+        tool_calls: msg.tool_calls.unwrap_or_default()
+            .into_iter()
+            .map(|tc| {
+                let parameters = match serde_json::from_str(&tc.function.arguments) {
+                    Ok(serde_json::Value::Object(object)) => json_object_to_proto(object),
+                    _ => HashMap::new(),
+                };
+                ProtoToolCall {
+                    id: tc.id,
+                    r#type: tc.tool_type,
+                    function: Some(ProtoFunction {
+                        name: tc.function.name,
+                        description: None,
+                        parameters,
+                        arguments: Some(tc.function.arguments),
+                    }),
+                    index: None,
+                }
+            })
+            .collect(),
+        tool_call_id: msg.tool_call_id,
+        content_parts,
+    }
+}
+
+fn convert_chunk_to_grpc(chunk: types::ChatCompletionChunk) -> ProtoChatCompletionChunk {
+    ProtoChatCompletionChunk {
+        id: chunk.id,
+        object: chunk.object,
+        created: chunk.created,
+        model: chunk.model,
+        choices: chunk.choices
+            .into_iter()
+            .map(|choice| ProtoChatChoiceDelta {
+                index: choice.index as i32,
+                delta: Some(ProtoChatMessageDelta {
+                    role: choice.delta.role,
+                    content: choice.delta.content,
+                    tool_calls: choice.delta.tool_calls.unwrap_or_default()
+                        .into_iter()
+                        .map(|delta| ProtoToolCall {
+                            id: delta.id.unwrap_or_default(),
+                            r#type: delta.tool_type.unwrap_or_default(),
+                            function: delta.function.map(|f| ProtoFunction {
+                                name: f.name.unwrap_or_default(),
+                                description: None,
+                                parameters: HashMap::new(),
+                                arguments: f.arguments,
+                            }),
+                            index: Some(delta.index),
+                        })
+                        .collect(),
+                }),
+                finish_reason: choice.finish_reason,
+            })
+            .collect(),
+        system_fingerprint: chunk.system_fingerprint,
+    }
+}
+
+fn proto_struct_to_json(fields: HashMap<String, prost_types::Value>) -> serde_json::Value {
+    serde_json::Value::Object(fields.into_iter().map(|(k, v)| (k, proto_value_to_json(v))).collect())
+}
+
+fn proto_value_to_json(value: prost_types::Value) -> serde_json::Value {
+    use prost_types::value::Kind;
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => serde_json::Value::Null,
+        Some(Kind::BoolValue(b)) => serde_json::Value::Bool(b),
+        // Protobuf only has doubles; keep whole numbers integral in the schema
+        Some(Kind::NumberValue(n)) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => serde_json::Value::from(n as i64),
+        Some(Kind::NumberValue(n)) => serde_json::Value::from(n),
+        Some(Kind::StringValue(s)) => serde_json::Value::String(s),
+        Some(Kind::ListValue(list)) => serde_json::Value::Array(list.values.into_iter().map(proto_value_to_json).collect()),
+        Some(Kind::StructValue(object)) => proto_struct_to_json(object.fields.into_iter().collect()),
+    }
+}
+
+fn json_object_to_proto(object: serde_json::Map<String, serde_json::Value>) -> HashMap<String, prost_types::Value> {
+    object.into_iter().map(|(k, v)| (k, json_to_proto_value(v))).collect()
+}
+
+fn json_to_proto_value(value: serde_json::Value) -> prost_types::Value {
+    use prost_types::value::Kind;
+    let kind = match value {
+        serde_json::Value::Null => Kind::NullValue(0),
+        serde_json::Value::Bool(b) => Kind::BoolValue(b),
+        serde_json::Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => Kind::StringValue(s),
+        serde_json::Value::Array(items) => Kind::ListValue(prost_types::ListValue {
+            values: items.into_iter().map(json_to_proto_value).collect(),
+        }),
+        serde_json::Value::Object(object) => Kind::StructValue(prost_types::Struct {
+            fields: json_object_to_proto(object).into_iter().collect(),
+        }),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
 fn convert_model_to_grpc(model: crate::types::Model) -> ProtoModel {
     ProtoModel {
         id: model.id,
@@ -320,74 +792,262 @@ fn convert_model_to_grpc(model: crate::types::Model) -> ProtoModel {
     }
 }
 
-fn create_request_context() -> crate::types::RequestContext {
-    crate::types::RequestContext {
-        request_id: Uuid::new_v4(),
-        user_id: None,
-        api_key: None,
-        intent: None,
-        tags: std::collections::HashMap::new(),
+/// The chat request and its context, built the way the HTTP API builds
+/// them: the authenticated key (set by `auth::grpc_interceptor`) names the
+/// user, the proto `metadata` map becomes the request's tags, and session
+/// metadata pins provider stickiness
+#[allow(clippy::result_large_err)]
+fn chat_call(request: Request<ProtoChatCompletionRequest>) -> std::result::Result<(types::ChatCompletionRequest, types::RequestContext), Status> {
+    let auth_info = request.extensions().get::<auth::ApiKeyInfo>().cloned();
+    let session_id = [stickiness::SESSION_HEADER, "x-session-id"]
+        .iter()
+        .find_map(|name| request.metadata().get(*name).and_then(|v| v.to_str().ok()))
+        .map(str::to_string);
+
+    let chat_request = convert_grpc_to_chat_request(request.into_inner())?;
+    let mut context = auth::create_authenticated_context(auth_info.as_ref(), &chat_request);
+    if let Some(session_id) = session_id {
+        context.tags.insert("session_id".to_string(), session_id);
     }
+    Ok((chat_request, context))
 }
 
-fn parse_sse_to_grpc_chunk(sse_text: &str) -> std::result::Result<ProtoChatCompletionChunk, Status> {
-    // Parse SSE format: "data: {json}\n\n"
-    for line in sse_text.lines() {
-        if line.starts_with("data: ") {
-            let json_str = line.trim_start_matches("data: ");
-            if json_str == "[DONE]" {
-                // End of stream marker - could send a special chunk
-                continue;
-            }
+/// Request context for calls other than chat, from the authenticated key
+fn caller_context<T>(request: &Request<T>, model: &str) -> types::RequestContext {
+    let auth_info = request.extensions().get::<auth::ApiKeyInfo>();
+    auth::create_authenticated_context(auth_info, &types::ChatCompletionRequest {
+        model: model.to_string(),
+        messages: vec![],
+        temperature: None,
+        max_tokens: None,
+        stream: false,
+        top_p: None,
+        frequency_penalty: None,
+        presence_penalty: None,
+        stop: None,
+        tools: None,
+        tool_choice: None,
+        tags: None,
+        omen: None,
+    })
+}
 
-            match serde_json::from_str::<crate::types::ChatCompletionChunk>(json_str) {
-                Ok(chunk) => {
-                    return Ok(ProtoChatCompletionChunk {
-                        id: chunk.id,
-                        object: chunk.object,
-                        created: chunk.created,
-                        model: chunk.model,
-                        choices: chunk.choices
-                            .into_iter()
-                            .map(|choice| ProtoChatChoiceDelta {
-                                index: choice.index as i32,
-                                delta: Some(ProtoChatMessageDelta {
-                                    role: choice.delta.role,
-                                    content: choice.delta.content,
-                                    tool_calls: vec![], // TODO: Convert tool calls
-                                }),
-                                finish_reason: choice.finish_reason,
-                            })
-                            .collect(),
-                        system_fingerprint: chunk.system_fingerprint,
-                    });
-                }
+/// Decodes a provider stream's SSE text into typed chunks. Providers may
+/// split a `data:` line across stream items, so partial lines wait for the
+/// rest.
+#[derive(Default)]
+struct ChunkDecoder {
+    pending: String,
+}
+
+impl ChunkDecoder {
+    fn push(&mut self, text: &str) -> Vec<types::ChatCompletionChunk> {
+        self.pending.push_str(text);
+        let Some(end) = self.pending.rfind('\n') else {
+            return Vec::new();
+        };
+        let complete: String = self.pending.drain(..=end).collect();
+
+        complete.lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(str::trim)
+            .filter(|data| !data.is_empty() && *data != "[DONE]")
+            .filter_map(|data| match serde_json::from_str(data) {
+                Ok(chunk) => Some(chunk),
                 Err(e) => {
-                    error!("Failed to parse SSE chunk: {}", e);
+                    warn!("Skipping undecodable stream chunk: {}", e);
+                    None
                 }
-            }
-        }
+            })
+            .collect()
     }
 
-    Err(Status::internal("Invalid SSE chunk format"))
+    /// Decode whatever is left once the stream ends
+    fn finish(&mut self) -> Vec<types::ChatCompletionChunk> {
+        self.push("\n")
+    }
 }
 
-/// Starts the gRPC server on the specified address
-/// This is a public API function that will be used when gRPC is enabled
-#[allow(dead_code)]
+/// Serve the gRPC API on `listener` until `shutdown` completes
 pub async fn start_grpc_server(
     router: Arc<OmenRouter>,
-    addr: std::net::SocketAddr,
+    auth_service: Arc<AuthService>,
+    listener: tokio::net::TcpListener,
+    shutdown: impl Future<Output = ()>,
 ) -> Result<()> {
     let service = OmenGrpcService::new(router);
 
-    info!("🚀 Starting OMEN gRPC server on {}", addr);
+    info!("🚀 OMEN gRPC API listening on {}", listener.local_addr()?);
 
     tonic::transport::Server::builder()
-        .add_service(service.into_service())
-        .serve(addr)
+        .add_service(service.into_service(auth_service))
+        .serve_with_incoming_shutdown(tokio_stream::wrappers::TcpListenerStream::new(listener), shutdown)
         .await
         .map_err(|e| OmenError::Server(format!("gRPC server error: {}", e)))?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ProtoChatMessage {
+        ProtoChatMessage { role: role.to_string(), content: content.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_request_keeps_tools_images_and_arguments() {
+        let schema = serde_json::json!({"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]});
+        let serde_json::Value::Object(schema) = schema else { unreachable!() };
+
+        let mut user = message("user", "");
+        user.content_parts = vec![
+            ProtoContentPart { part: Some(ProtoPart::Text("What's here?".to_string())) },
+            ProtoContentPart {
+                part: Some(ProtoPart::ImageUrl(ProtoImageUrl { url: "https://x/cat.png".to_string(), detail: Some("low".to_string()) })),
+            },
+        ];
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls = vec![ProtoToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: Some(ProtoFunction { name: "weather".to_string(), arguments: Some(r#"{"city":"Oslo"}"#.to_string()), ..Default::default() }),
+            index: None,
+        }];
+
+        let request = convert_grpc_to_chat_request(ProtoChatCompletionRequest {
+            model: "gpt-4o".to_string(),
+            messages: vec![user, assistant],
+            tools: vec![proto::Tool {
+                r#type: "function".to_string(),
+                function: Some(ProtoFunction { name: "weather".to_string(), parameters: json_object_to_proto(schema.clone()), ..Default::default() }),
+            }],
+            tool_choice: Some(ProtoToolChoice::ToolChoiceFunction(proto::ToolChoice {
+                r#type: "function".to_string(),
+                function: Some(ProtoFunction { name: "weather".to_string(), ..Default::default() }),
+            })),
+            ..Default::default()
+        }).unwrap();
+
+        assert!(request.messages[0].content.has_images());
+        assert_eq!(request.messages[1].tool_calls.as_ref().unwrap()[0].function.arguments, r#"{"city":"Oslo"}"#);
+        assert_eq!(request.tools.as_ref().unwrap()[0].function.parameters, serde_json::Value::Object(schema));
+        assert_eq!(
+            serde_json::to_value(&request.tool_choice).unwrap(),
+            serde_json::json!({"type": "function", "function": {"name": "weather"}})
+        );
+    }
+
+    #[test]
+    fn test_response_tool_call_carries_text_and_structured_arguments() {
+        let response_message = types::ChatMessage {
+            role: "assistant".to_string(),
+            content: types::MessageContent::Text(String::new()),
+            name: None,
+            tool_calls: Some(vec![types::ToolCall {
+                id: "call_1".to_string(),
+                tool_type: "function".to_string(),
+                function: types::ToolCallFunction { name: "weather".to_string(), arguments: r#"{"city":"Oslo","days":3}"#.to_string() },
+            }]),
+            tool_call_id: None,
+        };
+
+        let proto = convert_message_to_grpc(response_message);
+        let function = proto.tool_calls[0].function.clone().unwrap();
+        assert_eq!(function.arguments.as_deref(), Some(r#"{"city":"Oslo","days":3}"#));
+        assert_eq!(proto_struct_to_json(function.parameters), serde_json::json!({"city": "Oslo", "days": 3}));
+
+        // And back again, through the structured form alone
+        let mut echoed = proto.clone();
+        echoed.tool_calls[0].function.as_mut().unwrap().arguments = None;
+        let arguments = convert_grpc_to_message(echoed).unwrap().tool_calls.unwrap()[0].function.arguments.clone();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&arguments).unwrap(), serde_json::json!({"city": "Oslo", "days": 3}));
+    }
+
+    #[test]
+    fn test_chunk_decoder_joins_split_lines_and_tool_call_deltas() {
+        let mut decoder = ChunkDecoder::default();
+        let first = r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"weather","arguments":""}}]},"finish_reason":null}]}"#;
+        let second = r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\""}}]},"finish_reason":null}]}"#;
+
+        let (head, rest) = first.split_at(40);
+        assert!(decoder.push(head).is_empty());
+        let chunks = decoder.push(&format!("{}\n\n{}", rest, second));
+        assert_eq!(chunks.len(), 1);
+        let chunks = [chunks, decoder.push("\n\ndata: [DONE]"), decoder.finish()].concat();
+        assert_eq!(chunks.len(), 2);
+
+        let proto = convert_chunk_to_grpc(chunks[1].clone());
+        let delta = &proto.choices[0].delta.as_ref().unwrap().tool_calls[0];
+        assert_eq!(delta.index, Some(0));
+        assert!(delta.id.is_empty());
+        assert_eq!(delta.function.as_ref().unwrap().arguments.as_deref(), Some(r#"{"city""#));
+    }
+
+    #[test]
+    fn test_interceptor_authenticates_and_context_carries_tenant_and_tags() {
+        let mut config = crate::config::Config::default();
+        config.auth.require_api_key = true;
+        config.auth.master_key = Some("sk-master".to_string());
+        let mut interceptor = auth::grpc_interceptor(Arc::new(AuthService::new(Arc::new(config))));
+
+        let status = interceptor.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut call = Request::new(());
+        call.metadata_mut().insert("authorization", "Bearer sk-master".parse().unwrap());
+        let call = interceptor.call(call).unwrap();
+        let auth_info = call.extensions().get::<auth::ApiKeyInfo>().cloned().unwrap();
+
+        let mut request = Request::new(ProtoChatCompletionRequest {
+            model: "auto".to_string(),
+            messages: vec![message("user", "hi")],
+            metadata: HashMap::from([("team".to_string(), "search".to_string())]),
+            ..Default::default()
+        });
+        request.extensions_mut().insert(auth_info);
+        request.metadata_mut().insert(stickiness::SESSION_HEADER, "s-42".parse().unwrap());
+
+        let (chat_request, context) = chat_call(request).unwrap();
+        assert_eq!(context.user_id.as_deref(), Some("admin"));
+        assert_eq!(context.tags.get("session_id").map(String::as_str), Some("s-42"));
+        assert_eq!(chat_request.tags.unwrap().get("team").map(String::as_str), Some("search"));
+    }
+
+    #[test]
+    fn test_omen_options_and_routing_metadata() {
+        let request = convert_grpc_to_chat_request(ProtoChatCompletionRequest {
+            model: "auto".to_string(),
+            messages: vec![message("user", "hi")],
+            omen: Some(ProtoOmenOptions {
+                strategy: Some("speculate_k".to_string()),
+                k: Some(3),
+                budget_usd: Some(0.05),
+                stickiness: Some("session".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }).unwrap();
+        let omen = request.omen.unwrap();
+        assert_eq!((omen.strategy.as_deref(), omen.k, omen.budget_usd), (Some("speculate_k"), Some(3), Some(0.05)));
+        assert_eq!(omen.stickiness.as_deref(), Some("session"));
+        assert!(omen.providers.is_none() && omen.json_schema.is_none() && omen.explain.is_none());
+
+        let routing = types::RoutingMetadata {
+            provider: Some("anthropic".to_string()),
+            strategy: "single".to_string(),
+            cache: "miss".to_string(),
+            reasoning: vec!["cheapest healthy provider".to_string()],
+            ..Default::default()
+        };
+        assert!(convert_routing_to_grpc(routing.clone(), false).reasoning.is_empty());
+        let explained = convert_routing_to_grpc(routing.clone(), true);
+        assert_eq!((explained.provider.as_deref(), explained.reasoning.len()), (Some("anthropic"), 1));
+
+        let mut metadata = tonic::metadata::MetadataMap::new();
+        insert_routing_metadata(&mut metadata, &routing);
+        assert_eq!(metadata.get("x-omen-provider").unwrap(), "anthropic");
+        assert!(metadata.get("x-omen-model").is_none());
+    }
+}
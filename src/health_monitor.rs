@@ -0,0 +1,385 @@
+//! Background provider health monitoring
+//!
+//! Providers are probed on a fixed interval (plus jitter) by a single
+//! background task instead of on every `/health` or `/ready` request. The
+//! latest result and a short history are kept in memory per provider and
+//! mirrored to Redis when the cache is enabled, so health endpoints only ever
+//! read a snapshot.
+
+use crate::{cache::RedisCache, config::HealthMonitorConfig, providers::{Provider, ProviderRegistry}};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckRecord {
+    pub checked_at: DateTime<Utc>,
+    pub healthy: bool,
+    pub latency_ms: u64,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderHealthSnapshot {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub healthy: bool,
+    pub last_checked: DateTime<Utc>,
+    pub latency_ms: u64,
+    pub models_count: usize,
+    #[serde(default)]
+    pub avg_cost_per_1k: Option<f64>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub last_error_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_healthy_at: Option<DateTime<Utc>>,
+    pub consecutive_failures: u32,
+    pub history: VecDeque<HealthCheckRecord>,
+}
+
+impl ProviderHealthSnapshot {
+    /// Fraction of checks in the history that succeeded
+    pub fn success_rate(&self) -> f64 {
+        if self.history.is_empty() {
+            return if self.healthy { 1.0 } else { 0.0 };
+        }
+        let healthy = self.history.iter().filter(|r| r.healthy).count();
+        healthy as f64 / self.history.len() as f64
+    }
+}
+
+#[derive(Debug)]
+pub struct HealthMonitor {
+    config: HealthMonitorConfig,
+    providers: Arc<ProviderRegistry>,
+    cache: Option<Arc<RedisCache>>,
+    snapshots: RwLock<HashMap<String, ProviderHealthSnapshot>>,
+}
+
+/// HealthMonitor implementation - all public methods are part of the health API
+#[allow(dead_code)]
+impl HealthMonitor {
+    pub fn new(
+        config: HealthMonitorConfig,
+        providers: Arc<ProviderRegistry>,
+        cache: Option<Arc<RedisCache>>,
+    ) -> Self {
+        Self {
+            config,
+            providers,
+            cache,
+            snapshots: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &HealthMonitorConfig {
+        &self.config
+    }
+
+    /// Spawn the background check loop. Returns `None` when monitoring is disabled.
+    pub fn start(self: &Arc<Self>) -> Option<JoinHandle<()>> {
+        if !self.config.enabled {
+            info!("🏥 Background health monitor disabled, probing providers on demand");
+            return None;
+        }
+
+        info!(
+            "🏥 Starting background health monitor (every {}s, up to {}s jitter)",
+            self.config.interval_seconds, self.config.jitter_seconds
+        );
+
+        let monitor = Arc::clone(self);
+        Some(tokio::spawn(async move {
+            loop {
+                monitor.check_all().await;
+                tokio::time::sleep(monitor.next_delay()).await;
+            }
+        }))
+    }
+
+    /// Probe every provider concurrently and record the results
+    pub async fn check_all(&self) {
+        let providers = self.providers.all();
+        let checks = providers.iter().map(|provider| self.check_provider(provider.clone()));
+        let results = join_all(checks).await;
+
+        let healthy = results.iter().filter(|s| s.healthy).count();
+        debug!("🏥 Health check round complete: {}/{} providers healthy", healthy, results.len());
+    }
+
+    /// Probe a single provider now and record the result
+    pub async fn check_provider(&self, provider: Arc<dyn Provider>) -> ProviderHealthSnapshot {
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        let start = Instant::now();
+
+        let (healthy, error) = match tokio::time::timeout(timeout, provider.health_check()).await {
+            Ok(Ok(true)) => (true, None),
+            Ok(Ok(false)) => (false, Some("health check reported unhealthy".to_string())),
+            Ok(Err(e)) => (false, Some(e.to_string())),
+            Err(_) => (false, Some(format!("health check timed out after {}s", timeout.as_secs()))),
+        };
+        let latency_ms = start.elapsed().as_millis() as u64;
+
+        // Model listing is only worth doing against a reachable provider
+        let models = if healthy {
+            match tokio::time::timeout(timeout, provider.list_models()).await {
+                Ok(Ok(models)) => Some(models),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let record = HealthCheckRecord {
+            checked_at: Utc::now(),
+            healthy,
+            latency_ms,
+            error: error.clone(),
+        };
+
+        let snapshot = {
+            let mut snapshots = self.snapshots.write().await;
+            let snapshot = snapshots
+                .entry(provider.id().to_string())
+                .or_insert_with(|| ProviderHealthSnapshot {
+                    provider_id: provider.id().to_string(),
+                    provider_name: provider.name().to_string(),
+                    healthy: true,
+                    last_checked: record.checked_at,
+                    latency_ms,
+                    models_count: 0,
+                    avg_cost_per_1k: None,
+                    last_error: None,
+                    last_error_at: None,
+                    last_healthy_at: None,
+                    consecutive_failures: 0,
+                    history: VecDeque::new(),
+                });
+
+            if healthy && !snapshot.healthy {
+                info!("🟢 Provider {} is healthy again", snapshot.provider_id);
+            } else if !healthy && snapshot.healthy {
+                warn!(
+                    "🔴 Provider {} failed health check: {}",
+                    snapshot.provider_id,
+                    error.as_deref().unwrap_or("unknown error")
+                );
+            }
+
+            snapshot.healthy = healthy;
+            snapshot.last_checked = record.checked_at;
+            snapshot.latency_ms = latency_ms;
+
+            if healthy {
+                snapshot.consecutive_failures = 0;
+                snapshot.last_healthy_at = Some(record.checked_at);
+            } else {
+                snapshot.consecutive_failures += 1;
+                snapshot.last_error = error.clone();
+                snapshot.last_error_at = Some(record.checked_at);
+            }
+
+            // Keep the previous model count if listing failed this round
+            if let Some(models) = models {
+                snapshot.models_count = models.len();
+                snapshot.avg_cost_per_1k = if models.is_empty() {
+                    None
+                } else {
+                    let total: f64 = models.iter().map(|m| m.pricing.input_per_1k).sum();
+                    Some(total / models.len() as f64)
+                };
+            }
+
+            snapshot.history.push_back(record);
+            while snapshot.history.len() > self.config.history_size.max(1) {
+                snapshot.history.pop_front();
+            }
+
+            snapshot.clone()
+        };
+
+        if let Some(ref cache) = self.cache
+            && let Err(e) = cache.cache_provider_health(provider.id(), healthy, latency_ms, error).await
+        {
+            debug!("Failed to cache health for {}: {}", provider.id(), e);
+        }
+
+        snapshot
+    }
+
+    /// Latest snapshot for one provider, falling back to the Redis copy
+    /// written by another instance when this one has not checked it yet
+    pub async fn snapshot(&self, provider_id: &str) -> Option<ProviderHealthSnapshot> {
+        if let Some(snapshot) = self.snapshots.read().await.get(provider_id) {
+            return Some(snapshot.clone());
+        }
+
+        let provider = self.providers.get(provider_id)?;
+        let cached = self.cache.as_ref()?.get_cached_provider_health(provider_id).await.ok()??;
+
+        Some(ProviderHealthSnapshot {
+            provider_id: cached.provider_id,
+            provider_name: provider.name().to_string(),
+            healthy: cached.healthy,
+            last_checked: cached.last_checked,
+            latency_ms: cached.response_time_ms,
+            models_count: 0,
+            avg_cost_per_1k: None,
+            last_error_at: cached.error_message.as_ref().map(|_| cached.last_checked),
+            last_error: cached.error_message,
+            last_healthy_at: cached.healthy.then_some(cached.last_checked),
+            consecutive_failures: u32::from(!cached.healthy),
+            history: VecDeque::new(),
+        })
+    }
+
+    /// Snapshots for every registered provider, in registry order.
+    /// Providers that have never been checked are omitted.
+    pub async fn snapshots(&self) -> Vec<ProviderHealthSnapshot> {
+        let mut snapshots = Vec::new();
+        for provider in self.providers.all() {
+            if let Some(snapshot) = self.snapshot(provider.id()).await {
+                snapshots.push(snapshot);
+            }
+        }
+        snapshots
+    }
+
+    fn next_delay(&self) -> Duration {
+        let base = Duration::from_secs(self.config.interval_seconds.max(1));
+        if self.config.jitter_seconds == 0 {
+            return base;
+        }
+
+        // Spread instances out so replicas don't probe providers in lockstep
+        let jitter_ms = self.config.jitter_seconds * 1000;
+        let random = (uuid::Uuid::new_v4().as_u128() % (jitter_ms as u128 + 1)) as u64;
+        base + Duration::from_millis(random)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        error::{OmenError, Result},
+        types::{ChatCompletionRequest, ChatCompletionResponse, Model, ProviderType, RequestContext},
+    };
+    use async_trait::async_trait;
+    use futures::Stream;
+    use std::sync::Mutex;
+
+    /// Answers health checks from a script: healthy, failing, or never (None)
+    #[derive(Debug)]
+    struct ScriptedProvider {
+        checks: Mutex<VecDeque<Option<bool>>>,
+    }
+
+    impl ScriptedProvider {
+        fn scripted(checks: &[Option<bool>]) -> Arc<dyn Provider> {
+            Arc::new(Self { checks: Mutex::new(checks.iter().copied().collect()) })
+        }
+    }
+
+    #[async_trait]
+    impl Provider for ScriptedProvider {
+        fn id(&self) -> &str {
+            "ollama"
+        }
+
+        fn name(&self) -> &str {
+            "Scripted"
+        }
+
+        fn provider_type(&self) -> ProviderType {
+            ProviderType::Ollama
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            let check = self.checks.lock().unwrap().pop_front().flatten();
+            match check {
+                Some(true) => Ok(true),
+                Some(false) => Err(OmenError::ProviderUnavailable("connection refused".to_string())),
+                None => std::future::pending().await,
+            }
+        }
+
+        async fn list_models(&self) -> Result<Vec<Model>> {
+            Ok(Vec::new())
+        }
+
+        async fn chat_completion(&self, _request: &ChatCompletionRequest, _context: &RequestContext) -> Result<ChatCompletionResponse> {
+            Err(OmenError::ProviderUnavailable("scripted".to_string()))
+        }
+
+        async fn stream_chat_completion(
+            &self,
+            _request: &ChatCompletionRequest,
+            _context: &RequestContext,
+        ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
+            Err(OmenError::ProviderUnavailable("scripted".to_string()))
+        }
+    }
+
+    async fn monitor(history_size: usize) -> HealthMonitor {
+        let providers = Arc::new(ProviderRegistry::new(&Config::default()).await.unwrap());
+        let config = HealthMonitorConfig { history_size, timeout_seconds: 1, ..Default::default() };
+        HealthMonitor::new(config, providers, None)
+    }
+
+    #[tokio::test]
+    async fn test_history_is_capped() {
+        let monitor = monitor(3).await;
+        let provider = ScriptedProvider::scripted(&[Some(true); 5]);
+        for _ in 0..5 {
+            monitor.check_provider(provider.clone()).await;
+        }
+
+        let snapshot = monitor.snapshot("ollama").await.unwrap();
+        assert_eq!(snapshot.history.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failures_and_recovery() {
+        let monitor = monitor(10).await;
+        let provider = ScriptedProvider::scripted(&[Some(true), Some(false), Some(false), Some(true)]);
+
+        monitor.check_provider(provider.clone()).await;
+        monitor.check_provider(provider.clone()).await;
+        let snapshot = monitor.check_provider(provider.clone()).await;
+        assert!(!snapshot.healthy);
+        assert_eq!(snapshot.consecutive_failures, 2);
+        assert!(snapshot.last_error.as_deref().unwrap().contains("connection refused"));
+        assert!(snapshot.last_error_at.is_some());
+
+        // Recovery resets the failure count but keeps the last error for context
+        let snapshot = monitor.check_provider(provider).await;
+        assert!(snapshot.healthy);
+        assert_eq!(snapshot.consecutive_failures, 0);
+        assert_eq!(snapshot.last_healthy_at, Some(snapshot.last_checked));
+        assert!(snapshot.last_error.is_some());
+        assert!((snapshot.success_rate() - 0.5).abs() < 1e-9);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timed_out_probe_is_unhealthy() {
+        let monitor = monitor(10).await;
+        let snapshot = monitor.check_provider(ScriptedProvider::scripted(&[None])).await;
+
+        assert!(!snapshot.healthy);
+        assert_eq!(snapshot.consecutive_failures, 1);
+        assert!(snapshot.last_error.as_deref().unwrap().contains("timed out after 1s"));
+        assert_eq!(snapshot.success_rate(), 0.0);
+    }
+}
@@ -0,0 +1,201 @@
+//! Hedged requests for non-streaming completions
+//!
+//! When `[hedging]` is enabled and a request has a runner-up deployment, the
+//! primary gets a head start of its learned p95 latency (or a fixed
+//! `delay_ms`). If it hasn't answered by then, the same request goes to the
+//! runner-up and the first success wins; the slower call is dropped, which
+//! cancels it. A credit budget caps hedges to `max_hedge_percent` of eligible
+//! requests so a slow provider can't double the bill.
+
+use crate::{
+    config::HedgingConfig,
+    error::{OmenError, Result},
+};
+use std::{future::Future, sync::Mutex, time::Duration};
+
+/// Hedge delay selection and the budget of extra requests
+#[derive(Debug)]
+pub struct HedgePolicy {
+    config: HedgingConfig,
+    /// Hedges currently affordable; earned per request, spent per hedge
+    credits: Mutex<f64>,
+}
+
+impl HedgePolicy {
+    pub fn new(config: HedgingConfig) -> Self {
+        let credits = Mutex::new(config.burst.max(0.0));
+        Self { config, credits }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Head start given to the primary, from the fixed delay or its learned p95
+    pub fn delay(&self, learned_p95_ms: Option<f64>) -> Duration {
+        let ms = match (self.config.delay_ms, learned_p95_ms) {
+            (Some(fixed), _) => fixed,
+            (None, Some(p95)) => (p95.max(0.0) as u64).max(self.config.min_delay_ms),
+            (None, None) => self.config.default_delay_ms,
+        };
+        Duration::from_millis(ms)
+    }
+
+    /// Earn hedge credit for an eligible request
+    pub fn on_request(&self) {
+        let earned = self.config.max_hedge_percent.max(0.0) / 100.0;
+        let mut credits = self.credits.lock().unwrap();
+        *credits = (*credits + earned).min(self.config.burst.max(1.0));
+    }
+
+    /// Spend a credit on a hedge, if one is available
+    pub fn try_hedge(&self) -> bool {
+        let mut credits = self.credits.lock().unwrap();
+        if *credits < 1.0 {
+            return false;
+        }
+        *credits -= 1.0;
+        true
+    }
+}
+
+/// How a hedged pair of calls ended. Index 0 is the primary, 1 the hedge.
+pub struct HedgeOutcome<T> {
+    /// The first call to succeed
+    pub winner: Option<(usize, T)>,
+    /// Calls that failed, in the order they finished
+    pub failures: Vec<(usize, OmenError)>,
+    /// Whether the hedge was sent
+    pub hedged: bool,
+    /// Calls dropped unfinished because the other one won
+    pub cancelled: Vec<usize>,
+}
+
+impl<T> HedgeOutcome<T> {
+    /// The winner, else the error of the last call to fail
+    pub fn into_result(mut self) -> Result<(usize, T)> {
+        match self.winner {
+            Some(winner) => Ok(winner),
+            None => Err(self.failures.pop().map(|(_, e)| e).unwrap_or_else(|| {
+                OmenError::ProviderUnavailable("Hedged request produced no result".to_string())
+            })),
+        }
+    }
+}
+
+/// Run `primary`, and if it is still running after `delay` start the call
+/// returned by `hedge` (which may decline by returning None). The first
+/// success wins and the other call is dropped.
+pub async fn race<T, P, H, F>(primary: P, delay: Duration, hedge: H) -> HedgeOutcome<T>
+where
+    P: Future<Output = Result<T>>,
+    H: FnOnce() -> Option<F>,
+    F: Future<Output = Result<T>>,
+{
+    let mut outcome = HedgeOutcome { winner: None, failures: Vec::new(), hedged: false, cancelled: Vec::new() };
+    tokio::pin!(primary);
+
+    tokio::select! {
+        result = &mut primary => {
+            match result {
+                Ok(value) => outcome.winner = Some((0, value)),
+                Err(e) => outcome.failures.push((0, e)),
+            }
+            return outcome;
+        }
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    let Some(backup) = hedge() else {
+        match primary.await {
+            Ok(value) => outcome.winner = Some((0, value)),
+            Err(e) => outcome.failures.push((0, e)),
+        }
+        return outcome;
+    };
+    outcome.hedged = true;
+    tokio::pin!(backup);
+
+    let (mut primary_done, mut backup_done) = (false, false);
+    while !(primary_done && backup_done) {
+        let (index, result) = tokio::select! {
+            result = &mut primary, if !primary_done => {
+                primary_done = true;
+                (0, result)
+            }
+            result = &mut backup, if !backup_done => {
+                backup_done = true;
+                (1, result)
+            }
+        };
+        match result {
+            Ok(value) => {
+                outcome.winner = Some((index, value));
+                let other_done = if index == 0 { backup_done } else { primary_done };
+                if !other_done {
+                    outcome.cancelled.push(1 - index);
+                }
+                return outcome;
+            }
+            Err(e) => outcome.failures.push((index, e)),
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(percent: f64, burst: f64) -> HedgingConfig {
+        HedgingConfig { enabled: true, max_hedge_percent: percent, burst, ..Default::default() }
+    }
+
+    async fn after(ms: u64, result: Result<u32>) -> Result<u32> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        result
+    }
+
+    #[test]
+    fn test_budget_limits_hedge_rate() {
+        let policy = HedgePolicy::new(config(25.0, 2.0));
+        assert!(policy.try_hedge());
+        assert!(policy.try_hedge());
+        assert!(!policy.try_hedge());
+
+        for _ in 0..3 {
+            policy.on_request();
+        }
+        assert!(!policy.try_hedge());
+        policy.on_request();
+        assert!(policy.try_hedge());
+    }
+
+    #[test]
+    fn test_delay_prefers_fixed_then_learned() {
+        let policy = HedgePolicy::new(config(10.0, 1.0));
+        assert_eq!(policy.delay(None), Duration::from_millis(2000));
+        assert_eq!(policy.delay(Some(900.0)), Duration::from_millis(900));
+        assert_eq!(policy.delay(Some(10.0)), Duration::from_millis(250));
+
+        let fixed = HedgePolicy::new(HedgingConfig { delay_ms: Some(500), ..config(10.0, 1.0) });
+        assert_eq!(fixed.delay(Some(900.0)), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_race_takes_first_success() {
+        let outcome = race(after(500, Ok(1)), Duration::from_millis(20), || Some(after(10, Ok(2)))).await;
+        assert!(outcome.hedged);
+        assert_eq!(outcome.cancelled, vec![0]);
+        assert_eq!(outcome.into_result().unwrap(), (1, 2));
+
+        let outcome = race(after(5, Ok(1)), Duration::from_millis(200), || Some(after(10, Ok(2)))).await;
+        assert!(!outcome.hedged);
+        assert_eq!(outcome.into_result().unwrap(), (0, 1));
+
+        let failed = Err(OmenError::ProviderUnavailable("down".to_string()));
+        let outcome = race(after(30, failed), Duration::from_millis(10), || Some(after(60, Ok(2)))).await;
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.into_result().unwrap(), (1, 2));
+    }
+}
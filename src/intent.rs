@@ -0,0 +1,403 @@
+//! Intent classification
+//!
+//! Requests are labelled with an intent ("code", "tests", ...) that selects
+//! the latency target and scoring weights used to rank providers. Two
+//! classifiers are available:
+//!
+//! - `keyword`: substring matching on the last message (the default)
+//! - `embedding`: embeds the prompt with a local embedding model through
+//!   OMEN's own providers and picks the label whose example prompts are
+//!   closest, falling back to keywords when unsure or unavailable
+//!
+//! Labels, their keywords and example prompts come from `[intent.labels]`
+//! on top of the built-in set.
+
+use crate::{
+    config::{IntentConfig, IntentLabelConfig},
+    error::{OmenError, Result},
+    providers::{Provider, ProviderRegistry},
+    types::{ChatCompletionRequest, EmbeddingInput, EmbeddingsRequest},
+};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+
+/// Label for requests that match no other intent
+pub const DEFAULT_INTENT: &str = "general";
+
+/// Built-in labels with their keywords, checked in order
+const BUILTIN_KEYWORDS: &[(&str, &[&str])] = &[
+    ("code", &["code", "function", "implement"]),
+    ("tests", &["test", "unit test"]),
+    ("regex", &["regex", "pattern"]),
+    ("analysis", &["analyze", "review"]),
+    ("explanation", &["explain", "summarize"]),
+];
+
+/// Example prompts for the built-in labels, so the embedding classifier works unconfigured
+const BUILTIN_EXAMPLES: &[(&str, &[&str])] = &[
+    ("code", &[
+        "Write a function that parses a date string",
+        "Implement a thread-safe LRU cache in Rust",
+        "Fix the compile error in this code",
+    ]),
+    ("tests", &[
+        "Write unit tests for this function",
+        "Add integration tests covering the error paths",
+        "Why is this test failing?",
+    ]),
+    ("regex", &[
+        "Write a regex that matches email addresses",
+        "What pattern matches an IPv4 address?",
+    ]),
+    ("analysis", &[
+        "Review this pull request for bugs",
+        "Analyze the performance of this query",
+        "What are the security risks in this design?",
+    ]),
+    ("explanation", &[
+        "Explain how async/await works",
+        "Summarize this document in three bullet points",
+        "What does this error message mean?",
+    ]),
+    ("general", &[
+        "Hello, how are you?",
+        "Suggest a name for my project",
+        "What's a good book to read this weekend?",
+    ]),
+];
+
+/// Softmax temperature over cosine similarities; lower is more decisive
+const SIMILARITY_TEMPERATURE: f32 = 0.05;
+
+/// An intent label and how sure the classifier is of it
+#[derive(Debug, Clone, Serialize)]
+pub struct IntentClassification {
+    pub label: String,
+    /// 0.0-1.0
+    pub confidence: f32,
+    /// Classifier that produced the label
+    pub classifier: &'static str,
+}
+
+#[async_trait]
+pub trait IntentClassifier: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    async fn classify(&self, text: &str) -> IntentClassification;
+}
+
+/// Text the intent is classified from: the last message
+pub fn prompt_text(request: &ChatCompletionRequest) -> String {
+    request.messages.last().map(|m| m.content.text()).unwrap_or_default()
+}
+
+/// Classifier configured by `[intent]`, falling back to keywords when the
+/// embedding provider isn't configured
+pub fn build_classifier(config: &IntentConfig, providers: &ProviderRegistry) -> Arc<dyn IntentClassifier> {
+    let keywords = KeywordClassifier::new(&config.labels);
+
+    match config.classifier.as_str() {
+        "embedding" => match providers.get(&config.embedding_provider) {
+            Some(provider) => {
+                info!("🧭 Embedding intent classifier using {} ({})", config.embedding_provider, config.embedding_model);
+                Arc::new(EmbeddingClassifier::new(provider, config, keywords))
+            }
+            None => {
+                warn!(
+                    "Embedding provider {} not configured, classifying intents by keyword",
+                    config.embedding_provider
+                );
+                Arc::new(keywords)
+            }
+        },
+        "keyword" => Arc::new(keywords),
+        other => {
+            warn!("Unknown intent classifier '{}', classifying intents by keyword", other);
+            Arc::new(keywords)
+        }
+    }
+}
+
+/// Substring matching on the prompt; the first label with a matching keyword wins
+#[derive(Debug, Clone)]
+pub struct KeywordClassifier {
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl KeywordClassifier {
+    /// Built-in rules, with configured labels' keywords replacing or adding to them
+    pub fn new(labels: &HashMap<String, IntentLabelConfig>) -> Self {
+        let mut rules: Vec<(String, Vec<String>)> = BUILTIN_KEYWORDS
+            .iter()
+            .map(|(label, keywords)| (label.to_string(), keywords.iter().map(|k| k.to_string()).collect()))
+            .collect();
+
+        let mut configured: Vec<_> = labels.iter().filter(|(_, l)| !l.keywords.is_empty()).collect();
+        configured.sort_by_key(|(name, _)| name.as_str());
+        for (name, label) in configured {
+            let keywords = label.keywords.iter().map(|k| k.to_lowercase()).collect();
+            match rules.iter_mut().find(|(rule, _)| rule == name) {
+                Some(rule) => rule.1 = keywords,
+                None => rules.push((name.clone(), keywords)),
+            }
+        }
+
+        Self { rules }
+    }
+
+    /// Keyword matches are coarse, so confidence grows with the number of
+    /// matching keywords
+    pub fn classify_text(&self, text: &str) -> IntentClassification {
+        let content = text.to_lowercase();
+
+        for (label, keywords) in &self.rules {
+            let hits = keywords.iter().filter(|k| content.contains(k.as_str())).count();
+            if hits > 0 {
+                return IntentClassification {
+                    label: label.clone(),
+                    confidence: (0.4 + 0.2 * hits as f32).min(1.0),
+                    classifier: self.name(),
+                };
+            }
+        }
+
+        IntentClassification {
+            label: DEFAULT_INTENT.to_string(),
+            confidence: 0.3,
+            classifier: self.name(),
+        }
+    }
+}
+
+impl Default for KeywordClassifier {
+    fn default() -> Self {
+        Self::new(&HashMap::new())
+    }
+}
+
+#[async_trait]
+impl IntentClassifier for KeywordClassifier {
+    fn name(&self) -> &'static str {
+        "keyword"
+    }
+
+    async fn classify(&self, text: &str) -> IntentClassification {
+        self.classify_text(text)
+    }
+}
+
+/// Nearest-centroid classification over embeddings of each label's examples
+#[derive(Debug)]
+pub struct EmbeddingClassifier {
+    provider: Arc<dyn Provider>,
+    model: String,
+    timeout: Duration,
+    min_confidence: f32,
+    examples: Vec<(String, Vec<String>)>,
+    /// Normalized mean embedding per label, computed on first use
+    centroids: OnceCell<Vec<(String, Vec<f32>)>>,
+    fallback: KeywordClassifier,
+}
+
+impl EmbeddingClassifier {
+    pub fn new(provider: Arc<dyn Provider>, config: &IntentConfig, fallback: KeywordClassifier) -> Self {
+        let mut examples: Vec<(String, Vec<String>)> = BUILTIN_EXAMPLES
+            .iter()
+            .map(|(label, prompts)| (label.to_string(), prompts.iter().map(|p| p.to_string()).collect()))
+            .collect();
+
+        let mut configured: Vec<_> = config.labels.iter().filter(|(_, l)| !l.examples.is_empty()).collect();
+        configured.sort_by_key(|(name, _)| name.as_str());
+        for (name, label) in configured {
+            match examples.iter_mut().find(|(existing, _)| existing == name) {
+                Some(existing) => existing.1 = label.examples.clone(),
+                None => examples.push((name.clone(), label.examples.clone())),
+            }
+        }
+
+        Self {
+            provider,
+            model: config.embedding_model.clone(),
+            timeout: Duration::from_millis(config.embedding_timeout_ms),
+            min_confidence: config.min_confidence,
+            examples,
+            centroids: OnceCell::new(),
+            fallback,
+        }
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let expected = texts.len();
+        let response = self
+            .provider
+            .embeddings(&EmbeddingsRequest {
+                input: EmbeddingInput::Multiple(texts),
+                model: self.model.clone(),
+                encoding_format: None,
+                dimensions: None,
+            })
+            .await?;
+
+        let mut data = response.data;
+        if data.len() != expected {
+            return Err(OmenError::Provider(format!(
+                "Expected {} embeddings, got {}",
+                expected,
+                data.len()
+            )));
+        }
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    async fn centroids(&self) -> Result<&Vec<(String, Vec<f32>)>> {
+        self.centroids
+            .get_or_try_init(|| async {
+                let texts = self.examples.iter().flat_map(|(_, prompts)| prompts.clone()).collect();
+                let mut embeddings = self.embed(texts).await?.into_iter();
+
+                let centroids = self
+                    .examples
+                    .iter()
+                    .filter_map(|(label, prompts)| {
+                        let vectors: Vec<_> = embeddings.by_ref().take(prompts.len()).collect();
+                        centroid(&vectors).map(|c| (label.clone(), c))
+                    })
+                    .collect::<Vec<_>>();
+                debug!("🧭 Computed intent centroids for {} labels", centroids.len());
+                Ok(centroids)
+            })
+            .await
+    }
+
+    async fn classify_embedding(&self, text: &str) -> Result<Option<(String, f32)>> {
+        let centroids = self.centroids().await?;
+        let embedding = self.embed(vec![text.to_string()]).await?.pop().unwrap_or_default();
+        Ok(nearest(centroids, &embedding))
+    }
+}
+
+#[async_trait]
+impl IntentClassifier for EmbeddingClassifier {
+    fn name(&self) -> &'static str {
+        "embedding"
+    }
+
+    async fn classify(&self, text: &str) -> IntentClassification {
+        if text.trim().is_empty() {
+            return self.fallback.classify_text(text);
+        }
+
+        match tokio::time::timeout(self.timeout, self.classify_embedding(text)).await {
+            Ok(Ok(Some((label, confidence)))) if confidence >= self.min_confidence => IntentClassification {
+                label,
+                confidence,
+                classifier: self.name(),
+            },
+            Ok(Ok(low_confidence)) => {
+                debug!("Embedding intent too uncertain ({:?}), using keywords", low_confidence);
+                self.fallback.classify_text(text)
+            }
+            Ok(Err(e)) => {
+                debug!("Embedding intent classification failed ({}), using keywords", e);
+                self.fallback.classify_text(text)
+            }
+            Err(_) => {
+                debug!("Embedding intent classification timed out, using keywords");
+                self.fallback.classify_text(text)
+            }
+        }
+    }
+}
+
+/// Normalized mean of a label's example embeddings
+fn centroid(vectors: &[Vec<f32>]) -> Option<Vec<f32>> {
+    let dimensions = vectors.first()?.len();
+    let mut sum = vec![0.0; dimensions];
+    for vector in vectors.iter().filter(|v| v.len() == dimensions) {
+        for (total, value) in sum.iter_mut().zip(vector) {
+            *total += value;
+        }
+    }
+    normalize(sum)
+}
+
+fn normalize(vector: Vec<f32>) -> Option<Vec<f32>> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    (norm > 0.0).then(|| vector.into_iter().map(|v| v / norm).collect())
+}
+
+/// Closest label by cosine similarity, with a softmax confidence over all labels
+pub fn nearest(centroids: &[(String, Vec<f32>)], embedding: &[f32]) -> Option<(String, f32)> {
+    let embedding = normalize(embedding.to_vec())?;
+    let similarities: Vec<(&String, f32)> = centroids
+        .iter()
+        .filter(|(_, c)| c.len() == embedding.len())
+        .map(|(label, c)| (label, c.iter().zip(&embedding).map(|(a, b)| a * b).sum()))
+        .collect();
+
+    let (best_label, best) = similarities
+        .iter()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))?;
+    let total: f32 = similarities
+        .iter()
+        .map(|(_, s)| ((s - best) / SIMILARITY_TEMPERATURE).exp())
+        .sum();
+
+    Some(((*best_label).clone(), 1.0 / total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_builtin_labels() {
+        let classifier = KeywordClassifier::default();
+        assert_eq!(classifier.classify_text("Implement a parser").label, "code");
+        assert_eq!(classifier.classify_text("Write a unit test").label, "tests");
+        assert_eq!(classifier.classify_text("Explain monads").label, "explanation");
+
+        let general = classifier.classify_text("Hi there");
+        assert_eq!(general.label, DEFAULT_INTENT);
+        assert!(general.confidence < classifier.classify_text("Implement a function").confidence);
+    }
+
+    #[test]
+    fn test_configured_keywords_replace_and_extend() {
+        let mut labels = HashMap::new();
+        labels.insert("code".to_string(), IntentLabelConfig {
+            keywords: vec!["refactor".to_string()],
+            ..Default::default()
+        });
+        labels.insert("sql".to_string(), IntentLabelConfig {
+            keywords: vec!["SELECT".to_string()],
+            ..Default::default()
+        });
+
+        let classifier = KeywordClassifier::new(&labels);
+        assert_eq!(classifier.classify_text("refactor this module").label, "code");
+        assert_eq!(classifier.classify_text("implement it").label, DEFAULT_INTENT);
+        assert_eq!(classifier.classify_text("select * from users").label, "sql");
+    }
+
+    #[test]
+    fn test_nearest_centroid_confidence() {
+        let centroids = vec![
+            ("code".to_string(), centroid(&[vec![1.0, 0.0, 0.0], vec![0.9, 0.1, 0.0]]).unwrap()),
+            ("general".to_string(), centroid(&[vec![0.0, 1.0, 0.0]]).unwrap()),
+        ];
+
+        let (label, confidence) = nearest(&centroids, &[0.95, 0.05, 0.0]).unwrap();
+        assert_eq!(label, "code");
+        assert!(confidence > 0.99);
+
+        // Halfway between two labels is a coin toss
+        let (_, confidence) = nearest(&centroids, &[0.5, 0.5, 0.0]).unwrap();
+        assert!(confidence < 0.8);
+        assert!(nearest(&centroids, &[0.0, 0.0, 0.0]).is_none());
+    }
+}
@@ -0,0 +1,753 @@
+//! Learned provider metrics
+//!
+//! Every completion, stream and provider failure is recorded per provider and
+//! model as time-decayed statistics: time-to-first-token and total latency
+//! histograms (for p50/p95), output tokens per second, error rates by class
+//! and cost per request. Observations lose half their weight every
+//! `half_life_seconds`, so the numbers follow how a provider behaves now
+//! rather than since startup.
+//!
+//! Statistics are flushed to the SQLite `storage.db` in the background and
+//! reloaded on start, so routing doesn't forget what it learned on restart.
+
+use crate::{
+    budget::StreamBudget,
+    config::LearnedMetricsConfig,
+    error::{OmenError, Result},
+    storage,
+};
+use chrono::{DateTime, Utc};
+use futures::{stream::Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+
+/// Upper bounds (ms) of the latency histogram buckets; a final bucket holds the rest
+const LATENCY_BUCKETS_MS: [f64; 16] = [
+    50.0, 100.0, 200.0, 300.0, 500.0, 750.0, 1000.0, 1500.0, 2000.0, 3000.0, 5000.0, 8000.0, 13000.0,
+    20000.0, 30000.0, 60000.0,
+];
+
+/// Why a provider call failed, as far as routing cares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    Timeout,
+    RateLimit,
+    Unavailable,
+    Upstream,
+}
+
+impl ErrorClass {
+    /// Class of a provider failure; `None` for errors that aren't the provider's fault
+    pub fn of(error: &OmenError) -> Option<Self> {
+        match error {
+            OmenError::HttpClient(e) if e.is_timeout() => Some(ErrorClass::Timeout),
+            OmenError::ProviderUnavailable(msg) if msg.contains("timed out") => Some(ErrorClass::Timeout),
+            OmenError::RateLimitExceeded | OmenError::UpstreamRateLimited { .. } => Some(ErrorClass::RateLimit),
+            OmenError::ProviderUnavailable(_) | OmenError::HttpClient(_) => Some(ErrorClass::Unavailable),
+            OmenError::Provider(_) | OmenError::Upstream(_) | OmenError::Serialization(_) => Some(ErrorClass::Upstream),
+            _ => None,
+        }
+    }
+}
+
+/// One provider call as seen by the router
+#[derive(Debug, Clone, Default)]
+pub struct Observation {
+    /// Time to the first streamed chunk
+    pub ttft_ms: Option<u64>,
+    /// Time to the complete response; unset for streams cut short by the client or a race
+    pub latency_ms: Option<u64>,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+    pub cost_usd: f64,
+    pub error: Option<ErrorClass>,
+    /// The stream was dropped unfinished, by a disconnecting client or a lost race
+    pub cancelled: bool,
+}
+
+impl Observation {
+    pub fn completion(latency_ms: u64, output_tokens: u32, total_tokens: u32, cost_usd: f64) -> Self {
+        Self {
+            latency_ms: Some(latency_ms),
+            output_tokens,
+            total_tokens,
+            cost_usd,
+            ..Default::default()
+        }
+    }
+
+    pub fn cancelled(ttft_ms: Option<u64>) -> Self {
+        Self {
+            ttft_ms,
+            cancelled: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn failure(error: ErrorClass) -> Self {
+        Self {
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    /// Output tokens per second after the first token (or the whole call when not streamed)
+    fn tokens_per_second(&self) -> Option<f64> {
+        let latency_ms = self.latency_ms?;
+        let generating_ms = latency_ms.saturating_sub(self.ttft_ms.unwrap_or(0));
+        (self.output_tokens > 0 && generating_ms > 0)
+            .then(|| self.output_tokens as f64 * 1000.0 / generating_ms as f64)
+    }
+}
+
+/// Latency histogram whose counts decay with age
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecayedHistogram {
+    counts: Vec<f64>,
+}
+
+impl DecayedHistogram {
+    pub fn record(&mut self, value_ms: f64) {
+        if self.counts.is_empty() {
+            self.counts = vec![0.0; LATENCY_BUCKETS_MS.len() + 1];
+        }
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| value_ms <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[bucket] += 1.0;
+    }
+
+    fn decay(&mut self, factor: f64) {
+        self.counts.iter_mut().for_each(|count| *count *= factor);
+    }
+
+    fn merge(&mut self, other: &DecayedHistogram) {
+        if self.counts.is_empty() {
+            self.counts = other.counts.clone();
+            return;
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    /// Value below which fraction `p` of the weight falls, interpolated within a bucket
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let total: f64 = self.counts.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let target = total * p.clamp(0.0, 1.0);
+        let mut below = 0.0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            if count > 0.0 && below + count >= target {
+                let lower = if bucket == 0 { 0.0 } else { LATENCY_BUCKETS_MS[bucket - 1] };
+                let upper = LATENCY_BUCKETS_MS.get(bucket).copied().unwrap_or(lower * 2.0);
+                return Some(lower + (upper - lower) * ((target - below) / count));
+            }
+            below += count;
+        }
+        None
+    }
+}
+
+/// Time-decayed statistics for one provider and model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelStats {
+    pub updated_at: DateTime<Utc>,
+    /// Requests ever observed, without decay
+    pub total_requests: u64,
+    requests: f64,
+    errors: BTreeMap<ErrorClass, f64>,
+    ttft: DecayedHistogram,
+    latency: DecayedHistogram,
+    tokens_per_second_sum: f64,
+    tokens_per_second_weight: f64,
+    cost_sum: f64,
+    cost_requests: f64,
+    tokens_sum: f64,
+    #[serde(default)]
+    cancellations: f64,
+}
+
+impl ModelStats {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            updated_at: now,
+            total_requests: 0,
+            requests: 0.0,
+            errors: BTreeMap::new(),
+            ttft: DecayedHistogram::default(),
+            latency: DecayedHistogram::default(),
+            tokens_per_second_sum: 0.0,
+            tokens_per_second_weight: 0.0,
+            cost_sum: 0.0,
+            cost_requests: 0.0,
+            tokens_sum: 0.0,
+            cancellations: 0.0,
+        }
+    }
+
+    /// Age every statistic to `now`, halving weights once per half-life
+    fn decay_to(&mut self, now: DateTime<Utc>, half_life: Duration) {
+        let elapsed = (now - self.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        if elapsed > 0.0 && !half_life.is_zero() {
+            let factor = 0.5f64.powf(elapsed / half_life.as_secs_f64());
+            self.requests *= factor;
+            self.errors.values_mut().for_each(|count| *count *= factor);
+            self.ttft.decay(factor);
+            self.latency.decay(factor);
+            self.tokens_per_second_sum *= factor;
+            self.tokens_per_second_weight *= factor;
+            self.cost_sum *= factor;
+            self.cost_requests *= factor;
+            self.tokens_sum *= factor;
+            self.cancellations *= factor;
+        }
+        self.updated_at = self.updated_at.max(now);
+    }
+
+    pub fn record(&mut self, observation: &Observation, now: DateTime<Utc>, half_life: Duration) {
+        self.decay_to(now, half_life);
+        self.total_requests += 1;
+        self.requests += 1.0;
+
+        if let Some(class) = observation.error {
+            *self.errors.entry(class).or_insert(0.0) += 1.0;
+            return;
+        }
+
+        if let Some(ttft_ms) = observation.ttft_ms {
+            self.ttft.record(ttft_ms as f64);
+        }
+        if observation.cancelled {
+            self.cancellations += 1.0;
+            return;
+        }
+        if let Some(latency_ms) = observation.latency_ms {
+            self.latency.record(latency_ms as f64);
+            self.cost_sum += observation.cost_usd;
+            self.cost_requests += 1.0;
+            self.tokens_sum += observation.total_tokens as f64;
+        }
+        if let Some(tokens_per_second) = observation.tokens_per_second() {
+            self.tokens_per_second_sum += tokens_per_second;
+            self.tokens_per_second_weight += 1.0;
+        }
+    }
+
+    /// Fold another model's statistics into these (both aged to the same time)
+    fn merge(&mut self, other: &ModelStats) {
+        self.total_requests += other.total_requests;
+        self.requests += other.requests;
+        for (class, count) in &other.errors {
+            *self.errors.entry(*class).or_insert(0.0) += count;
+        }
+        self.ttft.merge(&other.ttft);
+        self.latency.merge(&other.latency);
+        self.tokens_per_second_sum += other.tokens_per_second_sum;
+        self.tokens_per_second_weight += other.tokens_per_second_weight;
+        self.cost_sum += other.cost_sum;
+        self.cost_requests += other.cost_requests;
+        self.tokens_sum += other.tokens_sum;
+        self.cancellations += other.cancellations;
+    }
+
+    pub fn summary(&self) -> MetricsSummary {
+        let ratio = |sum: f64, weight: f64| (weight > 0.0).then(|| sum / weight);
+        let errors: f64 = self.errors.values().sum();
+
+        MetricsSummary {
+            samples: self.requests,
+            total_requests: self.total_requests,
+            p50_ttft_ms: self.ttft.percentile(0.5),
+            p95_ttft_ms: self.ttft.percentile(0.95),
+            p50_latency_ms: self.latency.percentile(0.5),
+            p95_latency_ms: self.latency.percentile(0.95),
+            tokens_per_second: ratio(self.tokens_per_second_sum, self.tokens_per_second_weight),
+            error_rate: ratio(errors, self.requests).unwrap_or(0.0),
+            cancel_rate: ratio(self.cancellations, self.requests).unwrap_or(0.0),
+            error_rates: self
+                .errors
+                .iter()
+                .map(|(class, count)| (*class, ratio(*count, self.requests).unwrap_or(0.0)))
+                .collect(),
+            cost_per_request_usd: ratio(self.cost_sum, self.cost_requests),
+            cost_per_1k_tokens: ratio(self.cost_sum * 1000.0, self.tokens_sum),
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// Point-in-time view of learned statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSummary {
+    /// Decayed request weight behind these numbers
+    pub samples: f64,
+    pub total_requests: u64,
+    pub p50_ttft_ms: Option<f64>,
+    pub p95_ttft_ms: Option<f64>,
+    pub p50_latency_ms: Option<f64>,
+    pub p95_latency_ms: Option<f64>,
+    pub tokens_per_second: Option<f64>,
+    pub error_rate: f64,
+    pub error_rates: BTreeMap<ErrorClass, f64>,
+    /// Share of requests whose stream was dropped before finishing
+    #[serde(default)]
+    pub cancel_rate: f64,
+    pub cost_per_request_usd: Option<f64>,
+    pub cost_per_1k_tokens: Option<f64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl MetricsSummary {
+    /// Median responsiveness: time to first token when streamed, else full latency
+    pub fn p50_response_ms(&self) -> Option<f64> {
+        self.p50_ttft_ms.or(self.p50_latency_ms)
+    }
+
+    pub fn p95_response_ms(&self) -> Option<f64> {
+        self.p95_ttft_ms.or(self.p95_latency_ms)
+    }
+}
+
+/// Learned statistics for one provider, overall and per model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderLearnedMetrics {
+    pub overall: MetricsSummary,
+    pub models: BTreeMap<String, MetricsSummary>,
+}
+
+type StatsKey = (String, String);
+
+#[derive(Debug)]
+pub struct LearnedMetricsStore {
+    config: LearnedMetricsConfig,
+    stats: RwLock<HashMap<StatsKey, ModelStats>>,
+    dirty: Mutex<HashSet<StatsKey>>,
+    pool: Option<SqlitePool>,
+}
+
+/// LearnedMetricsStore implementation - all public methods are part of the metrics API
+#[allow(dead_code)]
+impl LearnedMetricsStore {
+    /// Store that keeps statistics in memory only
+    pub fn new(config: LearnedMetricsConfig) -> Self {
+        Self {
+            config,
+            stats: RwLock::new(HashMap::new()),
+            dirty: Mutex::new(HashSet::new()),
+            pool: None,
+        }
+    }
+
+    /// Store persisted in the SQLite database at `db_url`, loading what was
+    /// learned before. Falls back to memory when the database is unavailable.
+    pub async fn open(config: LearnedMetricsConfig, db_url: &str) -> Self {
+        let mut store = Self::new(config);
+        if !store.config.enabled || !store.config.persist {
+            return store;
+        }
+
+        match Self::connect(db_url).await {
+            Ok(pool) => {
+                store.pool = Some(pool);
+                match store.load().await {
+                    Ok(count) => info!("📈 Loaded learned metrics for {} provider models", count),
+                    Err(e) => warn!("Failed to load learned metrics: {}", e),
+                }
+            }
+            Err(e) => warn!("Learned metrics database unavailable ({}), keeping metrics in memory", e),
+        }
+        store
+    }
+
+    async fn connect(db_url: &str) -> Result<SqlitePool> {
+        let pool = storage::open_sqlite(db_url).await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS provider_metrics (
+                provider_id TEXT NOT NULL,
+                model TEXT NOT NULL,
+                stats TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY (provider_id, model)
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| OmenError::Database(format!("Failed to create provider_metrics table: {}", e)))?;
+
+        Ok(pool)
+    }
+
+    async fn load(&self) -> Result<usize> {
+        let Some(ref pool) = self.pool else {
+            return Ok(0);
+        };
+
+        let rows = sqlx::query("SELECT provider_id, model, stats FROM provider_metrics")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| OmenError::Database(format!("Failed to load provider metrics: {}", e)))?;
+
+        let mut stats = self.stats.write().await;
+        for row in rows {
+            let provider_id: String = row.get("provider_id");
+            let model: String = row.get("model");
+            match serde_json::from_str::<ModelStats>(row.get("stats")) {
+                Ok(loaded) => {
+                    stats.insert((provider_id, model), loaded);
+                }
+                Err(e) => debug!("Skipping unreadable metrics for {}/{}: {}", provider_id, model, e),
+            }
+        }
+        Ok(stats.len())
+    }
+
+    pub fn config(&self) -> &LearnedMetricsConfig {
+        &self.config
+    }
+
+    fn half_life(&self) -> Duration {
+        Duration::from_secs(self.config.half_life_seconds)
+    }
+
+    /// Spawn the background flush loop. Returns `None` when nothing is persisted.
+    pub fn start(self: &Arc<Self>) -> Option<JoinHandle<()>> {
+        self.pool.as_ref()?;
+
+        let store = Arc::clone(self);
+        let interval = Duration::from_secs(self.config.flush_interval_seconds.max(1));
+        Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = store.flush().await {
+                    warn!("Failed to persist learned metrics: {}", e);
+                }
+            }
+        }))
+    }
+
+    pub async fn record(&self, provider_id: &str, model: &str, observation: Observation) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let key = (provider_id.to_string(), model.to_string());
+        let now = Utc::now();
+        self.stats
+            .write()
+            .await
+            .entry(key.clone())
+            .or_insert_with(|| ModelStats::new(now))
+            .record(&observation, now, self.half_life());
+        self.dirty.lock().await.insert(key);
+    }
+
+    /// Write statistics changed since the last flush to SQLite
+    pub async fn flush(&self) -> Result<()> {
+        let Some(ref pool) = self.pool else {
+            return Ok(());
+        };
+
+        let keys: Vec<_> = self.dirty.lock().await.drain().collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let rows: Vec<_> = {
+            let stats = self.stats.read().await;
+            keys.iter()
+                .filter_map(|key| stats.get(key).map(|s| (key.clone(), s.clone())))
+                .collect()
+        };
+
+        for (index, ((provider_id, model), stats)) in rows.iter().enumerate() {
+            let saved = match serde_json::to_string(stats) {
+                Ok(json) => sqlx::query(
+                    r#"
+                    INSERT INTO provider_metrics (provider_id, model, stats, updated_at)
+                    VALUES (?, ?, ?, ?)
+                    ON CONFLICT(provider_id, model) DO UPDATE SET stats = excluded.stats, updated_at = excluded.updated_at
+                    "#,
+                )
+                .bind(provider_id)
+                .bind(model)
+                .bind(json)
+                .bind(stats.updated_at.to_rfc3339())
+                .execute(pool)
+                .await
+                .map_err(|e| OmenError::Database(format!("Failed to save provider metrics: {}", e))),
+                Err(e) => Err(e.into()),
+            };
+
+            // Unsaved rows stay dirty so the next flush retries them
+            if let Err(e) = saved {
+                self.dirty.lock().await.extend(rows[index..].iter().map(|(key, _)| key.clone()));
+                return Err(e);
+            }
+        }
+
+        debug!("📈 Persisted learned metrics for {} provider models", rows.len());
+        Ok(())
+    }
+
+    /// Statistics for one provider model, aged to now
+    pub async fn model_summary(&self, provider_id: &str, model: &str) -> Option<MetricsSummary> {
+        let stats = self.stats.read().await;
+        let mut model_stats = stats.get(&(provider_id.to_string(), model.to_string()))?.clone();
+        model_stats.decay_to(Utc::now(), self.half_life());
+        Some(model_stats.summary())
+    }
+
+    /// Statistics across every model of a provider, aged to now
+    pub async fn provider_metrics(&self, provider_id: &str) -> Option<ProviderLearnedMetrics> {
+        let now = Utc::now();
+        let stats = self.stats.read().await;
+
+        let mut overall: Option<ModelStats> = None;
+        let mut models = BTreeMap::new();
+        for ((provider, model), model_stats) in stats.iter() {
+            if provider != provider_id {
+                continue;
+            }
+            let mut model_stats = model_stats.clone();
+            model_stats.decay_to(now, self.half_life());
+            models.insert(model.clone(), model_stats.summary());
+            overall.get_or_insert_with(|| ModelStats::new(now)).merge(&model_stats);
+        }
+
+        Some(ProviderLearnedMetrics {
+            overall: overall?.summary(),
+            models,
+        })
+    }
+
+    /// The most specific summary with enough samples to trust: the model's,
+    /// then the provider's across models
+    pub async fn trusted_summary(&self, provider_id: &str, model: &str) -> Option<MetricsSummary> {
+        let min_samples = self.config.min_samples;
+        if let Some(summary) = self.model_summary(provider_id, model).await
+            && summary.samples >= min_samples
+        {
+            return Some(summary);
+        }
+
+        self.provider_metrics(provider_id)
+            .await
+            .map(|metrics| metrics.overall)
+            .filter(|summary| summary.samples >= min_samples)
+    }
+}
+
+/// Record TTFT, throughput and cost for a stream as it is consumed. The
+/// meter is a `StreamBudget` used only for its token and cost accounting.
+/// A stream dropped before it ends is recorded as cancelled.
+pub fn observe_stream(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    store: Arc<LearnedMetricsStore>,
+    provider_id: String,
+    model: String,
+    started: Instant,
+    meter: StreamBudget,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let guard = CancelGuard {
+        store: store.clone(),
+        provider_id: provider_id.clone(),
+        model: model.clone(),
+        ttft_ms: None,
+        finished: false,
+    };
+    let observed = futures::stream::unfold(
+        Some((stream, meter, guard)),
+        move |state| {
+            let (store, provider_id, model) = (store.clone(), provider_id.clone(), model.clone());
+            async move {
+                let (mut stream, mut meter, mut guard) = state?;
+                let item = stream.next().await;
+
+                match item {
+                    Some(Ok(chunk)) => {
+                        guard.ttft_ms.get_or_insert_with(|| started.elapsed().as_millis() as u64);
+                        meter.record_chunk(&chunk);
+                        Some((Ok(chunk), Some((stream, meter, guard))))
+                    }
+                    Some(Err(e)) => {
+                        guard.finished = true;
+                        if let Some(class) = ErrorClass::of(&e) {
+                            store.record(&provider_id, &model, Observation::failure(class)).await;
+                        }
+                        Some((Err(e), None))
+                    }
+                    None => {
+                        guard.finished = true;
+                        let input_tokens = meter.input_tokens();
+                        let mut observation = Observation::completion(
+                            started.elapsed().as_millis() as u64,
+                            meter.output_tokens(),
+                            input_tokens + meter.output_tokens(),
+                            meter.cost_usd(),
+                        );
+                        observation.ttft_ms = guard.ttft_ms;
+                        store.record(&provider_id, &model, observation).await;
+                        None
+                    }
+                }
+            }
+        },
+    );
+
+    Box::new(Box::pin(observed))
+}
+
+/// Records an observed stream as cancelled when it is dropped unfinished
+struct CancelGuard {
+    store: Arc<LearnedMetricsStore>,
+    provider_id: String,
+    model: String,
+    ttft_ms: Option<u64>,
+    finished: bool,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let (store, provider_id, model) = (self.store.clone(), self.provider_id.clone(), self.model.clone());
+        let observation = Observation::cancelled(self.ttft_ms);
+        runtime.spawn(async move { store.record(&provider_id, &model, observation).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LearnedMetricsConfig {
+        LearnedMetricsConfig {
+            half_life_seconds: 60,
+            ..LearnedMetricsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let mut histogram = DecayedHistogram::default();
+        for _ in 0..90 {
+            histogram.record(180.0);
+        }
+        for _ in 0..10 {
+            histogram.record(2500.0);
+        }
+
+        let p50 = histogram.percentile(0.5).unwrap();
+        let p95 = histogram.percentile(0.95).unwrap();
+        assert!((100.0..=200.0).contains(&p50));
+        assert!((2000.0..=3000.0).contains(&p95));
+        assert!(DecayedHistogram::default().percentile(0.5).is_none());
+    }
+
+    #[test]
+    fn test_old_observations_decay() {
+        let half_life = Duration::from_secs(60);
+        let start = Utc::now();
+        let mut stats = ModelStats::new(start);
+
+        for _ in 0..10 {
+            stats.record(&Observation::failure(ErrorClass::Timeout), start, half_life);
+        }
+        // Two half-lives later, ten successes outweigh the older failures
+        let later = start + chrono::Duration::seconds(120);
+        for _ in 0..10 {
+            stats.record(&Observation::completion(800, 100, 300, 0.002), later, half_life);
+        }
+
+        let summary = stats.summary();
+        assert!((summary.error_rate - 2.5 / 12.5).abs() < 1e-9);
+        assert!((summary.error_rates[&ErrorClass::Timeout] - summary.error_rate).abs() < 1e-9);
+        assert!((summary.cost_per_request_usd.unwrap() - 0.002).abs() < 1e-9);
+        assert!((summary.tokens_per_second.unwrap() - 125.0).abs() < 1e-9);
+        assert_eq!(summary.total_requests, 20);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_stream_counts_as_cancelled() {
+        let store = Arc::new(LearnedMetricsStore::new(config()));
+        let chunk = "data: {\"choices\":[{\"delta\":{\"content\":\"hello\"}}]}\n\n".to_string();
+        let upstream = futures::stream::iter(vec![Ok(chunk.clone()), Ok(chunk)]);
+        let meter = StreamBudget::new("gpt-4o", crate::budget::fallback_pricing(), 10, f64::INFINITY);
+        let mut stream = observe_stream(Box::new(upstream), store.clone(), "openai".into(), "gpt-4o".into(), Instant::now(), meter);
+
+        assert!(stream.next().await.unwrap().is_ok());
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let summary = store.model_summary("openai", "gpt-4o").await.unwrap();
+        assert_eq!(summary.total_requests, 1);
+        assert!((summary.cancel_rate - 1.0).abs() < 1e-9);
+        assert!(summary.p50_ttft_ms.is_some());
+        assert!(summary.p50_latency_ms.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_survive_restart() {
+        let path = std::env::temp_dir().join(format!("omen-metrics-{}.db", uuid::Uuid::new_v4()));
+        let db_url = format!("sqlite://{}", path.display());
+
+        let store = LearnedMetricsStore::open(config(), &db_url).await;
+        let mut observation = Observation::completion(1200, 50, 150, 0.001);
+        observation.ttft_ms = Some(300);
+        store.record("openai", "gpt-4o", observation).await;
+        store.record("openai", "gpt-4o", Observation::failure(ErrorClass::RateLimit)).await;
+        store.flush().await.unwrap();
+
+        let reopened = LearnedMetricsStore::open(config(), &db_url).await;
+        let metrics = reopened.provider_metrics("openai").await.unwrap();
+        assert_eq!(metrics.overall.total_requests, 2);
+        assert!(metrics.models["gpt-4o"].p50_ttft_ms.is_some());
+        assert!(metrics.overall.error_rates.contains_key(&ErrorClass::RateLimit));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_rows_dirty() {
+        let path = std::env::temp_dir().join(format!("omen-metrics-{}.db", uuid::Uuid::new_v4()));
+        let db_url = format!("sqlite://{}", path.display());
+
+        let store = LearnedMetricsStore::open(config(), &db_url).await;
+        store.record("openai", "gpt-4o", Observation::completion(900, 40, 120, 0.001)).await;
+
+        let pool = store.pool.clone().unwrap();
+        sqlx::query("ALTER TABLE provider_metrics RENAME TO provider_metrics_away").execute(&pool).await.unwrap();
+        assert!(store.flush().await.is_err());
+
+        sqlx::query("ALTER TABLE provider_metrics_away RENAME TO provider_metrics").execute(&pool).await.unwrap();
+        store.flush().await.unwrap();
+
+        let reopened = LearnedMetricsStore::open(config(), &db_url).await;
+        assert_eq!(reopened.model_summary("openai", "gpt-4o").await.unwrap().total_requests, 1);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -8,19 +8,39 @@
 
 pub mod auth;
 pub mod billing;
+pub mod bulkhead;
+pub mod budget;
 pub mod cache;
+pub mod circuit_breaker;
 pub mod config;
 pub mod context;  // NEW: Workspace and session management
+pub mod context_window;
 pub mod error;
+pub mod experiments;
+pub mod fair_share;
 pub mod ghost_ai;
 pub mod grpc;
+pub mod health_monitor;
+pub mod hedging;
+pub mod intent;
+pub mod learned_metrics;
+pub mod merge;
+pub mod model_catalog;
 pub mod multiplexer;
 pub mod providers;
 pub mod rate_limiter;
+pub mod realtime;
 pub mod router;
 pub mod routing;
+pub mod rules;
+pub mod shadow;
 pub mod server;
+pub mod stickiness;
+pub mod storage;
 pub mod types;
+pub mod upstream_quota;
+pub mod virtual_models;
+pub mod wait_queue;
 
 // Re-export commonly used types
 pub use config::Config;
@@ -5,19 +5,39 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod auth;
 mod billing;
+mod bulkhead;
+mod budget;
 mod cache;
+mod circuit_breaker;
 mod config;
 mod context;  // NEW: Workspace and session management
+mod context_window;
 mod error;
+mod experiments;
+mod fair_share;
 mod ghost_ai;
 mod grpc;
+mod health_monitor;
+mod hedging;
+mod intent;
+mod learned_metrics;
+mod merge;
+mod model_catalog;
 mod multiplexer;
 mod providers;
 mod rate_limiter;
+mod realtime;
 mod router;
 mod routing;
+mod rules;
+mod shadow;
 mod server;
+mod stickiness;
+mod storage;
 mod types;
+mod upstream_quota;
+mod virtual_models;
+mod wait_queue;
 
 use config::Config;
 use server::Server;
@@ -0,0 +1,588 @@
+//! Result selection for the parallel_merge strategy
+//!
+//! `parallel_merge` runs k providers to completion and hands the finished
+//! responses to a `ResultSelector`, which picks the one to return. Selectors
+//! are pluggable: longest valid answer, first JSON-schema-valid answer,
+//! majority vote over short answers, or an LLM judge.
+
+use crate::{
+    budget::{self, PricingCache},
+    error::{OmenError, Result},
+    providers::Provider,
+    types::*,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tracing::{debug, warn};
+
+/// A provider's finished response, in completion order
+#[derive(Debug, Clone)]
+pub struct MergeCandidate {
+    pub provider_id: String,
+    pub model: String,
+    pub response: ChatCompletionResponse,
+    pub latency_ms: u64,
+    pub cost_usd: f64,
+}
+
+impl MergeCandidate {
+    pub fn content(&self) -> String {
+        self.response
+            .choices
+            .first()
+            .map(|c| c.message.content.text())
+            .unwrap_or_default()
+    }
+
+    pub fn finish_reason(&self) -> Option<&str> {
+        self.response
+            .choices
+            .first()
+            .and_then(|c| c.finish_reason.as_deref())
+    }
+
+    /// Non-empty answer that wasn't cut off by the token limit
+    pub fn is_valid(&self) -> bool {
+        !self.content().trim().is_empty() && self.finish_reason() != Some("length")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub index: usize,
+    pub reason: String,
+    /// Cost incurred by the selector itself (e.g. the judge call)
+    pub cost_usd: f64,
+}
+
+pub struct SelectionContext<'a> {
+    pub request: &'a ChatCompletionRequest,
+    pub context: &'a RequestContext,
+    /// Budget left after the k candidate calls
+    pub budget_remaining_usd: f64,
+}
+
+#[async_trait]
+pub trait ResultSelector: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Pick the winning candidate. `candidates` is never empty.
+    async fn select(&self, ctx: &SelectionContext<'_>, candidates: &[MergeCandidate]) -> Result<Selection>;
+}
+
+/// Metadata about a candidate that did not win
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeLoser {
+    pub provider_id: String,
+    pub model: String,
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    pub cost_usd: f64,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeSummary {
+    pub selector: String,
+    pub reason: String,
+    pub winner_provider: String,
+    pub winner_model: String,
+    pub candidates_requested: usize,
+    /// Candidates dropped before dispatch because they would exceed the budget
+    pub skipped_for_budget: Vec<String>,
+    pub total_cost_usd: f64,
+    pub losers: Vec<MergeLoser>,
+}
+
+/// Longest non-empty answer that finished normally; falls back to the
+/// longest answer overall when none finished normally
+pub struct LongestValidSelector;
+
+#[async_trait]
+impl ResultSelector for LongestValidSelector {
+    fn name(&self) -> &'static str {
+        "longest_valid"
+    }
+
+    async fn select(&self, _ctx: &SelectionContext<'_>, candidates: &[MergeCandidate]) -> Result<Selection> {
+        Ok(longest(candidates))
+    }
+}
+
+fn longest(candidates: &[MergeCandidate]) -> Selection {
+    let pick = |valid_only: bool| {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !valid_only || c.is_valid())
+            .max_by_key(|(i, c)| (c.content().trim().len(), std::cmp::Reverse(*i)))
+            .map(|(i, _)| i)
+    };
+
+    match pick(true) {
+        Some(index) => Selection {
+            index,
+            reason: "longest valid answer".to_string(),
+            cost_usd: 0.0,
+        },
+        None => Selection {
+            index: pick(false).unwrap_or(0),
+            reason: "no valid answer, picked longest".to_string(),
+            cost_usd: 0.0,
+        },
+    }
+}
+
+/// First candidate (in completion order) whose answer is JSON matching the schema
+pub struct JsonSchemaSelector {
+    pub schema: Option<serde_json::Value>,
+}
+
+#[async_trait]
+impl ResultSelector for JsonSchemaSelector {
+    fn name(&self) -> &'static str {
+        "json_schema"
+    }
+
+    async fn select(&self, _ctx: &SelectionContext<'_>, candidates: &[MergeCandidate]) -> Result<Selection> {
+        for (index, candidate) in candidates.iter().enumerate() {
+            let Some(value) = parse_json_answer(&candidate.content()) else {
+                continue;
+            };
+            let valid = self.schema.as_ref().is_none_or(|schema| validate_schema(&value, schema));
+            if valid {
+                return Ok(Selection {
+                    index,
+                    reason: "first schema-valid answer".to_string(),
+                    cost_usd: 0.0,
+                });
+            }
+        }
+
+        let mut fallback = longest(candidates);
+        fallback.reason = format!("no schema-valid answer, {}", fallback.reason);
+        Ok(fallback)
+    }
+}
+
+/// Most common answer after normalisation; meant for short answers
+/// (classifications, numbers, yes/no). Ties go to the earliest answer.
+pub struct MajorityVoteSelector {
+    pub max_answer_chars: usize,
+}
+
+#[async_trait]
+impl ResultSelector for MajorityVoteSelector {
+    fn name(&self) -> &'static str {
+        "majority_vote"
+    }
+
+    async fn select(&self, _ctx: &SelectionContext<'_>, candidates: &[MergeCandidate]) -> Result<Selection> {
+        let mut votes: HashMap<String, (usize, usize)> = HashMap::new(); // answer -> (count, first index)
+        for (index, candidate) in candidates.iter().enumerate() {
+            if !candidate.is_valid() {
+                continue;
+            }
+            let content = candidate.content();
+            if content.trim().len() > self.max_answer_chars {
+                continue;
+            }
+            let entry = votes.entry(normalize_answer(&content)).or_insert((0, index));
+            entry.0 += 1;
+        }
+
+        let winner = votes
+            .values()
+            .max_by_key(|(count, first)| (*count, std::cmp::Reverse(*first)))
+            .copied();
+
+        match winner {
+            Some((count, index)) => Ok(Selection {
+                index,
+                reason: format!("{} of {} votes", count, candidates.len()),
+                cost_usd: 0.0,
+            }),
+            None => {
+                let mut fallback = longest(candidates);
+                fallback.reason = format!("no short answers to vote on, {}", fallback.reason);
+                Ok(fallback)
+            }
+        }
+    }
+}
+
+/// Asks a judge model from the provider registry to pick the best answer.
+/// Falls back to longest-valid when the judge is over budget or unusable.
+pub struct LlmJudgeSelector {
+    pub judge: Arc<dyn Provider>,
+    pub model: String,
+    /// The router's model pricing cache, for the judge's cost
+    pub pricing: Arc<PricingCache>,
+}
+
+#[async_trait]
+impl ResultSelector for LlmJudgeSelector {
+    fn name(&self) -> &'static str {
+        "llm_judge"
+    }
+
+    async fn select(&self, ctx: &SelectionContext<'_>, candidates: &[MergeCandidate]) -> Result<Selection> {
+        if candidates.len() == 1 {
+            return Ok(Selection {
+                index: 0,
+                reason: "only one candidate".to_string(),
+                cost_usd: 0.0,
+            });
+        }
+
+        let prompt = judge_prompt(ctx.request, candidates);
+        let judge_request = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: MessageContent::Text(
+                        "You compare candidate answers to a request and pick the best one. \
+                         Reply with the candidate number only."
+                            .to_string(),
+                    ),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: MessageContent::Text(prompt),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+            ],
+            temperature: Some(0.0),
+            max_tokens: Some(JUDGE_MAX_TOKENS),
+            stream: false,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            tools: None,
+            tool_choice: None,
+            tags: None,
+            omen: None,
+        };
+
+        let pricing = self.pricing.pricing(self.judge.as_ref(), &self.model).await;
+        let estimated_cost = cost_usd(&pricing, budget::count_request_tokens(&judge_request), JUDGE_MAX_TOKENS);
+        if estimated_cost > ctx.budget_remaining_usd {
+            let mut fallback = longest(candidates);
+            fallback.reason = format!("judge skipped (over budget), {}", fallback.reason);
+            return Ok(fallback);
+        }
+
+        match self.judge.chat_completion(&judge_request, ctx.context).await {
+            Ok(response) => {
+                let cost = cost_usd(&pricing, response.usage.prompt_tokens, response.usage.completion_tokens);
+                let verdict = response
+                    .choices
+                    .first()
+                    .map(|c| c.message.content.text())
+                    .unwrap_or_default();
+
+                match parse_judge_verdict(&verdict, candidates.len()) {
+                    Some(index) => Ok(Selection {
+                        index,
+                        reason: format!("judged best by {}/{}", self.judge.id(), self.model),
+                        cost_usd: cost,
+                    }),
+                    None => {
+                        debug!("Judge returned unusable verdict: {:?}", verdict);
+                        let mut fallback = longest(candidates);
+                        fallback.reason = format!("judge verdict unusable, {}", fallback.reason);
+                        fallback.cost_usd = cost;
+                        Ok(fallback)
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("⚖️ Judge {} failed: {}", self.judge.id(), e);
+                let mut fallback = longest(candidates);
+                fallback.reason = format!("judge failed, {}", fallback.reason);
+                Ok(fallback)
+            }
+        }
+    }
+}
+
+const JUDGE_MAX_TOKENS: u32 = 8;
+
+fn judge_prompt(request: &ChatCompletionRequest, candidates: &[MergeCandidate]) -> String {
+    let question = request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(|m| m.content.text())
+        .unwrap_or_default();
+
+    let mut prompt = format!("Request:\n{}\n", question);
+    for (i, candidate) in candidates.iter().enumerate() {
+        prompt.push_str(&format!("\nCandidate {}:\n{}\n", i + 1, candidate.content()));
+    }
+    prompt.push_str(&format!(
+        "\nWhich candidate (1-{}) best answers the request?",
+        candidates.len()
+    ));
+    prompt
+}
+
+/// First number in the judge's reply, converted to a zero-based index
+fn parse_judge_verdict(verdict: &str, candidates: usize) -> Option<usize> {
+    let digits: String = verdict
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let number: usize = digits.parse().ok()?;
+    (1..=candidates).contains(&number).then(|| number - 1)
+}
+
+fn normalize_answer(answer: &str) -> String {
+    answer
+        .trim()
+        .trim_end_matches(['.', '!', '?'])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Parse an answer as JSON, tolerating a surrounding ``` fence
+fn parse_json_answer(content: &str) -> Option<serde_json::Value> {
+    let trimmed = content.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(body.trim()).ok()
+}
+
+/// Validates the commonly used subset of JSON Schema: type, enum, required,
+/// properties, additionalProperties = false and items
+fn validate_schema(value: &serde_json::Value, schema: &serde_json::Value) -> bool {
+    use serde_json::Value;
+
+    let Some(schema) = schema.as_object() else {
+        return true;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let matches_type = |t: &str| match t {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        let ok = match expected {
+            Value::String(t) => matches_type(t),
+            Value::Array(types) => types.iter().filter_map(|t| t.as_str()).any(matches_type),
+            _ => true,
+        };
+        if !ok {
+            return false;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        return false;
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(Value::Array(required)) = schema.get("required")
+            && required.iter().filter_map(|r| r.as_str()).any(|r| !object.contains_key(r))
+        {
+            return false;
+        }
+
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        if let Some(properties) = properties {
+            for (key, property_schema) in properties {
+                if let Some(property) = object.get(key)
+                    && !validate_schema(property, property_schema)
+                {
+                    return false;
+                }
+            }
+        }
+
+        if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+            let known = |k: &String| properties.is_some_and(|p| p.contains_key(k));
+            if object.keys().any(|k| !known(k)) {
+                return false;
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array())
+        && !array.iter().all(|item| validate_schema(item, items))
+    {
+        return false;
+    }
+
+    true
+}
+
+pub fn cost_usd(pricing: &ModelPricing, input_tokens: u32, output_tokens: u32) -> f64 {
+    (input_tokens as f64 / 1000.0) * pricing.input_per_1k
+        + (output_tokens as f64 / 1000.0) * pricing.output_per_1k
+}
+
+/// Selectors that need no external resources, by name
+pub fn builtin_selector(
+    name: &str,
+    json_schema: Option<serde_json::Value>,
+    majority_max_chars: usize,
+) -> Result<Box<dyn ResultSelector>> {
+    match name {
+        "longest_valid" | "longest" => Ok(Box::new(LongestValidSelector)),
+        "json_schema" => Ok(Box::new(JsonSchemaSelector { schema: json_schema })),
+        "majority_vote" | "majority" => Ok(Box::new(MajorityVoteSelector {
+            max_answer_chars: majority_max_chars,
+        })),
+        other => Err(OmenError::InvalidRequest(format!("Unknown merge selector: {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(provider: &str, content: &str, finish_reason: &str) -> MergeCandidate {
+        MergeCandidate {
+            provider_id: provider.to_string(),
+            model: "test".to_string(),
+            response: ChatCompletionResponse {
+                id: "id".to_string(),
+                object: "chat.completion".to_string(),
+                created: 0,
+                model: "test".to_string(),
+                choices: vec![ChatChoice {
+                    index: 0,
+                    message: ChatMessage {
+                        role: "assistant".to_string(),
+                        content: MessageContent::Text(content.to_string()),
+                        name: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    },
+                    finish_reason: Some(finish_reason.to_string()),
+                }],
+                usage: Usage {
+                    prompt_tokens: 10,
+                    completion_tokens: 10,
+                    total_tokens: 20,
+                },
+                system_fingerprint: None,
+                omen: None,
+            },
+            latency_ms: 100,
+            cost_usd: 0.001,
+        }
+    }
+
+    fn ctx<'a>(request: &'a ChatCompletionRequest, context: &'a RequestContext) -> SelectionContext<'a> {
+        SelectionContext {
+            request,
+            context,
+            budget_remaining_usd: 1.0,
+        }
+    }
+
+    fn context() -> RequestContext {
+        RequestContext {
+            request_id: uuid::Uuid::new_v4(),
+            user_id: None,
+            api_key: None,
+            intent: None,
+            tags: HashMap::new(),
+        }
+    }
+
+    fn request() -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "auto",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_longest_valid_skips_truncated() {
+        let request = request();
+        let context = context();
+        let candidates = vec![
+            candidate("a", "short", "stop"),
+            candidate("b", "a much longer but truncated answer", "length"),
+            candidate("c", "a longer finished answer", "stop"),
+        ];
+
+        let selection = LongestValidSelector.select(&ctx(&request, &context), &candidates).await.unwrap();
+        assert_eq!(selection.index, 2);
+    }
+
+    #[tokio::test]
+    async fn test_json_schema_picks_first_valid() {
+        let request = request();
+        let context = context();
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["answer"],
+            "properties": {"answer": {"type": "integer"}}
+        });
+        let candidates = vec![
+            candidate("a", "not json", "stop"),
+            candidate("b", "{\"answer\": \"four\"}", "stop"),
+            candidate("c", "```json\n{\"answer\": 4}\n```", "stop"),
+        ];
+
+        let selector = JsonSchemaSelector { schema: Some(schema) };
+        let selection = selector.select(&ctx(&request, &context), &candidates).await.unwrap();
+        assert_eq!(selection.index, 2);
+    }
+
+    #[tokio::test]
+    async fn test_majority_vote() {
+        let request = request();
+        let context = context();
+        let candidates = vec![
+            candidate("a", "Paris", "stop"),
+            candidate("b", "Lyon", "stop"),
+            candidate("c", "paris.", "stop"),
+        ];
+
+        let selector = MajorityVoteSelector { max_answer_chars: 200 };
+        let selection = selector.select(&ctx(&request, &context), &candidates).await.unwrap();
+        assert_eq!(selection.index, 0);
+        assert_eq!(selection.reason, "2 of 3 votes");
+    }
+
+    #[test]
+    fn test_parse_judge_verdict() {
+        assert_eq!(parse_judge_verdict("2", 3), Some(1));
+        assert_eq!(parse_judge_verdict("Candidate 3 is best", 3), Some(2));
+        assert_eq!(parse_judge_verdict("4", 3), None);
+        assert_eq!(parse_judge_verdict("none", 3), None);
+    }
+}
@@ -0,0 +1,289 @@
+//! Canonical cross-provider model identities
+//!
+//! The same model is published under different ids by different hosts, e.g.
+//! `claude-sonnet-4-5-20250929` on Anthropic, `claude-sonnet-4-5@20250929` on
+//! Vertex AI and `anthropic.claude-sonnet-4-5-20250929-v1:0` on Bedrock. The
+//! catalog maps a canonical id (plus aliases and every provider-specific id)
+//! to the list of providers hosting it, so a request for any of those names
+//! can be served, and failed over, across all of them.
+
+use crate::{
+    config::{DeploymentOverrides, ModelCatalogConfig, ModelIdentityConfig, ProviderModelIdConfig},
+    virtual_models::Deployment,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderModelId {
+    pub provider: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelIdentity {
+    pub canonical: String,
+    pub aliases: Vec<String>,
+    /// Provider-specific ids, in failover order
+    pub providers: Vec<ProviderModelId>,
+}
+
+impl ModelIdentity {
+    fn from_config(canonical: &str, config: &ModelIdentityConfig) -> Self {
+        Self {
+            canonical: canonical.to_string(),
+            aliases: config.aliases.clone(),
+            providers: config
+                .providers
+                .iter()
+                .map(|p: &ProviderModelIdConfig| ProviderModelId {
+                    provider: p.provider.clone(),
+                    model: p.model.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Model id to send to a given provider, if it hosts this model
+    pub fn provider_model(&self, provider_id: &str) -> Option<&str> {
+        self.providers
+            .iter()
+            .find(|p| p.provider == provider_id)
+            .map(|p| p.model.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModelCatalog {
+    identities: HashMap<String, ModelIdentity>,
+    /// Every known name (canonical, alias, provider-specific id) -> canonical id
+    lookup: HashMap<String, String>,
+}
+
+/// ModelCatalog implementation - all public methods are part of the routing API
+#[allow(dead_code)]
+impl ModelCatalog {
+    pub fn new(config: &ModelCatalogConfig) -> Self {
+        let mut catalog = Self::default();
+
+        if config.builtin {
+            for identity in builtin_identities() {
+                catalog.insert(identity);
+            }
+        }
+
+        // Configured identities replace built-ins with the same canonical id
+        for (canonical, identity) in &config.models {
+            catalog.insert(ModelIdentity::from_config(canonical, identity));
+        }
+
+        catalog
+    }
+
+    fn insert(&mut self, identity: ModelIdentity) {
+        if let Some(previous) = self.identities.remove(&identity.canonical) {
+            self.lookup.retain(|_, canonical| *canonical != previous.canonical);
+        }
+
+        let canonical = identity.canonical.clone();
+        self.lookup.insert(canonical.clone(), canonical.clone());
+        for alias in &identity.aliases {
+            self.lookup.insert(alias.clone(), canonical.clone());
+        }
+        for provider_model in &identity.providers {
+            self.lookup.insert(provider_model.model.clone(), canonical.clone());
+        }
+
+        self.identities.insert(canonical, identity);
+    }
+
+    /// Identity for any known name of a model
+    pub fn identity(&self, model: &str) -> Option<&ModelIdentity> {
+        self.lookup.get(model).and_then(|c| self.identities.get(c))
+    }
+
+    pub fn canonical_id(&self, model: &str) -> Option<&str> {
+        self.identity(model).map(|i| i.canonical.as_str())
+    }
+
+    /// Translate any known name of a model to the id a provider expects.
+    /// Unknown models, or providers that don't host it, pass through unchanged.
+    pub fn provider_model_id<'a>(&'a self, provider_id: &str, model: &'a str) -> &'a str {
+        self.identity(model)
+            .and_then(|i| i.provider_model(provider_id))
+            .unwrap_or(model)
+    }
+
+    /// Every provider hosting the model, as deployments in failover order.
+    /// When the request names a provider-specific id, that provider goes first.
+    pub fn resolve(&self, model: &str) -> Option<Vec<Deployment>> {
+        let identity = self.identity(model)?;
+
+        let mut deployments: Vec<Deployment> = identity
+            .providers
+            .iter()
+            .map(|p| Deployment {
+                provider_id: p.provider.clone(),
+                model: p.model.clone(),
+                weight: 1,
+                overrides: DeploymentOverrides::default(),
+                messages: None,
+            })
+            .collect();
+
+        if let Some(index) = deployments.iter().position(|d| d.model == model) {
+            let native = deployments.remove(index);
+            deployments.insert(0, native);
+        }
+
+        Some(deployments)
+    }
+
+    pub fn all(&self) -> Vec<&ModelIdentity> {
+        let mut identities: Vec<_> = self.identities.values().collect();
+        identities.sort_by(|a, b| a.canonical.cmp(&b.canonical));
+        identities
+    }
+}
+
+fn identity(canonical: &str, aliases: &[&str], providers: &[(&str, &str)]) -> ModelIdentity {
+    ModelIdentity {
+        canonical: canonical.to_string(),
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        providers: providers
+            .iter()
+            .map(|(provider, model)| ProviderModelId {
+                provider: provider.to_string(),
+                model: model.to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// Claude models published on Anthropic, Vertex AI and Bedrock
+fn builtin_identities() -> Vec<ModelIdentity> {
+    vec![
+        identity(
+            "claude-sonnet-4-5",
+            &["claude-sonnet"],
+            &[
+                ("anthropic", "claude-sonnet-4-5-20250929"),
+                ("vertexai", "claude-sonnet-4-5@20250929"),
+                ("bedrock", "anthropic.claude-sonnet-4-5-20250929-v1:0"),
+            ],
+        ),
+        identity(
+            "claude-opus-4-1",
+            &["claude-opus"],
+            &[
+                ("anthropic", "claude-opus-4-1-20250805"),
+                ("vertexai", "claude-opus-4-1@20250805"),
+                ("bedrock", "anthropic.claude-opus-4-1-20250805-v1:0"),
+            ],
+        ),
+        identity(
+            "claude-sonnet-4",
+            &[],
+            &[
+                ("anthropic", "claude-sonnet-4-20250514"),
+                ("vertexai", "claude-sonnet-4@20250514"),
+                ("bedrock", "anthropic.claude-sonnet-4-20250514-v1:0"),
+            ],
+        ),
+        identity(
+            "claude-3-7-sonnet",
+            &[],
+            &[
+                ("anthropic", "claude-3-7-sonnet-20250219"),
+                ("vertexai", "claude-3-7-sonnet@20250219"),
+                ("bedrock", "anthropic.claude-3-7-sonnet-20250219-v1:0"),
+            ],
+        ),
+        identity(
+            "claude-3-5-sonnet",
+            &[],
+            &[
+                ("anthropic", "claude-3-5-sonnet-20241022"),
+                ("vertexai", "claude-3-5-sonnet@20241022"),
+                ("bedrock", "anthropic.claude-3-5-sonnet-20241022-v2:0"),
+            ],
+        ),
+        identity(
+            "claude-3-5-haiku",
+            &["claude-haiku"],
+            &[
+                ("anthropic", "claude-3-5-haiku-20241022"),
+                ("vertexai", "claude-3-5-haiku@20241022"),
+                ("bedrock", "anthropic.claude-3-5-haiku-20241022-v1:0"),
+            ],
+        ),
+        identity(
+            "claude-3-opus",
+            &[],
+            &[
+                ("anthropic", "claude-3-opus-20240229"),
+                ("vertexai", "claude-3-opus@20240229"),
+                ("bedrock", "anthropic.claude-3-opus-20240229-v1:0"),
+            ],
+        ),
+        identity(
+            "claude-3-haiku",
+            &[],
+            &[
+                ("anthropic", "claude-3-haiku-20240307"),
+                ("vertexai", "claude-3-haiku@20240307"),
+                ("bedrock", "anthropic.claude-3-haiku-20240307-v1:0"),
+            ],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolves_alias_to_all_hosts() {
+        let catalog = ModelCatalog::new(&ModelCatalogConfig::default());
+
+        let deployments = catalog.resolve("claude-sonnet").unwrap();
+        let providers: Vec<_> = deployments.iter().map(|d| d.provider_id.as_str()).collect();
+        assert_eq!(providers, vec!["anthropic", "vertexai", "bedrock"]);
+        assert_eq!(deployments[2].model, "anthropic.claude-sonnet-4-5-20250929-v1:0");
+    }
+
+    #[test]
+    fn test_provider_specific_id_goes_first() {
+        let catalog = ModelCatalog::new(&ModelCatalogConfig::default());
+
+        let deployments = catalog.resolve("claude-3-5-sonnet@20241022").unwrap();
+        assert_eq!(deployments[0].provider_id, "vertexai");
+        assert_eq!(deployments[1].model, "claude-3-5-sonnet-20241022");
+        assert_eq!(
+            catalog.provider_model_id("bedrock", "claude-3-5-sonnet-20241022"),
+            "anthropic.claude-3-5-sonnet-20241022-v2:0"
+        );
+        assert_eq!(catalog.provider_model_id("openai", "gpt-4o"), "gpt-4o");
+    }
+
+    #[test]
+    fn test_config_overrides_builtin() {
+        let mut config = ModelCatalogConfig::default();
+        config.models.insert(
+            "claude-sonnet-4-5".to_string(),
+            ModelIdentityConfig {
+                aliases: vec![],
+                providers: vec![ProviderModelIdConfig {
+                    provider: "bedrock".to_string(),
+                    model: "us.anthropic.claude-sonnet-4-5-20250929-v1:0".to_string(),
+                }],
+            },
+        );
+
+        let catalog = ModelCatalog::new(&config);
+        assert!(catalog.identity("claude-sonnet").is_none());
+        let deployments = catalog.resolve("claude-sonnet-4-5").unwrap();
+        assert_eq!(deployments.len(), 1);
+        assert_eq!(deployments[0].model, "us.anthropic.claude-sonnet-4-5-20250929-v1:0");
+    }
+}
@@ -1,10 +1,17 @@
 use crate::{
+    budget::{self, PricingCache, StreamBudget, StreamUsage, UsageCallback, UsageGuard},
+    bulkhead::{self, Admission},
+    circuit_breaker::CircuitBreakerRegistry,
     error::{OmenError, Result},
+    learned_metrics::{ErrorClass, LearnedMetricsStore, Observation},
+    merge::{self, LongestValidSelector, MergeCandidate, MergeLoser, MergeSummary, ResultSelector, SelectionContext},
     providers::Provider,
     types::*,
+    virtual_models::Deployment,
 };
 use futures::{stream::Stream, StreamExt};
-use std::{sync::Arc, time::Duration};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{
     select,
     sync::mpsc,
@@ -13,6 +20,30 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
+/// Called with the winning provider id and the model it served
+pub type WinnerCallback = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// Called with routing progress a streaming client may want to show
+pub type EventCallback = Arc<dyn Fn(MultiplexEvent) + Send + Sync>;
+
+/// Routing decisions made while a multiplexed stream is running
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MultiplexEvent {
+    /// A race or speculation settled on the provider it streams from
+    Selected {
+        provider: String,
+        model: Option<String>,
+        strategy: &'static str,
+    },
+    /// Speculation switched to a better provider mid-stream
+    Upgrade {
+        from_provider: String,
+        to_provider: String,
+        reason: String,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub enum MultiplexStrategy {
     Single,
@@ -27,6 +58,17 @@ impl Default for MultiplexStrategy {
     }
 }
 
+impl MultiplexStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MultiplexStrategy::Single => "single",
+            MultiplexStrategy::Race { .. } => "race",
+            MultiplexStrategy::SpeculateK { .. } => "speculate_k",
+            MultiplexStrategy::ParallelMerge { .. } => "parallel_merge",
+        }
+    }
+}
+
 impl From<&OmenConfig> for MultiplexStrategy {
     fn from(config: &OmenConfig) -> Self {
         match config.strategy.as_deref() {
@@ -51,15 +93,18 @@ impl From<&OmenConfig> for MultiplexStrategy {
 #[derive(Debug, Clone)]
 pub enum StreamEvent {
     Token {
+        candidate: usize,
         provider_id: String,
         chunk: String,
         latency_ms: u64,
     },
     Error {
+        candidate: usize,
         provider_id: String,
         error: String,
     },
     Done {
+        candidate: usize,
         provider_id: String,
         total_tokens: u32,
         cost_usd: f64,
@@ -71,13 +116,43 @@ pub enum StreamEvent {
     },
 }
 
+/// One provider call the multiplexer can make. Deployments that share a
+/// provider are separate candidates, told apart by their index.
+#[derive(Clone)]
+struct Candidate {
+    index: usize,
+    provider: Arc<dyn Provider>,
+    deployment: Option<Deployment>,
+}
+
+impl Candidate {
+    /// Request as this candidate is sent it, with its model id and overrides
+    fn request(&self, request: &ChatCompletionRequest) -> ChatCompletionRequest {
+        match self.deployment {
+            Some(ref deployment) => deployment.apply(request),
+            None => request.clone(),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct StreamMultiplexer {
-    providers: Vec<Arc<dyn Provider>>,
+    providers: Vec<Candidate>,
     budget_cap: f64,
     max_latency: Duration,
     min_useful_tokens: usize,
     cancellation_token: CancellationToken,
+    circuit_breakers: Option<Arc<CircuitBreakerRegistry>>,
+    selector: Arc<dyn ResultSelector>,
+    /// Per-call timeout for parallel_merge (only when the request sets max_latency_ms)
+    completion_timeout: Option<Duration>,
+    default_output_tokens: u32,
+    on_winner: Option<WinnerCallback>,
+    pricing: Arc<PricingCache>,
+    learned_metrics: Option<Arc<LearnedMetricsStore>>,
+    on_usage: Option<UsageCallback>,
+    admission: Option<Admission>,
+    on_event: Option<EventCallback>,
 }
 
 impl StreamMultiplexer {
@@ -85,13 +160,124 @@ impl StreamMultiplexer {
         providers: Vec<Arc<dyn Provider>>,
         config: &OmenConfig,
     ) -> Self {
+        let providers = providers
+            .into_iter()
+            .enumerate()
+            .map(|(index, provider)| Candidate { index, provider, deployment: None })
+            .collect();
         Self {
             providers,
-            budget_cap: config.budget_usd.unwrap_or(0.10),
+            budget_cap: config.budget_usd.unwrap_or(budget::DEFAULT_REQUEST_BUDGET_USD),
             max_latency: Duration::from_millis(config.max_latency_ms.unwrap_or(3000) as u64),
             min_useful_tokens: config.min_useful_tokens.unwrap_or(5) as usize,
             cancellation_token: CancellationToken::new(),
+            circuit_breakers: None,
+            selector: Arc::new(LongestValidSelector),
+            completion_timeout: config.max_latency_ms.map(|ms| Duration::from_millis(ms as u64)),
+            default_output_tokens: 1024,
+            on_winner: None,
+            pricing: Arc::new(PricingCache::new()),
+            learned_metrics: None,
+            on_usage: None,
+            admission: None,
+            on_event: None,
+        }
+    }
+
+    /// Share the router's model pricing cache for cost metering
+    pub fn with_pricing(mut self, pricing: Arc<PricingCache>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Record each candidate's latency, throughput, errors and cost
+    pub fn with_learned_metrics(mut self, learned_metrics: Arc<LearnedMetricsStore>) -> Self {
+        self.learned_metrics = Some(learned_metrics);
+        self
+    }
+
+    /// Meter for one provider's stream against the request budget
+    async fn stream_budget(&self, provider: &Arc<dyn Provider>, request: &ChatCompletionRequest) -> StreamBudget {
+        let pricing = self.pricing.pricing(provider.as_ref(), &request.model).await;
+        StreamBudget::new(&request.model, pricing, budget::count_request_tokens(request), self.budget_cap)
+    }
+
+    /// Report what each provider stream generated, including cancelled ones
+    pub fn with_usage_callback(mut self, on_usage: UsageCallback) -> Self {
+        self.on_usage = Some(on_usage);
+        self
+    }
+
+    /// Queue each provider call in its bulkhead with the request's priority
+    pub fn with_admission(mut self, admission: Admission) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// Report race winners and speculative upgrades while streaming
+    pub fn with_event_callback(mut self, on_event: EventCallback) -> Self {
+        self.on_event = Some(on_event);
+        self
+    }
+
+    /// Notify the caller which provider won a race, speculation or merge
+    pub fn with_winner_callback(mut self, on_winner: WinnerCallback) -> Self {
+        self.on_winner = Some(on_winner);
+        self
+    }
+
+    /// Provider and model of each candidate, for reporting the winner
+    fn candidate_models(candidates: &[Candidate], request: &ChatCompletionRequest) -> HashMap<usize, (String, String)> {
+        candidates
+            .iter()
+            .map(|c| (c.index, (c.provider.id().to_string(), c.request(request).model)))
+            .collect()
+    }
+
+    /// Result selector for the parallel_merge strategy
+    pub fn with_selector(mut self, selector: Arc<dyn ResultSelector>) -> Self {
+        self.selector = selector;
+        self
+    }
+
+    /// Output tokens assumed when budgeting requests without max_tokens
+    pub fn with_default_output_tokens(mut self, tokens: u32) -> Self {
+        self.default_output_tokens = tokens;
+        self
+    }
+
+    /// Skip providers with open circuits and report stream outcomes to the breakers
+    pub fn with_circuit_breakers(mut self, circuit_breakers: Arc<CircuitBreakerRegistry>) -> Self {
+        self.circuit_breakers = Some(circuit_breakers);
+        self
+    }
+
+    /// Run one candidate per deployment, each sent its own model id and
+    /// overrides (virtual model deployments); replaces the plain providers
+    pub fn with_deployments(mut self, deployments: Vec<(Arc<dyn Provider>, Deployment)>) -> Self {
+        self.providers = deployments
+            .into_iter()
+            .enumerate()
+            .map(|(index, (provider, deployment))| Candidate { index, provider, deployment: Some(deployment) })
+            .collect();
+        self
+    }
+
+    async fn routable_providers(&self, model: &str) -> Vec<Candidate> {
+        let Some(ref breakers) = self.circuit_breakers else {
+            return self.providers.clone();
+        };
+
+        let mut routable = Vec::new();
+        for candidate in &self.providers {
+            let provider_model = candidate.deployment.as_ref().map_or(model, |d| d.model.as_str());
+            if breakers.can_route(candidate.provider.id(), Self::breaker_model(provider_model)).await {
+                routable.push(candidate.clone());
+            } else {
+                debug!("⛔ Skipping provider {} ({}) with open circuit", candidate.provider.id(), provider_model);
+            }
         }
+        routable
     }
 
     pub async fn multiplex_stream(
@@ -100,26 +286,56 @@ impl StreamMultiplexer {
         context: RequestContext,
         strategy: MultiplexStrategy,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
+        let providers = self.routable_providers(&request.model).await;
+        if providers.is_empty() {
+            return Err(OmenError::ProviderUnavailable(
+                "All candidate providers have open circuits".to_string(),
+            ));
+        }
+
         match strategy {
-            MultiplexStrategy::Single => self.run_single(request, context).await,
-            MultiplexStrategy::Race { k } => self.run_race(request, context, k).await,
+            MultiplexStrategy::Single => self.run_single(&providers, request, context).await,
+            MultiplexStrategy::Race { k } => self.run_race(&providers, request, context, k).await,
             MultiplexStrategy::SpeculateK { k, delay_ms } => {
-                self.run_speculate_k(request, context, k, delay_ms).await
+                self.run_speculate_k(&providers, request, context, k, delay_ms).await
             }
             MultiplexStrategy::ParallelMerge { k } => {
-                self.run_parallel_merge(request, context, k).await
+                self.run_parallel_merge(&providers, request, context, k).await
             }
         }
     }
 
     async fn run_single(
         &self,
+        providers: &[Candidate],
         request: ChatCompletionRequest,
         context: RequestContext,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
-        if let Some(provider) = self.providers.first() {
+        if let Some(candidate) = providers.first() {
+            let provider = &candidate.provider;
             info!("🎯 Single strategy: using provider {}", provider.name());
-            provider.stream_chat_completion(&request, &context).await
+            let request = candidate.request(&request);
+            let permit = match self.admission {
+                Some(ref admission) => admission.acquire(provider.id(), &request.model).await?,
+                None => None,
+            };
+            let model = Self::breaker_model(&request.model);
+            if let Some(ref breakers) = self.circuit_breakers {
+                breakers.try_acquire(provider.id(), model).await?;
+            }
+            let result = provider.stream_chat_completion(&request, &context).await;
+            if let Some(ref breakers) = self.circuit_breakers {
+                breakers.record_result(provider.id(), model, result.as_ref().err()).await;
+            }
+            if let (Some(on_winner), true) = (&self.on_winner, result.is_ok()) {
+                on_winner(provider.id(), &request.model);
+            }
+            let stream_budget = self.stream_budget(provider, &request).await;
+            let usage = UsageGuard::new(stream_budget.clone(), provider.id(), self.on_usage.clone());
+            result.map(|stream| {
+                let stream = budget::meter_stream(budget::cap_stream(stream, stream_budget), usage);
+                bulkhead::hold_stream(stream, permit)
+            })
         } else {
             Err(OmenError::ProviderUnavailable("No providers available".to_string()))
         }
@@ -127,18 +343,20 @@ impl StreamMultiplexer {
 
     async fn run_race(
         &self,
+        providers: &[Candidate],
         request: ChatCompletionRequest,
         context: RequestContext,
         k: usize,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
-        info!("🏁 Race strategy: {} providers racing to first token", k.min(self.providers.len()));
+        info!("🏁 Race strategy: {} providers racing to first token", k.min(providers.len()));
 
-        let candidates = self.providers.iter().take(k).cloned().collect::<Vec<_>>();
+        let candidates = providers.iter().take(k).cloned().collect::<Vec<_>>();
         self.race_providers(request, context, candidates).await
     }
 
     async fn run_speculate_k(
         &self,
+        providers: &[Candidate],
         request: ChatCompletionRequest,
         context: RequestContext,
         k: usize,
@@ -147,21 +365,21 @@ impl StreamMultiplexer {
         info!("⚡ Speculative strategy: starting local immediately, cloud after {}ms", delay_ms);
 
         // Start local provider immediately
-        let local_providers = self.providers.iter()
-            .filter(|p| p.id() == "ollama")
+        let local_providers = providers.iter()
+            .filter(|c| c.provider.id() == "ollama")
             .take(1)
             .cloned()
             .collect::<Vec<_>>();
 
         if local_providers.is_empty() {
             // Fallback to race if no local provider
-            return self.run_race(request, context, k).await;
+            return self.run_race(providers, request, context, k).await;
         }
 
         // Start cloud providers with delay
-        let cloud_providers = self.providers.iter()
-            .filter(|p| p.id() != "ollama")
-            .take(k - 1)
+        let cloud_providers = providers.iter()
+            .filter(|c| c.provider.id() != "ollama")
+            .take(k.saturating_sub(1))
             .cloned()
             .collect::<Vec<_>>();
 
@@ -170,48 +388,320 @@ impl StreamMultiplexer {
 
     async fn run_parallel_merge(
         &self,
+        providers: &[Candidate],
         request: ChatCompletionRequest,
         context: RequestContext,
         k: usize,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
-        info!("🔀 Parallel merge strategy: {} providers with quality merging", k.min(self.providers.len()));
+        // Replayed from a finished response, so the calls are reported here
+        // rather than by metered provider streams
+        let response = self.merge_providers(providers, &request, &context, k, self.on_usage.as_ref()).await?;
+        Ok(Box::new(futures::stream::iter(Self::response_to_sse(&response).into_iter().map(Ok))))
+    }
+
+    /// Run the parallel_merge strategy to completion and return the selected response
+    pub async fn parallel_merge(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        k: usize,
+    ) -> Result<ChatCompletionResponse> {
+        let providers = self.routable_providers(&request.model).await;
+        if providers.is_empty() {
+            return Err(OmenError::ProviderUnavailable(
+                "All candidate providers have open circuits".to_string(),
+            ));
+        }
+        self.merge_providers(&providers, request, context, k, None).await
+    }
+
+    /// Run up to k candidates and select one. Each candidate's usage and the
+    /// selector's cost go to `on_usage` as soon as they are known.
+    async fn merge_providers(
+        &self,
+        providers: &[Candidate],
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        k: usize,
+        on_usage: Option<&UsageCallback>,
+    ) -> Result<ChatCompletionResponse> {
+        info!("🔀 Parallel merge strategy: {} providers with {} selection", k.min(providers.len()), self.selector.name());
+
+        // Reserve worst-case cost per candidate so all k calls together stay within budget
+        let input_tokens = budget::count_request_tokens(request);
+        let output_tokens = request.max_tokens.unwrap_or(self.default_output_tokens);
+        let mut planned = Vec::new();
+        let mut skipped_for_budget = Vec::new();
+        let mut reserved = 0.0;
+
+        for candidate in providers.iter().take(k) {
+            let provider = &candidate.provider;
+            let provider_request = candidate.request(request);
+            let pricing = self.pricing.pricing(provider.as_ref(), &provider_request.model).await;
+            let worst_case = merge::cost_usd(&pricing, input_tokens, output_tokens);
+
+            // The first candidate always runs; extra candidates must fit the budget
+            if !planned.is_empty() && reserved + worst_case > self.budget_cap {
+                debug!("💰 Skipping {} for parallel merge: ${:.4} would exceed budget", provider.id(), worst_case);
+                skipped_for_budget.push(provider.id().to_string());
+                continue;
+            }
+            reserved += worst_case;
+            planned.push((provider.clone(), provider_request, pricing));
+        }
+
+        let calls = planned.into_iter().map(|(provider, provider_request, pricing)| async move {
+            let breaker_model = Self::breaker_model(&provider_request.model).map(str::to_string);
+            let admitted = match self.admission {
+                Some(ref admission) => admission.acquire(provider.id(), &provider_request.model).await,
+                None => Ok(None),
+            };
+            let admitted = match (admitted, &self.circuit_breakers) {
+                (Ok(permit), Some(breakers)) => breakers.try_acquire(provider.id(), breaker_model.as_deref()).await.map(|_| permit),
+                (admitted, _) => admitted,
+            };
+            let _permit = match admitted {
+                Ok(permit) => permit,
+                Err(e) => {
+                    return Err(MergeLoser {
+                        provider_id: provider.id().to_string(),
+                        model: provider_request.model.clone(),
+                        latency_ms: None,
+                        cost_usd: 0.0,
+                        finish_reason: None,
+                        completion_tokens: None,
+                        error: Some(e.to_string()),
+                    });
+                }
+            };
+
+            let start = Instant::now();
+            let call = provider.chat_completion(&provider_request, context);
+            let result = match self.completion_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, call).await.unwrap_or_else(|_| {
+                    Err(OmenError::ProviderUnavailable(format!("timed out after {}ms", timeout.as_millis())))
+                }),
+                None => call.await,
+            };
+            let latency_ms = start.elapsed().as_millis() as u64;
+
+            if let Some(ref breakers) = self.circuit_breakers {
+                breakers.record_result(provider.id(), breaker_model.as_deref(), result.as_ref().err()).await;
+            }
+
+            let observation = match result {
+                Ok(ref response) => Some(Observation::completion(
+                    latency_ms,
+                    response.usage.completion_tokens,
+                    response.usage.total_tokens,
+                    merge::cost_usd(&pricing, response.usage.prompt_tokens, response.usage.completion_tokens),
+                )),
+                Err(ref e) => ErrorClass::of(e).map(Observation::failure),
+            };
+            if let Some(observation) = observation {
+                Self::record_observation(&self.learned_metrics, provider.id(), &provider_request.model, observation).await;
+            }
+
+            match result {
+                Ok(response) => Ok(MergeCandidate {
+                    provider_id: provider.id().to_string(),
+                    model: provider_request.model.clone(),
+                    cost_usd: merge::cost_usd(&pricing, response.usage.prompt_tokens, response.usage.completion_tokens),
+                    response,
+                    latency_ms,
+                }),
+                Err(e) => Err(MergeLoser {
+                    provider_id: provider.id().to_string(),
+                    model: provider_request.model.clone(),
+                    latency_ms: Some(latency_ms),
+                    cost_usd: 0.0,
+                    finish_reason: None,
+                    completion_tokens: None,
+                    error: Some(e.to_string()),
+                }),
+            }
+        });
+
+        let mut candidates = Vec::new();
+        let mut failed = Vec::new();
+        for result in futures::future::join_all(calls).await {
+            match result {
+                Ok(candidate) => candidates.push(candidate),
+                Err(loser) => {
+                    warn!("❌ Parallel merge candidate {} failed: {}", loser.provider_id, loser.error.as_deref().unwrap_or(""));
+                    failed.push(loser);
+                }
+            }
+        }
+
+        if let Some(on_usage) = on_usage {
+            for candidate in &candidates {
+                on_usage(StreamUsage {
+                    provider_id: candidate.provider_id.clone(),
+                    model: candidate.model.clone(),
+                    input_tokens: candidate.response.usage.prompt_tokens,
+                    output_tokens: candidate.response.usage.completion_tokens,
+                    cost_usd: candidate.cost_usd,
+                    cancelled: false,
+                });
+            }
+        }
 
-        // This is the most complex strategy - merge multiple streams
-        // For now, fallback to race (can be enhanced later)
-        self.run_race(request, context, k).await
+        if candidates.is_empty() {
+            let errors = failed.iter()
+                .map(|l| format!("{}: {}", l.provider_id, l.error.as_deref().unwrap_or("unknown error")))
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(OmenError::ProviderUnavailable(format!("All parallel merge candidates failed ({})", errors)));
+        }
+
+        // Selectors see candidates in completion order
+        candidates.sort_by_key(|c| c.latency_ms);
+        let candidates_cost: f64 = candidates.iter().map(|c| c.cost_usd).sum();
+
+        let selection_context = SelectionContext {
+            request,
+            context,
+            budget_remaining_usd: (self.budget_cap - candidates_cost).max(0.0),
+        };
+        let selection = self.selector.select(&selection_context, &candidates).await?;
+        let index = selection.index.min(candidates.len() - 1);
+        if let Some(on_usage) = on_usage
+            && selection.cost_usd > 0.0
+        {
+            on_usage(StreamUsage {
+                provider_id: self.selector.name().to_string(),
+                model: String::new(),
+                input_tokens: 0,
+                output_tokens: 0,
+                cost_usd: selection.cost_usd,
+                cancelled: false,
+            });
+        }
+        let total_cost_usd = candidates_cost + selection.cost_usd;
+
+        let winner = candidates.remove(index);
+        info!(
+            "🏆 Parallel merge picked {} ({}), total cost ${:.4}",
+            winner.provider_id, selection.reason, total_cost_usd
+        );
+
+        if let Some(ref on_winner) = self.on_winner {
+            on_winner(&winner.provider_id, &winner.model);
+        }
+
+        let mut losers: Vec<MergeLoser> = candidates.into_iter()
+            .map(|c| MergeLoser {
+                finish_reason: c.finish_reason().map(str::to_string),
+                completion_tokens: Some(c.response.usage.completion_tokens),
+                provider_id: c.provider_id,
+                model: c.model,
+                latency_ms: Some(c.latency_ms),
+                cost_usd: c.cost_usd,
+                error: None,
+            })
+            .collect();
+        losers.extend(failed);
+
+        let summary = MergeSummary {
+            selector: self.selector.name().to_string(),
+            reason: selection.reason,
+            winner_provider: winner.provider_id.clone(),
+            winner_model: winner.model.clone(),
+            candidates_requested: k,
+            skipped_for_budget,
+            total_cost_usd,
+            losers,
+        };
+
+        let mut response = winner.response;
+        response.omen.get_or_insert_with(OmenResponseMetadata::default).merge = Some(summary);
+        Ok(response)
+    }
+
+    /// Replay a finished response as an SSE chunk sequence
+    fn response_to_sse(response: &ChatCompletionResponse) -> Vec<String> {
+        let chunk = |choices: serde_json::Value, omen: Option<&OmenResponseMetadata>| {
+            let mut chunk = serde_json::json!({
+                "id": response.id,
+                "object": "chat.completion.chunk",
+                "created": response.created,
+                "model": response.model,
+                "choices": choices,
+            });
+            if let Some(omen) = omen {
+                chunk["omen"] = serde_json::to_value(omen).unwrap_or_default();
+            }
+            format!("data: {}\n\n", chunk)
+        };
+
+        let mut events = Vec::new();
+        for choice in &response.choices {
+            let delta = ChatMessageDelta {
+                role: Some(choice.message.role.clone()),
+                content: Some(choice.message.content.text()),
+                tool_calls: choice.message.tool_calls.as_ref().map(|calls| {
+                    calls.iter().enumerate().map(|(i, call)| ToolCallDelta::complete(i as u32, call)).collect()
+                }),
+            };
+            events.push(chunk(
+                serde_json::json!([{ "index": choice.index, "delta": delta, "finish_reason": null }]),
+                None,
+            ));
+        }
+
+        let finish = response.choices.iter()
+            .map(|c| serde_json::json!({ "index": c.index, "delta": {}, "finish_reason": c.finish_reason }))
+            .collect::<Vec<_>>();
+        events.push(chunk(serde_json::Value::Array(finish), response.omen.as_ref()));
+        events.push("data: [DONE]\n\n".to_string());
+        events
     }
 
     async fn race_providers(
         &self,
         request: ChatCompletionRequest,
         context: RequestContext,
-        candidates: Vec<Arc<dyn Provider>>,
+        candidates: Vec<Candidate>,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
         let (tx, mut rx) = mpsc::channel::<StreamEvent>(100);
-        let cancellation_token = CancellationToken::new();
-        let mut provider_handles = Vec::new();
+        // Cancelled as a whole when the client goes away; each candidate also
+        // has its own child so losers can be stopped without the winner
+        let cancellation_token = self.cancellation_token.child_token();
+        let mut candidate_tokens = HashMap::new();
+        let models = Self::candidate_models(&candidates, &request);
+        let on_winner = self.on_winner.clone();
+        let on_event = self.on_event.clone();
 
         // Start all providers concurrently
-        for provider in candidates {
-            let _provider_id = provider.id().to_string();
-            let req_clone = request.clone();
+        for candidate in candidates {
+            let req_clone = candidate.request(&request);
+            let stream_budget = self.stream_budget(&candidate.provider, &req_clone).await;
             let ctx_clone = context.clone();
             let tx_clone = tx.clone();
             let cancel_token = cancellation_token.child_token();
+            candidate_tokens.insert(candidate.index, cancel_token.clone());
             let start_time = Instant::now();
+            let breakers = self.circuit_breakers.clone();
+            let learned = self.learned_metrics.clone();
+            let on_usage = self.on_usage.clone();
+            let admission = self.admission.clone();
 
-            let handle = tokio::spawn(async move {
+            tokio::spawn(async move {
                 Self::stream_provider_with_events(
-                    provider,
+                    candidate,
                     req_clone,
                     ctx_clone,
                     tx_clone,
                     cancel_token,
                     start_time,
+                    breakers,
+                    stream_budget,
+                    learned,
+                    on_usage,
+                    admission,
                 ).await;
             });
-
-            provider_handles.push(handle);
         }
 
         // Create the multiplexed stream
@@ -222,12 +712,11 @@ impl StreamMultiplexer {
         let min_useful_tokens = self.min_useful_tokens;
 
         tokio::spawn(async move {
-            let mut winner: Option<String> = None;
+            let mut winner: Option<usize> = None;
             let mut total_cost = 0.0;
-            let mut token_count = 0;  // Reserved for future token tracking
             let race_start = Instant::now();
 
-            // Apply global timeout
+            // Time allowed to find a winner
             let timeout_future = tokio::time::sleep(max_latency);
             tokio::pin!(timeout_future);
 
@@ -235,28 +724,43 @@ impl StreamMultiplexer {
                 select! {
                     event = rx.recv() => {
                         match event {
-                            Some(StreamEvent::Token { provider_id, chunk, .. }) => {
+                            Some(StreamEvent::Token { candidate, provider_id, chunk, .. }) => {
                                 if Self::is_useful_token(&chunk, min_useful_tokens) && winner.is_none() {
-                                    winner = Some(provider_id.clone());
+                                    winner = Some(candidate);
                                     info!("🏆 Provider {} wins the race!", provider_id);
-                                    cancellation_clone.cancel();
+                                    candidate_tokens
+                                        .iter()
+                                        .filter(|(index, _)| **index != candidate)
+                                        .for_each(|(_, token)| token.cancel());
+                                    let model = models.get(&candidate).map(|(_, model)| model.clone());
+                                    if let (Some(on_winner), Some(model)) = (&on_winner, &model) {
+                                        on_winner(&provider_id, model);
+                                    }
+                                    if let Some(ref on_event) = on_event {
+                                        on_event(MultiplexEvent::Selected {
+                                            provider: provider_id.clone(),
+                                            model,
+                                            strategy: "race",
+                                        });
+                                    }
                                 }
 
-                                if winner.as_ref() == Some(&provider_id) {
-                                    token_count += 1;
-                                    let _ = stream_tx.send(Ok(chunk)).await;
+                                if winner == Some(candidate) && stream_tx.send(Ok(chunk)).await.is_err() {
+                                    info!("🔌 Client disconnected, cancelling race");
+                                    cancellation_clone.cancel();
+                                    break;
                                 }
                             }
-                            Some(StreamEvent::Done { provider_id, cost_usd, .. }) => {
-                                if winner.as_ref() == Some(&provider_id) {
+                            Some(StreamEvent::Done { candidate, cost_usd, .. }) => {
+                                if winner == Some(candidate) {
                                     total_cost += cost_usd;
                                     let elapsed = race_start.elapsed();
                                     info!("✅ Race completed in {}ms, cost: ${:.4}", elapsed.as_millis(), total_cost);
                                     break;
                                 }
                             }
-                            Some(StreamEvent::Error { provider_id, error }) => {
-                                if winner.as_ref() == Some(&provider_id) {
+                            Some(StreamEvent::Error { candidate, provider_id, error }) => {
+                                if winner == Some(candidate) {
                                     warn!("❌ Winning provider {} failed: {}", provider_id, error);
                                     let _ = stream_tx.send(Err(OmenError::Provider(error))).await;
                                     break;
@@ -273,7 +777,7 @@ impl StreamMultiplexer {
                             break;
                         }
                     }
-                    _ = &mut timeout_future => {
+                    _ = &mut timeout_future, if winner.is_none() => {
                         warn!("⏰ Race timeout ({}ms) exceeded", max_latency.as_millis());
                         cancellation_clone.cancel();
                         break;
@@ -282,64 +786,103 @@ impl StreamMultiplexer {
             }
         });
 
-        let stream = tokio_stream::wrappers::ReceiverStream::new(stream_rx);
-        Ok(Box::new(stream))
+        Ok(Self::cancel_on_drop(tokio_stream::wrappers::ReceiverStream::new(stream_rx), cancellation_token))
     }
 
     async fn speculate_with_delay(
         &self,
         request: ChatCompletionRequest,
         context: RequestContext,
-        local_providers: Vec<Arc<dyn Provider>>,
-        cloud_providers: Vec<Arc<dyn Provider>>,
+        local_providers: Vec<Candidate>,
+        cloud_providers: Vec<Candidate>,
         delay_ms: u64,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
         let (tx, mut rx) = mpsc::channel::<StreamEvent>(100);
-        let cancellation_token = CancellationToken::new();
+        let cancellation_token = self.cancellation_token.child_token();
+        let mut models = Self::candidate_models(&local_providers, &request);
+        models.extend(Self::candidate_models(&cloud_providers, &request));
+        let on_winner = self.on_winner.clone();
+        let on_event = self.on_event.clone();
+
+        // One token per candidate, so the stream given up on an upgrade can be stopped
+        let candidate_tokens: HashMap<usize, CancellationToken> = local_providers
+            .iter()
+            .chain(&cloud_providers)
+            .map(|c| (c.index, cancellation_token.child_token()))
+            .collect();
 
         // Start local provider immediately
-        for provider in local_providers {
-            let _provider_id = provider.id().to_string();
-            let req_clone = request.clone();
+        for candidate in local_providers {
+            let req_clone = candidate.request(&request);
+            let stream_budget = self.stream_budget(&candidate.provider, &req_clone).await;
             let ctx_clone = context.clone();
             let tx_clone = tx.clone();
-            let cancel_token = cancellation_token.child_token();
+            let cancel_token = candidate_tokens[&candidate.index].clone();
             let start_time = Instant::now();
+            let breakers = self.circuit_breakers.clone();
+            let learned = self.learned_metrics.clone();
+            let on_usage = self.on_usage.clone();
+            let admission = self.admission.clone();
 
             tokio::spawn(async move {
                 Self::stream_provider_with_events(
-                    provider,
+                    candidate,
                     req_clone,
                     ctx_clone,
                     tx_clone,
                     cancel_token,
                     start_time,
+                    breakers,
+                    stream_budget,
+                    learned,
+                    on_usage,
+                    admission,
                 ).await;
             });
         }
 
         // Start cloud providers with delay
         let cloud_tx = tx.clone();
-        let cloud_cancel = cancellation_token.child_token();
+        let cloud_cancel = cancellation_token.clone();
+        let cloud_breakers = self.circuit_breakers.clone();
+        let cloud_learned = self.learned_metrics.clone();
+        let cloud_usage = self.on_usage.clone();
+        let cloud_admission = self.admission.clone();
+        let mut cloud_targets = Vec::new();
+        for candidate in cloud_providers {
+            let req = candidate.request(&request);
+            let stream_budget = self.stream_budget(&candidate.provider, &req).await;
+            let cancel_token = candidate_tokens[&candidate.index].clone();
+            cloud_targets.push((candidate, req, stream_budget, cancel_token));
+        }
         tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            select! {
+                _ = tokio::time::sleep(Duration::from_millis(delay_ms)) => {}
+                _ = cloud_cancel.cancelled() => return,
+            }
 
-            for provider in cloud_providers {
-                let _provider_id = provider.id().to_string();
-                let req_clone = request.clone();
+            for (candidate, req_clone, stream_budget, cancel_token) in cloud_targets {
                 let ctx_clone = context.clone();
                 let tx_clone = cloud_tx.clone();
-                let cancel_token = cloud_cancel.child_token();
                 let start_time = Instant::now();
+                let breakers = cloud_breakers.clone();
+                let learned = cloud_learned.clone();
+                let on_usage = cloud_usage.clone();
+                let admission = cloud_admission.clone();
 
                 tokio::spawn(async move {
                     Self::stream_provider_with_events(
-                        provider,
+                        candidate,
                         req_clone,
                         ctx_clone,
                         tx_clone,
                         cancel_token,
                         start_time,
+                        breakers,
+                        stream_budget,
+                        learned,
+                        on_usage,
+                        admission,
                     ).await;
                 });
             }
@@ -350,36 +893,57 @@ impl StreamMultiplexer {
         let cancellation_clone = cancellation_token.clone();
 
         tokio::spawn(async move {
-            let mut current_provider: Option<String> = None;
+            let mut current: Option<usize> = None;
             let mut can_upgrade = true;
 
             while let Some(event) = rx.recv().await {
                 match event {
-                    StreamEvent::Token { provider_id, chunk, .. } => {
-                        if current_provider.is_none() {
-                            current_provider = Some(provider_id.clone());
+                    StreamEvent::Token { candidate, provider_id, chunk, .. } => {
+                        if current.is_none() {
+                            current = Some(candidate);
                             info!("🚀 Speculative start with provider {}", provider_id);
-                        } else if can_upgrade && provider_id != *current_provider.as_ref().unwrap() {
+                            if let Some(ref on_event) = on_event {
+                                on_event(MultiplexEvent::Selected {
+                                    provider: provider_id.clone(),
+                                    model: models.get(&candidate).map(|(_, model)| model.clone()),
+                                    strategy: "speculate_k",
+                                });
+                            }
+                        } else if can_upgrade && current != Some(candidate) {
                             // Check if this is a quality upgrade
                             if Self::should_upgrade(&chunk) {
-                                info!("⬆️ Upgrading from {} to {}", current_provider.as_ref().unwrap(), provider_id);
-                                current_provider = Some(provider_id.clone());
+                                let previous = current.replace(candidate).unwrap();
+                                let from_provider = models.get(&previous).map(|(id, _)| id.clone()).unwrap_or_default();
+                                info!("⬆️ Upgrading from {} to {}", from_provider, provider_id);
+                                candidate_tokens[&previous].cancel();
                                 can_upgrade = false; // Only upgrade once per request
+                                if let Some(ref on_event) = on_event {
+                                    on_event(MultiplexEvent::Upgrade {
+                                        from_provider,
+                                        to_provider: provider_id.clone(),
+                                        reason: "structured output (code or tool calls)".to_string(),
+                                    });
+                                }
                             }
                         }
 
-                        if current_provider.as_ref() == Some(&provider_id) {
-                            let _ = stream_tx.send(Ok(chunk)).await;
+                        if current == Some(candidate) && stream_tx.send(Ok(chunk)).await.is_err() {
+                            info!("🔌 Client disconnected, cancelling speculation");
+                            cancellation_clone.cancel();
+                            break;
                         }
                     }
-                    StreamEvent::Done { provider_id, .. } => {
-                        if current_provider.as_ref() == Some(&provider_id) {
+                    StreamEvent::Done { candidate, provider_id, .. } => {
+                        if current == Some(candidate) {
                             cancellation_clone.cancel();
+                            if let (Some(on_winner), Some((_, model))) = (&on_winner, models.get(&candidate)) {
+                                on_winner(&provider_id, model);
+                            }
                             break;
                         }
                     }
-                    StreamEvent::Error { provider_id, error } => {
-                        if current_provider.as_ref() == Some(&provider_id) {
+                    StreamEvent::Error { candidate, error, .. } => {
+                        if current == Some(candidate) {
                             let _ = stream_tx.send(Err(OmenError::Provider(error))).await;
                             break;
                         }
@@ -389,51 +953,152 @@ impl StreamMultiplexer {
             }
         });
 
-        let stream = tokio_stream::wrappers::ReceiverStream::new(stream_rx);
-        Ok(Box::new(stream))
+        Ok(Self::cancel_on_drop(tokio_stream::wrappers::ReceiverStream::new(stream_rx), cancellation_token))
     }
 
+    /// Cancel every provider behind a multiplexed stream once the client drops it
+    fn cancel_on_drop(
+        stream: tokio_stream::wrappers::ReceiverStream<Result<String>>,
+        token: CancellationToken,
+    ) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+        let guarded = futures::stream::unfold((stream, token.drop_guard()), |(mut stream, guard)| async move {
+            let item = stream.next().await?;
+            Some((item, (stream, guard)))
+        });
+        Box::new(Box::pin(guarded))
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn stream_provider_with_events(
-        provider: Arc<dyn Provider>,
+        candidate: Candidate,
         request: ChatCompletionRequest,
         context: RequestContext,
         tx: mpsc::Sender<StreamEvent>,
         cancel_token: CancellationToken,
         start_time: Instant,
+        circuit_breakers: Option<Arc<CircuitBreakerRegistry>>,
+        stream_budget: StreamBudget,
+        learned_metrics: Option<Arc<LearnedMetricsStore>>,
+        on_usage: Option<UsageCallback>,
+        admission: Option<Admission>,
     ) {
+        let Candidate { index, provider, .. } = candidate;
         let provider_id = provider.id().to_string();
+        let breaker_model = Self::breaker_model(&request.model);
+        let mut ttft_ms = None;
+        let completed = |ttft_ms: Option<u64>, stream_budget: &StreamBudget| Observation {
+            ttft_ms,
+            ..Observation::completion(
+                start_time.elapsed().as_millis() as u64,
+                stream_budget.output_tokens(),
+                stream_budget.input_tokens() + stream_budget.output_tokens(),
+                stream_budget.cost_usd(),
+            )
+        };
+
+        // Held until this provider's stream ends or is cancelled
+        let _permit = match admission {
+            Some(admission) => {
+                let admitted = select! {
+                    admitted = admission.acquire(&provider_id, &request.model) => admitted,
+                    _ = cancel_token.cancelled() => return,
+                };
+                match admitted {
+                    Ok(permit) => permit,
+                    Err(e) => {
+                        let _ = tx.send(StreamEvent::Error { candidate: index, provider_id, error: e.to_string() }).await;
+                        return;
+                    }
+                }
+            }
+            None => None,
+        };
+
+        if let Some(ref breakers) = circuit_breakers
+            && let Err(e) = breakers.try_acquire(&provider_id, breaker_model).await
+        {
+            let _ = tx.send(StreamEvent::Error { candidate: index, provider_id, error: e.to_string() }).await;
+            return;
+        }
 
         match provider.stream_chat_completion(&request, &context).await {
             Ok(mut stream) => {
-                let mut total_tokens = 0;
+                // Outcome is settled on the first chunk or on a terminal error
+                let mut outcome_recorded = false;
+                let mut usage = UsageGuard::new(stream_budget, &provider_id, on_usage);
 
                 loop {
                     select! {
                         chunk_result = stream.next() => {
                             match chunk_result {
-                                Some(Ok(chunk)) => {
-                                    total_tokens += 1;
+                                Some(Ok(mut chunk)) => {
                                     let latency = start_time.elapsed().as_millis() as u64;
 
+                                    ttft_ms.get_or_insert(latency);
+                                    if !outcome_recorded {
+                                        outcome_recorded = true;
+                                        if let Some(ref breakers) = circuit_breakers {
+                                            breakers.record_success(&provider_id, breaker_model).await;
+                                        }
+                                    }
+
+                                    // Close the stream ourselves once the budget is spent
+                                    let exhausted = usage.meter.record_chunk(&chunk);
+                                    if exhausted {
+                                        warn!("💰 Provider {} crossed budget at ${:.4}, cutting off", provider_id, usage.meter.cost_usd());
+                                        chunk.push_str(&usage.meter.finish_events());
+                                    }
+
                                     let _ = tx.send(StreamEvent::Token {
+                                        candidate: index,
                                         provider_id: provider_id.clone(),
                                         chunk,
                                         latency_ms: latency,
                                     }).await;
+
+                                    if exhausted {
+                                        usage.finish();
+                                        Self::record_observation(&learned_metrics, &provider_id, &request.model, completed(ttft_ms, &usage.meter)).await;
+                                        let _ = tx.send(StreamEvent::Done {
+                                            candidate: index,
+                                            provider_id: provider_id.clone(),
+                                            total_tokens: usage.meter.output_tokens(),
+                                            cost_usd: usage.meter.cost_usd(),
+                                        }).await;
+                                        break;
+                                    }
                                 }
                                 Some(Err(e)) => {
+                                    usage.finish();
+                                    if let Some(ref breakers) = circuit_breakers {
+                                        match CircuitBreakerRegistry::failure_scope(&e) {
+                                            Some(scope) => breakers.record_failure(&provider_id, breaker_model, scope).await,
+                                            None if !outcome_recorded => breakers.release(&provider_id, breaker_model).await,
+                                            None => {}
+                                        }
+                                    }
+                                    if let Some(class) = ErrorClass::of(&e) {
+                                        Self::record_observation(&learned_metrics, &provider_id, &request.model, Observation::failure(class)).await;
+                                    }
                                     let _ = tx.send(StreamEvent::Error {
+                                        candidate: index,
                                         provider_id: provider_id.clone(),
                                         error: e.to_string(),
                                     }).await;
                                     break;
                                 }
                                 None => {
+                                    usage.finish();
+                                    if !outcome_recorded && let Some(ref breakers) = circuit_breakers {
+                                        breakers.record_success(&provider_id, breaker_model).await;
+                                    }
                                     // Stream finished
+                                    Self::record_observation(&learned_metrics, &provider_id, &request.model, completed(ttft_ms, &usage.meter)).await;
                                     let _ = tx.send(StreamEvent::Done {
+                                        candidate: index,
                                         provider_id: provider_id.clone(),
-                                        total_tokens,
-                                        cost_usd: 0.01, // TODO: Calculate actual cost
+                                        total_tokens: usage.meter.output_tokens(),
+                                        cost_usd: usage.meter.cost_usd(),
                                     }).await;
                                     break;
                                 }
@@ -441,13 +1106,25 @@ impl StreamMultiplexer {
                         }
                         _ = cancel_token.cancelled() => {
                             debug!("🛑 Provider {} cancelled", provider_id);
+                            if !outcome_recorded && let Some(ref breakers) = circuit_breakers {
+                                breakers.release(&provider_id, breaker_model).await;
+                            }
+                            // A cancelled stream still tells us how quickly it started
+                            Self::record_observation(&learned_metrics, &provider_id, &request.model, Observation::cancelled(ttft_ms)).await;
                             break;
                         }
                     }
                 }
             }
             Err(e) => {
+                if let Some(ref breakers) = circuit_breakers {
+                    breakers.record_result(&provider_id, breaker_model, Some(&e)).await;
+                }
+                if let Some(class) = ErrorClass::of(&e) {
+                    Self::record_observation(&learned_metrics, &provider_id, &request.model, Observation::failure(class)).await;
+                }
                 let _ = tx.send(StreamEvent::Error {
+                    candidate: index,
                     provider_id: provider_id.clone(),
                     error: e.to_string(),
                 }).await;
@@ -455,6 +1132,21 @@ impl StreamMultiplexer {
         }
     }
 
+    async fn record_observation(
+        learned_metrics: &Option<Arc<LearnedMetricsStore>>,
+        provider_id: &str,
+        model: &str,
+        observation: Observation,
+    ) {
+        if let Some(store) = learned_metrics {
+            store.record(provider_id, model, observation).await;
+        }
+    }
+
+    fn breaker_model(model: &str) -> Option<&str> {
+        if model == "auto" { None } else { Some(model) }
+    }
+
     fn is_useful_token(chunk: &str, min_tokens: usize) -> bool {
         // Skip trivial whitespace/preamble
         let content = chunk.trim();
@@ -479,4 +1171,156 @@ impl StreamMultiplexer {
         chunk.contains("function_call") ||
         chunk.contains("tool_call")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Provider;
+    use async_trait::async_trait;
+
+    /// Answers with the model id it was sent
+    #[derive(Debug)]
+    struct EchoProvider;
+
+    #[async_trait]
+    impl Provider for EchoProvider {
+        fn id(&self) -> &str {
+            "openai"
+        }
+
+        fn name(&self) -> &str {
+            "Echo"
+        }
+
+        fn provider_type(&self) -> ProviderType {
+            ProviderType::OpenAI
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        async fn list_models(&self) -> Result<Vec<Model>> {
+            Ok(Vec::new())
+        }
+
+        async fn chat_completion(
+            &self,
+            request: &ChatCompletionRequest,
+            _context: &RequestContext,
+        ) -> Result<ChatCompletionResponse> {
+            Ok(ChatCompletionResponse {
+                id: "id".to_string(),
+                object: "chat.completion".to_string(),
+                created: 0,
+                model: request.model.clone(),
+                choices: vec![ChatChoice {
+                    index: 0,
+                    message: ChatMessage {
+                        role: "assistant".to_string(),
+                        content: MessageContent::Text(request.model.clone()),
+                        name: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                }],
+                usage: Usage {
+                    prompt_tokens: 1,
+                    completion_tokens: 1,
+                    total_tokens: 2,
+                },
+                system_fingerprint: None,
+                omen: None,
+            })
+        }
+
+        async fn stream_chat_completion(
+            &self,
+            _request: &ChatCompletionRequest,
+            _context: &RequestContext,
+        ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
+            Err(OmenError::ProviderUnavailable("not streaming".to_string()))
+        }
+    }
+
+    fn deployment(model: &str) -> Deployment {
+        Deployment {
+            provider_id: "openai".to_string(),
+            model: model.to_string(),
+            weight: 1,
+            overrides: Default::default(),
+            messages: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deployments_sharing_a_provider_both_run() {
+        let provider: Arc<dyn Provider> = Arc::new(EchoProvider);
+        let config = OmenConfig { budget_usd: Some(10.0), ..OmenConfig::default() };
+        let multiplexer = StreamMultiplexer::new(Vec::new(), &config).with_deployments(vec![
+            (provider.clone(), deployment("gpt-4o")),
+            (provider, deployment("gpt-4o-mini")),
+        ]);
+
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "smart",
+            "messages": [{"role": "user", "content": "hello"}],
+        }))
+        .unwrap();
+        let context = RequestContext {
+            request_id: uuid::Uuid::new_v4(),
+            user_id: None,
+            api_key: None,
+            intent: None,
+            tags: HashMap::new(),
+        };
+
+        let response = multiplexer.parallel_merge(&request, &context, 2).await.unwrap();
+        let summary = response.omen.and_then(|omen| omen.merge).unwrap();
+        let mut models = vec![summary.winner_model];
+        models.extend(summary.losers.into_iter().map(|loser| loser.model));
+        models.sort();
+        assert_eq!(models, vec!["gpt-4o", "gpt-4o-mini"]);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_parallel_merge_reports_usage() {
+        let provider: Arc<dyn Provider> = Arc::new(EchoProvider);
+        let config = OmenConfig { budget_usd: Some(10.0), ..OmenConfig::default() };
+        let usages = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let multiplexer = StreamMultiplexer::new(Vec::new(), &config)
+            .with_deployments(vec![(provider.clone(), deployment("gpt-4o")), (provider, deployment("gpt-4o-mini"))])
+            .with_usage_callback({
+                let usages = usages.clone();
+                Arc::new(move |usage: StreamUsage| usages.lock().unwrap().push(usage))
+            });
+
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "smart",
+            "messages": [{"role": "user", "content": "hello"}],
+            "stream": true,
+        }))
+        .unwrap();
+        let context = RequestContext {
+            request_id: uuid::Uuid::new_v4(),
+            user_id: Some("user-1".to_string()),
+            api_key: None,
+            intent: None,
+            tags: HashMap::new(),
+        };
+
+        let mut stream = multiplexer
+            .multiplex_stream(request, context, MultiplexStrategy::ParallelMerge { k: 2 })
+            .await
+            .unwrap();
+        while stream.next().await.is_some() {}
+
+        let usages = usages.lock().unwrap();
+        let mut models: Vec<_> = usages.iter().map(|usage| usage.model.as_str()).collect();
+        models.sort();
+        assert_eq!(models, vec!["gpt-4o", "gpt-4o-mini"]);
+        assert!(usages.iter().all(|usage| usage.output_tokens == 1 && !usage.cancelled));
+    }
+}
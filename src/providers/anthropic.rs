@@ -2,12 +2,13 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error, warn};
 
 #[derive(Debug)]
@@ -15,6 +16,7 @@ pub struct AnthropicProvider {
     client: Client,
     api_key: String,
     base_url: String,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl AnthropicProvider {
@@ -33,6 +35,7 @@ impl AnthropicProvider {
             client,
             api_key,
             base_url,
+            upstream_quota: Arc::default(),
         };
 
         debug!("✅ Anthropic provider initialized");
@@ -40,6 +43,12 @@ impl AnthropicProvider {
         Ok(provider)
     }
 
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
+
     fn openai_to_anthropic_messages(&self, messages: &[ChatMessage]) -> (String, Vec<serde_json::Value>) {
         let mut system_message = String::new();
         let mut anthropic_messages = Vec::new();
@@ -105,6 +114,7 @@ impl AnthropicProvider {
                 total_tokens: input_tokens + output_tokens,
             },
             system_fingerprint: None,
+            omen: None,
         })
     }
 }
@@ -323,13 +333,13 @@ impl Provider for AnthropicProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("Anthropic API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "Anthropic API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let anthropic_response: serde_json::Value = response.json().await?;
@@ -374,12 +384,12 @@ impl Provider for AnthropicProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "Anthropic API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let request_id = context.request_id.to_string();
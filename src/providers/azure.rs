@@ -2,12 +2,13 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error};
 
 #[derive(Debug)]
@@ -16,6 +17,7 @@ pub struct AzureProvider {
     endpoint: String,
     api_key: String,
     api_version: String,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl AzureProvider {
@@ -50,12 +52,19 @@ impl AzureProvider {
             endpoint: trimmed_endpoint,
             api_key,
             api_version,
+            upstream_quota: Arc::default(),
         };
 
         debug!("✅ Azure OpenAI provider initialized with endpoint: {}", provider.endpoint);
 
         Ok(provider)
     }
+
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
 }
 
 #[async_trait]
@@ -193,13 +202,13 @@ impl Provider for AzureProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("Azure OpenAI API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "Azure OpenAI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let azure_response: ChatCompletionResponse = response.json().await?;
@@ -242,12 +251,12 @@ impl Provider for AzureProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "Azure OpenAI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let stream = response
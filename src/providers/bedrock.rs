@@ -2,12 +2,13 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error};
 
 #[derive(Debug)]
@@ -18,6 +19,7 @@ pub struct BedrockProvider {
     access_key_id: String,
     secret_access_key: String,
     session_token: Option<String>,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl BedrockProvider {
@@ -38,6 +40,7 @@ impl BedrockProvider {
             access_key_id,
             secret_access_key,
             session_token,
+            upstream_quota: Arc::default(),
         };
 
         debug!("✅ AWS Bedrock provider initialized");
@@ -45,6 +48,12 @@ impl BedrockProvider {
         Ok(provider)
     }
 
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
+
     fn sign_request(&self, _payload: &str) -> Result<String> {
         // TODO: Implement AWS Signature Version 4
         // For now, return placeholder authorization header
@@ -208,13 +217,13 @@ impl Provider for BedrockProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("AWS Bedrock API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "AWS Bedrock API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let bedrock_response: serde_json::Value = response.json().await?;
@@ -256,12 +265,12 @@ impl Provider for BedrockProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "AWS Bedrock API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let stream = response
@@ -419,6 +428,7 @@ impl BedrockProvider {
             }],
             usage,
             system_fingerprint: None,
+            omen: None,
         })
     }
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
 use crate::{
     config::Config,
-    error::Result,
+    error::{OmenError, Result},
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::stream::Stream;
@@ -55,16 +56,28 @@ pub trait Provider: Send + Sync + std::fmt::Debug {
         request: &ChatCompletionRequest,
         context: &RequestContext,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>>;
+
+    /// Embed text; providers without an embeddings API report themselves unavailable
+    async fn embeddings(&self, request: &EmbeddingsRequest) -> Result<EmbeddingsResponse> {
+        Err(OmenError::ProviderUnavailable(format!(
+            "{} does not support embeddings (model {})",
+            self.name(),
+            request.model
+        )))
+    }
 }
 
 #[derive(Debug)]
 pub struct ProviderRegistry {
     providers: HashMap<String, Arc<dyn Provider>>,
+    /// Rate-limit state reported by every provider's responses
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl ProviderRegistry {
     pub async fn new(config: &Config) -> Result<Self> {
         let mut providers: HashMap<String, Arc<dyn Provider>> = HashMap::new();
+        let upstream_quota = Arc::new(UpstreamQuotaTracker::new(config.upstream_quota.clone()));
 
         // Initialize OpenAI provider
         if config.providers.openai.enabled {
@@ -75,6 +88,7 @@ impl ProviderRegistry {
                         config.providers.openai.base_url.clone(),
                         config.providers.openai.timeout_seconds,
                     ).await?
+                    .with_upstream_quota(upstream_quota.clone())
                 );
                 providers.insert("openai".to_string(), provider);
             }
@@ -89,6 +103,7 @@ impl ProviderRegistry {
                         config.providers.anthropic.base_url.clone(),
                         config.providers.anthropic.timeout_seconds,
                     ).await?
+                    .with_upstream_quota(upstream_quota.clone())
                 );
                 providers.insert("anthropic".to_string(), provider);
             }
@@ -103,6 +118,7 @@ impl ProviderRegistry {
                         config.providers.google.base_url.clone(),
                         config.providers.google.timeout_seconds,
                     ).await?
+                    .with_upstream_quota(upstream_quota.clone())
                 );
                 providers.insert("google".to_string(), provider);
             }
@@ -119,6 +135,7 @@ impl ProviderRegistry {
                             config.providers.azure.api_version.clone(),
                             config.providers.azure.timeout_seconds,
                         ).await?
+                        .with_upstream_quota(upstream_quota.clone())
                     );
                     providers.insert("azure".to_string(), provider);
                 }
@@ -134,6 +151,7 @@ impl ProviderRegistry {
                         config.providers.xai.base_url.clone(),
                         config.providers.xai.timeout_seconds,
                     ).await?
+                    .with_upstream_quota(upstream_quota.clone())
                 );
                 providers.insert("xai".to_string(), provider);
             }
@@ -146,6 +164,7 @@ impl ProviderRegistry {
                     config.providers.ollama.endpoints.clone(),
                     config.providers.ollama.timeout_seconds,
                 ).await?
+                .with_upstream_quota(upstream_quota.clone())
             );
             providers.insert("ollama".to_string(), provider);
         }
@@ -165,6 +184,7 @@ impl ProviderRegistry {
                         config.providers.bedrock.session_token.clone(),
                         config.providers.bedrock.timeout_seconds,
                     ).await?
+                    .with_upstream_quota(upstream_quota.clone())
                 );
                 providers.insert("bedrock".to_string(), provider);
             }
@@ -180,12 +200,13 @@ impl ProviderRegistry {
                         config.providers.vertexai.access_token.clone(),
                         config.providers.vertexai.timeout_seconds,
                     ).await?
+                    .with_upstream_quota(upstream_quota.clone())
                 );
                 providers.insert("vertexai".to_string(), provider);
             }
         }
 
-        Ok(Self { providers })
+        Ok(Self { providers, upstream_quota })
     }
 
     pub fn get(&self, id: &str) -> Option<Arc<dyn Provider>> {
@@ -196,6 +217,10 @@ impl ProviderRegistry {
         self.providers.values().cloned().collect()
     }
 
+    pub fn upstream_quota(&self) -> Arc<UpstreamQuotaTracker> {
+        self.upstream_quota.clone()
+    }
+
     pub fn len(&self) -> usize {
         self.providers.len()
     }
@@ -2,18 +2,20 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error};
 
 #[derive(Debug)]
 pub struct OllamaProvider {
     client: Client,
     endpoints: Vec<String>,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl OllamaProvider {
@@ -24,11 +26,17 @@ impl OllamaProvider {
 
         debug!("✅ Ollama provider initialized with {} endpoints", endpoints.len());
 
-        let provider = Self { client, endpoints };
+        let provider = Self { client, endpoints, upstream_quota: Arc::default() };
 
         Ok(provider)
     }
 
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
+
     async fn get_healthy_endpoint(&self) -> Option<String> {
         for endpoint in &self.endpoints {
             if self.check_endpoint_health(endpoint).await {
@@ -103,6 +111,7 @@ impl OllamaProvider {
                 total_tokens: estimated_prompt_tokens + estimated_completion_tokens,
             },
             system_fingerprint: None,
+            omen: None,
         })
     }
 }
@@ -213,13 +222,13 @@ impl Provider for OllamaProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("Ollama API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "Ollama API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let ollama_response: serde_json::Value = response.json().await?;
@@ -230,6 +239,60 @@ impl Provider for OllamaProvider {
         )
     }
 
+    async fn embeddings(&self, request: &EmbeddingsRequest) -> Result<EmbeddingsResponse> {
+        let endpoint = self
+            .get_healthy_endpoint()
+            .await
+            .ok_or_else(|| OmenError::ProviderUnavailable("No healthy Ollama endpoints".to_string()))?;
+
+        let input = match &request.input {
+            EmbeddingInput::Single(text) => vec![text.clone()],
+            EmbeddingInput::Multiple(texts) => texts.clone(),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/api/embed", endpoint))
+            .header("Content-Type", "application/json")
+            .json(&json!({
+                "model": request.model,
+                "input": input,
+            }))
+            .send()
+            .await?;
+
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Ollama embeddings error: {}", error_text);
+            return Err(OmenError::upstream(self.id(), status, &error_text));
+        }
+
+        let ollama_response: serde_json::Value = response.json().await?;
+        let embeddings: Vec<Vec<f32>> = serde_json::from_value(ollama_response["embeddings"].clone())?;
+        let prompt_tokens = ollama_response["prompt_eval_count"].as_u64().unwrap_or(0) as u32;
+
+        Ok(EmbeddingsResponse {
+            object: "list".to_string(),
+            data: embeddings
+                .into_iter()
+                .enumerate()
+                .map(|(index, embedding)| EmbeddingData {
+                    object: "embedding".to_string(),
+                    embedding,
+                    index,
+                })
+                .collect(),
+            model: request.model.clone(),
+            usage: EmbeddingUsage {
+                prompt_tokens,
+                total_tokens: prompt_tokens,
+            },
+        })
+    }
+
     async fn stream_chat_completion(
         &self,
         request: &ChatCompletionRequest,
@@ -264,12 +327,12 @@ impl Provider for OllamaProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "Ollama API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let request_id = context.request_id.to_string();
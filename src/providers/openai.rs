@@ -2,12 +2,13 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error, warn};
 
 #[derive(Debug)]
@@ -15,6 +16,7 @@ pub struct OpenAIProvider {
     client: Client,
     api_key: String,
     base_url: String,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl OpenAIProvider {
@@ -33,6 +35,7 @@ impl OpenAIProvider {
             client,
             api_key,
             base_url,
+            upstream_quota: Arc::default(),
         };
 
         // Test the connection
@@ -44,6 +47,12 @@ impl OpenAIProvider {
 
         Ok(provider)
     }
+
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
 }
 
 #[async_trait]
@@ -114,8 +123,8 @@ impl Provider for OpenAIProvider {
         if let Some(model_list) = data["data"].as_array() {
             for model_data in model_list {
                 if let Some(model_id) = model_data["id"].as_str() {
-                    // Only include chat models
-                    if model_id.starts_with("gpt-") {
+                    // Only include chat and embedding models
+                    if model_id.starts_with("gpt-") || model_id.starts_with("text-embedding-") {
                         let (input_price, output_price) = get_openai_pricing(model_id);
                         let context_length = get_openai_context_length(model_id);
 
@@ -132,8 +141,8 @@ impl Provider for OpenAIProvider {
                             },
                             capabilities: ModelCapabilities {
                                 vision: model_id.contains("vision") || model_id.contains("gpt-4"),
-                                functions: true,
-                                streaming: true,
+                                functions: model_id.starts_with("gpt-"),
+                                streaming: model_id.starts_with("gpt-"),
                             },
                         });
                     }
@@ -162,19 +171,41 @@ impl Provider for OpenAIProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("OpenAI API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "OpenAI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let openai_response: ChatCompletionResponse = response.json().await?;
         Ok(openai_response)
     }
 
+    async fn embeddings(&self, request: &EmbeddingsRequest) -> Result<EmbeddingsResponse> {
+        let response = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send()
+            .await?;
+
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("OpenAI embeddings error: {}", error_text);
+            return Err(OmenError::upstream(self.id(), status, &error_text));
+        }
+
+        Ok(response.json().await?)
+    }
+
     async fn stream_chat_completion(
         &self,
         request: &ChatCompletionRequest,
@@ -194,12 +225,12 @@ impl Provider for OpenAIProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "OpenAI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let stream = response
@@ -273,6 +304,9 @@ fn get_openai_pricing(model: &str) -> (f64, f64) {
         "gpt-4o-mini" => (0.00015, 0.0006),
         "gpt-3.5-turbo" | "gpt-3.5-turbo-0125" => (0.0005, 0.0015),
         "gpt-3.5-turbo-instruct" => (0.0015, 0.002),
+        "text-embedding-3-small" => (0.00002, 0.0),
+        "text-embedding-3-large" => (0.00013, 0.0),
+        "text-embedding-ada-002" => (0.0001, 0.0),
         _ => (0.001, 0.002), // Default pricing
     }
 }
@@ -285,6 +319,7 @@ fn get_openai_context_length(model: &str) -> u32 {
         "gpt-4o" | "gpt-4o-mini" => 128000,
         "gpt-3.5-turbo" | "gpt-3.5-turbo-0125" => 16385,
         "gpt-3.5-turbo-instruct" => 4096,
+        m if m.starts_with("text-embedding-") => 8191,
         _ => 4096, // Default context length
     }
 }
\ No newline at end of file
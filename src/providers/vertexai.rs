@@ -2,12 +2,13 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error, warn};
 
 #[derive(Debug)]
@@ -18,6 +19,7 @@ pub struct VertexAIProvider {
     location: String,
     access_token: Option<String>,
     base_url: String,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl VertexAIProvider {
@@ -43,6 +45,7 @@ impl VertexAIProvider {
             location,
             access_token,
             base_url,
+            upstream_quota: Arc::default(),
         };
 
         debug!("✅ Vertex AI provider initialized for project {}", provider.project_id);
@@ -50,6 +53,12 @@ impl VertexAIProvider {
         Ok(provider)
     }
 
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
+
     async fn get_access_token(&self) -> Result<String> {
         // If token provided via env var, use it
         if let Some(token) = &self.access_token {
@@ -139,6 +148,7 @@ impl VertexAIProvider {
                 total_tokens: input_tokens + output_tokens,
             },
             system_fingerprint: None,
+            omen: None,
         })
     }
 }
@@ -302,13 +312,13 @@ impl Provider for VertexAIProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("Vertex AI API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "Vertex AI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let vertex_response: serde_json::Value = response.json().await?;
@@ -356,12 +366,12 @@ impl Provider for VertexAIProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "Vertex AI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let request_id = context.request_id.to_string();
@@ -2,12 +2,13 @@ use crate::{
     error::{OmenError, Result},
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use async_trait::async_trait;
 use futures::{stream::Stream, StreamExt};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tracing::{debug, error};
 
 #[derive(Debug)]
@@ -15,6 +16,7 @@ pub struct XaiProvider {
     client: Client,
     api_key: String,
     base_url: String,
+    upstream_quota: Arc<UpstreamQuotaTracker>,
 }
 
 impl XaiProvider {
@@ -33,12 +35,19 @@ impl XaiProvider {
             client,
             api_key,
             base_url,
+            upstream_quota: Arc::default(),
         };
 
         debug!("✅ xAI Grok provider initialized");
 
         Ok(provider)
     }
+
+    /// Share the registry's upstream quota tracker
+    pub fn with_upstream_quota(mut self, upstream_quota: Arc<UpstreamQuotaTracker>) -> Self {
+        self.upstream_quota = upstream_quota;
+        self
+    }
 }
 
 #[async_trait]
@@ -199,13 +208,13 @@ impl Provider for XaiProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
             error!("xAI API error: {}", error_text);
-            return Err(OmenError::Provider(format!(
-                "xAI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let xai_response: ChatCompletionResponse = response.json().await?;
@@ -245,12 +254,12 @@ impl Provider for XaiProvider {
             .send()
             .await?;
 
+        self.upstream_quota.check(self.id(), &request.model, &response)?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(OmenError::Provider(format!(
-                "xAI API error: {}",
-                error_text
-            )));
+            return Err(OmenError::upstream(self.id(), status, &error_text));
         }
 
         let stream = response
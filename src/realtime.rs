@@ -0,0 +1,261 @@
+//! WebSocket chat (`GET /v1/realtime`)
+//!
+//! One long-lived socket carries any number of streaming chat completions,
+//! each under an id the client picks. Messages are JSON text frames tagged
+//! by `type`:
+//!
+//! - client: `chat.request` (`id`, `request`), `chat.cancel` (`id`), `ping`
+//! - server: `chat.started` (routing), `chat.chunk` (an OpenAI chunk),
+//!   `chat.event` (multiplexer decisions such as upgrades), `chat.done`,
+//!   `chat.cancelled`, `error`, `heartbeat`, `pong`
+//!
+//! Cancelling a completion, or closing the socket, drops its stream, which
+//! cancels the upstream provider requests.
+
+use crate::{
+    config::RealtimeConfig,
+    error::{ApiError, OmenError},
+    multiplexer::{EventCallback, MultiplexEvent},
+    router::OmenRouter,
+    types::{ChatCompletionRequest, RequestContext, RoutingMetadata},
+};
+use axum::extract::ws::{Message, WebSocket};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{select, sync::mpsc};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Server messages buffered per socket before completions wait on the client
+const OUTBOX_CAPACITY: usize = 256;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    #[serde(rename = "chat.request")]
+    Request { id: String, request: Box<ChatCompletionRequest> },
+    #[serde(rename = "chat.cancel")]
+    Cancel { id: String },
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    #[serde(rename = "chat.started")]
+    Started { id: String, routing: RoutingMetadata },
+    #[serde(rename = "chat.chunk")]
+    Chunk { id: String, chunk: serde_json::Value },
+    #[serde(rename = "chat.event")]
+    Event { id: String, event: MultiplexEvent },
+    #[serde(rename = "chat.done")]
+    Done { id: String },
+    #[serde(rename = "chat.cancelled")]
+    Cancelled { id: String },
+    #[serde(rename = "error")]
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        error: ApiError,
+    },
+    #[serde(rename = "heartbeat")]
+    Heartbeat { timestamp: i64 },
+    #[serde(rename = "pong")]
+    Pong,
+}
+
+impl ServerMessage {
+    fn error(id: Option<String>, error: OmenError) -> Self {
+        ServerMessage::Error { id, error: error.to_api_error() }
+    }
+}
+
+/// JSON payloads of the `data:` lines in a chunk of SSE text, without `[DONE]`
+pub fn sse_payloads(text: &str) -> Vec<serde_json::Value> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim)
+        .filter(|data| *data != "[DONE]")
+        .filter_map(|data| serde_json::from_str(data).ok())
+        .collect()
+}
+
+/// Serve one socket until the client closes it. `context_for` builds each
+/// completion's request context from the connection's credentials.
+pub async fn serve<C>(socket: WebSocket, router: Arc<OmenRouter>, config: RealtimeConfig, context_for: C)
+where
+    C: Fn(&ChatCompletionRequest) -> RequestContext,
+{
+    let (mut sink, mut incoming) = socket.split();
+    let (outbox, mut outbox_rx) = mpsc::channel::<ServerMessage>(OUTBOX_CAPACITY);
+    let (finished_tx, mut finished_rx) = mpsc::unbounded_channel::<(String, u64)>();
+    let session = CancellationToken::new();
+    let _cancel_all = session.clone().drop_guard();
+
+    // Running completions by client id; the generation tells a finished
+    // completion apart from a newer one that reused its id
+    let mut active: HashMap<String, (u64, CancellationToken)> = HashMap::new();
+    let mut generation = 0;
+
+    let period = Duration::from_secs(config.heartbeat_interval_seconds.max(1));
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        let outgoing = select! {
+            message = incoming.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Ping) => ServerMessage::Pong,
+                    Ok(ClientMessage::Cancel { id }) => match active.remove(&id) {
+                        Some((_, token)) => {
+                            debug!("🔌 Realtime client cancelled {}", id);
+                            token.cancel();
+                            continue;
+                        }
+                        None => ServerMessage::error(Some(id.clone()), OmenError::InvalidRequest(format!("No active request {}", id))),
+                    },
+                    Ok(ClientMessage::Request { id, request }) => {
+                        if active.contains_key(&id) {
+                            ServerMessage::error(Some(id.clone()), OmenError::InvalidRequest(format!("Request {} is already active", id)))
+                        } else if active.len() >= config.max_concurrent_requests {
+                            let message = format!("At most {} requests may stream at once per socket", config.max_concurrent_requests);
+                            ServerMessage::error(Some(id), OmenError::Overloaded { message, retry_after_seconds: 1, tenant_limited: true })
+                        } else {
+                            generation += 1;
+                            let token = session.child_token();
+                            active.insert(id.clone(), (generation, token.clone()));
+                            let context = context_for(&request);
+                            let completion = Completion { id, generation, outbox: outbox.clone(), finished: finished_tx.clone(), token };
+                            tokio::spawn(completion.run(router.clone(), *request, context));
+                            continue;
+                        }
+                    }
+                    Err(e) => ServerMessage::error(None, OmenError::InvalidRequest(format!("Invalid message: {}", e))),
+                }
+            }
+            Some(message) = outbox_rx.recv() => message,
+            Some((id, finished)) = finished_rx.recv() => {
+                if active.get(&id).is_some_and(|(current, _)| *current == finished) {
+                    active.remove(&id);
+                }
+                continue;
+            }
+            _ = heartbeat.tick() => ServerMessage::Heartbeat { timestamp: chrono::Utc::now().timestamp() },
+        };
+
+        let Ok(text) = serde_json::to_string(&outgoing) else {
+            continue;
+        };
+        if sink.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+
+    debug!("🔌 Realtime socket closed with {} requests active", active.len());
+}
+
+/// One streaming completion on a socket
+struct Completion {
+    id: String,
+    generation: u64,
+    outbox: mpsc::Sender<ServerMessage>,
+    finished: mpsc::UnboundedSender<(String, u64)>,
+    token: CancellationToken,
+}
+
+impl Completion {
+    async fn run(self, router: Arc<OmenRouter>, mut request: ChatCompletionRequest, context: RequestContext) {
+        request.stream = true;
+        if let Some(last) = self.stream(router, request, context).await {
+            let _ = self.outbox.send(last).await;
+        }
+        let _ = self.finished.send((self.id, self.generation));
+    }
+
+    /// Forward the completion to the socket; returns the message that ends it
+    async fn stream(&self, router: Arc<OmenRouter>, request: ChatCompletionRequest, context: RequestContext) -> Option<ServerMessage> {
+        let id = self.id.clone();
+        let events = self.outbox.clone();
+        let event_id = id.clone();
+        let on_event: EventCallback = Arc::new(move |event| {
+            let _ = events.try_send(ServerMessage::Event { id: event_id.clone(), event });
+        });
+
+        let started = select! {
+            started = router.stream_chat_completion_with_events(request, context, Some(on_event)) => started,
+            _ = self.token.cancelled() => return Some(ServerMessage::Cancelled { id }),
+        };
+        let (mut stream, routing) = match started {
+            Ok(started) => started,
+            Err(e) => return Some(ServerMessage::error(Some(id), e)),
+        };
+        self.outbox.send(ServerMessage::Started { id: id.clone(), routing }).await.ok()?;
+
+        loop {
+            let item = select! {
+                item = stream.next() => item,
+                _ = self.token.cancelled() => return Some(ServerMessage::Cancelled { id }),
+            };
+            match item {
+                Some(Ok(data)) => {
+                    for chunk in sse_payloads(&data) {
+                        self.outbox.send(ServerMessage::Chunk { id: id.clone(), chunk }).await.ok()?;
+                    }
+                }
+                Some(Err(e)) => {
+                    warn!("Realtime request {} failed mid-stream: {}", id, e);
+                    return Some(ServerMessage::error(Some(id), e));
+                }
+                None => return Some(ServerMessage::Done { id }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_payloads_skip_done_and_comments() {
+        let text = "data: {\"id\":\"a\"}\n\n: keep-alive\n\ndata: {\"id\":\"b\"}\n\ndata: [DONE]\n\n";
+        let payloads = sse_payloads(text);
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[1]["id"], "b");
+    }
+
+    #[test]
+    fn test_message_shapes() {
+        let request = r#"{"type": "chat.request", "id": "r1", "request": {"model": "auto", "messages": [{"role": "user", "content": "hi"}]}}"#;
+        match serde_json::from_str::<ClientMessage>(request).unwrap() {
+            ClientMessage::Request { id, request } => assert_eq!((id.as_str(), request.model.as_str()), ("r1", "auto")),
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert!(matches!(
+            serde_json::from_str::<ClientMessage>(r#"{"type": "chat.cancel", "id": "r1"}"#).unwrap(),
+            ClientMessage::Cancel { .. }
+        ));
+
+        let event = ServerMessage::Event {
+            id: "r1".to_string(),
+            event: MultiplexEvent::Upgrade {
+                from_provider: "ollama".to_string(),
+                to_provider: "anthropic".to_string(),
+                reason: "code".to_string(),
+            },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "chat.event");
+        assert_eq!(json["event"]["type"], "upgrade");
+
+        let error = serde_json::to_value(ServerMessage::error(None, OmenError::Unauthorized)).unwrap();
+        assert_eq!(error["error"]["code"], "invalid_api_key");
+        assert!(error.get("id").is_none());
+    }
+}
@@ -1,18 +1,34 @@
 use crate::{
     billing::BillingManager,
+    bulkhead::{self, Admission, BulkheadRegistry},
+    budget::{self, BudgetClamp, BudgetVerdict, PricingCache, StreamBudget, StreamUsage, UsageCallback, UsageGuard},
     cache::RedisCache,
-    config::Config,
+    circuit_breaker::{CircuitBreakerRegistry, CircuitBreakerStatus, CircuitState},
+    config::{Config, RoutingProfileConfig, RoutingWeightsConfig},
+    context_window::{self, ContextTruncation, OverflowPolicy},
     error::{OmenError, Result},
+    experiments::{self, ArmOutcome, ExperimentReport, ExperimentTracker},
+    fair_share::FairShareScheduler,
     ghost_ai::GhostOrchestrator,
-    multiplexer::{MultiplexStrategy, StreamMultiplexer},
+    health_monitor::{HealthMonitor, ProviderHealthSnapshot},
+    hedging::{self, HedgePolicy},
+    intent::{self, IntentClassifier},
+    learned_metrics::{self, ErrorClass, LearnedMetricsStore, Observation},
+    merge::{self, LlmJudgeSelector, ResultSelector},
+    model_catalog::ModelCatalog,
+    multiplexer::{EventCallback, MultiplexStrategy, StreamMultiplexer, WinnerCallback},
     providers::{Provider, ProviderRegistry},
     rate_limiter::AdaptiveRateLimiter,
-    routing::AdvancedRouter,
+    routing::{AdvancedRouter, RoutingDecision, RoutingPreferences, RoutingStrategy},
+    rules::{RuleEngine, RuleEvaluation},
+    shadow::{self, CallOutcome, ShadowMirror},
+    stickiness::{SessionKey, StickinessMode, StickinessStore},
     types::*,
+    virtual_models::{Deployment, VirtualModelRegistry},
 };
 use futures::stream::Stream;
 use std::{collections::HashMap, sync::Arc};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -22,17 +38,82 @@ pub struct OmenRouter {
     advanced_router: Arc<tokio::sync::Mutex<AdvancedRouter>>,
     billing_manager: Arc<BillingManager>,
     rate_limiter: Arc<AdaptiveRateLimiter>,
+    circuit_breakers: Arc<CircuitBreakerRegistry>,
+    health_monitor: Arc<HealthMonitor>,
+    virtual_models: Arc<VirtualModelRegistry>,
+    model_catalog: Arc<ModelCatalog>,
+    stickiness: Arc<StickinessStore>,
+    pricing: Arc<PricingCache>,
+    learned_metrics: Arc<LearnedMetricsStore>,
+    intent_classifier: Arc<dyn IntentClassifier>,
+    rules: Arc<RuleEngine>,
+    shadow: Arc<ShadowMirror>,
+    experiments: Arc<ExperimentTracker>,
+    hedging: Arc<HedgePolicy>,
+    bulkheads: Arc<BulkheadRegistry>,
+    fair_share: Arc<FairShareScheduler>,
     pub cache: Option<Arc<RedisCache>>,
 }
 
+/// What routing learned about a request besides where to send it
+#[derive(Debug, Default)]
+struct RouteNotes {
+    /// How the conversation was shortened, if it had to be
+    truncation: Option<ContextTruncation>,
+    /// The weighted decision, when candidates were scored
+    decision: Option<RoutingDecision>,
+    /// Candidates whose max_tokens was lowered to fit the request budget
+    clamps: Vec<BudgetClamp>,
+}
+
+impl RouteNotes {
+    /// Routing metadata carrying the decision's confidence and reasoning
+    fn routing(&self) -> RoutingMetadata {
+        let mut reasoning = self.decision.as_ref().map(|d| d.reasoning.clone()).unwrap_or_default();
+        reasoning.extend(self.clamps.iter().map(|clamp| {
+            format!(
+                "Clamped max_tokens to {} for {}/{} to fit the ${:.4} budget",
+                clamp.max_tokens, clamp.provider, clamp.model, clamp.budget_usd
+            )
+        }));
+        RoutingMetadata {
+            confidence: self.decision.as_ref().map(|d| d.confidence_score),
+            reasoning,
+            weights: self.decision.as_ref().map(|d| d.weights.clone()),
+            provider_multipliers: self.decision.as_ref().map(|d| d.provider_multipliers.clone()).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// The clamp applied to the candidate that served the request, if any
+    fn clamp_for(&self, provider_id: &str, model: &str) -> Option<BudgetClamp> {
+        self.clamps
+            .iter()
+            .find(|clamp| clamp.provider == provider_id && clamp.model == model)
+            .cloned()
+    }
+}
+
 /// OmenRouter implementation - all public methods are part of the API for clients
 #[allow(dead_code)]
 impl OmenRouter {
     pub async fn new(config: Config) -> Result<Self> {
         let providers = Arc::new(ProviderRegistry::new(&config).await?);
-        let advanced_router = Arc::new(tokio::sync::Mutex::new(AdvancedRouter::new()));
+        let learned_metrics = Arc::new(LearnedMetricsStore::open(config.learned_metrics.clone(), &config.storage.db).await);
+        let bulkheads = Arc::new(BulkheadRegistry::new(config.bulkheads.clone()));
+        let fair_share = Arc::new(FairShareScheduler::new(config.fair_share.clone()));
+        let mut advanced_router = AdvancedRouter::new();
+        advanced_router.set_strategy(RoutingStrategy::default().with_overrides(&config.routing.weights).normalized());
+        advanced_router.set_learned_metrics(learned_metrics.clone());
+        advanced_router.set_bulkheads(bulkheads.clone());
+        advanced_router.set_upstream_quota(providers.upstream_quota());
+        advanced_router.set_intent_labels(&config.intent.labels);
+        let advanced_router = Arc::new(tokio::sync::Mutex::new(advanced_router));
         let billing_manager = Arc::new(BillingManager::new());
         let rate_limiter = Arc::new(AdaptiveRateLimiter::new(billing_manager.clone()));
+        let circuit_breakers = Arc::new(CircuitBreakerRegistry::new(config.circuit_breaker.clone()));
+        let virtual_models = Arc::new(VirtualModelRegistry::new(&config.virtual_models));
+        let model_catalog = Arc::new(ModelCatalog::new(&config.model_catalog));
 
         // Initialize Redis cache if enabled
         let cache = if config.cache.enabled {
@@ -60,6 +141,28 @@ impl OmenRouter {
             None
         };
 
+        let health_monitor = Arc::new(HealthMonitor::new(
+            config.health_monitor.clone(),
+            providers.clone(),
+            cache.clone(),
+        ));
+        let stickiness = Arc::new(StickinessStore::new(config.stickiness.clone(), cache.clone()));
+        let pricing = Arc::new(PricingCache::new());
+        let intent_classifier = intent::build_classifier(&config.intent, &providers);
+        let rules = Arc::new(RuleEngine::new(&config.routing.rules)?);
+        let experiments = Arc::new(ExperimentTracker::new(&config.experiments)?);
+        let hedging = Arc::new(HedgePolicy::new(config.hedging.clone()));
+        let shadow = Arc::new(
+            ShadowMirror::open(
+                config.shadow.clone(),
+                providers.clone(),
+                pricing.clone(),
+                model_catalog.clone(),
+                &config.storage.db,
+            )
+            .await?,
+        );
+
         info!("✅ OMEN router initialized with {} providers", providers.len());
 
         Ok(Self {
@@ -68,11 +171,41 @@ impl OmenRouter {
             advanced_router,
             billing_manager,
             rate_limiter,
+            circuit_breakers,
+            health_monitor,
+            virtual_models,
+            model_catalog,
+            stickiness,
+            pricing,
+            learned_metrics,
+            intent_classifier,
+            rules,
+            shadow,
+            experiments,
+            hedging,
+            bulkheads,
+            fair_share,
             cache,
         })
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Start background tasks owned by the router (provider health checks,
+    /// persisting learned metrics)
+    pub fn start_background_tasks(&self) {
+        self.health_monitor.start();
+        self.learned_metrics.start();
+    }
+
     pub async fn chat_completion(&self, request: ChatCompletionRequest, context: RequestContext) -> Result<ChatCompletionResponse> {
+        let context = self.classify_intent(&request, context).await;
+        let (mut request, mut context) = self.apply_rules(request, context)?;
+        let experiment = self.experiments.assign(&mut request, &mut context);
+        let mut cache_status = "bypass";
+
         // Check cache first for identical requests
         if let (Some(cache), Some(user_id)) = (&self.cache, &context.user_id) {
             let cache_key = cache.generate_response_cache_key(
@@ -88,8 +221,20 @@ impl OmenRouter {
                 // Update cache hit count
                 let _ = cache.increment_cache_hit(&cache_key).await;
 
-                return Ok(cached.response);
+                let mut response = cached.response;
+                let routing = RoutingMetadata {
+                    provider: Some(cached.provider_used),
+                    model: Some(response.model.clone()),
+                    strategy: "cache".to_string(),
+                    cost_usd: Some(0.0),
+                    cache: "hit".to_string(),
+                    ..Default::default()
+                };
+                response.omen.get_or_insert_with(OmenResponseMetadata::default).routing =
+                    Some(Self::request_routing(&context, routing));
+                return Ok(response);
             }
+            cache_status = "miss";
         }
 
         // Check billing and rate limits
@@ -104,25 +249,65 @@ impl OmenRouter {
             self.rate_limiter.check_rate_limit(user_id, estimated_tokens).await?;
         }
 
-        let provider = self.select_provider(&request.model, &context).await?;
+        // Wait for a fair share of capacity, or shed the request
+        let _admitted = self.fair_share.admit(&context).await?;
 
-        info!(
-            "🎯 Routing request {} to provider {} for model {}",
-            context.request_id, provider.name(), request.model
-        );
+        // Shadow calls start alongside the primary and learn its outcome at the end
+        let shadow = self.shadow.start(&request, &context);
 
-        let start_time = std::time::Instant::now();
-        let response = provider.chat_completion(&request, &context).await?;
-        let latency_ms = start_time.elapsed().as_millis() as u64;
+        // A live sticky binding takes precedence over multi-provider strategies
+        let sticky = self.sticky_target(&request, &context).await.is_some();
+        let strategy = match sticky {
+            true => None,
+            false => request.omen.as_ref().map(MultiplexStrategy::from),
+        };
+        let started = std::time::Instant::now();
+        let served = match strategy {
+            Some(MultiplexStrategy::ParallelMerge { k }) => self.merge_completion(&request, &context, k).await,
+            _ => self.routed_completion(&request, &context).await,
+        };
+        let (provider_id, mut response, latency_ms) = match served {
+            Ok(served) => served,
+            Err(e) => {
+                if let Some(ref assignment) = experiment
+                    && let Some(outcome) = ArmOutcome::failed(&e, started.elapsed().as_millis() as u64)
+                {
+                    self.experiments.record(assignment, outcome).await;
+                }
+                return Err(e);
+            }
+        };
 
-        // Record usage for billing and cache the response
-        if let Some(ref user_id) = context.user_id {
-            let input_tokens = self.estimate_input_tokens(&request);
-            let output_tokens = response.usage.completion_tokens;
+        let input_tokens = self.estimate_input_tokens(&request);
+        let output_tokens = response.usage.completion_tokens;
 
-            // Estimate cost (would be more accurate with actual provider pricing)
-            let provider_cost = self.estimate_provider_cost(provider.id(), input_tokens + output_tokens);
+        let omen = response.omen.get_or_insert_with(OmenResponseMetadata::default);
+        let mut routing = omen.routing.take().unwrap_or_default();
+        routing.strategy = match strategy {
+            Some(MultiplexStrategy::ParallelMerge { .. }) => "parallel_merge",
+            _ if sticky => "sticky",
+            _ => "single",
+        }
+        .to_string();
+        routing.cache = cache_status.to_string();
+
+        // Priced from the serving model (all k calls for parallel merge),
+        // falling back to a per-provider estimate
+        let provider_cost = routing
+            .cost_usd
+            .unwrap_or_else(|| self.estimate_provider_cost(&provider_id, input_tokens + output_tokens));
+        routing.cost_usd = Some(provider_cost);
+        let served_model = routing.model.clone().unwrap_or_else(|| request.model.clone());
+        omen.routing = Some(Self::request_routing(&context, routing));
+        if let Some(shadow) = shadow {
+            shadow.complete(CallOutcome::completed(&provider_id, &served_model, &response, latency_ms, provider_cost));
+        }
 
+        // Record usage for billing and the experiment arm, and cache the response
+        if let Some(ref assignment) = experiment {
+            self.experiments.record(assignment, ArmOutcome::served(latency_ms, Some(provider_cost))).await;
+        }
+        if let Some(ref user_id) = context.user_id {
             self.billing_manager.record_usage(
                 user_id,
                 input_tokens,
@@ -130,15 +315,6 @@ impl OmenRouter {
                 provider_cost,
             ).await?;
 
-            // Update router metrics
-            self.update_provider_metrics(
-                provider.id(),
-                latency_ms,
-                true,
-                provider_cost,
-                input_tokens + output_tokens,
-            ).await;
-
             // Cache the response for future identical requests
             if let Some(cache) = &self.cache {
                 let cache_key = cache.generate_response_cache_key(
@@ -151,7 +327,7 @@ impl OmenRouter {
                 if let Err(e) = cache.cache_response(
                     &cache_key,
                     &response,
-                    provider.id(),
+                    &provider_id,
                     provider_cost,
                 ).await {
                     warn!("Failed to cache response: {}", e);
@@ -162,36 +338,1094 @@ impl OmenRouter {
         Ok(response)
     }
 
+    /// Send a request to its routed provider, failing over through deployments
+    /// and hedging to the runner-up when the primary is slow.
+    /// Returns the serving provider id, the response and its latency.
+    async fn routed_completion(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<(String, ChatCompletionResponse, u64)> {
+        let (targets, mut notes) = self.chat_targets(request, context).await?;
+        let admission = self.admission(context).await;
+        let mut next = 0;
+
+        while let Some((provider, provider_request)) = targets.get(next) {
+            info!(
+                "🎯 Routing request {} to provider {} for model {}",
+                context.request_id, provider.name(), provider_request.model
+            );
+
+            let (tried, result) = match targets.get(next + 1) {
+                Some(backup) if self.hedging.is_enabled() => {
+                    self.hedged_completion(&admission, &targets[next], backup, context).await
+                }
+                _ => (1, self.attempt_completion(&admission, provider, provider_request, context).await.map(|served| (0, served))),
+            };
+
+            match result {
+                Ok((offset, (mut response, latency_ms, cost_usd))) => {
+                    let (provider, provider_request) = &targets[next + offset];
+                    self.bind_session(request, context, provider.id(), &provider_request.model).await;
+                    let omen = response.omen.get_or_insert_with(OmenResponseMetadata::default);
+                    omen.context = notes.truncation.take();
+                    omen.budget = notes.clamp_for(provider.id(), &provider_request.model);
+                    let mut routing = notes.routing();
+                    if offset > 0 {
+                        routing.reasoning.push(format!("Hedged from slow primary {}", targets[next].0.id()));
+                    }
+                    omen.routing = Some(RoutingMetadata {
+                        provider: Some(provider.id().to_string()),
+                        model: Some(provider_request.model.clone()),
+                        cost_usd: Some(cost_usd),
+                        max_tokens_clamped: omen.budget.as_ref().map(|clamp| clamp.max_tokens),
+                        ..routing
+                    });
+                    return Ok((provider.id().to_string(), response, latency_ms));
+                }
+                Err(e) => {
+                    next += tried;
+                    if next < targets.len() && Self::should_failover(&e) {
+                        warn!("↪️ Provider {} failed ({}), failing over to next deployment", provider.name(), e);
+                        continue;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Err(OmenError::ProviderUnavailable(format!("No deployment could serve model {}", request.model)))
+    }
+
+    /// One provider call within its bulkhead, recorded with its circuit breaker
+    /// and learned metrics. Returns the response, its latency and cost.
+    async fn attempt_completion(
+        &self,
+        admission: &Admission,
+        provider: &Arc<dyn Provider>,
+        provider_request: &ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<(ChatCompletionResponse, u64, f64)> {
+        let _permit = admission.acquire(provider.id(), &provider_request.model).await?;
+        let breaker_model = Self::breaker_model(&provider_request.model);
+        self.circuit_breakers.try_acquire(provider.id(), breaker_model).await?;
+
+        let start_time = std::time::Instant::now();
+        let result = provider.chat_completion(provider_request, context).await;
+        let latency_ms = start_time.elapsed().as_millis() as u64;
+
+        self.circuit_breakers.record_result(provider.id(), breaker_model, result.as_ref().err()).await;
+        match result {
+            Ok(response) => {
+                let pricing = self.pricing.pricing(provider.as_ref(), &provider_request.model).await;
+                let usage = &response.usage;
+                let cost_usd = merge::cost_usd(&pricing, usage.prompt_tokens, usage.completion_tokens);
+                let observation = Observation::completion(latency_ms, usage.completion_tokens, usage.total_tokens, cost_usd);
+                self.learned_metrics.record(provider.id(), &provider_request.model, observation).await;
+                Ok((response, latency_ms, cost_usd))
+            }
+            Err(e) => {
+                if let Some(class) = ErrorClass::of(&e) {
+                    self.learned_metrics.record(provider.id(), &provider_request.model, Observation::failure(class)).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Call `primary`, hedging to `backup` if it outlasts its p95 and the hedge
+    /// budget allows. Returns how many targets were tried and, on success,
+    /// which of the two served (0 primary, 1 backup).
+    async fn hedged_completion(
+        &self,
+        admission: &Admission,
+        primary: &(Arc<dyn Provider>, ChatCompletionRequest),
+        backup: &(Arc<dyn Provider>, ChatCompletionRequest),
+        context: &RequestContext,
+    ) -> (usize, Result<(usize, (ChatCompletionResponse, u64, f64))>) {
+        self.hedging.on_request();
+        let p95 = self.learned_metrics
+            .trusted_summary(primary.0.id(), &primary.1.model)
+            .await
+            .and_then(|summary| summary.p95_latency_ms);
+        let delay = self.hedging.delay(p95);
+
+        let outcome = hedging::race(self.attempt_completion(admission, &primary.0, &primary.1, context), delay, || {
+            self.hedging.try_hedge().then(|| {
+                info!(
+                    "🪁 Request {} still waiting on {} after {}ms, hedging to {}",
+                    context.request_id, primary.0.name(), delay.as_millis(), backup.0.name()
+                );
+                self.attempt_completion(admission, &backup.0, &backup.1, context)
+            })
+        })
+        .await;
+
+        // Dropped calls never reported back to their breaker
+        for &index in &outcome.cancelled {
+            let (provider, provider_request) = if index == 0 { primary } else { backup };
+            self.circuit_breakers.release(provider.id(), Self::breaker_model(&provider_request.model)).await;
+        }
+
+        let tried = if outcome.hedged { 2 } else { 1 };
+        (tried, outcome.into_result())
+    }
+
+    /// parallel_merge for non-streaming requests: run k candidates to completion
+    /// and return the selected response, with merge metadata attached
+    async fn merge_completion(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        k: usize,
+    ) -> Result<(String, ChatCompletionResponse, u64)> {
+        let Some(ref omen_config) = request.omen else {
+            return Err(OmenError::InvalidRequest("parallel_merge requires omen options".to_string()));
+        };
+
+        let (multiplexer, notes) = self.build_multiplexer(request, context, omen_config).await?;
+        let start_time = std::time::Instant::now();
+        let mut response = multiplexer.parallel_merge(request, context, k).await?;
+        let latency_ms = start_time.elapsed().as_millis() as u64;
+
+        let omen = response.omen.get_or_insert_with(OmenResponseMetadata::default);
+        let provider_id = omen.merge.as_ref().map(|m| m.winner_provider.clone()).unwrap_or_default();
+        let model = omen.merge.as_ref().map(|m| m.winner_model.clone());
+        omen.budget = model.as_deref().and_then(|model| notes.clamp_for(&provider_id, model));
+        omen.routing = Some(RoutingMetadata {
+            provider: Some(provider_id.clone()),
+            model,
+            cost_usd: omen.merge.as_ref().map(|m| m.total_cost_usd),
+            max_tokens_clamped: omen.budget.as_ref().map(|clamp| clamp.max_tokens),
+            ..notes.routing()
+        });
+        omen.context = notes.truncation;
+
+        Ok((provider_id, response, latency_ms))
+    }
+
     pub async fn stream_chat_completion(
         &self,
         request: ChatCompletionRequest,
         context: RequestContext,
     ) -> Result<Box<dyn Stream<Item = Result<String>> + Send + Unpin>> {
-        // Check if OMEN config exists and determine strategy
-        if let Some(ref omen_config) = request.omen {
+        let (stream, _) = self.stream_chat_completion_routed(request, context).await?;
+        Ok(stream)
+    }
+
+    /// Start a streaming completion, also returning how it was routed.
+    /// Multiplexed streams pick their provider while streaming, so the
+    /// metadata names one only for single-provider streams.
+    pub async fn stream_chat_completion_routed(
+        &self,
+        request: ChatCompletionRequest,
+        context: RequestContext,
+    ) -> Result<(Box<dyn Stream<Item = Result<String>> + Send + Unpin>, RoutingMetadata)> {
+        self.stream_chat_completion_with_events(request, context, None).await
+    }
+
+    /// Start a streaming completion, reporting multiplexer decisions (race
+    /// winners, speculative upgrades) to `on_event` as they happen
+    pub async fn stream_chat_completion_with_events(
+        &self,
+        request: ChatCompletionRequest,
+        context: RequestContext,
+        on_event: Option<EventCallback>,
+    ) -> Result<(Box<dyn Stream<Item = Result<String>> + Send + Unpin>, RoutingMetadata)> {
+        let context = self.classify_intent(&request, context).await;
+        let (mut request, mut context) = self.apply_rules(request, context)?;
+        // The admission slot is held until the client finishes reading the stream
+        let admitted = self.fair_share.admit(&context).await?;
+        let Some(assignment) = self.experiments.assign(&mut request, &mut context) else {
+            let (stream, routing) = self.start_stream(request, context, on_event).await?;
+            return Ok((bulkhead::hold_stream(stream, admitted), routing));
+        };
+
+        let started = std::time::Instant::now();
+        let request_id = context.request_id;
+        self.experiments.track_stream_cost(request_id);
+        match self.start_stream(request, context, on_event).await {
+            Ok((stream, routing)) => {
+                let stream =
+                    experiments::observe_stream(stream, self.experiments.clone(), assignment, request_id, started);
+                Ok((bulkhead::hold_stream(stream, admitted), routing))
+            }
+            Err(e) => {
+                self.experiments.take_stream_cost(request_id);
+                if let Some(outcome) = ArmOutcome::failed(&e, started.elapsed().as_millis() as u64) {
+                    self.experiments.record(&assignment, outcome).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn start_stream(
+        &self,
+        request: ChatCompletionRequest,
+        context: RequestContext,
+        on_event: Option<EventCallback>,
+    ) -> Result<(Box<dyn Stream<Item = Result<String>> + Send + Unpin>, RoutingMetadata)> {
+
+        // Check if OMEN config exists and determine strategy; a live sticky
+        // binding bypasses multiplexing and streams from the bound provider
+        let sticky = self.sticky_target(&request, &context).await.is_some();
+        if let Some(ref omen_config) = request.omen
+            && !sticky
+        {
             let strategy = MultiplexStrategy::from(omen_config);
-            let candidates = self.select_candidates(&request, &context, omen_config).await?;
+            let (multiplexer, notes) = self.build_multiplexer(&request, &context, omen_config).await?;
+            let multiplexer = match on_event {
+                Some(on_event) => multiplexer.with_event_callback(on_event),
+                None => multiplexer,
+            };
 
             info!(
-                "🚀 Multiplexing request {} with strategy {:?} across {} providers",
+                "🚀 Multiplexing request {} with strategy {:?}",
                 context.request_id,
-                strategy,
-                candidates.len()
+                strategy
             );
 
-            let multiplexer = StreamMultiplexer::new(candidates, omen_config);
-            multiplexer.multiplex_stream(request, context, strategy).await
+            let routing = Self::request_routing(&context, RoutingMetadata {
+                strategy: strategy.name().to_string(),
+                cache: "bypass".to_string(),
+                ..notes.routing()
+            });
+            let model = request.model.clone();
+            let stream = multiplexer.multiplex_stream(request, context, strategy).await?;
+            let stream = match notes.truncation {
+                Some(truncation) => context_window::report_stream(stream, &model, truncation),
+                None => stream,
+            };
+            Ok((stream, routing))
         } else {
-            // Fallback to single provider
-            let provider = self.select_provider(&request.model, &context).await?;
+            // Fallback to single provider, failing over through virtual model deployments
+            let (targets, notes) = self.chat_targets(&request, &context).await?;
+            let attempts = targets.len();
+            let admission = self.admission(&context).await;
+            let mut shadow = self.shadow.start(&request, &context);
+
+            for (attempt, (provider, provider_request)) in targets.into_iter().enumerate() {
+                info!(
+                    "🌊 Streaming request {} to provider {} for model {}",
+                    context.request_id, provider.name(), provider_request.model
+                );
 
-            info!(
-                "🌊 Streaming request {} to provider {} for model {}",
-                context.request_id, provider.name(), request.model
-            );
+                let permit = match admission.acquire(provider.id(), &provider_request.model).await {
+                    Ok(permit) => permit,
+                    Err(e) if attempt + 1 < attempts => {
+                        warn!("↪️ Provider {} is at capacity ({}), failing over", provider.name(), e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let breaker_model = Self::breaker_model(&provider_request.model);
+                match self.circuit_breakers.try_acquire(provider.id(), breaker_model).await {
+                    Ok(()) => {}
+                    Err(e) if attempt + 1 < attempts => {
+                        warn!("↪️ Provider {} became unavailable ({}), failing over", provider.name(), e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                let start_time = std::time::Instant::now();
+                let result = provider.stream_chat_completion(&provider_request, &context).await;
+                self.circuit_breakers.record_result(provider.id(), breaker_model, result.as_ref().err()).await;
+                if let Err(ref e) = result
+                    && let Some(class) = ErrorClass::of(e)
+                {
+                    self.learned_metrics.record(provider.id(), &provider_request.model, Observation::failure(class)).await;
+                }
+
+                match result {
+                    Err(e) if attempt + 1 < attempts && Self::should_failover(&e) => {
+                        warn!("↪️ Provider {} failed to start stream ({}), failing over", provider.name(), e);
+                    }
+                    Ok(mut stream) => {
+                        stream = bulkhead::hold_stream(stream, permit);
+                        self.bind_session(&request, &context, provider.id(), &provider_request.model).await;
+                        let pricing = self.pricing.pricing(provider.as_ref(), &provider_request.model).await;
+                        let input_tokens = budget::count_request_tokens(&provider_request);
+                        if let Some(cap_usd) = budget::request_budget(&request) {
+                            let stream_budget = StreamBudget::new(&provider_request.model, pricing.clone(), input_tokens, cap_usd);
+                            stream = budget::cap_stream(stream, stream_budget);
+                        }
+                        // Metered for billing, learned metrics and shadow comparison, so uncapped
+                        let meter = StreamBudget::new(&provider_request.model, pricing, input_tokens, f64::INFINITY);
+                        let usage = UsageGuard::new(meter.clone(), provider.id(), self.usage_biller(&context));
+                        stream = budget::meter_stream(stream, usage);
+                        if let Some(handle) = shadow.take() {
+                            stream = shadow::tap_stream(
+                                stream,
+                                handle,
+                                provider.id().to_string(),
+                                provider_request.model.clone(),
+                                start_time,
+                                meter.clone(),
+                            );
+                        }
+                        stream = learned_metrics::observe_stream(
+                            stream,
+                            self.learned_metrics.clone(),
+                            provider.id().to_string(),
+                            provider_request.model.clone(),
+                            start_time,
+                            meter,
+                        );
+                        let routing = Self::request_routing(&context, RoutingMetadata {
+                            provider: Some(provider.id().to_string()),
+                            model: Some(provider_request.model.clone()),
+                            strategy: if sticky { "sticky" } else { "single" }.to_string(),
+                            cache: "bypass".to_string(),
+                            max_tokens_clamped: notes
+                                .clamp_for(provider.id(), &provider_request.model)
+                                .map(|clamp| clamp.max_tokens),
+                            ..notes.routing()
+                        });
+                        if let Some(truncation) = notes.truncation {
+                            stream = context_window::report_stream(stream, &provider_request.model, truncation);
+                        }
+                        return Ok((stream, routing));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Err(OmenError::ProviderUnavailable(format!(
+                "No deployment could serve model {}",
+                request.model
+            )))
+        }
+    }
+
+    /// Multiplexer over the request's candidates: deployments for virtual and
+    /// catalog models (in configured order), otherwise smart provider selection.
+    /// Also returns the routing decision and any conversation shortening.
+    async fn build_multiplexer(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        omen_config: &OmenConfig,
+    ) -> Result<(StreamMultiplexer, RouteNotes)> {
+        let selector = self.merge_selector(omen_config)?;
+
+        let k = omen_config.k.unwrap_or(2) as usize;
+        let mut decision = None;
+        let deployments = if let Some(deployments) = self.resolve_deployments(&request.model) {
+            let deployments = self.routable_deployments(request, deployments).await;
+            if deployments.is_empty() {
+                return Err(OmenError::ProviderUnavailable(format!(
+                    "No deployments available for model {}",
+                    request.model
+                )));
+            }
+            deployments
+        } else {
+            // Plain candidates become deployments of the requested model so
+            // context fitting and budget clamping can adjust them per provider
+            let (candidates, scored) = self.select_candidates(request, context, omen_config).await?;
+            decision = scored;
+            candidates
+                .into_iter()
+                .map(|provider| {
+                    let deployment = Deployment {
+                        provider_id: provider.id().to_string(),
+                        model: request.model.clone(),
+                        weight: 1,
+                        overrides: Default::default(),
+                        messages: None,
+                    };
+                    (provider, deployment)
+                })
+                .collect()
+        };
+        let (deployments, truncation) = self.deployments_within_context(request, context, deployments).await?;
+        let (deployments, clamps) = self.deployments_within_budget(request, deployments).await?;
+        let deployments: Vec<_> = deployments.into_iter().take(k).collect();
+
+        let multiplexer = StreamMultiplexer::new(Vec::new(), omen_config)
+            .with_deployments(deployments)
+            .with_pricing(self.pricing.clone())
+            .with_learned_metrics(self.learned_metrics.clone())
+            .with_circuit_breakers(self.circuit_breakers.clone())
+            .with_selector(selector)
+            .with_default_output_tokens(self.config.merge.default_output_tokens);
+
+        let multiplexer = match self.session_binder(request, context) {
+            Some(on_winner) => multiplexer.with_winner_callback(on_winner),
+            None => multiplexer,
+        };
+        let multiplexer = match self.usage_biller(context) {
+            Some(on_usage) => multiplexer.with_usage_callback(on_usage),
+            None => multiplexer,
+        };
+        let multiplexer = multiplexer.with_admission(self.admission(context).await);
+        Ok((multiplexer, RouteNotes { truncation, decision, clamps }))
+    }
+
+    /// Result selector for parallel_merge, from the request or the configured default
+    fn merge_selector(&self, omen_config: &OmenConfig) -> Result<Arc<dyn ResultSelector>> {
+        let merge_config = &self.config.merge;
+        let name = omen_config.selector.as_deref().unwrap_or(&merge_config.default_selector);
+
+        if name == "llm_judge" {
+            let (Some(provider_id), Some(model)) = (&merge_config.judge_provider, &merge_config.judge_model) else {
+                return Err(OmenError::InvalidRequest(
+                    "llm_judge selector requires merge.judge_provider and merge.judge_model".to_string(),
+                ));
+            };
+            let judge = self.providers.get(provider_id).ok_or_else(|| {
+                OmenError::ProviderUnavailable(format!("Judge provider {} not configured", provider_id))
+            })?;
+            let model = self.model_catalog.provider_model_id(provider_id, model).to_string();
+            return Ok(Arc::new(LlmJudgeSelector { judge, model, pricing: self.pricing.clone() }));
+        }
+
+        Ok(Arc::from(merge::builtin_selector(
+            name,
+            omen_config.json_schema.clone(),
+            merge_config.majority_max_chars,
+        )?))
+    }
+
+    /// Deployments for a virtual model alias or a model known to the catalog.
+    /// Virtual model deployments may name canonical ids; those are translated
+    /// to the id each provider expects.
+    fn resolve_deployments(&self, model: &str) -> Option<Vec<Deployment>> {
+        if let Some(mut deployments) = self.virtual_models.resolve(model) {
+            for deployment in &mut deployments {
+                deployment.model = self
+                    .model_catalog
+                    .provider_model_id(&deployment.provider_id, &deployment.model)
+                    .to_string();
+            }
+            return Some(deployments);
+        }
+
+        self.model_catalog.resolve(model)
+    }
+
+    /// Providers to try for a request, each paired with the request as it
+    /// should be sent. Virtual and catalog models expand to their deployments.
+    /// Also returns the routing decision and any conversation shortening.
+    async fn chat_targets(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<(Vec<(Arc<dyn Provider>, ChatCompletionRequest)>, RouteNotes)> {
+        let routed = self.routed_targets(request, context).await;
+
+        // The session's bound provider goes first; the rest remain for failover
+        let (targets, decision) = match self.sticky_target(request, context).await {
+            Some(sticky) => {
+                debug!("📌 Request {} pinned to {} by session stickiness", context.request_id, sticky.0.id());
+                let (routed, decision) = routed.unwrap_or_default();
+                let mut targets: Vec<_> = routed
+                    .into_iter()
+                    .filter(|(provider, _)| provider.id() != sticky.0.id())
+                    .collect();
+                targets.insert(0, sticky);
+                (targets, decision)
+            }
+            None => routed?,
+        };
+
+        let (targets, truncation) = self.targets_within_context(request, context, targets).await?;
+        let (targets, clamps) = self.targets_within_budget(request, targets).await?;
+        Ok((targets, RouteNotes { truncation, decision, clamps }))
+    }
+
+    async fn routed_targets(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<(Vec<(Arc<dyn Provider>, ChatCompletionRequest)>, Option<RoutingDecision>)> {
+        let Some(deployments) = self.resolve_deployments(&request.model) else {
+            // With omen options or an API key routing profile, score candidates
+            // by the effective weights and keep the runners-up for failover
+            if request.omen.is_some() || self.routing_profile(context).is_some() {
+                let omen_config = request.omen.clone().unwrap_or_else(OmenConfig::empty);
+                let (candidates, decision) = self.select_candidates(request, context, &omen_config).await?;
+                return Ok((candidates.into_iter().map(|p| (p, request.clone())).collect(), decision));
+            }
+            let provider = self.select_provider(request, context).await?;
+            return Ok((vec![(provider, request.clone())], None));
+        };
+
+        let deployment_ids: Vec<(String, String)> =
+            deployments.iter().map(|d| (d.provider_id.clone(), d.model.clone())).collect();
+        let targets: Vec<_> = self
+            .routable_deployments(request, deployments)
+            .await
+            .into_iter()
+            .map(|(provider, deployment)| {
+                let provider_request = deployment.apply(request);
+                (provider, provider_request)
+            })
+            .collect();
+
+        if targets.is_empty() {
+            let targets = deployment_ids.iter().map(|(provider_id, model)| (provider_id.as_str(), Some(model.as_str())));
+            if let Some(limited) = self.providers.upstream_quota().all_limited(targets) {
+                return Err(limited);
+            }
+            return Err(OmenError::ProviderUnavailable(format!(
+                "No deployments available for model {}",
+                request.model
+            )));
+        }
+
+        debug!("🔀 Model {} resolved to {} deployments", request.model, targets.len());
+        Ok((targets, None))
+    }
+
+    /// The provider a sticky session is bound to, with the request as it should
+    /// be sent, unless that provider is unavailable, unhealthy or over budget
+    async fn sticky_target(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Option<(Arc<dyn Provider>, ChatCompletionRequest)> {
+        let key = SessionKey::from_request(request, context)?;
+        let mode = self.stickiness.mode(request);
+        if !StickinessStore::applies(mode, request) {
+            return None;
+        }
+
+        let binding = self.stickiness.get(&key, &request.model, mode).await?;
+        if !Self::provider_allowed(request, &binding.provider_id) {
+            return None;
+        }
+        let provider = self.providers.get(&binding.provider_id)?;
+
+        if !self.can_route(provider.id(), Self::breaker_model(&binding.model)).await {
+            debug!("📌 Ignoring sticky provider {}: circuit open", provider.id());
+            return None;
+        }
+        if let Some(snapshot) = self.health_monitor.snapshot(provider.id()).await
+            && !snapshot.healthy
+        {
+            debug!("📌 Ignoring sticky provider {}: unhealthy", provider.id());
+            return None;
+        }
+
+        // Keep the deployment's overrides when the model resolves to deployments
+        let provider_request = self
+            .resolve_deployments(&request.model)
+            .and_then(|d| d.into_iter().find(|d| d.provider_id == binding.provider_id && d.model == binding.model))
+            .map(|d| d.apply(request))
+            .unwrap_or_else(|| {
+                let mut provider_request = request.clone();
+                provider_request.model = binding.model.clone();
+                provider_request
+            });
+
+        if self.context_window_fit(&provider, &provider_request).await == Some(false) {
+            debug!("📌 Ignoring sticky provider {}: prompt exceeds its context window", provider.id());
+            return None;
+        }
+
+        // Clamping, if it fits that way, is left to the caller's budget check
+        if let Some(budget_usd) = budget::request_budget(request)
+            && self.budget_verdict(&provider, &provider_request, budget_usd).await == BudgetVerdict::Exceeds
+        {
+            debug!("📌 Ignoring sticky provider {}: over request budget", binding.provider_id);
+            return None;
+        }
+        Some((provider, provider_request))
+    }
+
+    /// Whether a request fits its target's context window with room for the
+    /// requested output; `None` when the window is unknown
+    async fn context_window_fit(&self, provider: &Arc<dyn Provider>, provider_request: &ChatCompletionRequest) -> Option<bool> {
+        let context_length = self.pricing.context_length(provider.as_ref(), &provider_request.model).await?;
+        Some(context_window::fits(
+            budget::count_request_tokens(provider_request),
+            self.reserved_output_tokens(provider_request),
+            context_length,
+        ))
+    }
+
+    fn reserved_output_tokens(&self, request: &ChatCompletionRequest) -> u32 {
+        request.max_tokens.unwrap_or(self.config.context_window.default_output_tokens)
+    }
+
+    /// Drop targets whose context window can't hold the prompt plus requested
+    /// output. When none can, shorten the conversation per the overflow policy
+    /// to fit the largest window and keep the targets it then fits.
+    async fn targets_within_context(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        targets: Vec<(Arc<dyn Provider>, ChatCompletionRequest)>,
+    ) -> Result<(Vec<(Arc<dyn Provider>, ChatCompletionRequest)>, Option<ContextTruncation>)> {
+        if !self.config.context_window.enabled {
+            return Ok((targets, None));
+        }
+
+        let mut windows = Vec::with_capacity(targets.len());
+        for (provider, provider_request) in &targets {
+            windows.push(self.pricing.context_length(provider.as_ref(), &provider_request.model).await);
+        }
+
+        let fits_as_is = |provider_request: &ChatCompletionRequest, window: &Option<u32>| {
+            window.is_none_or(|window| {
+                context_window::fits(
+                    budget::count_request_tokens(provider_request),
+                    self.reserved_output_tokens(provider_request),
+                    window,
+                )
+            })
+        };
+        if targets.iter().zip(&windows).any(|((_, r), w)| fits_as_is(r, w)) {
+            let fitting = targets
+                .into_iter()
+                .zip(&windows)
+                .filter(|((provider, provider_request), window)| {
+                    let fits = fits_as_is(provider_request, window);
+                    if !fits {
+                        info!(
+                            "📏 Excluding {}: prompt plus {} output tokens exceeds {}'s {}-token context window",
+                            provider.id(), self.reserved_output_tokens(provider_request), provider_request.model, window.unwrap_or(0)
+                        );
+                    }
+                    fits
+                })
+                .map(|(target, _)| target)
+                .collect();
+            return Ok((fitting, None));
+        }
+
+        // Nothing fits: shorten the conversation for the target with the most room
+        let Some((reference, context_length)) = targets
+            .iter()
+            .zip(&windows)
+            .filter_map(|((_, r), w)| w.map(|w| (r, w)))
+            .max_by_key(|(r, w)| w.saturating_sub(self.reserved_output_tokens(r)))
+        else {
+            return Ok((targets, None));
+        };
+        let max_prompt_tokens = context_length.saturating_sub(self.reserved_output_tokens(reference));
+        let prompt_tokens = budget::count_request_tokens(reference);
+
+        let policy = self.overflow_policy(request)?;
+        let truncated = match policy {
+            OverflowPolicy::Reject => None,
+            _ => self.shorten_conversation(reference, context, policy, max_prompt_tokens).await?,
+        };
+        let Some((messages, summary_messages)) = truncated else {
+            return Err(OmenError::ContextLengthExceeded(format!(
+                "Prompt of {} tokens plus {} output tokens exceeds the largest available context window ({} tokens for {})",
+                prompt_tokens, self.reserved_output_tokens(reference), context_length, reference.model
+            )));
+        };
+
+        let mut fitted_reference = reference.clone();
+        fitted_reference.messages = messages.clone();
+        let truncation = ContextTruncation::new(policy, context_length, reference, &fitted_reference, summary_messages);
+        info!(
+            "✂️ Shortened conversation with {:?}: dropped {} messages ({} tokens) to fit {} tokens",
+            policy, truncation.dropped_messages, truncation.dropped_tokens, context_length
+        );
+
+        let fitting = targets
+            .into_iter()
+            .zip(windows)
+            .filter_map(|((provider, mut provider_request), window)| {
+                provider_request.messages = messages.clone();
+                fits_as_is(&provider_request, &window).then_some((provider, provider_request))
+            })
+            .collect();
+        Ok((fitting, Some(truncation)))
+    }
+
+    /// Context fitting for multiplexer deployments; a shortened conversation
+    /// is carried by each deployment that keeps it
+    async fn deployments_within_context(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        deployments: Vec<(Arc<dyn Provider>, Deployment)>,
+    ) -> Result<(Vec<(Arc<dyn Provider>, Deployment)>, Option<ContextTruncation>)> {
+        let targets = deployments
+            .iter()
+            .map(|(provider, deployment)| (provider.clone(), deployment.apply(request)))
+            .collect();
+        let (fitting, truncation) = self.targets_within_context(request, context, targets).await?;
+
+        let deployments = deployments
+            .into_iter()
+            .filter_map(|(provider, mut deployment)| {
+                let (_, provider_request) = fitting.iter().find(|(p, r)| {
+                    p.id() == provider.id() && r.model == deployment.model
+                })?;
+                if truncation.is_some() {
+                    deployment.messages = Some(provider_request.messages.clone());
+                }
+                Some((provider, deployment))
+            })
+            .collect();
+        Ok((deployments, truncation))
+    }
+
+    /// Overflow policy from the request, or the configured default
+    fn overflow_policy(&self, request: &ChatCompletionRequest) -> Result<OverflowPolicy> {
+        let name = request
+            .omen
+            .as_ref()
+            .and_then(|o| o.context_overflow.as_deref())
+            .unwrap_or(&self.config.context_window.default_overflow);
+
+        OverflowPolicy::parse(name).ok_or_else(|| {
+            OmenError::InvalidRequest(format!(
+                "Unknown context_overflow policy {}: expected reject, drop_oldest, keep_last or summarize",
+                name
+            ))
+        })
+    }
 
-            provider.stream_chat_completion(&request, &context).await
+    /// Apply an overflow policy so the prompt fits `max_prompt_tokens`. Returns
+    /// the messages to send and how many of them are summaries, or `None` when
+    /// even the system prompt and latest message don't fit.
+    async fn shorten_conversation(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        policy: OverflowPolicy,
+        max_prompt_tokens: u32,
+    ) -> Result<Option<(Vec<ChatMessage>, usize)>> {
+        let keep_last_turns = request
+            .omen
+            .as_ref()
+            .and_then(|o| o.keep_last_turns)
+            .map_or(self.config.context_window.keep_last_turns, |n| n as usize);
+
+        let mut shortened = request.clone();
+        let mut summary_messages = 0;
+        match policy {
+            OverflowPolicy::Reject | OverflowPolicy::DropOldest => {}
+            OverflowPolicy::KeepLast => {
+                shortened.messages = context_window::keep_last(&request.messages, keep_last_turns);
+            }
+            OverflowPolicy::Summarize => {
+                let turns = context_window::turns_to_summarize(&request.messages, keep_last_turns);
+                if !turns.is_empty() {
+                    let summary = self.summarize_turns(&turns, context).await?;
+                    shortened.messages = context_window::with_summary(&request.messages, &summary, keep_last_turns);
+                    summary_messages = 1;
+                }
+            }
+        }
+
+        // Every policy falls back to dropping the oldest remaining turns
+        Ok(context_window::drop_oldest(&shortened, max_prompt_tokens).map(|messages| (messages, summary_messages)))
+    }
+
+    /// Condense turns with the configured summarizer model
+    async fn summarize_turns(&self, turns: &[ChatMessage], context: &RequestContext) -> Result<String> {
+        let config = &self.config.context_window;
+        let (Some(provider_id), Some(model)) = (&config.summarizer_provider, &config.summarizer_model) else {
+            return Err(OmenError::InvalidRequest(
+                "summarize overflow policy requires context_window.summarizer_provider and summarizer_model".to_string(),
+            ));
+        };
+        let summarizer = self.providers.get(provider_id).ok_or_else(|| {
+            OmenError::ProviderUnavailable(format!("Summarizer provider {} not configured", provider_id))
+        })?;
+
+        let model = self.model_catalog.provider_model_id(provider_id, model);
+        let summary_request = context_window::summary_request(model, turns, config.summary_max_tokens);
+        let response = summarizer.chat_completion(&summary_request, context).await?;
+
+        debug!("📝 Summarized {} turns with {}/{}", turns.len(), provider_id, model);
+        Ok(response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.text())
+            .unwrap_or_default())
+    }
+
+    /// Drop targets whose worst-case cost exceeds the request budget, or clamp
+    /// their max_tokens to fit when clamping is enabled. Also returns the clamps.
+    async fn targets_within_budget(
+        &self,
+        request: &ChatCompletionRequest,
+        targets: Vec<(Arc<dyn Provider>, ChatCompletionRequest)>,
+    ) -> Result<(Vec<(Arc<dyn Provider>, ChatCompletionRequest)>, Vec<BudgetClamp>)> {
+        let Some(budget_usd) = budget::request_budget(request) else {
+            return Ok((targets, Vec::new()));
+        };
+
+        let mut affordable = Vec::new();
+        let mut clamps = Vec::new();
+        for (provider, mut provider_request) in targets {
+            match self.budget_verdict(&provider, &provider_request, budget_usd).await {
+                BudgetVerdict::Fits => {}
+                BudgetVerdict::Clamp(max_tokens) => {
+                    clamps.push(Self::budget_clamp(&provider, &provider_request, max_tokens, budget_usd));
+                    provider_request.max_tokens = Some(max_tokens);
+                }
+                BudgetVerdict::Exceeds => continue,
+            }
+            affordable.push((provider, provider_request));
+        }
+
+        if affordable.is_empty() {
+            return Err(Self::over_budget(request, budget_usd));
+        }
+        Ok((affordable, clamps))
+    }
+
+    /// Budget filtering for multiplexer deployments; clamps become max_tokens overrides
+    async fn deployments_within_budget(
+        &self,
+        request: &ChatCompletionRequest,
+        deployments: Vec<(Arc<dyn Provider>, Deployment)>,
+    ) -> Result<(Vec<(Arc<dyn Provider>, Deployment)>, Vec<BudgetClamp>)> {
+        let Some(budget_usd) = budget::request_budget(request) else {
+            return Ok((deployments, Vec::new()));
+        };
+
+        let mut affordable = Vec::new();
+        let mut clamps = Vec::new();
+        for (provider, mut deployment) in deployments {
+            let provider_request = deployment.apply(request);
+            match self.budget_verdict(&provider, &provider_request, budget_usd).await {
+                BudgetVerdict::Fits => {}
+                BudgetVerdict::Clamp(max_tokens) => {
+                    clamps.push(Self::budget_clamp(&provider, &provider_request, max_tokens, budget_usd));
+                    deployment.overrides.max_tokens = Some(max_tokens);
+                }
+                BudgetVerdict::Exceeds => continue,
+            }
+            affordable.push((provider, deployment));
+        }
+
+        if affordable.is_empty() {
+            return Err(Self::over_budget(request, budget_usd));
+        }
+        Ok((affordable, clamps))
+    }
+
+    fn budget_clamp(
+        provider: &Arc<dyn Provider>,
+        provider_request: &ChatCompletionRequest,
+        max_tokens: u32,
+        budget_usd: f64,
+    ) -> BudgetClamp {
+        BudgetClamp {
+            provider: provider.id().to_string(),
+            model: provider_request.model.clone(),
+            requested_max_tokens: provider_request.max_tokens,
+            max_tokens,
+            budget_usd,
+        }
+    }
+
+    async fn budget_verdict(
+        &self,
+        provider: &Arc<dyn Provider>,
+        provider_request: &ChatCompletionRequest,
+        budget_usd: f64,
+    ) -> BudgetVerdict {
+        let estimate = budget::estimate(
+            &self.pricing,
+            provider.as_ref(),
+            provider_request,
+            self.config.budget.default_output_tokens,
+        ).await;
+        let verdict = budget::check(&estimate, budget_usd, &self.config.budget);
+
+        match verdict {
+            BudgetVerdict::Fits => {}
+            BudgetVerdict::Clamp(max_tokens) => info!(
+                "✂️ Clamping max_tokens for {} to {} to fit budget ${:.4}",
+                provider.id(), max_tokens, budget_usd
+            ),
+            BudgetVerdict::Exceeds => info!(
+                "💰 Excluding {}: worst case ${:.4} ({} in + {} out tokens) exceeds budget ${:.4}",
+                provider.id(), estimate.worst_case_usd, estimate.input_tokens, estimate.max_output_tokens, budget_usd
+            ),
+        }
+        verdict
+    }
+
+    fn over_budget(request: &ChatCompletionRequest, budget_usd: f64) -> OmenError {
+        OmenError::BudgetExceeded(format!(
+            "No provider can serve model {} within the ${:.4} request budget",
+            request.model, budget_usd
+        ))
+    }
+
+    /// Remember which provider served a request in a sticky session
+    async fn bind_session(&self, request: &ChatCompletionRequest, context: &RequestContext, provider_id: &str, model: &str) {
+        if let Some(key) = SessionKey::from_request(request, context)
+            && self.stickiness.mode(request) != StickinessMode::None
+        {
+            self.stickiness.bind(&key, context.request_id, &request.model, provider_id, model).await;
+        }
+    }
+
+    /// Callback binding the session to whichever provider wins a multiplexed request
+    fn session_binder(&self, request: &ChatCompletionRequest, context: &RequestContext) -> Option<WinnerCallback> {
+        let key = SessionKey::from_request(request, context)?;
+        if self.stickiness.mode(request) == StickinessMode::None {
+            return None;
+        }
+
+        let store = self.stickiness.clone();
+        let requested_model = request.model.clone();
+        let request_id = context.request_id;
+        Some(Arc::new(move |provider_id: &str, model: &str| {
+            let (store, key, requested_model) = (store.clone(), key.clone(), requested_model.clone());
+            let (provider_id, model) = (provider_id.to_string(), model.to_string());
+            tokio::spawn(async move {
+                store.bind(&key, request_id, &requested_model, &provider_id, &model).await;
+            });
+        }))
+    }
+
+    /// Place in provider bulkhead queues: the request's `priority` tag (Ghost
+    /// service priority) scaled by the user's billing tier weight
+    async fn admission(&self, context: &RequestContext) -> Admission {
+        let base = context
+            .tags
+            .get("priority")
+            .and_then(|priority| priority.parse::<f64>().ok())
+            .unwrap_or(bulkhead::DEFAULT_PRIORITY);
+        let weight = match context.user_id {
+            Some(ref user_id) => self.billing_manager.get_or_create_user_billing(user_id).await.tier.priority_weight,
+            None => 1.0,
+        };
+        Admission::new(self.bulkheads.clone(), base * weight)
+    }
+
+    /// Callback billing the user, and charging the experiment arm, for what
+    /// each provider stream generated, which is less than requested when the
+    /// client disconnects early
+    fn usage_biller(&self, context: &RequestContext) -> Option<UsageCallback> {
+        let in_experiment = context.tags.contains_key("experiment");
+        if context.user_id.is_none() && !in_experiment {
+            return None;
+        }
+        let user_id = context.user_id.clone();
+        let billing_manager = self.billing_manager.clone();
+        let experiments = self.experiments.clone();
+        let request_id = context.request_id;
+        Some(Arc::new(move |usage: StreamUsage| {
+            if usage.cancelled {
+                info!(
+                    "🛑 Stream {} on {}/{} cancelled after {} output tokens (${:.6})",
+                    request_id, usage.provider_id, usage.model, usage.output_tokens, usage.cost_usd
+                );
+            }
+            if in_experiment {
+                experiments.add_stream_cost(request_id, usage.cost_usd);
+            }
+            let Some(user_id) = user_id.clone() else {
+                return;
+            };
+            let billing_manager = billing_manager.clone();
+            tokio::spawn(async move {
+                if let Err(e) = billing_manager
+                    .record_usage(&user_id, usage.input_tokens, usage.output_tokens, usage.cost_usd)
+                    .await
+                {
+                    warn!("Failed to record stream usage: {}", e);
+                }
+            });
+        }))
+    }
+
+    /// Keep deployments whose provider is configured, allowed by the request,
+    /// healthy and whose circuit allows traffic
+    async fn routable_deployments(
+        &self,
+        request: &ChatCompletionRequest,
+        deployments: Vec<Deployment>,
+    ) -> Vec<(Arc<dyn Provider>, Deployment)> {
+        let mut routable = Vec::new();
+        for deployment in deployments {
+            if !Self::provider_allowed(request, &deployment.provider_id) {
+                continue;
+            }
+            let Some(provider) = self.providers.get(&deployment.provider_id) else {
+                debug!("Skipping deployment on unconfigured provider {}", deployment.provider_id);
+                continue;
+            };
+            if self.can_route(provider.id(), Some(&deployment.model)).await && self.is_healthy(provider.id()).await {
+                routable.push((provider, deployment));
+            }
+        }
+        routable
+    }
+
+    /// Whether the request's provider allowlist (if any) admits a provider
+    fn provider_allowed(request: &ChatCompletionRequest, provider_id: &str) -> bool {
+        request
+            .omen
+            .as_ref()
+            .and_then(|o| o.providers.as_ref())
+            .is_none_or(|allowed| allowed.iter().any(|p| p == provider_id))
+    }
+
+    /// Scoring adjustments from the API key's routing profile, then the request.
+    /// Request `priority_weights` keys cost/latency/quality/reliability set
+    /// dimension weights; any other key is a provider score multiplier.
+    fn routing_preferences(&self, omen_config: &OmenConfig, context: &RequestContext) -> RoutingPreferences {
+        let mut preferences = RoutingPreferences::default();
+
+        if let Some(profile) = self.routing_profile(context) {
+            preferences.weight_overrides.push(profile.weights.clone());
+            preferences.provider_multipliers.extend(profile.provider_multipliers.clone());
         }
+
+        if let Some(ref priority_weights) = omen_config.priority_weights {
+            let mut weights = RoutingWeightsConfig::default();
+            for (key, value) in priority_weights {
+                match key.as_str() {
+                    "cost" => weights.cost = Some(*value),
+                    "latency" => weights.latency = Some(*value),
+                    "quality" => weights.quality = Some(*value),
+                    "reliability" => weights.reliability = Some(*value),
+                    provider_id => {
+                        preferences.provider_multipliers.insert(provider_id.to_string(), *value);
+                    }
+                }
+            }
+            if !weights.is_empty() {
+                preferences.weight_overrides.push(weights);
+            }
+        }
+
+        preferences
+    }
+
+    /// Routing profile configured for the request's API key, if any
+    fn routing_profile(&self, context: &RequestContext) -> Option<&RoutingProfileConfig> {
+        context
+            .tags
+            .get("api_key_name")
+            .and_then(|name| self.config.routing.api_keys.get(name))
+    }
+
+    /// Whether a provider may take traffic for a model: its circuits admit it
+    /// and it isn't waiting out an upstream Retry-After
+    async fn can_route(&self, provider_id: &str, model: Option<&str>) -> bool {
+        if let Some(wait) = self.providers.upstream_quota().cooldown(provider_id, model) {
+            debug!("🚥 Skipping provider {}: upstream rate limited for {}ms", provider_id, wait.as_millis());
+            return false;
+        }
+        self.circuit_breakers.can_route(provider_id, model).await
+    }
+
+    /// Errors worth retrying on the next deployment of a virtual model
+    fn should_failover(error: &OmenError) -> bool {
+        CircuitBreakerRegistry::is_breaker_failure(error)
+            || matches!(
+                error,
+                OmenError::RateLimitExceeded | OmenError::UpstreamRateLimited { .. } | OmenError::ProviderUnavailable(_)
+            )
     }
 
     pub async fn list_models(&self) -> Result<Vec<Model>> {
@@ -206,7 +1440,11 @@ impl OmenRouter {
             }
         }
 
-        Ok(all_models)
+        // Virtual model aliases are listed ahead of provider models
+        let mut models = self.virtual_models.to_models(&all_models);
+        models.extend(all_models);
+
+        Ok(models)
     }
 
     pub async fn embeddings(&self, request: EmbeddingsRequest, context: RequestContext) -> Result<EmbeddingsResponse> {
@@ -221,45 +1459,54 @@ impl OmenRouter {
                 EmbeddingInput::Single(text) => (text.len() / 4) as u32,
                 EmbeddingInput::Multiple(texts) => texts.iter().map(|t| (t.len() / 4) as u32).sum(),
             };
-            let _ = self.rate_limiter.check_rate_limit(user_id, estimated_tokens).await;
+            self.rate_limiter.check_rate_limit(user_id, estimated_tokens).await?;
         }
 
-        // Select provider (prefer OpenAI/Ollama for embeddings)
-        let _provider = self.providers.get("openai")
-            .or_else(|| self.providers.get("ollama"))
-            .or_else(|| self.providers.all().first().cloned())
-            .ok_or(OmenError::ProviderUnavailable("No providers available for embeddings".to_string()))?;
+        let targets = self.embedding_targets(&request.model).await;
+        let attempts = targets.len();
+        for (attempt, (provider, model)) in targets.into_iter().enumerate() {
+            let provider_request = EmbeddingsRequest { model, ..request.clone() };
+            match provider.embeddings(&provider_request).await {
+                Err(e) if attempt + 1 < attempts && Self::should_failover(&e) => {
+                    warn!("↪️ Provider {} failed embeddings ({}), failing over", provider.name(), e);
+                }
+                result => return result,
+            }
+        }
+
+        Err(OmenError::ProviderUnavailable(format!(
+            "No available provider serves embeddings model {}",
+            request.model
+        )))
+    }
 
-        // Convert input to vector of strings
-        let texts = match request.input {
-            EmbeddingInput::Single(text) => vec![text],
-            EmbeddingInput::Multiple(texts) => texts,
+    /// Providers to try for an embeddings model, each with the model id to
+    /// send: the deployments of a virtual or catalog model, otherwise every
+    /// provider whose model list includes it
+    async fn embedding_targets(&self, model: &str) -> Vec<(Arc<dyn Provider>, String)> {
+        let candidates: Vec<_> = match self.resolve_deployments(model) {
+            Some(deployments) => deployments
+                .into_iter()
+                .filter_map(|d| self.providers.get(&d.provider_id).map(|provider| (provider, d.model)))
+                .collect(),
+            None => {
+                let mut serving = Vec::new();
+                for provider in self.providers.all() {
+                    if self.pricing.serves(provider.as_ref(), model).await {
+                        serving.push((provider, model.to_string()));
+                    }
+                }
+                serving
+            }
         };
 
-        // Generate embeddings (simplified - actual implementation would call provider)
-        let mut data = Vec::new();
-        for (i, _text) in texts.iter().enumerate() {
-            // TODO: Call actual provider embedding API (_text will be used)
-            // For now, return mock embeddings (1536 dimensions for OpenAI compatibility)
-            let embedding = vec![0.0; 1536];
-            data.push(EmbeddingData {
-                object: "embedding".to_string(),
-                embedding,
-                index: i,
-            });
+        let mut targets = Vec::new();
+        for (provider, provider_model) in candidates {
+            if self.can_route(provider.id(), Some(&provider_model)).await && self.is_healthy(provider.id()).await {
+                targets.push((provider, provider_model));
+            }
         }
-
-        let total_tokens: u32 = texts.iter().map(|t| (t.len() / 4) as u32).sum();
-
-        Ok(EmbeddingsResponse {
-            object: "list".to_string(),
-            data,
-            model: request.model.clone(),
-            usage: EmbeddingUsage {
-                prompt_tokens: total_tokens,
-                total_tokens,
-            },
-        })
+        targets
     }
 
     pub async fn text_completion(&self, request: CompletionRequest, context: RequestContext) -> Result<CompletionResponse> {
@@ -321,38 +1568,64 @@ impl OmenRouter {
         })
     }
 
+    /// Provider health from the background monitor's latest snapshot.
+    /// Probes providers directly only when the monitor is disabled.
     pub async fn get_provider_health(&self) -> Result<Vec<ProviderHealth>> {
+        if !self.health_monitor.config().enabled {
+            self.health_monitor.check_all().await;
+        }
+
         let mut health_status = Vec::new();
 
         for provider in self.providers.all() {
-            let start = std::time::Instant::now();
-            let healthy = provider.health_check().await.unwrap_or(false);
-            let latency_ms = start.elapsed().as_millis() as u64;
-
-            let models = provider.list_models().await.unwrap_or_default();
-
-            // Calculate average cost (simplified)
-            let avg_cost = if !models.is_empty() {
-                let total: f64 = models.iter().map(|m| m.pricing.input_per_1k).sum();
-                Some(total / models.len() as f64)
-            } else {
-                None
+            let circuit_state = self.circuit_breakers.provider_state(provider.id()).await;
+
+            let health = match self.health_monitor.snapshot(provider.id()).await {
+                Some(snapshot) => ProviderHealth {
+                    id: snapshot.provider_id.clone(),
+                    name: snapshot.provider_name.clone(),
+                    healthy: snapshot.healthy,
+                    models_count: snapshot.models_count,
+                    latency_ms: Some(snapshot.latency_ms),
+                    avg_cost_per_1k: snapshot.avg_cost_per_1k,
+                    success_rate: Some(snapshot.success_rate()),
+                    circuit_state,
+                    last_checked: Some(snapshot.last_checked),
+                    last_error: snapshot.last_error,
+                },
+                // Not checked yet (first round still running)
+                None => ProviderHealth {
+                    id: provider.id().to_string(),
+                    name: provider.name().to_string(),
+                    healthy: false,
+                    models_count: 0,
+                    latency_ms: None,
+                    avg_cost_per_1k: None,
+                    success_rate: None,
+                    circuit_state,
+                    last_checked: None,
+                    last_error: None,
+                },
             };
 
-            health_status.push(ProviderHealth {
-                id: provider.id().to_string(),
-                name: provider.name().to_string(),
-                healthy,
-                models_count: models.len(),
-                latency_ms: Some(latency_ms),
-                avg_cost_per_1k: avg_cost,
-                success_rate: Some(if healthy { 1.0 } else { 0.0 }),
-            });
+            health_status.push(health);
         }
 
         Ok(health_status)
     }
 
+    /// Detailed health snapshot, including check history, for one provider
+    pub async fn get_provider_health_snapshot(&self, provider_id: &str) -> Result<Option<ProviderHealthSnapshot>> {
+        if self.providers.get(provider_id).is_none() {
+            return Err(OmenError::ProviderUnavailable(format!(
+                "Provider {} not found",
+                provider_id
+            )));
+        }
+
+        Ok(self.health_monitor.snapshot(provider_id).await)
+    }
+
     pub async fn get_provider_scores(&self) -> Result<Vec<ProviderScore>> {
         let health_status = self.get_provider_health().await?;
         let mut scores = Vec::new();
@@ -362,7 +1635,11 @@ impl OmenRouter {
             let avg_cost = health.avg_cost_per_1k.unwrap_or(10.0);
 
             // Scoring algorithm for Zeke
-            let health_score = if health.healthy { 100.0 } else { 0.0 };
+            let health_score = match (health.healthy, health.circuit_state) {
+                (false, _) | (_, CircuitState::Open) => 0.0,
+                (true, CircuitState::HalfOpen) => 50.0,
+                (true, CircuitState::Closed) => 100.0,
+            };
 
             // Lower latency = higher score (max 100)
             let latency_score = ((5000.0 - latency_ms as f64) / 50.0).max(0.0).min(100.0);
@@ -386,7 +1663,11 @@ impl OmenRouter {
                 cost_score,
                 reliability_score,
                 overall_score,
-                recommended: overall_score > 60.0 && health.healthy,
+                recommended: overall_score > 60.0
+                    && health.healthy
+                    && health.circuit_state == CircuitState::Closed,
+                circuit_state: health.circuit_state,
+                learned: self.learned_metrics.provider_metrics(&health.id).await,
             });
         }
 
@@ -396,9 +1677,10 @@ impl OmenRouter {
         Ok(scores)
     }
 
+    /// Probe a provider immediately, recording the result in the health monitor
     pub async fn check_provider_health(&self, provider_id: &str) -> Result<bool> {
         if let Some(provider) = self.providers.get(provider_id) {
-            Ok(provider.health_check().await.unwrap_or(false))
+            Ok(self.health_monitor.check_provider(provider).await.healthy)
         } else {
             Err(OmenError::ProviderUnavailable(format!(
                 "Provider {} not found",
@@ -411,15 +1693,18 @@ impl OmenRouter {
         self.providers.all()
     }
 
+    pub async fn get_circuit_breakers(&self) -> Vec<CircuitBreakerStatus> {
+        self.circuit_breakers.snapshot().await
+    }
+
+    /// Model key for circuit breakers; `auto` has no model-level breaker
+    fn breaker_model(model: &str) -> Option<&str> {
+        if model == "auto" { None } else { Some(model) }
+    }
+
     fn create_request_context(&self, request: &ChatCompletionRequest) -> RequestContext {
         let mut tags = HashMap::new();
 
-        // Extract intent from request
-        let intent = self.classify_intent(request);
-        if let Some(ref intent_str) = intent {
-            tags.insert("intent".to_string(), intent_str.clone());
-        }
-
         // Add user-provided tags
         if let Some(ref user_tags) = request.tags {
             tags.extend(user_tags.clone());
@@ -429,80 +1714,221 @@ impl OmenRouter {
             request_id: Uuid::new_v4(),
             user_id: None, // TODO: Extract from auth headers
             api_key: None, // TODO: Extract from auth headers
-            intent,
+            intent: None, // classified when the request is routed
             tags,
         }
     }
 
-    fn classify_intent(&self, request: &ChatCompletionRequest) -> Option<String> {
-        // Simple intent classification based on message content
-        if let Some(last_message) = request.messages.last() {
-            let content = last_message.content.to_lowercase();
+    /// Rewrite the request with the configured routing rules, or deny it
+    fn apply_rules(
+        &self,
+        mut request: ChatCompletionRequest,
+        mut context: RequestContext,
+    ) -> Result<(ChatCompletionRequest, RequestContext)> {
+        if self.rules.is_empty() {
+            return Ok((request, context));
+        }
 
-            if content.contains("code") || content.contains("function") || content.contains("implement") {
-                return Some("code".to_string());
-            }
-            if content.contains("test") || content.contains("unit test") {
-                return Some("tests".to_string());
-            }
-            if content.contains("regex") || content.contains("pattern") {
-                return Some("regex".to_string());
+        let evaluation = self.rules.evaluate(&request, &context, chrono::Utc::now());
+        let matched = evaluation.matched_rules();
+        if !matched.is_empty() {
+            debug!("📜 Request {} matched routing rules {:?}", context.request_id, matched);
+        }
+        evaluation.apply(&mut request, &mut context)?;
+        Ok((request, context))
+    }
+
+    /// How the routing rules would treat a request, rule by rule
+    pub async fn trace_rules(&self, request: &ChatCompletionRequest, context: RequestContext) -> RuleEvaluation {
+        let context = self.classify_intent(request, context).await;
+        self.rules.evaluate(request, &context, chrono::Utc::now())
+    }
+
+    /// Routing metadata completed with the request's intent and matched rules
+    fn request_routing(context: &RequestContext, mut routing: RoutingMetadata) -> RoutingMetadata {
+        routing.intent = context.intent.clone();
+        routing.rules = context
+            .tags
+            .get("rules")
+            .map(|rules| rules.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        routing
+    }
+
+    /// Dry-run routing: classify, apply the rules and score every candidate
+    /// from circuit state, health snapshots and metrics, without calling any
+    /// provider. Model support is only checked when routing for real, since
+    /// it needs each provider's model list.
+    pub async fn explain_route(&self, request: ChatCompletionRequest, context: RequestContext) -> Result<RouteExplainResponse> {
+        let mut context = self.classify_intent(&request, context).await;
+        let mut request = request;
+        let rules = self.rules.evaluate(&request, &context, chrono::Utc::now());
+        let denied = rules.apply(&mut request, &mut context).is_err();
+        let experiment = if denied { None } else { self.experiments.assign(&mut request, &mut context) };
+
+        let mut explanation = RouteExplainResponse {
+            model: request.model.clone(),
+            strategy: request
+                .omen
+                .as_ref()
+                .map(|omen| MultiplexStrategy::from(omen).name())
+                .unwrap_or("single")
+                .to_string(),
+            intent: context.intent.clone(),
+            intent_confidence: context.tags.get("intent_confidence").and_then(|c| c.parse().ok()),
+            rules,
+            experiment,
+            sticky_provider: None,
+            excluded: Vec::new(),
+            scoring: None,
+        };
+        if denied {
+            return Ok(explanation);
+        }
+
+        if let Some(key) = SessionKey::from_request(&request, &context) {
+            let mode = self.stickiness.mode(&request);
+            if StickinessStore::applies(mode, &request) {
+                explanation.sticky_provider = self
+                    .stickiness
+                    .get(&key, &request.model, mode)
+                    .await
+                    .map(|binding| binding.provider_id);
             }
-            if content.contains("analyze") || content.contains("review") {
-                return Some("analysis".to_string());
+        }
+
+        let omen_config = request.omen.clone().unwrap_or_default();
+        let candidate_ids: Vec<String> = if let Some(deployments) = self.resolve_deployments(&request.model) {
+            let mut ids: Vec<String> = Vec::new();
+            for deployment in deployments {
+                if !ids.contains(&deployment.provider_id) {
+                    ids.push(deployment.provider_id);
+                }
             }
-            if content.contains("explain") || content.contains("summarize") {
-                return Some("explanation".to_string());
+            ids
+        } else if let Some(ref providers) = omen_config.providers {
+            providers.clone()
+        } else {
+            self.providers.all().iter().map(|p| p.id().to_string()).collect()
+        };
+
+        let breaker_model = Self::breaker_model(&request.model);
+        let mut candidates = Vec::new();
+        for provider_id in candidate_ids {
+            let reason = match self.providers.get(&provider_id) {
+                None => Some("provider not configured"),
+                Some(_) if !Self::provider_allowed(&request, &provider_id) => Some("not in provider allowlist"),
+                Some(_) if self.providers.upstream_quota().cooldown(&provider_id, breaker_model).is_some() => Some("upstream rate limited"),
+                Some(_) if !self.circuit_breakers.can_route(&provider_id, breaker_model).await => Some("circuit open"),
+                Some(provider) => match self.health_monitor.snapshot(&provider_id).await {
+                    Some(snapshot) if !snapshot.healthy => Some("unhealthy"),
+                    _ => {
+                        candidates.push(provider);
+                        None
+                    }
+                },
+            };
+            if let Some(reason) = reason {
+                explanation.excluded.push(ExcludedCandidate { provider_id, reason: reason.to_string() });
             }
         }
+        if candidates.is_empty() {
+            return Ok(explanation);
+        }
 
-        Some("general".to_string())
+        let k = if request.omen.is_some() { omen_config.k.unwrap_or(2) as usize } else { 1 };
+        let preferences = self.routing_preferences(&omen_config, &context);
+        let mut advanced_router = self.advanced_router.lock().await;
+        for provider in &candidates {
+            let state = self.circuit_breakers.provider_state(provider.id()).await;
+            advanced_router.set_circuit_state(provider.id(), state);
+        }
+        explanation.scoring = Some(advanced_router.explain(&candidates, &request, &context, &preferences, k).await?);
+        Ok(explanation)
+    }
+
+    /// Label the request's intent unless the caller already did, recording
+    /// the classifier's confidence in the context tags
+    async fn classify_intent(&self, request: &ChatCompletionRequest, mut context: RequestContext) -> RequestContext {
+        if context.intent.is_some() {
+            return context;
+        }
+
+        let classification = self.intent_classifier.classify(&intent::prompt_text(request)).await;
+        debug!(
+            "🧭 Request {} classified as '{}' ({:.2} confidence, {})",
+            context.request_id, classification.label, classification.confidence, classification.classifier
+        );
+        context.tags.insert("intent".to_string(), classification.label.clone());
+        context.tags.insert("intent_confidence".to_string(), format!("{:.3}", classification.confidence));
+        context.intent = Some(classification.label);
+        context
     }
 
-    async fn select_provider(&self, model: &str, context: &RequestContext) -> Result<Arc<dyn Provider>> {
+    /// Health as of the monitor's last check, so routing never waits on a
+    /// probe. Providers the monitor hasn't checked yet count as healthy.
+    async fn is_healthy(&self, provider_id: &str) -> bool {
+        self.health_monitor.snapshot(provider_id).await.is_none_or(|snapshot| snapshot.healthy)
+    }
+
+    async fn select_provider(&self, request: &ChatCompletionRequest, context: &RequestContext) -> Result<Arc<dyn Provider>> {
+        let model = request.model.as_str();
+
         // Handle special model names
         if model == "auto" {
-            return self.select_auto_provider(context).await;
+            return self.select_auto_provider(request, context).await;
         }
 
         // Try to find provider by exact model match
         for provider in self.providers.all() {
-            if let Ok(models) = provider.list_models().await {
-                if models.iter().any(|m| m.id == model) {
-                    if provider.health_check().await.unwrap_or(false) {
-                        return Ok(provider);
-                    }
-                }
+            if !Self::provider_allowed(request, provider.id())
+                || !self.can_route(provider.id(), Some(model)).await
+                || !self.is_healthy(provider.id()).await
+            {
+                continue;
+            }
+            if self.pricing.serves(provider.as_ref(), model).await {
+                return Ok(provider);
             }
         }
 
         Err(OmenError::ModelNotFound(format!("Model {} not found or provider unavailable", model)))
     }
 
-    async fn select_auto_provider(&self, context: &RequestContext) -> Result<Arc<dyn Provider>> {
+    async fn select_auto_provider(&self, request: &ChatCompletionRequest, context: &RequestContext) -> Result<Arc<dyn Provider>> {
         let intent = context.intent.as_deref().unwrap_or("general");
 
-        // Check if we should prefer local models for this intent
-        if self.config.routing.prefer_local_for.contains(&intent.to_string()) {
-            // Try Ollama first for local models
-            if let Some(ollama) = self.providers.get("ollama") {
-                if ollama.health_check().await.unwrap_or(false) {
-                    return Ok(ollama);
-                }
-            }
+        // Try Ollama first when local models are preferred for this intent
+        if self.config.routing.prefer_local_for.contains(&intent.to_string())
+            && Self::provider_allowed(request, "ollama")
+            && let Some(ollama) = self.providers.get("ollama")
+            && self.can_route(ollama.id(), None).await
+            && self.is_healthy(ollama.id()).await
+        {
+            return Ok(ollama);
         }
 
         // Fallback to cloud providers based on availability and routing rules
         let cloud_providers = ["openai", "anthropic", "google", "azure", "xai"];
 
-        for provider_id in &cloud_providers {
-            if let Some(provider) = self.providers.get(provider_id) {
-                if provider.health_check().await.unwrap_or(false) {
-                    return Ok(provider);
-                }
+        for provider_id in cloud_providers.iter().filter(|id| Self::provider_allowed(request, id)) {
+            if let Some(provider) = self.providers.get(provider_id)
+                && self.can_route(provider.id(), None).await
+                && self.is_healthy(provider.id()).await
+            {
+                return Ok(provider);
             }
         }
 
+        // Every provider turned away by upstream rate limits: tell the client when to come back
+        let allowed = cloud_providers
+            .iter()
+            .copied()
+            .filter(|id| Self::provider_allowed(request, id) && self.providers.get(id).is_some())
+            .map(|id| (id, None));
+        if let Some(limited) = self.providers.upstream_quota().all_limited(allowed) {
+            return Err(limited);
+        }
         Err(OmenError::ProviderUnavailable("No providers available".to_string()))
     }
 
@@ -511,26 +1937,27 @@ impl OmenRouter {
         request: &ChatCompletionRequest,
         context: &RequestContext,
         omen_config: &OmenConfig,
-    ) -> Result<Vec<Arc<dyn Provider>>> {
+    ) -> Result<(Vec<Arc<dyn Provider>>, Option<RoutingDecision>)> {
         let mut candidates = Vec::new();
 
         // If specific providers are requested, use those
         if let Some(ref provider_list) = omen_config.providers {
             for provider_id in provider_list {
-                if let Some(provider) = self.providers.get(provider_id) {
-                    if provider.health_check().await.unwrap_or(false) {
-                        candidates.push(provider);
-                    }
+                if let Some(provider) = self.providers.get(provider_id)
+                    && self.can_route(provider.id(), Self::breaker_model(&request.model)).await
+                    && self.is_healthy(provider.id()).await
+                {
+                    candidates.push(provider);
                 }
             }
         } else {
             // Use smart selection based on intent and model
             if request.model == "auto" {
                 // For auto model, use intent-based selection
-                candidates = self.select_candidates_by_intent(context).await?;
+                candidates = self.select_candidates_by_intent(request, context).await?;
             } else {
                 // For specific model, find providers that support it
-                candidates = self.select_candidates_by_model(&request.model).await?;
+                candidates = self.select_candidates_by_model(request).await?;
             }
         }
 
@@ -540,11 +1967,17 @@ impl OmenRouter {
 
         // Use advanced routing for optimal selection
         let k = omen_config.k.unwrap_or(2) as usize;
-        let advanced_router = self.advanced_router.lock().await;
+        let mut advanced_router = self.advanced_router.lock().await;
+        for provider in &candidates {
+            let state = self.circuit_breakers.provider_state(provider.id()).await;
+            advanced_router.set_circuit_state(provider.id(), state);
+        }
 
-        match advanced_router.select_optimal_providers(&candidates, request, context, k).await {
+        let preferences = self.routing_preferences(omen_config, context);
+        match advanced_router.select_optimal_providers(&candidates, request, context, &preferences, k).await {
             Ok(decision) => {
                 info!("🧠 Advanced routing decision: {}", decision.strategy_used);
+                debug!("⚖️ Routing weights: {:?}, multipliers: {:?}", decision.weights, decision.provider_multipliers);
                 info!("💰 Estimated cost: ${:.4}, latency: {}ms, confidence: {:.2}",
                       decision.estimated_cost, decision.estimated_latency_ms, decision.confidence_score);
 
@@ -555,75 +1988,91 @@ impl OmenRouter {
                         optimal_candidates.push(provider);
                     }
                 }
-                Ok(optimal_candidates)
+                Ok((optimal_candidates, Some(decision)))
             }
             Err(_) => {
-                // Fallback to simple priority weights if advanced routing fails
-                if let Some(ref weights) = omen_config.priority_weights {
-                    candidates.sort_by(|a, b| {
-                        let weight_a = weights.get(a.id()).unwrap_or(&1.0);
-                        let weight_b = weights.get(b.id()).unwrap_or(&1.0);
-                        weight_b.partial_cmp(weight_a).unwrap_or(std::cmp::Ordering::Equal)
-                    });
-                }
-                Ok(candidates.into_iter().take(k).collect())
+                // Fallback to provider multipliers if advanced routing fails
+                candidates.sort_by(|a, b| {
+                    preferences.provider_multiplier(b.id())
+                        .partial_cmp(&preferences.provider_multiplier(a.id()))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                Ok((candidates.into_iter().take(k).collect(), None))
             }
         }
     }
 
-    async fn select_candidates_by_intent(&self, context: &RequestContext) -> Result<Vec<Arc<dyn Provider>>> {
+    async fn select_candidates_by_intent(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<Vec<Arc<dyn Provider>>> {
         let intent = context.intent.as_deref().unwrap_or("general");
         let mut candidates = Vec::new();
 
         // Start with local if preferred for this intent
-        if self.config.routing.prefer_local_for.contains(&intent.to_string()) {
-            if let Some(ollama) = self.providers.get("ollama") {
-                if ollama.health_check().await.unwrap_or(false) {
-                    candidates.push(ollama);
-                }
-            }
+        if self.config.routing.prefer_local_for.contains(&intent.to_string())
+            && Self::provider_allowed(request, "ollama")
+            && let Some(ollama) = self.providers.get("ollama")
+            && self.can_route(ollama.id(), None).await
+            && self.is_healthy(ollama.id()).await
+        {
+            candidates.push(ollama);
         }
 
         // Add cloud providers
         let cloud_providers = ["anthropic", "openai", "google", "azure", "xai"];
-        for provider_id in &cloud_providers {
-            if let Some(provider) = self.providers.get(provider_id) {
-                if provider.health_check().await.unwrap_or(false) {
-                    candidates.push(provider);
-                }
+        for provider_id in cloud_providers.iter().filter(|id| Self::provider_allowed(request, id)) {
+            if let Some(provider) = self.providers.get(provider_id)
+                && self.can_route(provider.id(), None).await
+                && self.is_healthy(provider.id()).await
+            {
+                candidates.push(provider);
             }
         }
 
         Ok(candidates)
     }
 
-    async fn select_candidates_by_model(&self, model: &str) -> Result<Vec<Arc<dyn Provider>>> {
+    async fn select_candidates_by_model(&self, request: &ChatCompletionRequest) -> Result<Vec<Arc<dyn Provider>>> {
+        let model = request.model.as_str();
         let mut candidates = Vec::new();
 
         // Find all providers that support this model
         for provider in self.providers.all() {
-            if let Ok(models) = provider.list_models().await {
-                if models.iter().any(|m| m.id == model) {
-                    if provider.health_check().await.unwrap_or(false) {
-                        candidates.push(provider);
-                    }
-                }
+            if !Self::provider_allowed(request, provider.id())
+                || !self.can_route(provider.id(), Some(model)).await
+                || !self.is_healthy(provider.id()).await
+            {
+                continue;
+            }
+            if self.pricing.serves(provider.as_ref(), model).await {
+                candidates.push(provider);
             }
         }
 
         Ok(candidates)
     }
 
-    pub async fn update_provider_metrics(
-        &self,
-        provider_id: &str,
-        latency_ms: u64,
-        success: bool,
-        cost_usd: f64,
-        tokens_used: u32,
-    ) {
-        let mut advanced_router = self.advanced_router.lock().await;
-        advanced_router.update_metrics_from_response(provider_id, latency_ms, success, cost_usd, tokens_used);
+    pub async fn get_experiment_report(&self) -> Vec<ExperimentReport> {
+        self.experiments.report().await
+    }
+
+    /// Record feedback on an experiment arm, named or found from the caller's
+    /// user or session id
+    pub async fn record_experiment_feedback(&self, feedback: &ExperimentFeedback, user_id: &str) -> Result<()> {
+        let experiment = feedback.experiment.as_str();
+        let arm = match feedback.arm {
+            Some(ref arm) => arm.clone(),
+            None => {
+                let user_id = feedback.user_id.as_deref().unwrap_or(user_id);
+                self.experiments
+                    .arm_for(experiment, Some(user_id), feedback.session_id.as_deref())
+                    .ok_or_else(|| OmenError::InvalidRequest(format!("Unknown experiment {}", experiment)))?
+            }
+        };
+        let score = feedback.score;
+        self.experiments.record_feedback(experiment, &arm, score).await
     }
 
     pub async fn set_user_budget(&self, user_id: &str, budget_usd: f64) {
@@ -632,30 +2081,7 @@ impl OmenRouter {
     }
 
     fn estimate_input_tokens(&self, request: &ChatCompletionRequest) -> u32 {
-        // Rough approximation: 4 characters per token for text
-        // Images add significant tokens based on resolution
-        let mut total_tokens = 0u32;
-
-        for message in &request.messages {
-            // Text content
-            total_tokens += (message.content.text().len() / 4) as u32;
-
-            // Vision tokens (images are expensive!)
-            if let MessageContent::Parts(parts) = &message.content {
-                for part in parts {
-                    if let ContentPart::ImageUrl { image_url } = part {
-                        let image_tokens = match image_url.detail.as_deref() {
-                            Some("low") => 85,   // Low detail
-                            Some("high") => 765, // High detail
-                            _ => 425, // Auto/default
-                        };
-                        total_tokens += image_tokens;
-                    }
-                }
-            }
-        }
-
-        total_tokens
+        budget::count_request_tokens(request)
     }
 
     fn estimate_provider_cost(&self, provider_id: &str, total_tokens: u32) -> f64 {
@@ -710,6 +2136,20 @@ impl OmenRouter {
                 advanced_router: self.advanced_router.clone(),
                 billing_manager: self.billing_manager.clone(),
                 rate_limiter: self.rate_limiter.clone(),
+                circuit_breakers: self.circuit_breakers.clone(),
+                health_monitor: self.health_monitor.clone(),
+                virtual_models: self.virtual_models.clone(),
+                model_catalog: self.model_catalog.clone(),
+                stickiness: self.stickiness.clone(),
+                pricing: self.pricing.clone(),
+                learned_metrics: self.learned_metrics.clone(),
+                intent_classifier: self.intent_classifier.clone(),
+                rules: self.rules.clone(),
+                shadow: self.shadow.clone(),
+                experiments: self.experiments.clone(),
+                hedging: self.hedging.clone(),
+                bulkheads: self.bulkheads.clone(),
+                fair_share: self.fair_share.clone(),
                 cache: self.cache.clone(),
             }),
             self.billing_manager.clone()
@@ -720,4 +2160,95 @@ impl OmenRouter {
         let ghost_orchestrator = self.create_ghost_orchestrator();
         ghost_orchestrator.process_ghost_request(request).await
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderConfig;
+
+    /// Router with OpenAI and xAI configured against an address nothing listens on
+    async fn router() -> OmenRouter {
+        router_with(|_| {}).await
+    }
+
+    async fn router_with(configure: impl FnOnce(&mut Config)) -> OmenRouter {
+        let provider = || ProviderConfig {
+            enabled: true,
+            api_key: Some("test".to_string()),
+            base_url: Some("http://127.0.0.1:9".to_string()),
+            timeout_seconds: 1,
+            models: Vec::new(),
+        };
+        let mut config = Config::default();
+        let db = std::env::temp_dir().join(format!("omen-router-{}.db", Uuid::new_v4()));
+        config.storage.db = format!("sqlite://{}", db.display());
+        config.providers.openai = provider();
+        config.providers.xai = provider();
+        configure(&mut config);
+        OmenRouter::new(config).await.unwrap()
+    }
+
+    fn deployment(provider_id: &str, model: &str) -> Deployment {
+        Deployment {
+            provider_id: provider_id.to_string(),
+            model: model.to_string(),
+            weight: 1,
+            overrides: Default::default(),
+            messages: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deployments_skip_unhealthy_providers() {
+        let router = router().await;
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "smart",
+            "messages": [{"role": "user", "content": "hello"}],
+        }))
+        .unwrap();
+        let deployments = || vec![deployment("openai", "gpt-4o"), deployment("xai", "grok-2"), deployment("google", "gemini")];
+
+        // Unconfigured providers are dropped; unchecked ones count as healthy
+        let routable = router.routable_deployments(&request, deployments()).await;
+        let providers: Vec<_> = routable.iter().map(|(provider, _)| provider.id()).collect();
+        assert_eq!(providers, vec!["openai", "xai"]);
+
+        // OpenAI's probe fails, so its deployment is skipped before any attempt
+        router.health_monitor.check_provider(router.providers.get("openai").unwrap()).await;
+        let routable = router.routable_deployments(&request, deployments()).await;
+        let providers: Vec<_> = routable.iter().map(|(provider, _)| provider.id()).collect();
+        assert_eq!(providers, vec!["xai"]);
+    }
+
+    #[tokio::test]
+    async fn test_api_key_profile_applies_without_omen_options() {
+        let router = router_with(|config| {
+            let profile = RoutingProfileConfig {
+                weights: RoutingWeightsConfig { cost: Some(1.0), latency: Some(0.0), quality: Some(0.0), reliability: Some(0.0) },
+                provider_multipliers: HashMap::from([("xai".to_string(), 2.0)]),
+            };
+            config.routing.api_keys.insert("batch".to_string(), profile);
+        })
+        .await;
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "auto",
+            "messages": [{"role": "user", "content": "hello"}],
+        }))
+        .unwrap();
+        let context = RequestContext {
+            request_id: Uuid::new_v4(),
+            user_id: None,
+            api_key: None,
+            intent: None,
+            tags: HashMap::from([("api_key_name".to_string(), "batch".to_string())]),
+        };
+
+        let (_, decision) = router.routed_targets(&request, &context).await.unwrap();
+        let routing = RouteNotes { decision, ..Default::default() }.routing();
+        let weights = routing.weights.expect("plain request scored with the key's profile");
+        assert_eq!(weights.cost_weight, 1.0);
+        assert_eq!(weights.quality_weight, 0.0);
+        assert_eq!(routing.provider_multipliers.get("xai"), Some(&2.0));
+    }
+}
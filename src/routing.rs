@@ -1,7 +1,12 @@
 use crate::{
+    bulkhead::BulkheadRegistry,
+    circuit_breaker::CircuitState,
+    config::{IntentLabelConfig, RoutingWeightsConfig},
     error::Result,
+    learned_metrics::LearnedMetricsStore,
     providers::Provider,
     types::*,
+    upstream_quota::UpstreamQuotaTracker,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
@@ -26,15 +31,66 @@ impl Default for RoutingStrategy {
     }
 }
 
-#[derive(Debug, Clone)]
+impl RoutingStrategy {
+    /// Replace the dimensions the overrides set, keeping the rest
+    pub fn with_overrides(mut self, overrides: &RoutingWeightsConfig) -> Self {
+        if let Some(cost) = overrides.cost {
+            self.cost_weight = cost.max(0.0);
+        }
+        if let Some(latency) = overrides.latency {
+            self.latency_weight = latency.max(0.0);
+        }
+        if let Some(quality) = overrides.quality {
+            self.quality_weight = quality.max(0.0);
+        }
+        if let Some(reliability) = overrides.reliability {
+            self.reliability_weight = reliability.max(0.0);
+        }
+        self
+    }
+
+    /// Scale weights to sum to 1.0 so scores stay comparable across requests
+    pub fn normalized(mut self) -> Self {
+        let total = self.cost_weight + self.latency_weight + self.quality_weight + self.reliability_weight;
+        if total > 0.0 {
+            self.cost_weight /= total;
+            self.latency_weight /= total;
+            self.quality_weight /= total;
+            self.reliability_weight /= total;
+        }
+        self
+    }
+}
+
+/// Per-request adjustments layered over the intent's weights
+#[derive(Debug, Clone, Default)]
+pub struct RoutingPreferences {
+    /// Weight overrides applied in order (API key profile, then request)
+    pub weight_overrides: Vec<RoutingWeightsConfig>,
+    /// Score multipliers by provider id
+    pub provider_multipliers: HashMap<String, f32>,
+}
+
+impl RoutingPreferences {
+    pub fn provider_multiplier(&self, provider_id: &str) -> f64 {
+        self.provider_multipliers.get(provider_id).copied().unwrap_or(1.0).max(0.0) as f64
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ProviderMetrics {
     pub provider_id: String,
     pub avg_latency_ms: f64,
+    pub p95_latency_ms: f64,
     pub success_rate: f64,        // 0.0-1.0
     pub cost_per_1k_tokens: f64,
     pub quality_score: f64,       // 0.0-1.0, based on user feedback/model capabilities
     pub current_load: f64,        // 0.0-1.0, current utilization
     pub availability: f64,        // 0.0-1.0, uptime percentage
+    /// Recent wait for a bulkhead slot, added to latency when scoring
+    pub queue_wait_ms: f64,
+    /// Fraction of the provider's tightest upstream rate limit left
+    pub upstream_headroom: f64,
 }
 
 impl Default for ProviderMetrics {
@@ -42,16 +98,19 @@ impl Default for ProviderMetrics {
         Self {
             provider_id: String::new(),
             avg_latency_ms: 1000.0,
+            p95_latency_ms: 2000.0,
             success_rate: 0.95,
             cost_per_1k_tokens: 0.01,
             quality_score: 0.8,
             current_load: 0.5,
             availability: 0.99,
+            queue_wait_ms: 0.0,
+            upstream_headroom: 1.0,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 #[allow(dead_code)]
 pub struct RoutingDecision {
     pub selected_providers: Vec<String>,
@@ -60,6 +119,48 @@ pub struct RoutingDecision {
     pub estimated_latency_ms: u64,
     pub confidence_score: f64,
     pub reasoning: Vec<String>,
+    /// Normalized weights the scores were computed with
+    pub weights: RoutingStrategy,
+    /// Provider multipliers that were applied
+    pub provider_multipliers: HashMap<String, f32>,
+}
+
+/// Weighted components of a provider's routing score
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ScoreBreakdown {
+    pub latency: f64,
+    pub cost: f64,
+    pub quality: f64,
+    pub reliability: f64,
+    /// Sum of the dimension scores times their weights
+    pub weighted: f64,
+    pub load_penalty: f64,
+    pub circuit_penalty: f64,
+    pub quota_penalty: f64,
+    /// `weighted` after load, circuit and quota penalties
+    pub total: f64,
+}
+
+/// One provider's scoring as seen by the router
+#[derive(Debug, Clone, Serialize)]
+pub struct CandidateScore {
+    pub provider_id: String,
+    /// Final score, after the preference multiplier
+    pub score: f64,
+    pub breakdown: ScoreBreakdown,
+    pub multiplier: f64,
+    pub circuit_state: CircuitState,
+    pub metrics: ProviderMetrics,
+}
+
+/// Full candidate scoring alongside the decision it produces
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteExplanation {
+    pub intent: String,
+    pub latency_target_ms: u64,
+    /// Every candidate, best score first
+    pub candidates: Vec<CandidateScore>,
+    pub decision: RoutingDecision,
 }
 
 #[derive(Debug)]
@@ -68,6 +169,11 @@ pub struct AdvancedRouter {
     strategy: RoutingStrategy,
     cost_budgets: HashMap<String, f64>, // per-user budget tracking
     latency_targets: HashMap<String, u64>, // per-intent SLA targets
+    intent_weights: HashMap<String, RoutingWeightsConfig>, // configured per-intent weights
+    circuit_states: HashMap<String, CircuitState>, // fed by the circuit breaker registry
+    learned: Option<Arc<LearnedMetricsStore>>, // statistics learned from live traffic
+    bulkheads: Option<Arc<BulkheadRegistry>>, // queue waits behind concurrency limits
+    upstream_quota: Option<Arc<UpstreamQuotaTracker>>, // rate limits reported by providers
 }
 
 /// AdvancedRouter implementation - all public methods are part of the routing API
@@ -85,6 +191,11 @@ impl AdvancedRouter {
             strategy: RoutingStrategy::default(),
             cost_budgets: HashMap::new(),
             latency_targets,
+            intent_weights: HashMap::new(),
+            circuit_states: HashMap::new(),
+            learned: None,
+            bulkheads: None,
+            upstream_quota: None,
         }
     }
 
@@ -92,6 +203,64 @@ impl AdvancedRouter {
         self.metrics.insert(provider_id.to_string(), metrics);
     }
 
+    /// Latency targets and weights for configured intent labels
+    pub fn set_intent_labels(&mut self, labels: &HashMap<String, IntentLabelConfig>) {
+        for (name, label) in labels {
+            if let Some(target) = label.latency_target_ms {
+                self.latency_targets.insert(name.clone(), target);
+            }
+            if !label.weights.is_empty() {
+                self.intent_weights.insert(name.clone(), label.weights.clone());
+            }
+        }
+    }
+
+    pub fn set_learned_metrics(&mut self, store: Arc<LearnedMetricsStore>) {
+        self.learned = Some(store);
+    }
+
+    pub fn set_bulkheads(&mut self, bulkheads: Arc<BulkheadRegistry>) {
+        self.bulkheads = Some(bulkheads);
+    }
+
+    pub fn set_upstream_quota(&mut self, upstream_quota: Arc<UpstreamQuotaTracker>) {
+        self.upstream_quota = Some(upstream_quota);
+    }
+
+    /// Metrics used to score a provider for a model: learned statistics once
+    /// there are enough samples, otherwise configured or per-provider defaults
+    pub async fn metrics_for(&self, provider_id: &str, model: &str) -> ProviderMetrics {
+        let mut metrics = self.metrics.get(provider_id)
+            .cloned()
+            .unwrap_or_else(|| {
+                let default_metrics = ProviderMetrics { provider_id: provider_id.to_string(), ..Default::default() };
+                self.get_default_metrics_for_provider(provider_id, default_metrics)
+            });
+        if let Some(ref bulkheads) = self.bulkheads {
+            metrics.queue_wait_ms = bulkheads.queue_wait_ms(provider_id, model);
+        }
+        if let Some(ref upstream_quota) = self.upstream_quota {
+            metrics.upstream_headroom = upstream_quota.headroom(provider_id, model);
+        }
+
+        let Some(ref store) = self.learned else {
+            return metrics;
+        };
+        if let Some(learned) = store.trusted_summary(provider_id, model).await {
+            if let Some(p50) = learned.p50_response_ms() {
+                metrics.avg_latency_ms = p50;
+            }
+            if let Some(p95) = learned.p95_response_ms() {
+                metrics.p95_latency_ms = p95;
+            }
+            if let Some(cost_per_1k) = learned.cost_per_1k_tokens {
+                metrics.cost_per_1k_tokens = cost_per_1k;
+            }
+            metrics.success_rate = 1.0 - learned.error_rate;
+        }
+        metrics
+    }
+
     pub fn set_strategy(&mut self, strategy: RoutingStrategy) {
         self.strategy = strategy;
     }
@@ -100,29 +269,34 @@ impl AdvancedRouter {
         self.cost_budgets.insert(user_id.to_string(), budget_usd);
     }
 
+    pub fn set_circuit_state(&mut self, provider_id: &str, state: CircuitState) {
+        self.circuit_states.insert(provider_id.to_string(), state);
+    }
+
+    pub fn circuit_state(&self, provider_id: &str) -> CircuitState {
+        self.circuit_states.get(provider_id).copied().unwrap_or_default()
+    }
+
     pub async fn select_optimal_providers(
         &self,
         providers: &[Arc<dyn Provider>],
         request: &ChatCompletionRequest,
         context: &RequestContext,
+        preferences: &RoutingPreferences,
         k: usize,
     ) -> Result<RoutingDecision> {
         let mut provider_scores = Vec::new();
         let intent = context.intent.as_deref().unwrap_or("general");
         let target_latency = self.latency_targets.get(intent).unwrap_or(&3000);
+        let weights = self.effective_weights(intent, preferences);
 
         // Calculate score for each provider
         for provider in providers {
             let provider_id = provider.id();
-            let metrics = self.metrics.get(provider_id)
-                .cloned()
-                .unwrap_or_else(|| {
-                    let mut default_metrics = ProviderMetrics::default();
-                    default_metrics.provider_id = provider_id.to_string();
-                    self.get_default_metrics_for_provider(provider_id, default_metrics)
-                });
-
-            let score = self.calculate_provider_score(&metrics, *target_latency, intent);
+            let metrics = self.metrics_for(provider_id, &request.model).await;
+
+            let score = self.calculate_provider_score(&metrics, *target_latency, &weights)
+                * preferences.provider_multiplier(provider_id);
             provider_scores.push((provider.clone(), metrics, score));
         }
 
@@ -138,7 +312,14 @@ impl AdvancedRouter {
         let estimated_latency = self.estimate_response_latency(&selected);
         let confidence_score = self.calculate_confidence_score(&selected);
 
-        let reasoning = self.generate_reasoning(&selected, intent, *target_latency);
+        let mut reasoning = self.generate_reasoning(&selected, intent, *target_latency);
+        reasoning.insert(2, format!(
+            "Weights: cost={:.2}, latency={:.2}, quality={:.2}, reliability={:.2}",
+            weights.cost_weight, weights.latency_weight, weights.quality_weight, weights.reliability_weight
+        ));
+        for (provider_id, multiplier) in &preferences.provider_multipliers {
+            reasoning.push(format!("{} score multiplied by {:.2}", provider_id, multiplier));
+        }
 
         info!("🎯 Advanced routing selected {} providers for intent '{}': {:?}",
               selected_count, intent, selected_providers);
@@ -150,31 +331,109 @@ impl AdvancedRouter {
             estimated_latency_ms: estimated_latency,
             confidence_score,
             reasoning,
+            weights,
+            provider_multipliers: preferences.provider_multipliers.clone(),
         })
     }
 
-    fn calculate_provider_score(&self, metrics: &ProviderMetrics, target_latency: u64, intent: &str) -> f64 {
-        // Normalize metrics to 0-1 range
-        let latency_score = self.calculate_latency_score(metrics.avg_latency_ms, target_latency as f64);
+    /// Score every candidate without selecting, for the route explain endpoint
+    pub async fn explain(
+        &self,
+        providers: &[Arc<dyn Provider>],
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        preferences: &RoutingPreferences,
+        k: usize,
+    ) -> Result<RouteExplanation> {
+        let intent = context.intent.as_deref().unwrap_or("general");
+        let latency_target_ms = *self.latency_targets.get(intent).unwrap_or(&3000);
+        let weights = self.effective_weights(intent, preferences);
+
+        let mut candidates = Vec::with_capacity(providers.len());
+        for provider in providers {
+            let provider_id = provider.id();
+            let metrics = self.metrics_for(provider_id, &request.model).await;
+            let breakdown = self.score_breakdown(&metrics, latency_target_ms, &weights);
+            let multiplier = preferences.provider_multiplier(provider_id);
+            candidates.push(CandidateScore {
+                provider_id: provider_id.to_string(),
+                score: breakdown.total * multiplier,
+                breakdown,
+                multiplier,
+                circuit_state: self.circuit_state(provider_id),
+                metrics,
+            });
+        }
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+
+        let decision = self.select_optimal_providers(providers, request, context, preferences, k).await?;
+
+        Ok(RouteExplanation {
+            intent: intent.to_string(),
+            latency_target_ms,
+            candidates,
+            decision,
+        })
+    }
+
+    /// Intent weights with the API key and request overrides applied, normalized
+    pub fn effective_weights(&self, intent: &str, preferences: &RoutingPreferences) -> RoutingStrategy {
+        let (cost_weight, latency_weight, quality_weight, reliability_weight) = self.get_intent_weights(intent);
+        let base = RoutingStrategy { cost_weight, latency_weight, quality_weight, reliability_weight };
+
+        preferences
+            .weight_overrides
+            .iter()
+            .fold(base, |weights, overrides| weights.with_overrides(overrides))
+            .normalized()
+    }
+
+    fn calculate_provider_score(&self, metrics: &ProviderMetrics, target_latency: u64, weights: &RoutingStrategy) -> f64 {
+        self.score_breakdown(metrics, target_latency, weights).total
+    }
+
+    /// Per-dimension scores for one provider, before preference multipliers
+    pub fn score_breakdown(&self, metrics: &ProviderMetrics, target_latency: u64, weights: &RoutingStrategy) -> ScoreBreakdown {
+        // Normalize metrics to 0-1 range; tail latency counts for part of the latency score,
+        // and time spent queued for a bulkhead slot counts as latency
+        let latency_score = 0.7 * self.calculate_latency_score(metrics.avg_latency_ms + metrics.queue_wait_ms, target_latency as f64)
+            + 0.3 * self.calculate_latency_score(metrics.p95_latency_ms + metrics.queue_wait_ms, target_latency as f64);
         let cost_score = self.calculate_cost_score(metrics.cost_per_1k_tokens);
         let quality_score = metrics.quality_score;
         let reliability_score = metrics.success_rate * metrics.availability;
 
-        // Apply intent-specific weights
-        let (cost_w, latency_w, quality_w, reliability_w) = self.get_intent_weights(intent);
-
-        let total_score = (cost_score * cost_w as f64) +
-                         (latency_score * latency_w as f64) +
-                         (quality_score * quality_w as f64) +
-                         (reliability_score * reliability_w as f64);
+        let weighted_score = (cost_score * weights.cost_weight as f64) +
+                         (latency_score * weights.latency_weight as f64) +
+                         (quality_score * weights.quality_weight as f64) +
+                         (reliability_score * weights.reliability_weight as f64);
 
         // Apply load balancing penalty
         let load_penalty = 1.0 - (metrics.current_load * 0.2); // Max 20% penalty for high load
 
-        debug!("Provider {} scores: latency={:.3}, cost={:.3}, quality={:.3}, reliability={:.3}, total={:.3}",
-               metrics.provider_id, latency_score, cost_score, quality_score, reliability_score, total_score);
+        // Half-open providers only receive probe traffic, so rank them last
+        let circuit_penalty = match self.circuit_state(&metrics.provider_id) {
+            CircuitState::Closed => 1.0,
+            CircuitState::HalfOpen => 0.5,
+            CircuitState::Open => 0.0,
+        };
 
-        total_score * load_penalty
+        // Providers close to their upstream rate limit are saved for when they recover
+        let quota_penalty = self.upstream_quota.as_ref().map_or(1.0, |quota| quota.penalty(metrics.upstream_headroom));
+
+        debug!("Provider {} scores: latency={:.3}, cost={:.3}, quality={:.3}, reliability={:.3}, total={:.3}",
+               metrics.provider_id, latency_score, cost_score, quality_score, reliability_score, weighted_score);
+
+        ScoreBreakdown {
+            latency: latency_score,
+            cost: cost_score,
+            quality: quality_score,
+            reliability: reliability_score,
+            weighted: weighted_score,
+            load_penalty,
+            circuit_penalty,
+            quota_penalty,
+            total: weighted_score * load_penalty * circuit_penalty * quota_penalty,
+        }
     }
 
     fn calculate_latency_score(&self, avg_latency_ms: f64, target_latency_ms: f64) -> f64 {
@@ -194,6 +453,19 @@ impl AdvancedRouter {
     }
 
     fn get_intent_weights(&self, intent: &str) -> (f32, f32, f32, f32) {
+        let weights = self.builtin_intent_weights(intent);
+        match self.intent_weights.get(intent) {
+            Some(overrides) => {
+                let (cost_weight, latency_weight, quality_weight, reliability_weight) = weights;
+                let weights = RoutingStrategy { cost_weight, latency_weight, quality_weight, reliability_weight }
+                    .with_overrides(overrides);
+                (weights.cost_weight, weights.latency_weight, weights.quality_weight, weights.reliability_weight)
+            }
+            None => weights,
+        }
+    }
+
+    fn builtin_intent_weights(&self, intent: &str) -> (f32, f32, f32, f32) {
         match intent {
             "code" => (0.2, 0.5, 0.2, 0.1),      // Latency-focused for coding
             "tests" => (0.3, 0.4, 0.2, 0.1),     // Balanced for test generation
@@ -252,6 +524,7 @@ impl AdvancedRouter {
             }
             _ => {} // Keep defaults
         }
+        metrics.p95_latency_ms = metrics.avg_latency_ms * 2.0;
         metrics
     }
 
@@ -306,10 +579,15 @@ impl AdvancedRouter {
 
         for (i, (provider, metrics, score)) in selected.iter().enumerate() {
             reasoning.push(format!(
-                "#{}: {} (score: {:.3}, latency: {:.0}ms, cost: ${:.4}/1k, quality: {:.2})",
-                i + 1, provider.name(), score, metrics.avg_latency_ms,
+                "#{}: {} (score: {:.3}, latency: {:.0}ms p50 / {:.0}ms p95, cost: ${:.4}/1k, quality: {:.2})",
+                i + 1, provider.name(), score, metrics.avg_latency_ms, metrics.p95_latency_ms,
                 metrics.cost_per_1k_tokens, metrics.quality_score
             ));
+
+            let circuit_state = self.circuit_state(provider.id());
+            if circuit_state != CircuitState::Closed {
+                reasoning.push(format!("{} circuit is {}, probing only", provider.name(), circuit_state));
+            }
         }
 
         if selected.len() > 1 {
@@ -318,43 +596,120 @@ impl AdvancedRouter {
 
         reasoning
     }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_weights_layer_and_normalize() {
+        let router = AdvancedRouter::new();
+        let preferences = RoutingPreferences {
+            weight_overrides: vec![
+                RoutingWeightsConfig { cost: Some(0.8), ..Default::default() },
+                RoutingWeightsConfig { latency: Some(0.0), ..Default::default() },
+            ],
+            provider_multipliers: HashMap::new(),
+        };
+
+        // general = (0.3, 0.4, 0.2, 0.1) -> (0.8, 0.0, 0.2, 0.1) / 1.1
+        let weights = router.effective_weights("general", &preferences);
+        assert!((weights.cost_weight - 0.8 / 1.1).abs() < 1e-6);
+        assert_eq!(weights.latency_weight, 0.0);
+        let total = weights.cost_weight + weights.latency_weight + weights.quality_weight + weights.reliability_weight;
+        assert!((total - 1.0).abs() < 1e-6);
+    }
 
-    pub fn update_metrics_from_response(
-        &mut self,
-        provider_id: &str,
-        latency_ms: u64,
-        success: bool,
-        cost_usd: f64,
-        tokens_used: u32,
-    ) {
-        // Check if we need to create default metrics first
-        if !self.metrics.contains_key(provider_id) {
-            let mut default = ProviderMetrics::default();
-            default.provider_id = provider_id.to_string();
-            let default_metrics = self.get_default_metrics_for_provider(provider_id, default);
-            self.metrics.insert(provider_id.to_string(), default_metrics);
-        }
+    #[test]
+    fn test_cost_weight_favours_cheaper_provider() {
+        let router = AdvancedRouter::new();
+        let cheap = router.get_default_metrics_for_provider("google", ProviderMetrics {
+            provider_id: "google".to_string(),
+            ..Default::default()
+        });
+        let premium = router.get_default_metrics_for_provider("anthropic", ProviderMetrics {
+            provider_id: "anthropic".to_string(),
+            ..Default::default()
+        });
+
+        let quality_first = RoutingStrategy { cost_weight: 0.0, latency_weight: 0.0, quality_weight: 1.0, reliability_weight: 0.0 };
+        let cost_first = RoutingStrategy { cost_weight: 1.0, latency_weight: 0.0, quality_weight: 0.0, reliability_weight: 0.0 };
+
+        assert!(router.calculate_provider_score(&premium, 3000, &quality_first) > router.calculate_provider_score(&cheap, 3000, &quality_first));
+        assert!(router.calculate_provider_score(&cheap, 3000, &cost_first) > router.calculate_provider_score(&premium, 3000, &cost_first));
+    }
 
-        // Now safely get the metrics for update
-        let metrics = self.metrics.get_mut(provider_id).unwrap();
+    #[test]
+    fn test_score_breakdown_applies_penalties() {
+        let mut router = AdvancedRouter::new();
+        let weights = RoutingStrategy::default().normalized();
+        let metrics = ProviderMetrics {
+            provider_id: "openai".to_string(),
+            current_load: 0.5,
+            ..Default::default()
+        };
 
-        // Update with exponential moving average
-        let alpha = 0.1; // Learning rate
-        metrics.avg_latency_ms = metrics.avg_latency_ms * (1.0 - alpha) + (latency_ms as f64) * alpha;
+        let breakdown = router.score_breakdown(&metrics, 3000, &weights);
+        assert!((breakdown.load_penalty - 0.9).abs() < 1e-9);
+        assert!((breakdown.total - breakdown.weighted * 0.9).abs() < 1e-9);
+        assert_eq!(router.calculate_provider_score(&metrics, 3000, &weights), breakdown.total);
 
-        if success {
-            metrics.success_rate = metrics.success_rate * (1.0 - alpha) + alpha;
-        } else {
-            metrics.success_rate = metrics.success_rate * (1.0 - alpha);
-        }
+        let queued = ProviderMetrics { queue_wait_ms: 2000.0, ..metrics.clone() };
+        assert!(router.score_breakdown(&queued, 3000, &weights).latency < breakdown.latency);
+
+        router.set_upstream_quota(Arc::new(UpstreamQuotaTracker::default()));
+        let exhausted = ProviderMetrics { upstream_headroom: 0.02, ..metrics.clone() };
+        assert!(router.score_breakdown(&exhausted, 3000, &weights).quota_penalty < 0.5);
+        assert_eq!(router.score_breakdown(&metrics, 3000, &weights).quota_penalty, 1.0);
+
+        router.set_circuit_state("openai", CircuitState::Open);
+        assert_eq!(router.score_breakdown(&metrics, 3000, &weights).total, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_learned_metrics_replace_defaults() {
+        use crate::{config::LearnedMetricsConfig, learned_metrics::{ErrorClass, Observation}};
+
+        let store = Arc::new(LearnedMetricsStore::new(LearnedMetricsConfig::default()));
+        let mut router = AdvancedRouter::new();
+        router.set_learned_metrics(store.clone());
 
-        // Update cost per token if we have data
-        if tokens_used > 0 {
-            let cost_per_1k = (cost_usd / tokens_used as f64) * 1000.0;
-            metrics.cost_per_1k_tokens = metrics.cost_per_1k_tokens * (1.0 - alpha) + cost_per_1k * alpha;
+        // Too few samples: the provider defaults still apply
+        store.record("openai", "gpt-4o", Observation::completion(250, 100, 1000, 0.001)).await;
+        assert_eq!(router.metrics_for("openai", "gpt-4o").await.avg_latency_ms, 1500.0);
+
+        for _ in 0..7 {
+            store.record("openai", "gpt-4o", Observation::completion(250, 100, 1000, 0.001)).await;
+        }
+        for _ in 0..2 {
+            store.record("openai", "gpt-4o", Observation::failure(ErrorClass::Timeout)).await;
         }
 
-        debug!("Updated metrics for {}: latency={:.1}ms, success_rate={:.3}, cost_per_1k=${:.4}",
-               provider_id, metrics.avg_latency_ms, metrics.success_rate, metrics.cost_per_1k_tokens);
+        let metrics = router.metrics_for("openai", "gpt-4o").await;
+        assert!(metrics.avg_latency_ms < 300.0);
+        assert!((metrics.success_rate - 0.8).abs() < 1e-3);
+        assert!((metrics.cost_per_1k_tokens - 0.001).abs() < 1e-6);
+
+        // Other models of the provider fall back to its overall statistics
+        assert!(router.metrics_for("openai", "gpt-4o-mini").await.avg_latency_ms < 300.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_configured_intent_labels() {
+        let mut labels = HashMap::new();
+        labels.insert("sql".to_string(), IntentLabelConfig {
+            latency_target_ms: Some(1500),
+            weights: RoutingWeightsConfig { latency: Some(0.9), ..Default::default() },
+            ..Default::default()
+        });
+
+        let mut router = AdvancedRouter::new();
+        router.set_intent_labels(&labels);
+        assert_eq!(router.latency_targets.get("sql"), Some(&1500));
+
+        // Unset dimensions keep the default strategy's weights
+        let (cost, latency, _, _) = router.get_intent_weights("sql");
+        assert_eq!(latency, 0.9);
+        assert_eq!(cost, router.get_intent_weights("general").0);
+    }
+}
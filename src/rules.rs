@@ -0,0 +1,463 @@
+//! Declarative routing rules
+//!
+//! `[[routing.rules]]` in omen.toml is an ordered list of rules evaluated
+//! against each request before provider scoring. A rule matches on the API
+//! key or user, tags, intent, requested model, estimated prompt tokens,
+//! images, tools and time of day, and can route to a provider or model, set
+//! the strategy, cap the budget, add tags or deny the request.
+//!
+//! Every matching rule applies, with earlier rules taking precedence for
+//! each action; `deny` and `stop` end evaluation. The evaluation keeps a
+//! trace of every rule and why it did or didn't match, for debugging.
+
+use crate::{
+    budget,
+    config::{RoutingRuleConfig, RuleMatchConfig},
+    error::{OmenError, Result},
+    types::{ChatCompletionRequest, OmenConfig, RequestContext},
+};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// One rule's outcome for a request
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTraceEntry {
+    pub rule: String,
+    pub matched: bool,
+    /// First condition that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Actions this rule applied; actions an earlier rule already set are skipped
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+}
+
+/// Result of evaluating the rules for a request
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RuleEvaluation {
+    pub trace: Vec<RuleTraceEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denied: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_usd: Option<f64>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>,
+}
+
+impl RuleEvaluation {
+    pub fn matched_rules(&self) -> Vec<&str> {
+        self.trace.iter().filter(|t| t.matched).map(|t| t.rule.as_str()).collect()
+    }
+
+    /// Rewrite the request and context with the rules' actions
+    pub fn apply(&self, request: &mut ChatCompletionRequest, context: &mut RequestContext) -> Result<()> {
+        if let Some(ref message) = self.denied {
+            return Err(OmenError::Forbidden(message.clone()));
+        }
+
+        if let Some(ref model) = self.model {
+            request.model = model.clone();
+        }
+
+        // Only the options a rule sets; the rest keep their request defaults
+        let needs_omen = self.provider.is_some() || self.strategy.is_some() || self.k.is_some() || self.budget_usd.is_some();
+        if needs_omen {
+            let omen = request.omen.get_or_insert_with(OmenConfig::empty);
+            if let Some(ref provider) = self.provider {
+                omen.providers = Some(vec![provider.clone()]);
+            }
+            if let Some(ref strategy) = self.strategy {
+                omen.strategy = Some(strategy.clone());
+            }
+            if let Some(k) = self.k {
+                omen.k = Some(k);
+            }
+            if let Some(cap) = self.budget_usd {
+                omen.budget_usd = Some(omen.budget_usd.map_or(cap, |budget| budget.min(cap)));
+            }
+        }
+
+        context.tags.extend(self.tags.clone());
+        let matched = self.matched_rules();
+        if !matched.is_empty() {
+            context.tags.insert("rules".to_string(), matched.join(","));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    config: RoutingRuleConfig,
+    matcher: RequestMatcher,
+}
+
+/// Compiled `match` conditions, shared by routing rules and mirroring policies
+#[derive(Debug, Clone)]
+pub struct RequestMatcher {
+    matches: RuleMatchConfig,
+    hours: Option<(NaiveTime, NaiveTime)>,
+    days: Vec<Weekday>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuleEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleEngine {
+    /// Compile the configured rules, rejecting malformed time windows and days
+    pub fn new(rules: &[RoutingRuleConfig]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let matcher = RequestMatcher::new(&format!("Rule '{}'", rule.name), &rule.matches)?;
+                Ok(CompiledRule { config: rule.clone(), matcher })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn evaluate(&self, request: &ChatCompletionRequest, context: &RequestContext, now: DateTime<Utc>) -> RuleEvaluation {
+        let mut evaluation = RuleEvaluation::default();
+        // Counted once, on the first rule with a token condition
+        let mut tokens = None;
+
+        for rule in &self.rules {
+            let reason = rule.matcher.mismatch(request, context, now, &mut tokens);
+            let mut entry = RuleTraceEntry {
+                rule: rule.config.name.clone(),
+                matched: reason.is_none(),
+                reason,
+                actions: Vec::new(),
+            };
+            if !entry.matched {
+                evaluation.trace.push(entry);
+                continue;
+            }
+
+            let config = &rule.config;
+            if let Some(ref message) = config.deny {
+                entry.actions.push("deny".to_string());
+                evaluation.denied = Some(message.clone());
+                evaluation.trace.push(entry);
+                break;
+            }
+            if let Some(ref route) = config.route {
+                if let Some(ref provider) = route.provider
+                    && evaluation.provider.is_none()
+                {
+                    entry.actions.push(format!("provider={}", provider));
+                    evaluation.provider = Some(provider.clone());
+                }
+                if let Some(ref model) = route.model
+                    && evaluation.model.is_none()
+                {
+                    entry.actions.push(format!("model={}", model));
+                    evaluation.model = Some(model.clone());
+                }
+            }
+            if let Some(ref strategy) = config.strategy
+                && evaluation.strategy.is_none()
+            {
+                entry.actions.push(format!("strategy={}", strategy));
+                evaluation.strategy = Some(strategy.clone());
+            }
+            if let Some(k) = config.k
+                && evaluation.k.is_none()
+            {
+                entry.actions.push(format!("k={}", k));
+                evaluation.k = Some(k);
+            }
+            if let Some(budget_usd) = config.budget_usd
+                && evaluation.budget_usd.is_none()
+            {
+                entry.actions.push(format!("budget_usd={}", budget_usd));
+                evaluation.budget_usd = Some(budget_usd);
+            }
+            for (key, value) in &config.add_tags {
+                if !evaluation.tags.contains_key(key) {
+                    entry.actions.push(format!("tag {}={}", key, value));
+                    evaluation.tags.insert(key.clone(), value.clone());
+                }
+            }
+
+            evaluation.trace.push(entry);
+            if config.stop {
+                break;
+            }
+        }
+
+        evaluation
+    }
+}
+
+impl RequestMatcher {
+    /// Compile the conditions, rejecting malformed time windows and days.
+    /// `owner` names the rule or policy in error messages.
+    pub fn new(owner: &str, matches: &RuleMatchConfig) -> Result<Self> {
+        let hours = matches
+            .hours
+            .as_deref()
+            .map(|hours| {
+                parse_hours(hours).ok_or_else(|| {
+                    OmenError::Config(format!("{}: invalid hours '{}', expected HH:MM-HH:MM", owner, hours))
+                })
+            })
+            .transpose()?;
+        let days = matches
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| OmenError::Config(format!("{}: invalid day '{}'", owner, day)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { matches: matches.clone(), hours, days })
+    }
+
+    /// The first condition the request fails, if any. `tokens` caches the
+    /// prompt token count across matchers.
+    pub fn mismatch(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        now: DateTime<Utc>,
+        tokens: &mut Option<u32>,
+    ) -> Option<String> {
+        let when = &self.matches;
+
+        if !when.api_keys.is_empty() {
+            let api_key = context.tags.get("api_key_name");
+            if !api_key.is_some_and(|key| when.api_keys.contains(key)) {
+                return Some(format!("api key {:?} not in {:?}", api_key, when.api_keys));
+            }
+        }
+        if !when.users.is_empty() && !context.user_id.as_ref().is_some_and(|user| when.users.contains(user)) {
+            return Some(format!("user {:?} not in {:?}", context.user_id, when.users));
+        }
+        for (key, value) in &when.tags {
+            let tag = context
+                .tags
+                .get(key)
+                .or_else(|| request.tags.as_ref().and_then(|tags| tags.get(key)));
+            if tag != Some(value) {
+                return Some(format!("tag {}={:?}, wanted {:?}", key, tag, value));
+            }
+        }
+        if !when.intents.is_empty() && !context.intent.as_ref().is_some_and(|intent| when.intents.contains(intent)) {
+            return Some(format!("intent {:?} not in {:?}", context.intent, when.intents));
+        }
+        if !when.models.is_empty() && !when.models.iter().any(|pattern| glob_match(pattern, &request.model)) {
+            return Some(format!("model {} not in {:?}", request.model, when.models));
+        }
+        if when.min_tokens.is_some() || when.max_tokens.is_some() {
+            let tokens = *tokens.get_or_insert_with(|| budget::count_request_tokens(request));
+            if let Some(min) = when.min_tokens
+                && tokens < min
+            {
+                return Some(format!("{} prompt tokens below {}", tokens, min));
+            }
+            if let Some(max) = when.max_tokens
+                && tokens > max
+            {
+                return Some(format!("{} prompt tokens above {}", tokens, max));
+            }
+        }
+        if let Some(wanted) = when.has_images {
+            let has_images = request.messages.iter().any(|m| m.content.has_images());
+            if has_images != wanted {
+                return Some(format!("has_images is {}", has_images));
+            }
+        }
+        if let Some(wanted) = when.has_tools {
+            let has_tools = request.tools.as_ref().is_some_and(|tools| !tools.is_empty());
+            if has_tools != wanted {
+                return Some(format!("has_tools is {}", has_tools));
+            }
+        }
+        if let Some((start, end)) = self.hours {
+            let time = now.time();
+            let inside = if start <= end {
+                time >= start && time < end
+            } else {
+                time >= start || time < end
+            };
+            if !inside {
+                return Some(format!("{} UTC outside {}-{}", time.format("%H:%M"), start.format("%H:%M"), end.format("%H:%M")));
+            }
+        }
+        if !self.days.is_empty() && !self.days.contains(&now.weekday()) {
+            return Some(format!("{} not in {:?}", now.weekday(), self.matches.days));
+        }
+
+        None
+    }
+}
+
+fn parse_hours(hours: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = hours.split_once('-')?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+    Some((start, end))
+}
+
+/// Wildcard match where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RuleMatchConfig, RuleRouteConfig};
+    use chrono::TimeZone;
+
+    fn request(model: &str) -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": model,
+            "messages": [{"role": "user", "content": "hello"}],
+        }))
+        .unwrap()
+    }
+
+    fn context(api_key: &str, intent: &str) -> RequestContext {
+        RequestContext {
+            request_id: uuid::Uuid::new_v4(),
+            user_id: Some("alice".to_string()),
+            api_key: None,
+            intent: Some(intent.to_string()),
+            tags: HashMap::from([("api_key_name".to_string(), api_key.to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("gpt-4*", "gpt-4o-mini"));
+        assert!(glob_match("*sonnet*", "claude-3-5-sonnet-latest"));
+        assert!(glob_match("llama3", "llama3"));
+        assert!(!glob_match("gpt-4*", "o1-gpt-4"));
+        assert!(!glob_match("llama3", "llama3.1"));
+    }
+
+    #[test]
+    fn test_earlier_rules_take_precedence_and_trace_explains() {
+        let rules = vec![
+            RoutingRuleConfig {
+                name: "ci-keys-local".to_string(),
+                matches: RuleMatchConfig { api_keys: vec!["ci".to_string()], ..Default::default() },
+                route: Some(RuleRouteConfig { provider: Some("ollama".to_string()), model: Some("llama3".to_string()) }),
+                ..Default::default()
+            },
+            RoutingRuleConfig {
+                name: "code-to-claude".to_string(),
+                matches: RuleMatchConfig { intents: vec!["code".to_string()], ..Default::default() },
+                route: Some(RuleRouteConfig { provider: Some("anthropic".to_string()), model: None }),
+                budget_usd: Some(0.05),
+                add_tags: HashMap::from([("team".to_string(), "dev".to_string())]),
+                ..Default::default()
+            },
+        ];
+        let engine = RuleEngine::new(&rules).unwrap();
+
+        let evaluation = engine.evaluate(&request("gpt-4o"), &context("ci", "code"), Utc::now());
+        assert_eq!(evaluation.provider.as_deref(), Some("ollama"));
+        assert_eq!(evaluation.model.as_deref(), Some("llama3"));
+        assert_eq!(evaluation.budget_usd, Some(0.05));
+        assert_eq!(evaluation.trace[1].actions, ["budget_usd=0.05", "tag team=dev"]);
+
+        let mut routed = request("gpt-4o");
+        let mut routed_context = context("ci", "code");
+        evaluation.apply(&mut routed, &mut routed_context).unwrap();
+        assert_eq!(routed.model, "llama3");
+        assert_eq!(routed.omen.unwrap().providers, Some(vec!["ollama".to_string()]));
+        assert_eq!(routed_context.tags["rules"], "ci-keys-local,code-to-claude");
+
+        let evaluation = engine.evaluate(&request("gpt-4o"), &context("web", "general"), Utc::now());
+        assert!(evaluation.matched_rules().is_empty());
+        assert!(evaluation.trace[1].reason.as_ref().unwrap().contains("intent"));
+    }
+
+    #[test]
+    fn test_provider_rule_sets_only_providers() {
+        let rules = vec![RoutingRuleConfig {
+            name: "pin-anthropic".to_string(),
+            route: Some(RuleRouteConfig { provider: Some("anthropic".to_string()), model: None }),
+            ..Default::default()
+        }];
+        let engine = RuleEngine::new(&rules).unwrap();
+
+        let mut routed = request("gpt-4o");
+        let mut routed_context = context("web", "general");
+        engine.evaluate(&routed, &routed_context, Utc::now()).apply(&mut routed, &mut routed_context).unwrap();
+
+        let omen = routed.omen.unwrap();
+        assert_eq!(omen.providers, Some(vec!["anthropic".to_string()]));
+        assert!(omen.strategy.is_none() && omen.k.is_none() && omen.budget_usd.is_none());
+        assert!(omen.stickiness.is_none() && omen.max_latency_ms.is_none() && omen.min_useful_tokens.is_none());
+    }
+
+    #[test]
+    fn test_deny_within_time_window() {
+        let rules = vec![RoutingRuleConfig {
+            name: "night-only-batch".to_string(),
+            matches: RuleMatchConfig {
+                tags: HashMap::from([("batch".to_string(), "true".to_string())]),
+                hours: Some("22:00-06:00".to_string()),
+                days: vec!["sat".to_string(), "sun".to_string()],
+                ..Default::default()
+            },
+            deny: Some("Batch jobs are paused on weekend nights".to_string()),
+            ..Default::default()
+        }];
+        let engine = RuleEngine::new(&rules).unwrap();
+
+        let mut ctx = context("batch", "general");
+        ctx.tags.insert("batch".to_string(), "true".to_string());
+        let saturday_night = Utc.with_ymd_and_hms(2026, 10, 17, 23, 30, 0).unwrap();
+        let saturday_noon = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+
+        let evaluation = engine.evaluate(&request("gpt-4o"), &ctx, saturday_night);
+        assert!(evaluation.denied.is_some());
+        assert!(matches!(evaluation.apply(&mut request("gpt-4o"), &mut ctx.clone()), Err(OmenError::Forbidden(_))));
+        assert!(engine.evaluate(&request("gpt-4o"), &ctx, saturday_noon).denied.is_none());
+
+        let bad = RoutingRuleConfig {
+            name: "bad".to_string(),
+            matches: RuleMatchConfig { hours: Some("9am-5pm".to_string()), ..Default::default() },
+            ..Default::default()
+        };
+        assert!(RuleEngine::new(&[bad]).is_err());
+    }
+}
@@ -1,12 +1,13 @@
-use crate::{auth, config::Config, error::Result, router::OmenRouter, types::*};
+use crate::{auth, config::Config, error::{OmenError, Result}, grpc, realtime, router::OmenRouter, stickiness, types::*};
 use axum::{
-    extract::{Extension, Path, Query, State},
-    http::{Method, StatusCode},
+    extract::{ws::WebSocketUpgrade, Extension, Path, Query, State},
+    http::{request::Parts, HeaderMap, HeaderValue, Method, StatusCode},
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 use std::{collections::HashMap, sync::Arc};
+use tokio_util::sync::CancellationToken;
 use tower_http::{
     compression::CompressionLayer,
     cors::{Any, CorsLayer},
@@ -23,6 +24,7 @@ pub struct Server {
 impl Server {
     pub async fn new(config: Config) -> Result<Self> {
         let router = Arc::new(OmenRouter::new(config.clone()).await?);
+        router.start_background_tasks();
         let auth_service = Arc::new(auth::AuthService::new(Arc::new(config.clone())));
 
         Ok(Self { config, router, auth_service })
@@ -34,14 +36,42 @@ impl Server {
         let http_addr = format!("{}:{}", self.config.server.bind, self.config.server.port);
 
         info!("🌟 OMEN HTTP API listening on {}", http_addr);
-        // TODO: Re-enable gRPC server after fixing compilation issues
-        // info!("🚀 OMEN gRPC API will be available on port {}", self.config.server.port + 1);
+
+        // One signal drains both servers
+        let shutdown = CancellationToken::new();
+        tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown_signal().await;
+                shutdown.cancel();
+            }
+        });
 
         let listener = tokio::net::TcpListener::bind(&http_addr).await?;
-        axum::serve(listener, http_app)
-            .with_graceful_shutdown(shutdown_signal())
-            .await?;
+        let http = async {
+            axum::serve(listener, http_app)
+                .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+                .await
+                .map_err(OmenError::from)
+        };
+
+        if !self.config.grpc.enabled {
+            return http.await;
+        }
 
+        let grpc_addr = format!("{}:{}", self.config.server.bind, self.config.grpc.port);
+        // The gRPC API is an extra; HTTP keeps serving if its port is taken
+        let grpc_listener = match tokio::net::TcpListener::bind(&grpc_addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("⚠️ gRPC API not started: could not bind {}: {}", grpc_addr, e);
+                return http.await;
+            }
+        };
+        let grpc = grpc::start_grpc_server(self.router.clone(), self.auth_service.clone(), grpc_listener, shutdown.clone().cancelled_owned());
+
+        // Serve both until shutdown; if either fails, the other is dropped with it
+        tokio::try_join!(http, grpc)?;
         Ok(())
     }
 
@@ -57,17 +87,22 @@ impl Server {
         let protected_routes = Router::new()
             .route("/v1/models", get(list_models))
             .route("/v1/chat/completions", post(chat_completions))
+            .route("/v1/realtime", get(realtime_chat))
             .route("/v1/completions", post(completions))
             .route("/v1/embeddings", post(embeddings))
             .route("/omen/providers", get(list_providers))
             .route("/omen/providers/:id/health", get(provider_health))
             .route("/omen/providers/scores", get(provider_scores))
+            .route("/omen/rules/trace", post(rules_trace))
+            .route("/omen/route/explain", post(route_explain))
+            .route("/omen/experiments/feedback", post(experiment_feedback))
             .route("/admin/usage", get(usage_stats))
             .route("/admin/config", get(config_info))
             .route("/billing/usage", get(user_usage_stats))
             .route("/billing/tiers", get(billing_tiers))
             .route("/billing/tier", post(update_user_tier))
             .route("/admin/billing/summary", get(billing_summary))
+            .route("/admin/experiments", get(experiment_report))
             .route("/rate-limit/status", get(rate_limit_status))
             .route("/ghost/chat/completions", post(ghost_chat_completions))
             .route("/ghost/session/:session_id/stats", get(ghost_session_stats))
@@ -204,36 +239,92 @@ async fn chat_completions(
     let chat_request: ChatCompletionRequest = serde_json::from_slice(&bytes)
         .map_err(|e| crate::error::OmenError::InvalidRequest(format!("Invalid JSON: {}", e)))?;
 
-    // Extract auth info from request extensions (set by middleware)
-    let auth_info = parts.extensions.get::<auth::ApiKeyInfo>();
-    let context = auth::create_authenticated_context(auth_info, &chat_request);
+    let context = chat_context(&parts, &chat_request);
 
     if chat_request.stream {
         // Return SSE stream
-        let stream = router.stream_chat_completion(chat_request, context).await?;
+        let (stream, routing) = router.stream_chat_completion_routed(chat_request, context).await?;
 
         // Convert string stream to text/event-stream format
-        let text_stream = futures::stream::unfold(stream, |mut stream| async {
+        let text_stream = futures::stream::unfold(Some(stream), |stream| async {
             use futures::StreamExt;
+            let mut stream = stream?;
             match stream.next().await {
-                Some(Ok(data)) => Some((Ok::<String, std::io::Error>(data), stream)),
-                Some(Err(_)) => None, // End stream on error
+                Some(Ok(data)) => Some((Ok::<String, std::io::Error>(data), Some(stream))),
+                // Tell the client what went wrong, then end the stream
+                Some(Err(e)) => Some((Ok(e.to_api_error().to_sse_event()), None)),
                 None => None,
             }
         });
 
-        let response = Response::builder()
+        let mut response = Response::builder()
             .header("Content-Type", "text/event-stream")
             .header("Cache-Control", "no-cache")
             .header("Connection", "keep-alive")
             .body(axum::body::Body::from_stream(text_stream))
             .unwrap();
+        insert_routing_headers(response.headers_mut(), &routing);
 
         Ok(response)
     } else {
-        // Return regular JSON response
-        let response = router.chat_completion(chat_request, context).await?;
-        Ok(Json(response).into_response())
+        // Return regular JSON response; routing metadata stays in the body only when asked for
+        let explain = chat_request.omen.as_ref().and_then(|o| o.explain).unwrap_or(false);
+        let mut response = router.chat_completion(chat_request, context).await?;
+        let routing = match response.omen.as_mut() {
+            Some(omen) if explain => omen.routing.clone(),
+            Some(omen) => omen.routing.take(),
+            None => None,
+        };
+        if response.omen.as_ref().is_some_and(|omen| omen.is_empty()) {
+            response.omen = None;
+        }
+
+        let mut response = Json(response).into_response();
+        if let Some(ref routing) = routing {
+            insert_routing_headers(response.headers_mut(), routing);
+        }
+        Ok(response)
+    }
+}
+
+/// WebSocket chat: many concurrent streaming completions over one socket
+async fn realtime_chat(State(router): State<Arc<OmenRouter>>, parts: Parts, ws: WebSocketUpgrade) -> Response {
+    let config = router.config().realtime.clone();
+    ws.on_upgrade(move |socket| {
+        realtime::serve(socket, router, config, move |request| chat_context(&parts, request))
+    })
+}
+
+/// Request context from the authenticated key and the session header
+fn chat_context(parts: &Parts, chat_request: &ChatCompletionRequest) -> RequestContext {
+    // Extract auth info from request extensions (set by middleware)
+    let auth_info = parts.extensions.get::<auth::ApiKeyInfo>();
+    let mut context = auth::create_authenticated_context(auth_info, chat_request);
+
+    // Session id for provider stickiness
+    if let Some(session_id) = [stickiness::SESSION_HEADER, "x-session-id"]
+        .iter()
+        .find_map(|name| parts.headers.get(*name).and_then(|v| v.to_str().ok()))
+    {
+        context.tags.insert("session_id".to_string(), session_id.to_string());
+    }
+    context
+}
+
+/// `x-omen-*` headers describing where a request was routed
+fn insert_routing_headers(headers: &mut HeaderMap, routing: &RoutingMetadata) {
+    let values = [
+        ("x-omen-provider", routing.provider.clone()),
+        ("x-omen-model", routing.model.clone()),
+        ("x-omen-strategy", Some(routing.strategy.clone())),
+        ("x-omen-cost-usd", routing.cost_usd.map(|cost| format!("{:.6}", cost))),
+        ("x-omen-max-tokens", routing.max_tokens_clamped.map(|tokens| tokens.to_string())),
+        ("x-omen-cache", Some(routing.cache.clone())),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value.and_then(|v| HeaderValue::from_str(&v).ok()) {
+            headers.insert(name, value);
+        }
     }
 }
 
@@ -303,21 +394,31 @@ async fn embeddings(
 
 async fn list_providers(State(router): State<Arc<OmenRouter>>) -> Result<Json<serde_json::Value>> {
     let providers = router.get_provider_health().await?;
+    let circuit_breakers = router.get_circuit_breakers().await;
 
     Ok(Json(serde_json::json!({
-        "providers": providers
+        "providers": providers,
+        "circuit_breakers": circuit_breakers
     })))
 }
 
 async fn provider_health(
     State(router): State<Arc<OmenRouter>>,
     Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<serde_json::Value>> {
-    let health = router.check_provider_health(&id).await?;
+    // ?refresh=true forces a live probe instead of serving the last snapshot
+    if params.get("refresh").is_some_and(|v| v == "true") {
+        router.check_provider_health(&id).await?;
+    }
+
+    let snapshot = router.get_provider_health_snapshot(&id).await?;
 
     Ok(Json(serde_json::json!({
         "provider_id": id,
-        "healthy": health,
+        "healthy": snapshot.as_ref().is_some_and(|s| s.healthy),
+        "checked": snapshot.is_some(),
+        "snapshot": snapshot,
         "timestamp": chrono::Utc::now().to_rfc3339()
     })))
 }
@@ -329,6 +430,39 @@ async fn provider_scores(
     Ok(Json(scores))
 }
 
+/// Evaluate the routing rules for a chat request without sending it
+async fn rules_trace(
+    State(router): State<Arc<OmenRouter>>,
+    request: axum::http::Request<axum::body::Body>,
+) -> Result<Json<crate::rules::RuleEvaluation>> {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await
+        .map_err(|e| crate::error::OmenError::InvalidRequest(format!("Failed to read request body: {}", e)))?;
+
+    let chat_request: ChatCompletionRequest = serde_json::from_slice(&bytes)
+        .map_err(|e| crate::error::OmenError::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+
+    let auth_info = parts.extensions.get::<auth::ApiKeyInfo>();
+    let context = auth::create_authenticated_context(auth_info, &chat_request);
+    Ok(Json(router.trace_rules(&chat_request, context).await))
+}
+
+/// Score every routing candidate for a chat request without sending it
+async fn route_explain(
+    State(router): State<Arc<OmenRouter>>,
+    request: axum::http::Request<axum::body::Body>,
+) -> Result<Json<RouteExplainResponse>> {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await
+        .map_err(|e| crate::error::OmenError::InvalidRequest(format!("Failed to read request body: {}", e)))?;
+
+    let chat_request: ChatCompletionRequest = serde_json::from_slice(&bytes)
+        .map_err(|e| crate::error::OmenError::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+
+    let context = chat_context(&parts, &chat_request);
+    Ok(Json(router.explain_route(chat_request, context).await?))
+}
+
 async fn usage_stats(
     State(_router): State<Arc<OmenRouter>>,
     Query(_params): Query<HashMap<String, String>>,
@@ -413,6 +547,22 @@ async fn billing_summary(
     Ok(Json(summary))
 }
 
+// Experiment endpoints
+async fn experiment_report(
+    State(router): State<Arc<OmenRouter>>,
+) -> Result<Json<Vec<crate::experiments::ExperimentReport>>> {
+    Ok(Json(router.get_experiment_report().await))
+}
+
+async fn experiment_feedback(
+    State(router): State<Arc<OmenRouter>>,
+    Extension(auth_info): Extension<crate::auth::ApiKeyInfo>,
+    Json(feedback): Json<ExperimentFeedback>,
+) -> Result<StatusCode> {
+    router.record_experiment_feedback(&feedback, &auth_info.user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 // Rate limiting endpoints
 async fn rate_limit_status(
     State(router): State<Arc<OmenRouter>>,
@@ -0,0 +1,505 @@
+//! Shadow traffic mirroring
+//!
+//! `[[shadow.policies]]` mirror a sampled share of matching requests to
+//! shadow providers, to compare a candidate model on real traffic before
+//! switching to it. Shadow calls start alongside the primary, run in the
+//! background and never change what the client receives; they bypass
+//! billing, rate limits and request budgets. Each primary/shadow pair is
+//! stored in the `shadow_comparisons` SQLite table with both outputs,
+//! latencies and costs.
+//!
+//! Non-streaming and single-provider streaming requests are mirrored;
+//! multiplexed streams have no single primary to compare against.
+
+use crate::{
+    budget::{PricingCache, StreamBudget},
+    config::{ShadowConfig, ShadowPolicyConfig},
+    error::{OmenError, Result},
+    merge,
+    model_catalog::ModelCatalog,
+    providers::{Provider, ProviderRegistry},
+    rules::RequestMatcher,
+    storage,
+    types::{ChatCompletionRequest, ChatCompletionResponse, RequestContext},
+};
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::{sync::Arc, time::{Duration, Instant}};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, oneshot};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// One side of a comparison: what a provider returned and what it cost
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CallOutcome {
+    pub provider_id: String,
+    pub model: String,
+    pub output: Option<String>,
+    pub latency_ms: u64,
+    pub cost_usd: f64,
+    pub error: Option<String>,
+}
+
+impl CallOutcome {
+    pub fn completed(provider_id: &str, model: &str, response: &ChatCompletionResponse, latency_ms: u64, cost_usd: f64) -> Self {
+        Self {
+            provider_id: provider_id.to_string(),
+            model: model.to_string(),
+            output: response.choices.first().map(|choice| choice.message.content.text()),
+            latency_ms,
+            cost_usd,
+            error: None,
+        }
+    }
+
+    fn failed(provider_id: &str, model: &str, latency_ms: u64, error: String) -> Self {
+        Self {
+            provider_id: provider_id.to_string(),
+            model: model.to_string(),
+            latency_ms,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+}
+
+/// A shadow call side by side with the primary it mirrored
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowComparison {
+    pub request_id: Uuid,
+    pub policy: String,
+    pub primary: CallOutcome,
+    pub shadow: CallOutcome,
+}
+
+/// Hands the primary's outcome to the request's shadow calls. Dropping it
+/// without completing records the primary as failed.
+pub struct ShadowHandle {
+    sender: oneshot::Sender<CallOutcome>,
+}
+
+impl ShadowHandle {
+    pub fn complete(self, primary: CallOutcome) {
+        let _ = self.sender.send(primary);
+    }
+}
+
+#[derive(Debug)]
+pub struct ShadowMirror {
+    config: ShadowConfig,
+    policies: Vec<(ShadowPolicyConfig, RequestMatcher)>,
+    providers: Arc<ProviderRegistry>,
+    pricing: Arc<PricingCache>,
+    model_catalog: Arc<ModelCatalog>,
+    in_flight: Arc<Semaphore>,
+    pool: Option<SqlitePool>,
+}
+
+impl ShadowMirror {
+    /// Compile the mirroring policies, rejecting bad sample rates and match conditions
+    pub fn new(
+        config: ShadowConfig,
+        providers: Arc<ProviderRegistry>,
+        pricing: Arc<PricingCache>,
+        model_catalog: Arc<ModelCatalog>,
+    ) -> Result<Self> {
+        let policies = config
+            .policies
+            .iter()
+            .map(|policy| {
+                let owner = format!("Shadow policy '{}'", policy.name);
+                if !(0.0..=100.0).contains(&policy.sample_percent) {
+                    return Err(OmenError::Config(format!(
+                        "{}: sample_percent {} outside 0-100",
+                        owner, policy.sample_percent
+                    )));
+                }
+                Ok((policy.clone(), RequestMatcher::new(&owner, &policy.matches)?))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight)),
+            config,
+            policies,
+            providers,
+            pricing,
+            model_catalog,
+            pool: None,
+        })
+    }
+
+    /// Mirror with comparisons stored in the SQLite database at `db_url`.
+    /// Without the database, comparisons are only logged.
+    pub async fn open(
+        config: ShadowConfig,
+        providers: Arc<ProviderRegistry>,
+        pricing: Arc<PricingCache>,
+        model_catalog: Arc<ModelCatalog>,
+        db_url: &str,
+    ) -> Result<Self> {
+        let mut mirror = Self::new(config, providers, pricing, model_catalog)?;
+        if mirror.policies.is_empty() {
+            return Ok(mirror);
+        }
+
+        match Self::connect(db_url).await {
+            Ok(pool) => {
+                info!("🪞 Shadow mirroring enabled with {} policies", mirror.policies.len());
+                mirror.pool = Some(pool);
+            }
+            Err(e) => warn!("Shadow comparison database unavailable ({}), comparisons will only be logged", e),
+        }
+        Ok(mirror)
+    }
+
+    async fn connect(db_url: &str) -> Result<SqlitePool> {
+        let pool = storage::open_sqlite(db_url).await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS shadow_comparisons (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                request_id TEXT NOT NULL,
+                policy TEXT NOT NULL,
+                primary_provider TEXT NOT NULL,
+                primary_model TEXT NOT NULL,
+                primary_output TEXT,
+                primary_latency_ms INTEGER NOT NULL,
+                primary_cost_usd REAL NOT NULL,
+                primary_error TEXT,
+                shadow_provider TEXT NOT NULL,
+                shadow_model TEXT NOT NULL,
+                shadow_output TEXT,
+                shadow_latency_ms INTEGER NOT NULL,
+                shadow_cost_usd REAL NOT NULL,
+                shadow_error TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| OmenError::Database(format!("Failed to create shadow_comparisons table: {}", e)))?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_shadow_comparisons_policy ON shadow_comparisons(policy, created_at)")
+            .execute(&pool)
+            .await
+            .map_err(|e| OmenError::Database(format!("Failed to index shadow_comparisons: {}", e)))?;
+
+        Ok(pool)
+    }
+
+    /// The first policy matching the request, if it samples this request
+    fn policy_for(&self, request: &ChatCompletionRequest, context: &RequestContext) -> Option<&ShadowPolicyConfig> {
+        let now = Utc::now();
+        let mut tokens = None;
+        let (policy, _) = self
+            .policies
+            .iter()
+            .find(|(_, matcher)| matcher.mismatch(request, context, now, &mut tokens).is_none())?;
+        sampled(context.request_id, policy.sample_percent).then_some(policy)
+    }
+
+    /// Start shadow calls for a request a policy selects. The handle takes
+    /// the primary's outcome; comparisons are stored once both sides finish.
+    pub fn start(self: &Arc<Self>, request: &ChatCompletionRequest, context: &RequestContext) -> Option<ShadowHandle> {
+        let policy = self.policy_for(request, context)?;
+
+        let mut calls = Vec::new();
+        for target in &policy.targets {
+            let Some(provider) = self.providers.get(&target.provider) else {
+                debug!("🪞 Shadow provider {} not configured, skipping", target.provider);
+                continue;
+            };
+            let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+                debug!("🪞 Too many shadow calls in flight, not mirroring request {}", context.request_id);
+                break;
+            };
+
+            let model = target.model.as_deref().unwrap_or(&request.model);
+            let mut shadow_request = request.clone();
+            shadow_request.model = self.model_catalog.provider_model_id(provider.id(), model).to_string();
+            shadow_request.stream = false;
+            shadow_request.omen = None;
+            calls.push((provider, shadow_request, permit));
+        }
+        if calls.is_empty() {
+            return None;
+        }
+
+        debug!("🪞 Mirroring request {} to {} shadow targets ({})", context.request_id, calls.len(), policy.name);
+        let (sender, receiver) = oneshot::channel();
+        let mirror = self.clone();
+        let policy = policy.name.clone();
+        let context = context.clone();
+        tokio::spawn(async move {
+            let shadows = futures::future::join_all(
+                calls
+                    .into_iter()
+                    .map(|(provider, shadow_request, permit)| mirror.call(provider, shadow_request, &context, permit)),
+            )
+            .await;
+            let primary = receiver
+                .await
+                .unwrap_or_else(|_| CallOutcome::failed("", "", 0, "primary request failed".to_string()));
+
+            for shadow in shadows {
+                let comparison = ShadowComparison {
+                    request_id: context.request_id,
+                    policy: policy.clone(),
+                    primary: primary.clone(),
+                    shadow,
+                };
+                if let Err(e) = mirror.store(&comparison).await {
+                    warn!("Failed to store shadow comparison: {}", e);
+                }
+            }
+        });
+
+        Some(ShadowHandle { sender })
+    }
+
+    async fn call(
+        &self,
+        provider: Arc<dyn Provider>,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+        _permit: OwnedSemaphorePermit,
+    ) -> CallOutcome {
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        let started = Instant::now();
+        let result = tokio::time::timeout(timeout, provider.chat_completion(&request, context)).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        match result {
+            Ok(Ok(response)) => {
+                let pricing = self.pricing.pricing(provider.as_ref(), &request.model).await;
+                let usage = &response.usage;
+                let cost_usd = merge::cost_usd(&pricing, usage.prompt_tokens, usage.completion_tokens);
+                CallOutcome::completed(provider.id(), &request.model, &response, latency_ms, cost_usd)
+            }
+            Ok(Err(e)) => CallOutcome::failed(provider.id(), &request.model, latency_ms, e.to_string()),
+            Err(_) => CallOutcome::failed(
+                provider.id(),
+                &request.model,
+                latency_ms,
+                format!("timed out after {}s", timeout.as_secs()),
+            ),
+        }
+    }
+
+    async fn store(&self, comparison: &ShadowComparison) -> Result<()> {
+        let (primary, shadow) = (&comparison.primary, &comparison.shadow);
+        info!(
+            "🪞 Shadow {} for {}: primary {}/{} {}ms ${:.5}, shadow {}/{} {}ms ${:.5}{}",
+            comparison.policy, comparison.request_id,
+            primary.provider_id, primary.model, primary.latency_ms, primary.cost_usd,
+            shadow.provider_id, shadow.model, shadow.latency_ms, shadow.cost_usd,
+            shadow.error.as_ref().map(|e| format!(" (error: {})", e)).unwrap_or_default()
+        );
+        let Some(ref pool) = self.pool else {
+            return Ok(());
+        };
+
+        let output = |outcome: &CallOutcome| outcome.output.clone().filter(|_| self.config.store_outputs);
+        sqlx::query(
+            r#"
+            INSERT INTO shadow_comparisons (
+                request_id, policy,
+                primary_provider, primary_model, primary_output, primary_latency_ms, primary_cost_usd, primary_error,
+                shadow_provider, shadow_model, shadow_output, shadow_latency_ms, shadow_cost_usd, shadow_error,
+                created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(comparison.request_id.to_string())
+        .bind(&comparison.policy)
+        .bind(&primary.provider_id)
+        .bind(&primary.model)
+        .bind(output(primary))
+        .bind(primary.latency_ms as i64)
+        .bind(primary.cost_usd)
+        .bind(&primary.error)
+        .bind(&shadow.provider_id)
+        .bind(&shadow.model)
+        .bind(output(shadow))
+        .bind(shadow.latency_ms as i64)
+        .bind(shadow.cost_usd)
+        .bind(&shadow.error)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .map_err(|e| OmenError::Database(format!("Failed to store shadow comparison: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+/// Whether a request falls in the sampled share; stable per request id
+fn sampled(request_id: Uuid, percent: f64) -> bool {
+    ((request_id.as_u128() % 10_000) as f64) < percent * 100.0
+}
+
+/// Pass a primary stream through, completing the shadow handle with the
+/// streamed text and metered cost when it ends
+pub fn tap_stream(
+    stream: Box<dyn Stream<Item = Result<String>> + Send + Unpin>,
+    handle: ShadowHandle,
+    provider_id: String,
+    model: String,
+    started: Instant,
+    meter: StreamBudget,
+) -> Box<dyn Stream<Item = Result<String>> + Send + Unpin> {
+    let tapped = futures::stream::unfold(
+        Some((stream, meter, String::new(), Some(handle))),
+        move |state| {
+            let (provider_id, model) = (provider_id.clone(), model.clone());
+            async move {
+                let (mut stream, mut meter, mut output, mut handle) = state?;
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        meter.record_chunk(&chunk);
+                        output.push_str(&delta_text(&chunk));
+                        Some((Ok(chunk), Some((stream, meter, output, handle))))
+                    }
+                    // Dropping the handle records the primary as failed
+                    Some(Err(e)) => Some((Err(e), None)),
+                    None => {
+                        if let Some(handle) = handle.take() {
+                            handle.complete(CallOutcome {
+                                provider_id,
+                                model,
+                                output: Some(output),
+                                latency_ms: started.elapsed().as_millis() as u64,
+                                cost_usd: meter.cost_usd(),
+                                error: None,
+                            });
+                        }
+                        None
+                    }
+                }
+            }
+        },
+    );
+
+    Box::new(Box::pin(tapped))
+}
+
+/// Generated text in an SSE chunk
+fn delta_text(chunk: &str) -> String {
+    chunk
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<serde_json::Value>(data.trim()).ok())
+        .flat_map(|event| {
+            event["choices"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|choice| choice["delta"]["content"].as_str().map(str::to_string))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, RuleMatchConfig, ShadowTargetConfig};
+    use std::collections::HashMap;
+
+    fn request(model: &str) -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": model,
+            "messages": [{ "role": "user", "content": "hello" }],
+        }))
+        .unwrap()
+    }
+
+    fn context() -> RequestContext {
+        RequestContext {
+            request_id: Uuid::new_v4(),
+            user_id: None,
+            api_key: None,
+            intent: Some("general".to_string()),
+            tags: HashMap::new(),
+        }
+    }
+
+    async fn build_mirror(policies: Vec<ShadowPolicyConfig>) -> Result<ShadowMirror> {
+        let config = Config::default();
+        let providers = Arc::new(ProviderRegistry::new(&config).await?);
+        let shadow = ShadowConfig { policies, ..Default::default() };
+        ShadowMirror::new(shadow, providers, Arc::new(PricingCache::new()), Arc::new(ModelCatalog::new(&config.model_catalog)))
+    }
+
+    #[test]
+    fn test_sampling_bounds() {
+        for _ in 0..100 {
+            assert!(!sampled(Uuid::new_v4(), 0.0));
+            assert!(sampled(Uuid::new_v4(), 100.0));
+        }
+        let hits = (0..10_000).filter(|_| sampled(Uuid::new_v4(), 25.0)).count();
+        assert!((2_000..3_000).contains(&hits));
+    }
+
+    #[tokio::test]
+    async fn test_first_matching_sampled_policy() {
+        let policy = |name: &str, models: &[&str], sample_percent: f64| ShadowPolicyConfig {
+            name: name.to_string(),
+            sample_percent,
+            matches: RuleMatchConfig { models: models.iter().map(|m| m.to_string()).collect(), ..Default::default() },
+            targets: vec![ShadowTargetConfig { provider: "ollama".to_string(), model: Some("llama3".to_string()) }],
+        };
+        let mirror = build_mirror(vec![
+            policy("claude", &["claude-*"], 100.0),
+            policy("gpt-off", &["gpt-*"], 0.0),
+        ])
+        .await
+        .unwrap();
+
+        assert_eq!(mirror.policy_for(&request("claude-3-5-sonnet"), &context()).unwrap().name, "claude");
+        assert!(mirror.policy_for(&request("gpt-4o"), &context()).is_none());
+        assert!(mirror.policy_for(&request("gemini-pro"), &context()).is_none());
+
+        assert!(build_mirror(vec![policy("bad", &[], 150.0)]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_comparisons_stored_side_by_side() {
+        let path = std::env::temp_dir().join(format!("omen-shadow-{}.db", Uuid::new_v4()));
+        let db_url = format!("sqlite://{}", path.display());
+        let mut mirror = build_mirror(Vec::new()).await.unwrap();
+        mirror.pool = Some(ShadowMirror::connect(&db_url).await.unwrap());
+
+        let outcome = |provider_id: &str, latency_ms: u64| CallOutcome {
+            provider_id: provider_id.to_string(),
+            model: "m".to_string(),
+            output: Some(format!("from {}", provider_id)),
+            latency_ms,
+            cost_usd: 0.002,
+            error: None,
+        };
+        let comparison = ShadowComparison {
+            request_id: Uuid::new_v4(),
+            policy: "trial".to_string(),
+            primary: outcome("openai", 800),
+            shadow: outcome("anthropic", 650),
+        };
+        mirror.store(&comparison).await.unwrap();
+
+        let row: (String, String, i64, String) = sqlx::query_as(
+            "SELECT primary_output, shadow_output, shadow_latency_ms, policy FROM shadow_comparisons",
+        )
+        .fetch_one(mirror.pool.as_ref().unwrap())
+        .await
+        .unwrap();
+        assert_eq!(row, ("from openai".to_string(), "from anthropic".to_string(), 650, "trial".to_string()));
+        assert_eq!(delta_text("data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n"), "hi");
+
+        let _ = std::fs::remove_file(path);
+    }
+}
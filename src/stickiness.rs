@@ -0,0 +1,376 @@
+//! Session and turn stickiness
+//!
+//! When a request carries a session id (the `x-omen-session-id` header or a
+//! `session_id` tag), the provider and model that served it are remembered
+//! and preferred for later requests in the same session. This keeps
+//! multi-turn conversations on one provider, which preserves prompt caching
+//! and gives consistent answers.
+//!
+//! - `session`: every later request in the session prefers the bound provider
+//! - `turn`: only follow-ups within the same turn (tool results) are pinned
+//! - `none`: no stickiness
+//!
+//! Bindings live in memory and, when Redis is enabled, in the session's
+//! `CachedSession` so they survive restarts and are shared across replicas.
+
+use crate::{
+    cache::RedisCache,
+    config::StickinessConfig,
+    types::{ChatCompletionRequest, RequestContext},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use tracing::debug;
+use uuid::Uuid;
+
+/// Header clients use to identify a conversation
+pub const SESSION_HEADER: &str = "x-omen-session-id";
+
+/// Key under which the binding is stored in `CachedSession::workflow_data`
+const WORKFLOW_KEY: &str = "omen_sticky";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StickinessMode {
+    None,
+    Turn,
+    Session,
+}
+
+impl StickinessMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(StickinessMode::None),
+            "turn" => Some(StickinessMode::Turn),
+            "session" => Some(StickinessMode::Session),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StickyBinding {
+    /// Model the client asked for (alias, canonical id or provider id)
+    pub requested_model: String,
+    pub provider_id: String,
+    /// Model id actually sent to the provider
+    pub model: String,
+    pub bound_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+    pub requests: u32,
+    /// Last request counted, so binding twice in one request counts once
+    #[serde(default)]
+    pub last_request: Option<Uuid>,
+}
+
+/// Identifies a sticky session, scoped to the calling user. Unauthenticated
+/// callers are scoped to the session id alone, never to a shared user.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+impl SessionKey {
+    /// Session id from the request context or request tags, if any
+    pub fn from_request(request: &ChatCompletionRequest, context: &RequestContext) -> Option<Self> {
+        let session_id = context
+            .tags
+            .get("session_id")
+            .or_else(|| request.tags.as_ref().and_then(|t| t.get("session_id")))?
+            .trim();
+        if session_id.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            user_id: context
+                .user_id
+                .clone()
+                .unwrap_or_else(|| format!("anonymous:{}", session_id)),
+            session_id: session_id.to_string(),
+        })
+    }
+
+    fn memory_key(&self) -> String {
+        format!("{}:{}", self.user_id, self.session_id)
+    }
+
+    /// Redis sessions are keyed by UUID, derived from the user and session id
+    /// (even one that is already a UUID) so tenants reusing an id never share
+    /// a session, and stable across restarts, replicas and toolchains
+    fn cache_uuid(&self) -> Uuid {
+        let digest = Sha256::new()
+            .chain_update(self.user_id.as_bytes())
+            .chain_update([0])
+            .chain_update(self.session_id.as_bytes())
+            .finalize();
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        Uuid::from_bytes(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub struct StickinessStore {
+    config: StickinessConfig,
+    cache: Option<Arc<RedisCache>>,
+    bindings: RwLock<HashMap<String, StickyBinding>>,
+}
+
+/// StickinessStore implementation - all public methods are part of the routing API
+#[allow(dead_code)]
+impl StickinessStore {
+    pub fn new(config: StickinessConfig, cache: Option<Arc<RedisCache>>) -> Self {
+        Self {
+            config,
+            cache,
+            bindings: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Effective mode for a request: the request's own setting, else the default
+    pub fn mode(&self, request: &ChatCompletionRequest) -> StickinessMode {
+        if !self.config.enabled {
+            return StickinessMode::None;
+        }
+
+        request
+            .omen
+            .as_ref()
+            .and_then(|o| o.stickiness.as_deref())
+            .and_then(StickinessMode::parse)
+            .or_else(|| StickinessMode::parse(&self.config.default_mode))
+            .unwrap_or(StickinessMode::None)
+    }
+
+    /// Whether the binding should be honoured for this request
+    pub fn applies(mode: StickinessMode, request: &ChatCompletionRequest) -> bool {
+        match mode {
+            StickinessMode::None => false,
+            StickinessMode::Session => true,
+            // Mid-turn follow-ups carry tool results back to the model
+            StickinessMode::Turn => request.messages.last().is_some_and(|m| m.role == "tool"),
+        }
+    }
+
+    fn ttl_seconds(&self, mode: StickinessMode) -> i64 {
+        match mode {
+            StickinessMode::Turn => self.config.turn_ttl_seconds as i64,
+            _ => self.config.session_ttl_seconds as i64,
+        }
+    }
+
+    /// The live binding for a session and requested model, if any
+    pub async fn get(&self, key: &SessionKey, requested_model: &str, mode: StickinessMode) -> Option<StickyBinding> {
+        let binding = match self.bindings.read().await.get(&key.memory_key()).cloned() {
+            Some(binding) => Some(binding),
+            None => self.load_from_cache(key).await,
+        }?;
+
+        let expired = (Utc::now() - binding.last_used).num_seconds() > self.ttl_seconds(mode);
+        if expired || binding.requested_model != requested_model {
+            return None;
+        }
+
+        Some(binding)
+    }
+
+    /// Remember the provider and model that served a session's request
+    pub async fn bind(&self, key: &SessionKey, request_id: Uuid, requested_model: &str, provider_id: &str, model: &str) {
+        let now = Utc::now();
+        let (binding, new_request) = {
+            let mut bindings = self.bindings.write().await;
+            let binding = bindings
+                .entry(key.memory_key())
+                .and_modify(|b| {
+                    if b.provider_id != provider_id || b.model != model || b.requested_model != requested_model {
+                        debug!("📌 Session {} rebinding {} -> {}/{}", key.session_id, b.provider_id, provider_id, model);
+                        b.provider_id = provider_id.to_string();
+                        b.model = model.to_string();
+                        b.requested_model = requested_model.to_string();
+                        b.bound_at = now;
+                        b.requests = 0;
+                    }
+                })
+                .or_insert_with(|| StickyBinding {
+                    requested_model: requested_model.to_string(),
+                    provider_id: provider_id.to_string(),
+                    model: model.to_string(),
+                    bound_at: now,
+                    last_used: now,
+                    requests: 0,
+                    last_request: None,
+                });
+            binding.last_used = now;
+            let new_request = binding.last_request != Some(request_id);
+            if new_request {
+                binding.requests += 1;
+                binding.last_request = Some(request_id);
+            }
+            (binding.clone(), new_request)
+        };
+
+        self.store_in_cache(key, &binding, new_request).await;
+    }
+
+    /// Forget a session's binding (e.g. after its provider failed)
+    pub async fn unbind(&self, key: &SessionKey) {
+        self.bindings.write().await.remove(&key.memory_key());
+    }
+
+    /// Drop expired in-memory bindings
+    pub async fn prune(&self) {
+        let ttl = self.config.session_ttl_seconds.max(self.config.turn_ttl_seconds) as i64;
+        let now = Utc::now();
+        self.bindings
+            .write()
+            .await
+            .retain(|_, b| (now - b.last_used).num_seconds() <= ttl);
+    }
+
+    async fn load_from_cache(&self, key: &SessionKey) -> Option<StickyBinding> {
+        let cache = self.cache.as_ref()?;
+        let session = cache.get_cached_session(key.cache_uuid()).await.ok()??;
+        if session.user_id != key.user_id {
+            return None;
+        }
+        let binding: StickyBinding = serde_json::from_value(session.workflow_data.get(WORKFLOW_KEY)?.clone()).ok()?;
+
+        self.bindings.write().await.insert(key.memory_key(), binding.clone());
+        Some(binding)
+    }
+
+    async fn store_in_cache(&self, key: &SessionKey, binding: &StickyBinding, new_request: bool) {
+        let Some(ref cache) = self.cache else {
+            return;
+        };
+
+        // Keep whatever else the session already holds (e.g. Ghost workflow data)
+        let session_id = key.cache_uuid();
+        let existing = cache.get_cached_session(session_id).await.ok().flatten();
+        if existing.as_ref().is_some_and(|session| session.user_id != key.user_id) {
+            debug!("Not caching sticky binding for session {}: cached session belongs to another user", key.session_id);
+            return;
+        }
+        let (service, mut workflow_data, request_count, total_cost) = match existing {
+            Some(session) => (session.service, session.workflow_data, session.request_count, session.total_cost),
+            None => ("omen".to_string(), serde_json::json!({}), 0, 0.0),
+        };
+
+        if !workflow_data.is_object() {
+            workflow_data = serde_json::json!({});
+        }
+        workflow_data[WORKFLOW_KEY] = serde_json::to_value(binding).unwrap_or_default();
+
+        if let Err(e) = cache
+            .cache_session(session_id, &service, &key.user_id, workflow_data, request_count + new_request as u32, total_cost)
+            .await
+        {
+            debug!("Failed to cache sticky binding for session {}: {}", key.session_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(stickiness: Option<&str>, last_role: &str) -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet",
+            "messages": [{"role": last_role, "content": "hi"}],
+            "omen": stickiness.map(|s| serde_json::json!({"stickiness": s})),
+        }))
+        .unwrap()
+    }
+
+    fn key() -> SessionKey {
+        SessionKey {
+            user_id: "user".to_string(),
+            session_id: "conversation-1".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_binding_round_trip() {
+        let store = StickinessStore::new(StickinessConfig::default(), None);
+        let mode = StickinessMode::Session;
+
+        assert!(store.get(&key(), "claude-sonnet", mode).await.is_none());
+        let request_id = Uuid::new_v4();
+        store.bind(&key(), request_id, "claude-sonnet", "bedrock", "anthropic.claude-sonnet-4-5-20250929-v1:0").await;
+        // Binding again for the same request doesn't count it twice
+        store.bind(&key(), request_id, "claude-sonnet", "bedrock", "anthropic.claude-sonnet-4-5-20250929-v1:0").await;
+
+        let binding = store.get(&key(), "claude-sonnet", mode).await.unwrap();
+        assert_eq!(binding.provider_id, "bedrock");
+        assert_eq!(binding.requests, 1);
+
+        store.bind(&key(), Uuid::new_v4(), "claude-sonnet", "bedrock", "anthropic.claude-sonnet-4-5-20250929-v1:0").await;
+        assert_eq!(store.get(&key(), "claude-sonnet", mode).await.unwrap().requests, 2);
+
+        // A different requested model doesn't reuse the binding
+        assert!(store.get(&key(), "gpt-4o", mode).await.is_none());
+    }
+
+    #[test]
+    fn test_anonymous_sessions_are_not_shared() {
+        let request = request(None, "user");
+        let context = |session_id: &str| RequestContext {
+            request_id: Uuid::new_v4(),
+            user_id: None,
+            api_key: None,
+            intent: None,
+            tags: HashMap::from([("session_id".to_string(), session_id.to_string())]),
+        };
+
+        let first = SessionKey::from_request(&request, &context("a")).unwrap();
+        let second = SessionKey::from_request(&request, &context("b")).unwrap();
+        assert_ne!(first.user_id, second.user_id);
+    }
+
+    #[test]
+    fn test_mode_resolution() {
+        let store = StickinessStore::new(StickinessConfig::default(), None);
+        assert_eq!(store.mode(&request(Some("turn"), "user")), StickinessMode::Turn);
+        assert_eq!(store.mode(&request(None, "user")), StickinessMode::Session);
+
+        assert!(!StickinessStore::applies(StickinessMode::Turn, &request(None, "user")));
+        assert!(StickinessStore::applies(StickinessMode::Turn, &request(None, "tool")));
+        assert!(!StickinessStore::applies(StickinessMode::None, &request(None, "tool")));
+    }
+
+    #[test]
+    fn test_derived_session_uuid_is_stable() {
+        assert_eq!(key().cache_uuid(), key().cache_uuid());
+        // Pinned so cached sessions survive upgrades
+        assert_eq!(key().cache_uuid().to_string(), "c266926b-5cae-43ac-6892-5ab0a5464175");
+
+        let uuid = Uuid::new_v4();
+        let key = SessionKey {
+            user_id: "user".to_string(),
+            session_id: uuid.to_string(),
+        };
+        assert_ne!(key.cache_uuid(), uuid);
+    }
+
+    #[tokio::test]
+    async fn test_users_sharing_a_session_id_are_isolated() {
+        let session_id = Uuid::new_v4().to_string();
+        let alice = SessionKey { user_id: "alice".to_string(), session_id: session_id.clone() };
+        let bob = SessionKey { user_id: "bob".to_string(), session_id };
+        assert_ne!(alice.cache_uuid(), bob.cache_uuid());
+
+        let store = StickinessStore::new(StickinessConfig::default(), None);
+        store.bind(&alice, Uuid::new_v4(), "claude-sonnet", "anthropic", "claude-3-5-sonnet").await;
+        assert!(store.get(&bob, "claude-sonnet", StickinessMode::Session).await.is_none());
+
+        store.bind(&bob, Uuid::new_v4(), "claude-sonnet", "bedrock", "anthropic.claude-3-5-sonnet").await;
+        let binding = store.get(&alice, "claude-sonnet", StickinessMode::Session).await.unwrap();
+        assert_eq!((binding.provider_id.as_str(), binding.requests), ("anthropic", 1));
+    }
+}
@@ -0,0 +1,36 @@
+//! SQLite access shared by the stores kept in `storage.db`
+//!
+//! Learned metrics and shadow comparisons each create their own tables but
+//! open the database the same way: the file and its directory are created on
+//! first use, and a small pool is enough for their background writes.
+
+use crate::error::{OmenError, Result};
+use sqlx::{
+    SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+use std::str::FromStr;
+
+/// Connections per pool; writes are batched in the background
+const MAX_CONNECTIONS: u32 = 2;
+
+/// Pool for the SQLite database at `db_url`, creating it if it doesn't exist
+pub async fn open_sqlite(db_url: &str) -> Result<SqlitePool> {
+    // Make sure the database's directory exists
+    let path = db_url.trim_start_matches("sqlite://").trim_start_matches("sqlite:");
+    if let Some(parent) = std::path::Path::new(path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        tokio::fs::create_dir_all(parent).await
+            .map_err(|e| OmenError::Database(format!("Failed to create parent directory: {}", e)))?;
+    }
+
+    let options = SqliteConnectOptions::from_str(db_url)
+        .map_err(|e| OmenError::Database(format!("Failed to parse database URL: {}", e)))?
+        .create_if_missing(true);
+    SqlitePoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .connect_with(options)
+        .await
+        .map_err(|e| OmenError::Database(format!("Failed to connect to SQLite: {}", e)))
+}
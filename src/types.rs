@@ -1,3 +1,7 @@
+use crate::{
+    circuit_breaker::CircuitState, experiments::Assignment, learned_metrics::ProviderLearnedMetrics,
+    routing::{RouteExplanation, RoutingStrategy}, rules::RuleEvaluation,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -7,9 +11,9 @@ use uuid::Uuid;
 pub struct EmbeddingsRequest {
     pub input: EmbeddingInput,
     pub model: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub encoding_format: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dimensions: Option<u32>,
 }
 
@@ -267,14 +271,54 @@ pub struct ToolFunction {
     pub parameters: serde_json::Value,
 }
 
+/// Serialized the OpenAI way: `"auto"`, `"none"` or
+/// `{"type": "function", "function": {"name": ...}}`
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
+#[serde(try_from = "ToolChoiceRepr", into = "ToolChoiceRepr")]
 pub enum ToolChoice {
     Auto,
     None,
     Function { function: ToolFunctionChoice },
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ToolChoiceRepr {
+    Mode(String),
+    Function {
+        #[serde(rename = "type", default = "default_tool_type")]
+        tool_type: String,
+        function: ToolFunctionChoice,
+    },
+}
+
+fn default_tool_type() -> String {
+    "function".to_string()
+}
+
+impl TryFrom<ToolChoiceRepr> for ToolChoice {
+    type Error = String;
+
+    fn try_from(repr: ToolChoiceRepr) -> Result<Self, Self::Error> {
+        match repr {
+            ToolChoiceRepr::Mode(mode) if mode == "auto" => Ok(ToolChoice::Auto),
+            ToolChoiceRepr::Mode(mode) if mode == "none" => Ok(ToolChoice::None),
+            ToolChoiceRepr::Mode(mode) => Err(format!("unsupported tool_choice \"{}\"", mode)),
+            ToolChoiceRepr::Function { function, .. } => Ok(ToolChoice::Function { function }),
+        }
+    }
+}
+
+impl From<ToolChoice> for ToolChoiceRepr {
+    fn from(choice: ToolChoice) -> Self {
+        match choice {
+            ToolChoice::Auto => ToolChoiceRepr::Mode("auto".to_string()),
+            ToolChoice::None => ToolChoiceRepr::Mode("none".to_string()),
+            ToolChoice::Function { function } => ToolChoiceRepr::Function { tool_type: default_tool_type(), function },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolFunctionChoice {
     pub name: String,
@@ -294,6 +338,42 @@ pub struct ToolCallFunction {
     pub arguments: String,
 }
 
+/// A streamed piece of a tool call. Deltas with the same `index` build up
+/// one call; only the first carries its id, type and name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub tool_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<ToolCallFunctionDelta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallFunctionDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+impl ToolCallDelta {
+    /// The whole of `call` as a single delta
+    pub fn complete(index: u32, call: &ToolCall) -> Self {
+        Self {
+            index,
+            id: Some(call.id.clone()),
+            tool_type: Some(call.tool_type.clone()),
+            function: Some(ToolCallFunctionDelta {
+                name: Some(call.function.name.clone()),
+                arguments: Some(call.function.arguments.clone()),
+            }),
+        }
+    }
+}
+
 // Chat completion response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatCompletionResponse {
@@ -305,6 +385,68 @@ pub struct ChatCompletionResponse {
     pub usage: Usage,
     #[serde(default)]
     pub system_fingerprint: Option<String>,
+    /// OMEN routing metadata (only present when OMEN has something to report)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub omen: Option<OmenResponseMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct OmenResponseMetadata {
+    /// Outcome of a parallel_merge request: winner, selector and losing candidates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge: Option<crate::merge::MergeSummary>,
+    /// How the conversation was shortened to fit the serving model's context window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<crate::context_window::ContextTruncation>,
+    /// How max_tokens was lowered to fit the request budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<crate::budget::BudgetClamp>,
+    /// Where the request was routed and why; returned when `omen.explain` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<RoutingMetadata>,
+}
+
+impl OmenResponseMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.merge.is_none() && self.context.is_none() && self.budget.is_none() && self.routing.is_none()
+    }
+}
+
+/// How a request was routed, reported in `x-omen-*` headers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingMetadata {
+    /// Serving provider; unset for multiplexed streams until a winner emerges
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// single, sticky, race, speculate_k, parallel_merge or cache
+    pub strategy: String,
+    /// Actual cost when known; streams are billed as they complete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// max_tokens the serving model was sent, when clamped to fit the budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_clamped: Option<u32>,
+    /// hit, miss or bypass
+    pub cache: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intent: Option<String>,
+    /// Routing rules that matched the request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+    /// Confidence of the weighted routing decision, when one was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasoning: Vec<String>,
+    /// Normalized weights the routing decision scored with, after the API
+    /// key's profile and the request's priority weights
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<RoutingStrategy>,
+    /// Provider score multipliers the routing decision applied
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub provider_multipliers: HashMap<String, f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -347,7 +489,7 @@ pub struct ChatMessageDelta {
     #[serde(default)]
     pub content: Option<String>,
     #[serde(default)]
-    pub tool_calls: Option<Vec<ToolCall>>,
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
 }
 
 // Model information
@@ -405,6 +547,12 @@ pub struct ProviderHealth {
     pub avg_cost_per_1k: Option<f64>,
     #[serde(default)]
     pub success_rate: Option<f64>,
+    #[serde(default)]
+    pub circuit_state: CircuitState,
+    #[serde(default)]
+    pub last_checked: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -417,6 +565,52 @@ pub struct ProviderScore {
     pub reliability_score: f64,
     pub overall_score: f64,
     pub recommended: bool,
+    #[serde(default)]
+    pub circuit_state: CircuitState,
+    /// Statistics learned from live traffic, once the provider has served requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub learned: Option<ProviderLearnedMetrics>,
+}
+
+/// Dry-run routing of a request; nothing is sent to any provider
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteExplainResponse {
+    /// Model after routing rules were applied
+    pub model: String,
+    pub strategy: String,
+    pub intent: Option<String>,
+    pub intent_confidence: Option<f64>,
+    pub rules: RuleEvaluation,
+    /// Experiment arm the request was placed in
+    pub experiment: Option<Assignment>,
+    /// Provider the request's session is bound to, which would be tried first
+    pub sticky_provider: Option<String>,
+    /// Candidates ruled out before scoring
+    pub excluded: Vec<ExcludedCandidate>,
+    /// Scores of the remaining candidates; absent when the request is denied
+    pub scoring: Option<RouteExplanation>,
+}
+
+/// A user's rating of an answer served by an experiment arm
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExperimentFeedback {
+    pub experiment: String,
+    /// Arm being rated; found from the user or session id when unset
+    #[serde(default)]
+    pub arm: Option<String>,
+    /// Defaults to the authenticated user
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// 0 (bad) to 1 (good)
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludedCandidate {
+    pub provider_id: String,
+    pub reason: String,
 }
 
 // OMEN-specific configuration for advanced routing strategies
@@ -446,6 +640,43 @@ pub struct OmenConfig {
     /// Minimum useful token threshold for race conditions
     #[serde(default)]
     pub min_useful_tokens: Option<u32>,
+    /// Result selector for parallel_merge: longest_valid, json_schema, majority_vote, llm_judge
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// JSON schema candidates must satisfy when using the json_schema selector
+    #[serde(default)]
+    pub json_schema: Option<serde_json::Value>,
+    /// What to do when the prompt exceeds every candidate's context window:
+    /// reject, drop_oldest, keep_last, summarize
+    #[serde(default)]
+    pub context_overflow: Option<String>,
+    /// Most recent turns kept by the keep_last and summarize overflow policies
+    #[serde(default)]
+    pub keep_last_turns: Option<u32>,
+    /// Include routing metadata in the response body
+    #[serde(default)]
+    pub explain: Option<bool>,
+}
+
+impl OmenConfig {
+    /// Options with nothing set; `Default` fills in the request defaults instead
+    pub fn empty() -> Self {
+        Self {
+            strategy: None,
+            k: None,
+            providers: None,
+            budget_usd: None,
+            max_latency_ms: None,
+            stickiness: None,
+            priority_weights: None,
+            min_useful_tokens: None,
+            selector: None,
+            json_schema: None,
+            context_overflow: None,
+            keep_last_turns: None,
+            explain: None,
+        }
+    }
 }
 
 impl Default for OmenConfig {
@@ -459,6 +690,11 @@ impl Default for OmenConfig {
             stickiness: Some("turn".to_string()),
             priority_weights: None,
             min_useful_tokens: Some(5),
+            selector: None,
+            json_schema: None,
+            context_overflow: None,
+            keep_last_turns: None,
+            explain: None,
         }
     }
 }
@@ -0,0 +1,365 @@
+//! Upstream rate-limit awareness
+//!
+//! Provider adapters pass every completion response through
+//! [`UpstreamQuotaTracker::check`], which reads the provider's rate-limit
+//! headers (`x-ratelimit-*` from OpenAI-compatible APIs, `anthropic-ratelimit-*`
+//! from Anthropic, and `retry-after`/`retry-after-ms`). Providers limit each
+//! model separately, so quota is tracked per provider and model: routing
+//! ranks a model down as its remaining quota runs low and skips it until its
+//! Retry-After has passed, leaving the provider's other models routable. An
+//! upstream 429 becomes
+//! [`OmenError::UpstreamRateLimited`], which fails over to the next provider
+//! and reaches the client as a 429 with an accurate Retry-After.
+
+use crate::{
+    config::UpstreamQuotaConfig,
+    error::{OmenError, Result},
+};
+use reqwest::header::HeaderMap;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+/// Quota kinds reported as `x-ratelimit-{remaining,limit,reset}-<kind>`
+const OPENAI_KINDS: [&str; 2] = ["requests", "tokens"];
+
+/// Quota kinds reported as `anthropic-ratelimit-<kind>-{remaining,limit,reset}`
+const ANTHROPIC_KINDS: [&str; 4] = ["requests", "tokens", "input-tokens", "output-tokens"];
+
+/// Lowest score multiplier for a provider with no quota left
+const MIN_PENALTY: f64 = 0.05;
+
+/// One rate-limit window as a provider reported it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotaWindow {
+    pub remaining: u64,
+    pub limit: Option<u64>,
+    /// Time until the window refills
+    pub reset_in: Option<Duration>,
+}
+
+/// Everything a response said about the provider's rate limits
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuotaReport {
+    pub windows: Vec<QuotaWindow>,
+    pub retry_after: Option<Duration>,
+}
+
+impl QuotaReport {
+    /// Read rate-limit headers in any of the formats providers use
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut windows = Vec::new();
+        for kind in OPENAI_KINDS {
+            windows.extend(window(
+                headers,
+                &format!("x-ratelimit-remaining-{}", kind),
+                &format!("x-ratelimit-limit-{}", kind),
+                &format!("x-ratelimit-reset-{}", kind),
+            ));
+        }
+        for kind in ANTHROPIC_KINDS {
+            windows.extend(window(
+                headers,
+                &format!("anthropic-ratelimit-{}-remaining", kind),
+                &format!("anthropic-ratelimit-{}-limit", kind),
+                &format!("anthropic-ratelimit-{}-reset", kind),
+            ));
+        }
+
+        let retry_after = header(headers, "retry-after-ms")
+            .and_then(|ms| ms.parse::<f64>().ok())
+            .map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+            .or_else(|| header(headers, "retry-after").and_then(parse_wait));
+
+        Self { windows, retry_after }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok().map(str::trim)
+}
+
+fn window(headers: &HeaderMap, remaining: &str, limit: &str, reset: &str) -> Option<QuotaWindow> {
+    let remaining = header(headers, remaining)?.parse().ok()?;
+    Some(QuotaWindow {
+        remaining,
+        limit: header(headers, limit).and_then(|limit| limit.parse().ok()),
+        reset_in: header(headers, reset).and_then(parse_wait),
+    })
+}
+
+/// A wait given as seconds, a Go-style duration ("6m0s", "20ms"), an
+/// RFC 3339 timestamp or an HTTP date
+fn parse_wait(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    if let Some(duration) = parse_go_duration(value) {
+        return Some(duration);
+    }
+    let at = chrono::DateTime::parse_from_rfc3339(value)
+        .or_else(|_| chrono::DateTime::parse_from_rfc2822(value))
+        .ok()?;
+    let wait = at.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+fn parse_go_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail.find(|c: char| c.is_ascii_digit()).unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        total += number * match unit {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = tail;
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[derive(Debug, Default)]
+struct ModelQuota {
+    /// (remaining, limit, refills at) per reported window
+    windows: Vec<(u64, Option<u64>, Option<Instant>)>,
+    cooldown_until: Option<Instant>,
+}
+
+/// Latest rate-limit state of every provider model, shared by all adapters
+#[derive(Debug)]
+pub struct UpstreamQuotaTracker {
+    config: UpstreamQuotaConfig,
+    quotas: Mutex<HashMap<(String, String), ModelQuota>>,
+}
+
+impl Default for UpstreamQuotaTracker {
+    fn default() -> Self {
+        Self::new(UpstreamQuotaConfig::default())
+    }
+}
+
+impl UpstreamQuotaTracker {
+    pub fn new(config: UpstreamQuotaConfig) -> Self {
+        Self { config, quotas: Mutex::new(HashMap::new()) }
+    }
+
+    /// Record the rate-limit headers of a provider's response for a model; a
+    /// 429 becomes an error carrying how long to wait before retrying
+    pub fn check(&self, provider_id: &str, model: &str, response: &reqwest::Response) -> Result<()> {
+        let limited = response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        let report = QuotaReport::from_headers(response.headers());
+        match self.record(provider_id, model, &report, limited) {
+            Some(wait) => {
+                warn!("🚥 {}/{} rate limited the request; cooling down for {}s", provider_id, model, wait.as_secs());
+                Err(OmenError::UpstreamRateLimited {
+                    provider: provider_id.to_string(),
+                    retry_after_seconds: ceil_seconds(wait),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Update a provider model's quota from a report. Returns the cooldown
+    /// started when the provider turned the request away.
+    pub fn record(&self, provider_id: &str, model: &str, report: &QuotaReport, limited: bool) -> Option<Duration> {
+        let now = Instant::now();
+        let max_wait = Duration::from_secs(self.config.max_retry_after_seconds);
+        let mut quotas = self.quotas.lock().unwrap();
+        let quota = quotas.entry((provider_id.to_string(), model.to_string())).or_default();
+
+        if !report.windows.is_empty() {
+            quota.windows = report
+                .windows
+                .iter()
+                .map(|w| (w.remaining, w.limit, w.reset_in.map(|reset| now + reset.min(max_wait))))
+                .collect();
+        }
+
+        // Without an explicit Retry-After, wait for the exhausted window to refill
+        let exhausted_reset = report.windows.iter().filter(|w| w.remaining == 0).filter_map(|w| w.reset_in).max();
+        let wait = match (limited, report.retry_after.or(exhausted_reset)) {
+            (true, wait) => wait.unwrap_or(Duration::from_secs(self.config.default_retry_after_seconds)),
+            (false, Some(wait)) if exhausted_reset.is_some() => wait,
+            (false, _) => return None,
+        };
+        let wait = wait.min(max_wait);
+        quota.cooldown_until = Some(now + wait);
+        debug!("🚥 {}/{} quota exhausted for {}ms", provider_id, model, wait.as_millis());
+        limited.then_some(wait)
+    }
+
+    /// Time left before a provider model should be sent more traffic. With
+    /// the model not yet chosen, the provider waits only while every model it
+    /// was seen serving is cooling down.
+    pub fn cooldown(&self, provider_id: &str, model: Option<&str>) -> Option<Duration> {
+        let now = Instant::now();
+        let quotas = self.quotas.lock().unwrap();
+        let wait = |quota: &ModelQuota| {
+            quota.cooldown_until?.checked_duration_since(now).filter(|wait| !wait.is_zero())
+        };
+        if let Some(model) = model {
+            return wait(quotas.get(&(provider_id.to_string(), model.to_string()))?);
+        }
+
+        let mut waits = quotas.iter().filter(|((provider, _), _)| provider == provider_id).map(|(_, quota)| wait(quota));
+        let first = waits.next()??;
+        waits.try_fold(first, |shortest, wait| wait.map(|wait| wait.min(shortest)))
+    }
+
+    /// Fraction of the provider model's tightest quota window left, 1.0 when unknown
+    pub fn headroom(&self, provider_id: &str, model: &str) -> f64 {
+        let now = Instant::now();
+        let quotas = self.quotas.lock().unwrap();
+        let Some(quota) = quotas.get(&(provider_id.to_string(), model.to_string())) else {
+            return 1.0;
+        };
+        quota
+            .windows
+            .iter()
+            .filter(|(_, _, reset_at)| reset_at.is_none_or(|at| at > now))
+            .filter_map(|(remaining, limit, _)| match limit {
+                Some(limit) if *limit > 0 => Some(*remaining as f64 / *limit as f64),
+                _ => None,
+            })
+            .fold(1.0, f64::min)
+    }
+
+    /// Score multiplier for a provider with the given headroom: 1.0 until it
+    /// drops below `low_headroom`, then proportionally less
+    pub fn penalty(&self, headroom: f64) -> f64 {
+        if headroom >= self.config.low_headroom || self.config.low_headroom <= 0.0 {
+            return 1.0;
+        }
+        (headroom / self.config.low_headroom).clamp(MIN_PENALTY, 1.0)
+    }
+
+    /// The client-facing error when every one of the `(provider, model)`
+    /// targets is cooling down, with the shortest of their waits
+    pub fn all_limited<'a>(&self, targets: impl IntoIterator<Item = (&'a str, Option<&'a str>)>) -> Option<OmenError> {
+        let mut shortest: Option<(Duration, &str)> = None;
+        for (provider_id, model) in targets {
+            let wait = self.cooldown(provider_id, model)?;
+            if shortest.is_none_or(|(min, _)| wait < min) {
+                shortest = Some((wait, provider_id));
+            }
+        }
+        let (wait, provider_id) = shortest?;
+        Some(OmenError::UpstreamRateLimited {
+            provider: provider_id.to_string(),
+            retry_after_seconds: ceil_seconds(wait),
+        })
+    }
+}
+
+fn ceil_seconds(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parses_openai_and_anthropic_headers() {
+        let openai = QuotaReport::from_headers(&headers(&[
+            ("x-ratelimit-limit-requests", "500"),
+            ("x-ratelimit-remaining-requests", "499"),
+            ("x-ratelimit-reset-requests", "120ms"),
+            ("x-ratelimit-limit-tokens", "30000"),
+            ("x-ratelimit-remaining-tokens", "1200"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+            ("retry-after-ms", "1500"),
+        ]));
+        assert_eq!(openai.windows.len(), 2);
+        assert_eq!(openai.windows[0].reset_in, Some(Duration::from_millis(120)));
+        assert_eq!(openai.windows[1], QuotaWindow { remaining: 1200, limit: Some(30000), reset_in: Some(Duration::from_secs(360)) });
+        assert_eq!(openai.retry_after, Some(Duration::from_millis(1500)));
+
+        let reset = (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc3339();
+        let anthropic = QuotaReport::from_headers(&headers(&[
+            ("anthropic-ratelimit-requests-limit", "50"),
+            ("anthropic-ratelimit-requests-remaining", "0"),
+            ("anthropic-ratelimit-requests-reset", &reset),
+            ("retry-after", "12"),
+        ]));
+        let window = anthropic.windows[0];
+        assert_eq!((window.remaining, window.limit), (0, Some(50)));
+        assert!(window.reset_in.unwrap() > Duration::from_secs(25));
+        assert_eq!(anthropic.retry_after, Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn test_rate_limit_starts_cooldown() {
+        let tracker = UpstreamQuotaTracker::default();
+        let report = QuotaReport { retry_after: Some(Duration::from_secs(7)), ..Default::default() };
+        assert_eq!(tracker.record("openai", "gpt-4o", &report, true), Some(Duration::from_secs(7)));
+        assert!(tracker.cooldown("openai", Some("gpt-4o")).unwrap() > Duration::from_secs(6));
+        assert!(tracker.cooldown("openai", None).unwrap() > Duration::from_secs(6));
+        assert!(tracker.cooldown("anthropic", None).is_none());
+
+        match tracker.all_limited([("openai", Some("gpt-4o"))]) {
+            Some(OmenError::UpstreamRateLimited { provider, retry_after_seconds }) => {
+                assert_eq!(provider, "openai");
+                assert_eq!(retry_after_seconds, 7);
+            }
+            other => panic!("expected upstream rate limit, got {:?}", other),
+        }
+        assert!(tracker.all_limited([("openai", Some("gpt-4o")), ("anthropic", None)]).is_none());
+
+        // A 429 without Retry-After still backs off
+        assert_eq!(tracker.record("xai", "grok-2", &QuotaReport::default(), true), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_rate_limit_is_per_model() {
+        let tracker = UpstreamQuotaTracker::default();
+        let report = QuotaReport { retry_after: Some(Duration::from_secs(30)), ..Default::default() };
+        tracker.record("openai", "text-embedding-3-small", &report, true);
+
+        assert!(tracker.cooldown("openai", Some("text-embedding-3-small")).is_some());
+        assert!(tracker.cooldown("openai", Some("gpt-4o")).is_none());
+        assert!(tracker.all_limited([("openai", Some("gpt-4o"))]).is_none());
+
+        // The provider stays routable for an unchosen model while one of its models has quota
+        tracker.record("openai", "gpt-4o", &QuotaReport::default(), false);
+        assert!(tracker.cooldown("openai", None).is_none());
+    }
+
+    #[test]
+    fn test_low_headroom_ranks_provider_down() {
+        let tracker = UpstreamQuotaTracker::default();
+        let window = |remaining| QuotaWindow { remaining, limit: Some(1000), reset_in: Some(Duration::from_secs(60)) };
+        let report = QuotaReport { windows: vec![window(900), window(50)], retry_after: None };
+        assert_eq!(tracker.record("openai", "gpt-4o", &report, false), None);
+
+        let headroom = tracker.headroom("openai", "gpt-4o");
+        assert!((headroom - 0.05).abs() < 1e-9);
+        assert!((tracker.penalty(headroom) - 0.5).abs() < 1e-9);
+        assert_eq!(tracker.penalty(tracker.headroom("openai", "gpt-4o-mini")), 1.0);
+        assert!(tracker.cooldown("openai", Some("gpt-4o")).is_none());
+
+        // An exhausted window keeps the model out until it refills
+        tracker.record("openai", "gpt-4o", &QuotaReport { windows: vec![window(0)], retry_after: None }, false);
+        assert!(tracker.cooldown("openai", Some("gpt-4o")).is_some());
+    }
+}
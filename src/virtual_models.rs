@@ -0,0 +1,287 @@
+//! Virtual model aliases
+//!
+//! A virtual model such as `fast` or `smart` maps to a list of concrete
+//! deployments (provider instance + provider model id + parameter overrides).
+//! Resolving an alias yields the deployments in the order they should be
+//! tried; the router fails over down the list when a deployment errors.
+
+use crate::{
+    config::{DeploymentConfig, DeploymentOverrides, DeploymentSelection, VirtualModelConfig},
+    types::{ChatCompletionRequest, ChatMessage, Model, ModelCapabilities, ModelPricing},
+};
+use std::collections::HashMap;
+
+/// A concrete provider/model target with the overrides to apply to requests
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub provider_id: String,
+    pub model: String,
+    pub weight: u32,
+    pub overrides: DeploymentOverrides,
+    /// Conversation shortened to fit this deployment's context window
+    pub messages: Option<Vec<ChatMessage>>,
+}
+
+impl From<&DeploymentConfig> for Deployment {
+    fn from(config: &DeploymentConfig) -> Self {
+        Self {
+            provider_id: config.provider.clone(),
+            model: config.model.clone(),
+            weight: config.weight,
+            overrides: config.overrides.clone(),
+            messages: None,
+        }
+    }
+}
+
+impl Deployment {
+    /// Rewrite a request for this deployment: swap in the provider model id
+    /// and force the configured parameter overrides
+    pub fn apply(&self, request: &ChatCompletionRequest) -> ChatCompletionRequest {
+        let mut request = request.clone();
+        request.model = self.model.clone();
+        if let Some(ref messages) = self.messages {
+            request.messages = messages.clone();
+        }
+
+        let overrides = &self.overrides;
+        if overrides.temperature.is_some() {
+            request.temperature = overrides.temperature;
+        }
+        if overrides.max_tokens.is_some() {
+            request.max_tokens = overrides.max_tokens;
+        }
+        if overrides.top_p.is_some() {
+            request.top_p = overrides.top_p;
+        }
+        if overrides.frequency_penalty.is_some() {
+            request.frequency_penalty = overrides.frequency_penalty;
+        }
+        if overrides.presence_penalty.is_some() {
+            request.presence_penalty = overrides.presence_penalty;
+        }
+        if overrides.stop.is_some() {
+            request.stop = overrides.stop.clone();
+        }
+
+        request
+    }
+}
+
+/// Resolved virtual model - all fields are part of the public API
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct VirtualModel {
+    pub name: String,
+    pub description: Option<String>,
+    pub selection: DeploymentSelection,
+    pub deployments: Vec<Deployment>,
+}
+
+impl VirtualModel {
+    /// Deployments in the order they should be tried for one request
+    pub fn ordered_deployments(&self) -> Vec<Deployment> {
+        match self.selection {
+            DeploymentSelection::Ordered => self.deployments.clone(),
+            DeploymentSelection::Weighted => Self::weighted_order(&self.deployments, random_unit),
+        }
+    }
+
+    /// Weighted sampling without replacement: heavier deployments tend to go
+    /// first, but every deployment remains in the list for failover
+    fn weighted_order(deployments: &[Deployment], mut random: impl FnMut() -> f64) -> Vec<Deployment> {
+        let mut remaining = deployments.to_vec();
+        let mut ordered = Vec::with_capacity(remaining.len());
+
+        while !remaining.is_empty() {
+            let total: u64 = remaining.iter().map(|d| d.weight as u64).sum();
+            let index = if total == 0 {
+                0
+            } else {
+                let mut target = random() * total as f64;
+                remaining
+                    .iter()
+                    .position(|d| {
+                        target -= d.weight as f64;
+                        target < 0.0
+                    })
+                    .unwrap_or(remaining.len() - 1)
+            };
+            ordered.push(remaining.remove(index));
+        }
+
+        ordered
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VirtualModelRegistry {
+    models: HashMap<String, VirtualModel>,
+}
+
+/// VirtualModelRegistry implementation - all public methods are part of the routing API
+#[allow(dead_code)]
+impl VirtualModelRegistry {
+    pub fn new(configs: &HashMap<String, VirtualModelConfig>) -> Self {
+        let models = configs
+            .iter()
+            .filter(|(_, config)| !config.deployments.is_empty())
+            .map(|(name, config)| {
+                let model = VirtualModel {
+                    name: name.clone(),
+                    description: config.description.clone(),
+                    selection: config.selection,
+                    deployments: config.deployments.iter().map(Deployment::from).collect(),
+                };
+                (name.clone(), model)
+            })
+            .collect();
+
+        Self { models }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VirtualModel> {
+        self.models.get(name)
+    }
+
+    pub fn is_virtual(&self, name: &str) -> bool {
+        self.models.contains_key(name)
+    }
+
+    /// Resolve an alias into the deployments to try, in order
+    pub fn resolve(&self, name: &str) -> Option<Vec<Deployment>> {
+        self.models.get(name).map(|m| m.ordered_deployments())
+    }
+
+    pub fn all(&self) -> Vec<&VirtualModel> {
+        let mut models: Vec<_> = self.models.values().collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        models
+    }
+
+    /// Describe each alias as a model entry, borrowing context length,
+    /// pricing and capabilities from the underlying provider models
+    pub fn to_models(&self, provider_models: &[Model]) -> Vec<Model> {
+        self.all()
+            .into_iter()
+            .map(|virtual_model| {
+                let backing: Vec<&Model> = virtual_model
+                    .deployments
+                    .iter()
+                    .filter_map(|d| {
+                        provider_models
+                            .iter()
+                            .find(|m| m.provider == d.provider_id && m.id == d.model)
+                    })
+                    .collect();
+
+                let primary = backing.first();
+
+                Model {
+                    id: virtual_model.name.clone(),
+                    object: "model".to_string(),
+                    created: chrono::Utc::now().timestamp(),
+                    owned_by: "omen".to_string(),
+                    provider: "omen".to_string(),
+                    // Smallest window so any deployment can serve the request
+                    context_length: backing.iter().map(|m| m.context_length).min().unwrap_or(0),
+                    pricing: primary.map(|m| m.pricing.clone()).unwrap_or(ModelPricing {
+                        input_per_1k: 0.0,
+                        output_per_1k: 0.0,
+                    }),
+                    capabilities: ModelCapabilities {
+                        vision: !backing.is_empty() && backing.iter().all(|m| m.capabilities.vision),
+                        functions: !backing.is_empty() && backing.iter().all(|m| m.capabilities.functions),
+                        streaming: !backing.is_empty() && backing.iter().all(|m| m.capabilities.streaming),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+fn random_unit() -> f64 {
+    (uuid::Uuid::new_v4().as_u128() >> 75) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deployment(provider: &str, model: &str, weight: u32) -> DeploymentConfig {
+        DeploymentConfig {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            weight,
+            overrides: DeploymentOverrides::default(),
+        }
+    }
+
+    #[test]
+    fn test_ordered_resolution_keeps_config_order() {
+        let mut configs = HashMap::new();
+        configs.insert(
+            "fast".to_string(),
+            VirtualModelConfig {
+                description: None,
+                selection: DeploymentSelection::Ordered,
+                deployments: vec![
+                    deployment("ollama", "llama3", 1),
+                    deployment("openai", "gpt-4o-mini", 1),
+                ],
+            },
+        );
+
+        let registry = VirtualModelRegistry::new(&configs);
+        let resolved = registry.resolve("fast").unwrap();
+        assert_eq!(resolved[0].provider_id, "ollama");
+        assert_eq!(resolved[1].model, "gpt-4o-mini");
+        assert!(registry.resolve("gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_weighted_order_respects_weights_and_keeps_all() {
+        let deployments = vec![
+            Deployment::from(&deployment("openai", "gpt-4o", 1)),
+            Deployment::from(&deployment("anthropic", "claude-3-5-sonnet", 3)),
+        ];
+
+        // 0.5 * 4 = 2.0 falls in the second deployment's range [1, 4)
+        let ordered = VirtualModel::weighted_order(&deployments, || 0.5);
+        assert_eq!(ordered.len(), 2);
+        assert_eq!(ordered[0].provider_id, "anthropic");
+        assert_eq!(ordered[1].provider_id, "openai");
+
+        let ordered = VirtualModel::weighted_order(&deployments, || 0.1);
+        assert_eq!(ordered[0].provider_id, "openai");
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut config = deployment("openai", "gpt-4o-mini", 1);
+        config.overrides.temperature = Some(0.2);
+        config.overrides.max_tokens = Some(256);
+
+        let request = ChatCompletionRequest {
+            model: "fast".to_string(),
+            messages: vec![],
+            temperature: Some(0.9),
+            max_tokens: None,
+            stream: false,
+            top_p: Some(0.5),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            tools: None,
+            tool_choice: None,
+            tags: None,
+            omen: None,
+        };
+
+        let applied = Deployment::from(&config).apply(&request);
+        assert_eq!(applied.model, "gpt-4o-mini");
+        assert_eq!(applied.temperature, Some(0.2));
+        assert_eq!(applied.max_tokens, Some(256));
+        assert_eq!(applied.top_p, Some(0.5));
+    }
+}
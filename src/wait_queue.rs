@@ -0,0 +1,170 @@
+//! Slot queues shared by bulkheads and fair-share admission
+//!
+//! Both cap the requests in flight and queue the rest by rank. A waiter that
+//! gives up (times out or is dropped) leaves the queue, and a slot granted
+//! to it after it stopped waiting is handed on instead of leaking.
+
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc, time::Duration};
+use tokio::sync::oneshot;
+
+/// Requests in flight and those waiting for a slot, highest rank first
+#[derive(Debug)]
+pub struct WaitQueue<T> {
+    pub in_flight: usize,
+    waiters: BinaryHeap<Waiter<T>>,
+    next_seq: u64,
+}
+
+impl<T> Default for WaitQueue<T> {
+    fn default() -> Self {
+        Self { in_flight: 0, waiters: BinaryHeap::new(), next_seq: 0 }
+    }
+}
+
+impl<T> WaitQueue<T> {
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    /// Join the queue, returning the waiter's sequence number and its grant
+    pub fn enqueue(&mut self, rank: f64, tag: T) -> (u64, oneshot::Receiver<()>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let (grant, granted) = oneshot::channel();
+        self.waiters.push(Waiter { rank, seq, tag, grant });
+        (seq, granted)
+    }
+
+    /// Leave the queue, returning the waiter's tag if it was still queued
+    pub fn remove(&mut self, seq: u64) -> Option<T> {
+        let mut removed = None;
+        let waiters = std::mem::take(&mut self.waiters).into_vec();
+        self.waiters = waiters
+            .into_iter()
+            .filter_map(|waiter| {
+                if waiter.seq == seq {
+                    removed = Some(waiter.tag);
+                    None
+                } else {
+                    Some(waiter)
+                }
+            })
+            .collect();
+        removed
+    }
+
+    /// Hand a freed slot to the best waiter still waiting, or free it.
+    /// `on_dequeue` sees every waiter taken off the queue.
+    pub fn release(&mut self, mut on_dequeue: impl FnMut(&T)) {
+        while let Some(waiter) = self.waiters.pop() {
+            on_dequeue(&waiter.tag);
+            if waiter.grant.send(()).is_ok() {
+                return;
+            }
+        }
+        self.in_flight = self.in_flight.saturating_sub(1);
+    }
+}
+
+#[derive(Debug)]
+struct Waiter<T> {
+    rank: f64,
+    seq: u64,
+    tag: T,
+    grant: oneshot::Sender<()>,
+}
+
+impl<T> Ord for Waiter<T> {
+    /// Higher rank first, then earlier arrival
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank.total_cmp(&other.rank).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl<T> PartialOrd for Waiter<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Waiter<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Waiter<T> {}
+
+/// Owner of a `WaitQueue`, locked by the owner alongside its own state
+pub trait SlotPool: Send + Sync {
+    /// Take a waiter off the queue, returning whether it was still queued
+    fn cancel(&self, seq: u64) -> bool;
+
+    /// Return a slot granted to a waiter that stopped waiting
+    fn release_unused(&self);
+}
+
+/// A queued request; leaves the queue when dropped, returning a slot granted
+/// after it stopped waiting
+pub struct Waiting<P: SlotPool> {
+    pool: Arc<P>,
+    seq: u64,
+    grant: Option<oneshot::Receiver<()>>,
+}
+
+impl<P: SlotPool> Waiting<P> {
+    pub fn new(pool: Arc<P>, seq: u64, grant: oneshot::Receiver<()>) -> Self {
+        Self { pool, seq, grant: Some(grant) }
+    }
+
+    /// Wait up to `timeout` for a slot; false once the waiter has left the queue
+    pub async fn granted(mut self, timeout: Duration) -> bool {
+        let granted = tokio::time::timeout(timeout, self.grant.as_mut().unwrap()).await;
+        if let Ok(Ok(())) = granted {
+            self.grant = None;
+            return true;
+        }
+        false
+    }
+}
+
+impl<P: SlotPool> Drop for Waiting<P> {
+    fn drop(&mut self) {
+        let Some(mut grant) = self.grant.take() else {
+            return;
+        };
+        if !self.pool.cancel(self.seq) && grant.try_recv().is_ok() {
+            self.pool.release_unused();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_skips_abandoned_waiters() {
+        let mut queue = WaitQueue { in_flight: 1, ..WaitQueue::default() };
+        let (_, low) = queue.enqueue(1.0, "low");
+        let (_, abandoned) = queue.enqueue(5.0, "abandoned");
+        let (high_seq, mut high) = queue.enqueue(5.0, "high");
+        drop(abandoned);
+        assert_eq!(queue.remove(high_seq), Some("high"));
+        assert!(high.try_recv().is_err());
+
+        let mut dequeued = Vec::new();
+        queue.release(|tag| dequeued.push(*tag));
+        assert_eq!(dequeued, vec!["abandoned", "low"]);
+        assert_eq!(queue.in_flight, 1);
+        drop(low);
+
+        queue.release(|_| {});
+        assert_eq!(queue.in_flight, 0);
+        assert!(queue.is_empty());
+    }
+}